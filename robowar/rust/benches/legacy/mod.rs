@@ -0,0 +1,635 @@
+//! The interpreter the pre-decoded core replaced, kept so the benchmark can compare the two on the same program.
+//!
+//! It clones each [`Instruction`] out of the program every step and resolves every operand by matching on it, as
+//! `VirtualMachine::step` used to. Robot sensors are plain fields here rather than being read from a physics actor,
+//! and the scanner is a closure like the new core's.
+
+use std::{collections::HashSet, rc::Rc};
+
+use robowar::simulation::{
+    language::*,
+    vm::{ClockTime, StackOrHeapValue, StepError},
+};
+
+struct ResolvedValue<T> {
+    value: T,
+    clock_cost: ClockTime,
+}
+
+pub struct VirtualMachine {
+    program: Rc<Program>,
+
+    stack: Vec<StackOrHeapValue>,
+    heap: Vec<StackOrHeapValue>,
+
+    program_counter: ProgramPointer,
+    clock: ClockTime,
+    instruction_count: u64,
+
+    pending_interrupts: HashSet<InterruptKind>,
+    in_interrupt: bool,
+
+    health: f64,
+    energy: f64,
+    position: (f64, f64),
+    velocity: (f64, f64),
+    turret_angle: f64,
+    turret_angular_velocity: f64,
+    fire: f64,
+
+    register_general_purpose_u64: [u64; 8],
+    register_general_purpose_f64: [f64; 8],
+}
+
+impl VirtualMachine {
+    pub fn new(program: Rc<Program>) -> Self {
+        let stack = Vec::with_capacity(program.stack_size);
+        let heap = vec![StackOrHeapValue::U64(0); program.heap_size];
+        Self {
+            program,
+            stack,
+            heap,
+
+            program_counter: 0.into(),
+            clock: ClockTime(0),
+            instruction_count: 0,
+
+            pending_interrupts: HashSet::new(),
+            in_interrupt: false,
+
+            health: 100.0,
+            energy: 100.0,
+            position: (0., 0.),
+            velocity: (0., 0.),
+            turret_angle: 0.,
+            turret_angular_velocity: 0.,
+            fire: 0.,
+
+            register_general_purpose_u64: [0; 8],
+            register_general_purpose_f64: [0.; 8],
+        }
+    }
+
+    pub fn step(&mut self, scanner: &impl Fn() -> f64) -> Result<ClockTime, StepError> {
+        if self.dispatch_pending_interrupt()? {
+            return Ok(self.clock);
+        }
+        match self.read_next_instruction() {
+            Some(Instruction::SetU64 {
+                destination,
+                source,
+            }) => {
+                let source = self.resolve_source_u64(source);
+                self.write_destination_u64(destination, source.value);
+                self.clock += source.clock_cost;
+            }
+            Some(Instruction::SetF64 {
+                destination,
+                source,
+            }) => {
+                let source = self.resolve_source_f64(source, scanner);
+                self.write_destination_f64(destination, source.value);
+                self.clock += source.clock_cost;
+            }
+            Some(Instruction::AddU64 {
+                destination,
+                left,
+                right,
+            }) => self.binary_operator_common_u64(destination, left, right, |left, right| {
+                Ok(left.wrapping_add(right))
+            })?,
+            Some(Instruction::AddF64 {
+                destination,
+                left,
+                right,
+            }) => self.binary_operator_common_f64(
+                destination,
+                left,
+                right,
+                |left, right| left + right,
+                scanner,
+            )?,
+            Some(Instruction::SubU64 {
+                destination,
+                left,
+                right,
+            }) => self.binary_operator_common_u64(destination, left, right, |left, right| {
+                Ok(left.wrapping_sub(right))
+            })?,
+            Some(Instruction::SubF64 {
+                destination,
+                left,
+                right,
+            }) => self.binary_operator_common_f64(
+                destination,
+                left,
+                right,
+                |left, right| left - right,
+                scanner,
+            )?,
+            Some(Instruction::MulU64 {
+                destination,
+                left,
+                right,
+            }) => self.binary_operator_common_u64(destination, left, right, |left, right| {
+                Ok(left.wrapping_mul(right))
+            })?,
+            Some(Instruction::MulF64 {
+                destination,
+                left,
+                right,
+            }) => self.binary_operator_common_f64(
+                destination,
+                left,
+                right,
+                |left, right| left * right,
+                scanner,
+            )?,
+            Some(Instruction::DivU64 {
+                destination,
+                left,
+                right,
+            }) => self.binary_operator_common_u64(destination, left, right, |left, right| {
+                left.checked_div(right).ok_or(StepError::DivideByZero)
+            })?,
+            Some(Instruction::DivF64 {
+                destination,
+                left,
+                right,
+            }) => self.binary_operator_common_f64(
+                destination,
+                left,
+                right,
+                |left, right| left / right,
+                scanner,
+            )?,
+            Some(Instruction::Jump { address }) => {
+                let address = self.resolve_source_u64(address);
+                self.program_counter = address
+                    .value
+                    .try_into()
+                    .map_err(StepError::TryFromIntError)?;
+                self.clock += address.clock_cost;
+            }
+            Some(Instruction::JumpEqualU64 {
+                address,
+                left,
+                right,
+            }) => self.jump_comparison_u64(address, left, right, |left, right| left == right)?,
+            Some(Instruction::JumpEqualF64 {
+                address,
+                left,
+                right,
+            }) => self.jump_comparison_f64(
+                address,
+                left,
+                right,
+                |left, right| left == right,
+                scanner,
+            )?,
+            Some(Instruction::JumpNotEqualU64 {
+                address,
+                left,
+                right,
+            }) => self.jump_comparison_u64(address, left, right, |left, right| left != right)?,
+            Some(Instruction::JumpNotEqualF64 {
+                address,
+                left,
+                right,
+            }) => self.jump_comparison_f64(
+                address,
+                left,
+                right,
+                |left, right| left != right,
+                scanner,
+            )?,
+            Some(Instruction::JumpLessThanU64 {
+                address,
+                left,
+                right,
+            }) => self.jump_comparison_u64(address, left, right, |left, right| left < right)?,
+            Some(Instruction::JumpLessThanF64 {
+                address,
+                left,
+                right,
+            }) => {
+                self.jump_comparison_f64(address, left, right, |left, right| left < right, scanner)?
+            }
+            Some(Instruction::JumpLessThanOrEqualToU64 {
+                address,
+                left,
+                right,
+            }) => self.jump_comparison_u64(address, left, right, |left, right| left <= right)?,
+            Some(Instruction::JumpLessThanOrEqualToF64 {
+                address,
+                left,
+                right,
+            }) => self.jump_comparison_f64(
+                address,
+                left,
+                right,
+                |left, right| left <= right,
+                scanner,
+            )?,
+            Some(Instruction::JumpGreaterThanU64 {
+                address,
+                left,
+                right,
+            }) => self.jump_comparison_u64(address, left, right, |left, right| left > right)?,
+            Some(Instruction::JumpGreaterThanF64 {
+                address,
+                left,
+                right,
+            }) => {
+                self.jump_comparison_f64(address, left, right, |left, right| left > right, scanner)?
+            }
+            Some(Instruction::JumpGreaterThanOrEqualToU64 {
+                address,
+                left,
+                right,
+            }) => self.jump_comparison_u64(address, left, right, |left, right| left >= right)?,
+            Some(Instruction::JumpGreaterThanOrEqualToF64 {
+                address,
+                left,
+                right,
+            }) => self.jump_comparison_f64(
+                address,
+                left,
+                right,
+                |left, right| left >= right,
+                scanner,
+            )?,
+            Some(Instruction::ShiftLeft {
+                destination,
+                source,
+            }) => self.unary_operator_common_u64(destination, source, |source| source << 1)?,
+            Some(Instruction::ShiftRight {
+                destination,
+                source,
+            }) => self.unary_operator_common_u64(destination, source, |source| source >> 1)?,
+            Some(Instruction::PushU64 { source }) => {
+                let source = self.resolve_source_u64(source);
+                self.push(StackOrHeapValue::U64(source.value))?;
+                self.clock += source.clock_cost;
+            }
+            Some(Instruction::PushF64 { source }) => {
+                let source = self.resolve_source_f64(source, scanner);
+                self.push(StackOrHeapValue::F64(source.value))?;
+                self.clock += source.clock_cost;
+            }
+            Some(Instruction::PopU64 { destination }) => {
+                let value = self.pop_u64()?;
+                self.write_destination_u64(destination, value);
+                self.clock += ClockTime(1);
+            }
+            Some(Instruction::PopF64 { destination }) => {
+                let value = self.pop_f64()?;
+                self.write_destination_f64(destination, value);
+                self.clock += ClockTime(1);
+            }
+            Some(Instruction::LoadU64 {
+                destination,
+                source_address,
+            }) => {
+                let source_address = self.resolve_source_u64(source_address);
+                let value = self.load_u64(source_address.value)?;
+                self.write_destination_u64(destination, value);
+                self.clock += source_address.clock_cost;
+            }
+            Some(Instruction::LoadF64 {
+                destination,
+                source_address,
+            }) => {
+                let source_address = self.resolve_source_u64(source_address);
+                let value = self.load_f64(source_address.value)?;
+                self.write_destination_f64(destination, value);
+                self.clock += source_address.clock_cost;
+            }
+            Some(Instruction::StoreU64 {
+                source,
+                destination_address,
+            }) => {
+                let destination_address = self.resolve_source_u64(destination_address);
+                let source = self.resolve_source_u64(source);
+                self.store(
+                    destination_address.value,
+                    StackOrHeapValue::U64(source.value),
+                )?;
+                self.clock += destination_address.clock_cost;
+                self.clock += source.clock_cost;
+            }
+            Some(Instruction::StoreF64 {
+                source,
+                destination_address,
+            }) => {
+                let destination_address = self.resolve_source_u64(destination_address);
+                let source = self.resolve_source_f64(source, scanner);
+                self.store(
+                    destination_address.value,
+                    StackOrHeapValue::F64(source.value),
+                )?;
+                self.clock += destination_address.clock_cost;
+                self.clock += source.clock_cost;
+            }
+            Some(Instruction::InterruptReturn) => {
+                let address = self.pop_u64()?;
+                self.program_counter = address.try_into().map_err(StepError::TryFromIntError)?;
+                self.in_interrupt = false;
+                self.clock += ClockTime(2);
+            }
+            None => Err(StepError::Halted)?,
+        };
+        Ok(self.clock)
+    }
+
+    fn dispatch_pending_interrupt(&mut self) -> Result<bool, StepError> {
+        if self.in_interrupt {
+            return Ok(false);
+        }
+        let Some(kind) = InterruptKind::ALL
+            .into_iter()
+            .find(|kind| self.pending_interrupts.contains(kind))
+        else {
+            return Ok(false);
+        };
+        self.pending_interrupts.remove(&kind);
+        let Some(handler) = self.program.interrupt_vector(kind) else {
+            return Ok(false);
+        };
+        let return_address = self
+            .program_counter
+            .0
+            .try_into()
+            .map_err(StepError::TryFromIntError)?;
+        self.push(StackOrHeapValue::U64(return_address))?;
+        self.program_counter = handler;
+        self.in_interrupt = true;
+        self.clock += ClockTime(4);
+        Ok(true)
+    }
+
+    fn unary_operator_common_u64(
+        &mut self,
+        destination: DestinationU64,
+        source: SourceU64,
+        f: impl FnOnce(u64) -> u64,
+    ) -> Result<(), StepError> {
+        let source = self.resolve_source_u64(source);
+        self.write_destination_u64(destination, f(source.value));
+        self.clock += source.clock_cost;
+        Ok(())
+    }
+
+    fn binary_operator_common_u64(
+        &mut self,
+        destination: DestinationU64,
+        left: SourceU64,
+        right: SourceU64,
+        f: impl FnOnce(u64, u64) -> Result<u64, StepError>,
+    ) -> Result<(), StepError> {
+        let left = self.resolve_source_u64(left);
+        let right = self.resolve_source_u64(right);
+        self.write_destination_u64(destination, f(left.value, right.value)?);
+        self.clock += left.clock_cost + right.clock_cost;
+        Ok(())
+    }
+
+    fn binary_operator_common_f64(
+        &mut self,
+        destination: DestinationF64,
+        left: SourceF64,
+        right: SourceF64,
+        f: impl FnOnce(f64, f64) -> f64,
+        scanner: &impl Fn() -> f64,
+    ) -> Result<(), StepError> {
+        let left = self.resolve_source_f64(left, scanner);
+        let right = self.resolve_source_f64(right, scanner);
+        self.write_destination_f64(destination, f(left.value, right.value));
+        self.clock += left.clock_cost + right.clock_cost;
+        Ok(())
+    }
+
+    fn jump_comparison_u64(
+        &mut self,
+        address: SourceU64,
+        left: SourceU64,
+        right: SourceU64,
+        f: impl FnOnce(u64, u64) -> bool,
+    ) -> Result<(), StepError> {
+        let left = self.resolve_source_u64(left);
+        let right = self.resolve_source_u64(right);
+        if f(left.value, right.value) {
+            let address = self.resolve_source_u64(address);
+            self.program_counter = address
+                .value
+                .try_into()
+                .map_err(StepError::TryFromIntError)?;
+            self.clock += address.clock_cost;
+        }
+        self.clock += left.clock_cost + right.clock_cost;
+        Ok(())
+    }
+
+    fn jump_comparison_f64(
+        &mut self,
+        address: SourceU64,
+        left: SourceF64,
+        right: SourceF64,
+        f: impl FnOnce(f64, f64) -> bool,
+        scanner: &impl Fn() -> f64,
+    ) -> Result<(), StepError> {
+        let left = self.resolve_source_f64(left, scanner);
+        let right = self.resolve_source_f64(right, scanner);
+        if f(left.value, right.value) {
+            let address = self.resolve_source_u64(address);
+            self.program_counter = address
+                .value
+                .try_into()
+                .map_err(StepError::TryFromIntError)?;
+            self.clock += address.clock_cost;
+        }
+        self.clock += left.clock_cost + right.clock_cost;
+        Ok(())
+    }
+
+    fn read_next_instruction(&mut self) -> Option<Instruction> {
+        self.program.get(self.program_counter).map(|result| {
+            self.program_counter.advance();
+            self.instruction_count += 1;
+            result.clone()
+        })
+    }
+
+    fn resolve_source_u64(&self, source: SourceU64) -> ResolvedValue<u64> {
+        match source {
+            SourceU64::Register(r) => ResolvedValue {
+                value: self.read_register_u64(r),
+                clock_cost: ClockTime(2),
+            },
+            SourceU64::Literal(value) => ResolvedValue {
+                value,
+                clock_cost: ClockTime(1),
+            },
+        }
+    }
+
+    fn resolve_source_f64(
+        &mut self,
+        source: SourceF64,
+        scanner: &impl Fn() -> f64,
+    ) -> ResolvedValue<f64> {
+        match source {
+            SourceF64::Register(r) => ResolvedValue {
+                value: self.read_register_f64(r, scanner),
+                clock_cost: ClockTime(4),
+            },
+            SourceF64::Literal(value) => ResolvedValue {
+                value,
+                clock_cost: ClockTime(2),
+            },
+        }
+    }
+
+    fn write_destination_u64(&mut self, destination: DestinationU64, value: u64) {
+        match destination {
+            DestinationU64::Register(r) => self.write_register_u64(r, value),
+        }
+    }
+
+    fn write_destination_f64(&mut self, destination: DestinationF64, value: f64) {
+        match destination {
+            DestinationF64::Register(r) => self.write_register_f64(r, value),
+        }
+    }
+
+    fn read_register_u64(&self, r: ReadableRegisterU64) -> u64 {
+        match r {
+            ReadableRegisterU64::GeneralPurpose0 => self.register_general_purpose_u64[0],
+            ReadableRegisterU64::GeneralPurpose1 => self.register_general_purpose_u64[1],
+            ReadableRegisterU64::GeneralPurpose2 => self.register_general_purpose_u64[2],
+            ReadableRegisterU64::GeneralPurpose3 => self.register_general_purpose_u64[3],
+            ReadableRegisterU64::GeneralPurpose4 => self.register_general_purpose_u64[4],
+            ReadableRegisterU64::GeneralPurpose5 => self.register_general_purpose_u64[5],
+            ReadableRegisterU64::GeneralPurpose6 => self.register_general_purpose_u64[6],
+            ReadableRegisterU64::GeneralPurpose7 => self.register_general_purpose_u64[7],
+        }
+    }
+
+    fn write_register_u64(&mut self, r: WritableRegisterU64, value: u64) {
+        *match r {
+            WritableRegisterU64::GeneralPurpose0 => &mut self.register_general_purpose_u64[0],
+            WritableRegisterU64::GeneralPurpose1 => &mut self.register_general_purpose_u64[1],
+            WritableRegisterU64::GeneralPurpose2 => &mut self.register_general_purpose_u64[2],
+            WritableRegisterU64::GeneralPurpose3 => &mut self.register_general_purpose_u64[3],
+            WritableRegisterU64::GeneralPurpose4 => &mut self.register_general_purpose_u64[4],
+            WritableRegisterU64::GeneralPurpose5 => &mut self.register_general_purpose_u64[5],
+            WritableRegisterU64::GeneralPurpose6 => &mut self.register_general_purpose_u64[6],
+            WritableRegisterU64::GeneralPurpose7 => &mut self.register_general_purpose_u64[7],
+        } = value;
+    }
+
+    fn read_register_f64(&mut self, r: ReadableRegisterF64, scanner: &impl Fn() -> f64) -> f64 {
+        match r {
+            ReadableRegisterF64::PositionX => self.position.0,
+            ReadableRegisterF64::PositionY => self.position.1,
+            ReadableRegisterF64::VelocityX | ReadableRegisterF64::ActualVelocityX => {
+                self.velocity.0
+            }
+            ReadableRegisterF64::VelocityY | ReadableRegisterF64::ActualVelocityY => {
+                self.velocity.1
+            }
+            ReadableRegisterF64::TurretAngle => self.turret_angle,
+            ReadableRegisterF64::TurretAngularVelocity => self.turret_angular_velocity,
+            ReadableRegisterF64::ScannerDistance => {
+                let distance = scanner();
+                if distance < f64::MAX
+                    && self
+                        .program
+                        .interrupt_vector(InterruptKind::ScanFound)
+                        .is_some()
+                {
+                    self.pending_interrupts.insert(InterruptKind::ScanFound);
+                }
+                distance
+            }
+            ReadableRegisterF64::Health => self.health,
+            ReadableRegisterF64::Energy => self.energy,
+            ReadableRegisterF64::PickupDistance => f64::MAX,
+            ReadableRegisterF64::PickupBearing => 0.,
+            ReadableRegisterF64::Fire => self.fire,
+            ReadableRegisterF64::GeneralPurpose0 => self.register_general_purpose_f64[0],
+            ReadableRegisterF64::GeneralPurpose1 => self.register_general_purpose_f64[1],
+            ReadableRegisterF64::GeneralPurpose2 => self.register_general_purpose_f64[2],
+            ReadableRegisterF64::GeneralPurpose3 => self.register_general_purpose_f64[3],
+            ReadableRegisterF64::GeneralPurpose4 => self.register_general_purpose_f64[4],
+            ReadableRegisterF64::GeneralPurpose5 => self.register_general_purpose_f64[5],
+            ReadableRegisterF64::GeneralPurpose6 => self.register_general_purpose_f64[6],
+            ReadableRegisterF64::GeneralPurpose7 => self.register_general_purpose_f64[7],
+        }
+    }
+
+    fn write_register_f64(&mut self, r: WritableRegisterF64, value: f64) {
+        *match r {
+            WritableRegisterF64::VelocityX => &mut self.velocity.0,
+            WritableRegisterF64::VelocityY => &mut self.velocity.1,
+            WritableRegisterF64::TurretAngularVelocity => &mut self.turret_angular_velocity,
+            WritableRegisterF64::Fire => &mut self.fire,
+            WritableRegisterF64::GeneralPurpose0 => &mut self.register_general_purpose_f64[0],
+            WritableRegisterF64::GeneralPurpose1 => &mut self.register_general_purpose_f64[1],
+            WritableRegisterF64::GeneralPurpose2 => &mut self.register_general_purpose_f64[2],
+            WritableRegisterF64::GeneralPurpose3 => &mut self.register_general_purpose_f64[3],
+            WritableRegisterF64::GeneralPurpose4 => &mut self.register_general_purpose_f64[4],
+            WritableRegisterF64::GeneralPurpose5 => &mut self.register_general_purpose_f64[5],
+            WritableRegisterF64::GeneralPurpose6 => &mut self.register_general_purpose_f64[6],
+            WritableRegisterF64::GeneralPurpose7 => &mut self.register_general_purpose_f64[7],
+        } = value;
+    }
+
+    fn push(&mut self, value: StackOrHeapValue) -> Result<(), StepError> {
+        if self.stack.len() >= self.program.stack_size {
+            return Err(StepError::StackOverflow);
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    fn pop_u64(&mut self) -> Result<u64, StepError> {
+        match self.stack.pop() {
+            Some(StackOrHeapValue::U64(value)) => Ok(value),
+            Some(StackOrHeapValue::F64(value)) => Ok(value as u64),
+            None => Err(StepError::StackUnderflow),
+        }
+    }
+
+    fn pop_f64(&mut self) -> Result<f64, StepError> {
+        match self.stack.pop() {
+            Some(StackOrHeapValue::F64(value)) => Ok(value),
+            Some(StackOrHeapValue::U64(value)) => Ok(value as f64),
+            None => Err(StepError::StackUnderflow),
+        }
+    }
+
+    fn load_u64(&self, address: u64) -> Result<u64, StepError> {
+        match self.heap.get(address as usize) {
+            Some(StackOrHeapValue::U64(value)) => Ok(*value),
+            Some(StackOrHeapValue::F64(value)) => Ok(*value as u64),
+            None => Err(StepError::AddressOutOfBounds),
+        }
+    }
+
+    fn load_f64(&self, address: u64) -> Result<f64, StepError> {
+        match self.heap.get(address as usize) {
+            Some(StackOrHeapValue::F64(value)) => Ok(*value),
+            Some(StackOrHeapValue::U64(value)) => Ok(*value as f64),
+            None => Err(StepError::AddressOutOfBounds),
+        }
+    }
+
+    fn store(&mut self, address: u64, value: StackOrHeapValue) -> Result<(), StepError> {
+        match self.heap.get_mut(address as usize) {
+            Some(slot) => *slot = value,
+            None => Err(StepError::AddressOutOfBounds)?,
+        }
+        Ok(())
+    }
+}
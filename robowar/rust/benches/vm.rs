@@ -0,0 +1,71 @@
+//! Instructions per second through the interpreter the pre-decoded core replaced, and through the core itself, one
+//! step per call and in batches.
+
+mod legacy;
+
+use std::{hint::black_box, rc::Rc};
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use robowar::{assembler, simulation::vm::VirtualMachine};
+
+/// A mix of integer, float, memory and branch instructions, looping forever. The counters reset periodically so the
+/// stores stay inside the heap.
+const PROGRAM: &str = r"
+    loop:
+        add r0, r0, 1
+        mul r1, r0, 3
+        div r2, r1, 2
+        add f0, f0, 0.5
+        mul f1, f0, f0
+        store r2, f1
+        load f2, r2
+        push r0
+        pop r3
+        jlt skip, f2, 1000.0
+        set f0, 0.0
+        set r0, 0
+    skip:
+        jmp loop
+";
+
+const INSTRUCTIONS: u64 = 10_000;
+
+fn nothing_in_sight() -> f64 {
+    f64::MAX
+}
+
+fn vm(c: &mut Criterion) {
+    let program = Rc::new(
+        assembler::parse(PROGRAM)
+            .expect("benchmark program should assemble")
+            .runnable_program,
+    );
+    let mut group = c.benchmark_group("vm");
+    group.throughput(Throughput::Elements(INSTRUCTIONS));
+
+    group.bench_function("legacy_step", |b| {
+        let mut vm = legacy::VirtualMachine::new(program.clone());
+        b.iter(|| {
+            for _ in 0..INSTRUCTIONS {
+                black_box(vm.step(&nothing_in_sight)).unwrap();
+            }
+        })
+    });
+    // one call per instruction, the way the simulation drove robots before batching
+    group.bench_function("step", |b| {
+        let mut vm = VirtualMachine::new(program.clone());
+        b.iter(|| {
+            for _ in 0..INSTRUCTIONS {
+                black_box(vm.run(1, &nothing_in_sight)).unwrap();
+            }
+        })
+    });
+    group.bench_function("run", |b| {
+        let mut vm = VirtualMachine::new(program.clone());
+        b.iter(|| black_box(vm.run(INSTRUCTIONS, &nothing_in_sight)).unwrap())
+    });
+    group.finish();
+}
+
+criterion_group!(benches, vm);
+criterion_main!(benches);
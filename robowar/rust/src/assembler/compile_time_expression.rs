@@ -166,7 +166,6 @@ pub fn compile_time_expression<'a>() -> impl Parser<'a, &'a str, Box<AST>, Err<R
             Add,
             Subtract,
         }
-        
 
         mulop.clone().foldl(
             choice((
@@ -0,0 +1,342 @@
+use std::collections::BTreeSet;
+
+use crate::simulation::language::{
+    DestinationF64, DestinationU64, Instruction, Program, SourceF64, SourceU64,
+};
+
+/// Turns a runnable program back into assembler text that parses to the same program.
+///
+/// Label names are lost when assembling, so every literal jump target and interrupt handler gets a generated label.
+pub fn disassemble(program: &Program) -> Result<String, String> {
+    let instructions = program.instructions();
+    let mut targets = BTreeSet::new();
+    for instruction in instructions {
+        if let Some(SourceU64::Literal(address)) = instruction.jump_address()
+            && *address <= instructions.len() as u64
+        {
+            targets.insert(*address);
+        }
+    }
+    let mut vectors = program.interrupt_vectors().iter().collect::<Vec<_>>();
+    vectors.sort_by_key(|(kind, _)| kind.to_string());
+    for (_, address) in vectors.iter() {
+        targets.insert(address.0 as u64);
+    }
+
+    let mut result = String::new();
+    if let Some(loadout) = program.loadout {
+        result.push_str(&format!(
+            ".chassis {}\n.armor {}\n.battery {}\n.scanner {}\n.weapon {}\n",
+            loadout.chassis, loadout.armor, loadout.battery, loadout.scanner, loadout.weapon
+        ));
+    }
+    for (kind, address) in vectors {
+        result.push_str(&format!(".on {kind} {}\n", label(address.0 as u64)));
+    }
+    for (address, instruction) in instructions.iter().enumerate() {
+        if targets.contains(&(address as u64)) {
+            result.push_str(&format!("{}:\n", label(address as u64)));
+        }
+        let (mnemonic, operands) = operands(instruction, &targets)?;
+        if operands.is_empty() {
+            result.push_str(&format!("    {mnemonic}\n"));
+        } else {
+            result.push_str(&format!("    {mnemonic} {}\n", operands.join(", ")));
+        }
+    }
+    if targets.contains(&(instructions.len() as u64)) {
+        result.push_str(&format!("{}:\n", label(instructions.len() as u64)));
+    }
+    Ok(result)
+}
+
+fn label(address: u64) -> String {
+    format!("l{address}")
+}
+
+fn source_u64(source: &SourceU64) -> String {
+    match source {
+        SourceU64::Register(register) => register.to_string(),
+        SourceU64::Literal(value) => value.to_string(),
+    }
+}
+
+fn source_f64(source: &SourceF64) -> Result<String, String> {
+    match source {
+        SourceF64::Register(register) => Ok(register.to_string()),
+        // debug formatting always includes a '.' or an exponent, so the literal isn't mistaken for an integer
+        SourceF64::Literal(value) if value.is_finite() => Ok(format!("{value:?}")),
+        SourceF64::Literal(value) => Err(format!("can't write non-finite literal: {value}")),
+    }
+}
+
+fn destination_u64(destination: &DestinationU64) -> String {
+    match destination {
+        DestinationU64::Register(register) => register.to_string(),
+    }
+}
+
+fn destination_f64(destination: &DestinationF64) -> String {
+    match destination {
+        DestinationF64::Register(register) => register.to_string(),
+    }
+}
+
+fn address(address: &SourceU64, targets: &BTreeSet<u64>) -> String {
+    match address {
+        SourceU64::Literal(value) if targets.contains(value) => label(*value),
+        _ => source_u64(address),
+    }
+}
+
+fn operands(
+    instruction: &Instruction,
+    targets: &BTreeSet<u64>,
+) -> Result<(&'static str, Vec<String>), String> {
+    Ok(match instruction {
+        Instruction::SetU64 {
+            destination,
+            source,
+        } => (
+            "set",
+            vec![destination_u64(destination), source_u64(source)],
+        ),
+        Instruction::SetF64 {
+            destination,
+            source,
+        } => (
+            "set",
+            vec![destination_f64(destination), source_f64(source)?],
+        ),
+        Instruction::AddU64 {
+            destination,
+            left,
+            right,
+        }
+        | Instruction::SubU64 {
+            destination,
+            left,
+            right,
+        }
+        | Instruction::MulU64 {
+            destination,
+            left,
+            right,
+        }
+        | Instruction::DivU64 {
+            destination,
+            left,
+            right,
+        } => (
+            arithmetic_mnemonic(instruction),
+            vec![
+                destination_u64(destination),
+                source_u64(left),
+                source_u64(right),
+            ],
+        ),
+        Instruction::AddF64 {
+            destination,
+            left,
+            right,
+        }
+        | Instruction::SubF64 {
+            destination,
+            left,
+            right,
+        }
+        | Instruction::MulF64 {
+            destination,
+            left,
+            right,
+        }
+        | Instruction::DivF64 {
+            destination,
+            left,
+            right,
+        } => (
+            arithmetic_mnemonic(instruction),
+            vec![
+                destination_f64(destination),
+                source_f64(left)?,
+                source_f64(right)?,
+            ],
+        ),
+        Instruction::Jump { address: target } => ("jmp", vec![address(target, targets)]),
+        Instruction::JumpEqualU64 {
+            address: target,
+            left,
+            right,
+        }
+        | Instruction::JumpNotEqualU64 {
+            address: target,
+            left,
+            right,
+        }
+        | Instruction::JumpLessThanU64 {
+            address: target,
+            left,
+            right,
+        }
+        | Instruction::JumpLessThanOrEqualToU64 {
+            address: target,
+            left,
+            right,
+        }
+        | Instruction::JumpGreaterThanU64 {
+            address: target,
+            left,
+            right,
+        }
+        | Instruction::JumpGreaterThanOrEqualToU64 {
+            address: target,
+            left,
+            right,
+        } => (
+            jump_mnemonic(instruction),
+            vec![
+                address(target, targets),
+                source_u64(left),
+                source_u64(right),
+            ],
+        ),
+        Instruction::JumpEqualF64 {
+            address: target,
+            left,
+            right,
+        }
+        | Instruction::JumpNotEqualF64 {
+            address: target,
+            left,
+            right,
+        }
+        | Instruction::JumpLessThanF64 {
+            address: target,
+            left,
+            right,
+        }
+        | Instruction::JumpLessThanOrEqualToF64 {
+            address: target,
+            left,
+            right,
+        }
+        | Instruction::JumpGreaterThanF64 {
+            address: target,
+            left,
+            right,
+        }
+        | Instruction::JumpGreaterThanOrEqualToF64 {
+            address: target,
+            left,
+            right,
+        } => (
+            jump_mnemonic(instruction),
+            vec![
+                address(target, targets),
+                source_f64(left)?,
+                source_f64(right)?,
+            ],
+        ),
+        Instruction::ShiftLeft {
+            destination,
+            source,
+        } => ("sl", vec![destination_u64(destination), source_u64(source)]),
+        Instruction::ShiftRight {
+            destination,
+            source,
+        } => ("sr", vec![destination_u64(destination), source_u64(source)]),
+        Instruction::PushU64 { source } => ("push", vec![source_u64(source)]),
+        Instruction::PushF64 { source } => ("push", vec![source_f64(source)?]),
+        Instruction::PopU64 { destination } => ("pop", vec![destination_u64(destination)]),
+        Instruction::PopF64 { destination } => ("pop", vec![destination_f64(destination)]),
+        Instruction::LoadU64 {
+            destination,
+            source_address,
+        } => (
+            "load",
+            vec![destination_u64(destination), source_u64(source_address)],
+        ),
+        Instruction::LoadF64 {
+            destination,
+            source_address,
+        } => (
+            "load",
+            vec![destination_f64(destination), source_u64(source_address)],
+        ),
+        Instruction::StoreU64 {
+            destination_address,
+            source,
+        } => (
+            "store",
+            vec![source_u64(destination_address), source_u64(source)],
+        ),
+        Instruction::StoreF64 {
+            destination_address,
+            source,
+        } => (
+            "store",
+            vec![source_u64(destination_address), source_f64(source)?],
+        ),
+        Instruction::InterruptReturn => ("iret", vec![]),
+    })
+}
+
+fn arithmetic_mnemonic(instruction: &Instruction) -> &'static str {
+    match instruction {
+        Instruction::AddU64 { .. } | Instruction::AddF64 { .. } => "add",
+        Instruction::SubU64 { .. } | Instruction::SubF64 { .. } => "sub",
+        Instruction::MulU64 { .. } | Instruction::MulF64 { .. } => "mul",
+        _ => "div",
+    }
+}
+
+fn jump_mnemonic(instruction: &Instruction) -> &'static str {
+    match instruction {
+        Instruction::JumpEqualU64 { .. } | Instruction::JumpEqualF64 { .. } => "jeq",
+        Instruction::JumpNotEqualU64 { .. } | Instruction::JumpNotEqualF64 { .. } => "jne",
+        Instruction::JumpLessThanU64 { .. } | Instruction::JumpLessThanF64 { .. } => "jlt",
+        Instruction::JumpLessThanOrEqualToU64 { .. }
+        | Instruction::JumpLessThanOrEqualToF64 { .. } => "jle",
+        Instruction::JumpGreaterThanU64 { .. } | Instruction::JumpGreaterThanF64 { .. } => "jgt",
+        _ => "jge",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::parse;
+
+    #[test]
+    fn round_trip() {
+        let input = r"
+        .chassis heavy
+        .weapon blaster
+        .on collision bump
+        .on scan_found found
+        start:
+            set velocity_x, 2.5
+            set r1, 7
+            add r0, r0, 1
+            mul f0, f0, -0.125
+            jlt start, r0, 10
+            jge start, f0, 1e20
+            push 3.0
+            pop f1
+            store r0, f1
+            load r2, 16
+            jmp start
+        bump:
+            sub f2, position_x, 0.0
+            iret
+        found:
+            iret
+        ";
+        let program = parse(input).unwrap().runnable_program;
+        let text = disassemble(&program).unwrap();
+        let reassembled = parse(&text).unwrap().runnable_program;
+        assert_eq!(program.instructions(), reassembled.instructions());
+        assert_eq!(program.interrupt_vectors(), reassembled.interrupt_vectors());
+        assert_eq!(program.loadout, reassembled.loadout);
+    }
+}
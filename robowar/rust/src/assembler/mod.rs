@@ -1,11 +1,20 @@
 mod basic_types;
 mod compile_time_expression;
+mod disassembler;
 
-use crate::{assembler::compile_time_expression::compile_time_expression, simulation::language};
+use crate::{
+    assembler::compile_time_expression::compile_time_expression,
+    simulation::{
+        language,
+        loadout::{Chassis, Loadout, WeaponKind},
+    },
+};
 use basic_types::*;
 use chumsky::prelude::*;
 use std::collections::HashMap;
 
+pub use disassembler::disassemble;
+
 // TODO custom error types for everything here, no Result<_, String>
 
 #[derive(Debug, Clone)]
@@ -112,6 +121,10 @@ impl TryInto<SourceU64> for Argument {
         match self {
             Argument::Identifier(s) => match s.as_str().try_into() {
                 Ok(register) => Ok(SourceU64::Register(register)),
+                // an f64 register isn't a label, so the instruction's f64 form gets a chance to match
+                Result::Err(_) if language::ReadableRegisterF64::try_from(s.as_str()).is_ok() => {
+                    Err(format!("expected a u64 source, found f64 register: {s}"))
+                }
                 Result::Err(_) => Ok(SourceU64::Label(s)),
             },
             Argument::Number(NumberLiteral::U64(result)) => Ok(SourceU64::Literal(result)),
@@ -153,6 +166,9 @@ impl TryInto<SourceF64> for Argument {
         match self {
             Argument::Identifier(s) => match s.as_str().try_into() {
                 Ok(register) => Ok(SourceF64::Register(register)),
+                Result::Err(_) if language::ReadableRegisterU64::try_from(s.as_str()).is_ok() => {
+                    Err(format!("expected an f64 source, found u64 register: {s}"))
+                }
                 Result::Err(_) => Ok(SourceF64::Label(s)),
             },
             Argument::Number(NumberLiteral::U64(result)) => Ok(SourceF64::Literal(result as f64)),
@@ -311,6 +327,7 @@ pub enum Instruction {
         destination_address: SourceU64,
         source: SourceF64,
     },
+    InterruptReturn,
 }
 
 impl Instruction {
@@ -892,6 +909,15 @@ impl Instruction {
                 )),
             },
 
+            "iret" => match arguments.as_slice() {
+                [] => Ok(Instruction::InterruptReturn),
+                _ => Err(format!(
+                    "expected 0 arguments for '{}', found {}",
+                    instruction.to_uppercase(),
+                    arguments.len()
+                )),
+            },
+
             _ => Err(format!("unrecognized instruction: {instruction}")),
         }
     }
@@ -1152,6 +1178,7 @@ impl Instruction {
                 destination_address: destination_address.into_runnable(values)?,
                 source: source.into_runnable(values)?,
             }),
+            Instruction::InterruptReturn => Ok(language::Instruction::InterruptReturn),
         }
     }
 }
@@ -1161,6 +1188,41 @@ enum Statement {
     Instruction(Instruction),
     Label(String),
     Definition(String, Box<compile_time_expression::AST>),
+    InterruptVector(language::InterruptKind, String),
+    /// A `.chassis`, `.armor`, `.battery`, `.scanner` or `.weapon` line.
+    Loadout(String, Argument),
+}
+
+/// Applies one loadout directive. Each can only appear once per program.
+fn apply_loadout_directive(
+    loadout: &mut Loadout,
+    seen: &mut Vec<String>,
+    name: String,
+    argument: Argument,
+) -> Result<(), String> {
+    if seen.contains(&name) {
+        return Err(format!("duplicate .{name} directive"));
+    }
+    let level = match &argument {
+        Argument::Number(NumberLiteral::U64(value)) => u32::try_from(*value).ok(),
+        Argument::Number(NumberLiteral::I64(value)) => u32::try_from(*value).ok(),
+        _ => None,
+    }
+    .ok_or(format!(".{name} needs a level, found: {argument:?}"));
+    let identifier = match &argument {
+        Argument::Identifier(s) => Ok(s.as_str()),
+        Argument::Number(number) => Err(format!(".{name} needs a name, found: {number:?}")),
+    };
+    match name.as_str() {
+        "chassis" => loadout.chassis = Chassis::try_from(identifier?)?,
+        "weapon" => loadout.weapon = WeaponKind::try_from(identifier?)?,
+        "armor" => loadout.armor = level?,
+        "battery" => loadout.battery = level?,
+        "scanner" => loadout.scanner = level?,
+        _ => return Err(format!("unknown directive: .{name}")),
+    }
+    seen.push(name);
+    Ok(())
 }
 
 #[derive(Debug, Clone)]
@@ -1174,6 +1236,9 @@ impl Program {
         let mut instructions = Vec::new();
         let mut next_address = language::ProgramPointer(0);
         let mut expressions = Vec::new();
+        let mut interrupt_handlers = Vec::new();
+        let mut loadout = None;
+        let mut loadout_directives = Vec::new();
 
         // collect all the input into different lists, turning labels into address values as we go
         for item in content {
@@ -1193,6 +1258,15 @@ impl Program {
                 Statement::Definition(name, expr) => {
                     expressions.push((name, expr));
                 }
+                Statement::InterruptVector(kind, handler) => {
+                    interrupt_handlers.push((kind, handler));
+                }
+                Statement::Loadout(name, argument) => apply_loadout_directive(
+                    loadout.get_or_insert_with(Loadout::default),
+                    &mut loadout_directives,
+                    name,
+                    argument,
+                )?,
             }
         }
 
@@ -1204,6 +1278,24 @@ impl Program {
             values.insert(name, value);
         }
 
+        // resolve interrupt handlers into addresses
+        let mut interrupt_vectors = HashMap::new();
+        for (kind, handler) in interrupt_handlers {
+            let address = match values.get(&handler) {
+                Some(NumberLiteral::U64(value)) => language::ProgramPointer::try_from(*value)
+                    .map_err(|e| format!("invalid address for handler '{handler}': {e:?}"))?,
+                Some(value) => {
+                    return Err(format!(
+                        "handler '{handler}' for {kind:?} must be a label, found: {value:?}"
+                    ));
+                }
+                None => return Err(format!("label '{handler}' not found in program")),
+            };
+            if interrupt_vectors.insert(kind, address).is_some() {
+                return Err(format!("duplicate handler for {kind:?}"));
+            }
+        }
+
         // turn instructions into runnable instructions by substituting label and expression values
         let runnable_instructions = instructions
             .iter()
@@ -1214,9 +1306,14 @@ impl Program {
         let stack_size = 1024;
         let heap_size = 65536;
 
-        Ok(Program {
-            runnable_program: language::Program::new(runnable_instructions, stack_size, heap_size),
-        })
+        let mut runnable_program = language::Program::new(
+            runnable_instructions,
+            interrupt_vectors,
+            stack_size,
+            heap_size,
+        );
+        runnable_program.loadout = loadout;
+        Ok(Program { runnable_program })
     }
 }
 
@@ -1224,9 +1321,15 @@ pub fn parse(input: &str) -> Result<Program, String> {
     let argument = choice((
         identifier().map(|s| Argument::Identifier(s.to_string())),
         number_literal().map(Argument::Number),
-    ));
+    ))
+    .boxed();
 
-    let argument_list = argument.padded().separated_by(just(',')).collect();
+    // arguments must be on the same line as their instruction, so instructions with no arguments don't consume the next line
+    let argument_list = argument
+        .clone()
+        .padded_by(text::inline_whitespace())
+        .separated_by(just(','))
+        .collect();
 
     /*
     the various kinds of statement will be parsed as options, because we want to do a validator to show errors and skip invalids but still
@@ -1235,7 +1338,7 @@ pub fn parse(input: &str) -> Result<Program, String> {
     that means even stuff that doesn't need that will return options
     */
 
-    let instruction = identifier().padded().then(argument_list).validate(
+    let instruction = identifier().then(argument_list).padded().validate(
         |(instruction, arguments), e, emitter| match Instruction::new(instruction, arguments) {
             Ok(instruction) => Some(Statement::Instruction(instruction)),
             Err(error) => {
@@ -1251,14 +1354,42 @@ pub fn parse(input: &str) -> Result<Program, String> {
         .map(|(name, _)| Some(Statement::Label(name)));
 
     let expression = identifier()
+        .padded()
         .then_ignore(just("=").padded())
         .then(compile_time_expression())
         .map(|(name, expr)| Some(Statement::Definition(name, expr)));
 
+    let interrupt_vector = just(".on")
+        .ignore_then(identifier().padded())
+        .then(identifier())
+        .padded()
+        .validate(|(kind, handler), e, emitter| {
+            match language::InterruptKind::try_from(kind.as_str()) {
+                Ok(kind) => Some(Statement::InterruptVector(kind, handler)),
+                Err(error) => {
+                    emitter.emit(Rich::custom(e.span(), error));
+                    None
+                }
+            }
+        });
+
+    // checked against the loadout once the whole program is collected, so unknown names get a proper error
+    let loadout_directive = just('.')
+        .ignore_then(identifier())
+        .then(argument.padded_by(text::inline_whitespace()))
+        .padded()
+        .map(|(name, argument)| Some(Statement::Loadout(name, argument)));
+
     // a list of either valid statements, or None that represents places where invalid instructions were validated and rejected
-    let program = choice((expression, label, instruction))
-        .repeated()
-        .collect::<Vec<_>>();
+    let program = choice((
+        interrupt_vector,
+        loadout_directive,
+        expression,
+        label,
+        instruction,
+    ))
+    .repeated()
+    .collect::<Vec<_>>();
 
     Program::new(
         program
@@ -1297,4 +1428,66 @@ mod tests {
         }
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn interrupt_vectors() {
+        let input = r"
+        .on collision on_collision
+        .on scan_found on_scan
+        loop:
+            jmp loop
+        on_collision:
+            add r0, r0, 1
+            iret
+        on_scan:
+            iret
+        ";
+        let program = parse(input).unwrap().runnable_program;
+        assert_eq!(
+            program
+                .interrupt_vector(language::InterruptKind::Collision)
+                .map(|p| p.0),
+            Some(1)
+        );
+        assert_eq!(
+            program
+                .interrupt_vector(language::InterruptKind::ScanFound)
+                .map(|p| p.0),
+            Some(3)
+        );
+        assert!(
+            program
+                .interrupt_vector(language::InterruptKind::Hit)
+                .is_none()
+        );
+
+        assert!(parse(".on explosion handler\nhandler: iret").is_err());
+        assert!(parse(".on hit missing\niret").is_err());
+        assert!(parse(".on hit a\n.on hit a\na: iret").is_err());
+    }
+
+    #[test]
+    fn loadout_directives() {
+        assert_eq!(parse("iret").unwrap().runnable_program.loadout, None);
+
+        let input = r"
+        .chassis light
+        .armor 2
+        .weapon cannon
+        .on hit a
+        a:
+            iret
+        ";
+        let loadout = parse(input).unwrap().runnable_program.loadout.unwrap();
+        assert_eq!(loadout.chassis, Chassis::Light);
+        assert_eq!(loadout.armor, 2);
+        assert_eq!(loadout.battery, 0);
+        assert_eq!(loadout.weapon, WeaponKind::Cannon);
+
+        assert!(parse(".chassis tank\niret").is_err());
+        assert!(parse(".armor heavy\niret").is_err());
+        assert!(parse(".armor -1\niret").is_err());
+        assert!(parse(".armor 1\n.armor 2\niret").is_err());
+        assert!(parse(".wings 2\niret").is_err());
+    }
 }
@@ -0,0 +1,54 @@
+use tiny_skia::Transform;
+
+use crate::math::{Rect, Vec2};
+
+/// Maps world coordinates onto a region of the screen, preserving aspect ratio and centering the world in that region.
+pub struct Camera {
+    source_bounds: Rect<f64>,
+    destination_bounds: Rect<f64>,
+    scale: f64,
+    offset: Vec2<f64>,
+}
+
+impl Camera {
+    pub fn new(source_bounds: Rect<f64>, destination_bounds: Rect<f64>) -> Self {
+        let scale = (destination_bounds.width() / source_bounds.width())
+            .min(destination_bounds.height() / source_bounds.height());
+        let offset_x = (destination_bounds.width() - source_bounds.width() * scale) * 0.5;
+        let offset_y = (destination_bounds.height() - source_bounds.height() * scale) * 0.5;
+        Self {
+            source_bounds,
+            destination_bounds,
+            scale,
+            offset: Vec2::new(offset_x, offset_y),
+        }
+    }
+
+    pub fn scale(&self) -> f64 {
+        self.scale
+    }
+
+    pub fn tinyskia_transform(&self) -> Transform {
+        Transform::from_scale(self.scale as f32, self.scale as f32)
+            .pre_translate(
+                -self.source_bounds.minimum().x as f32,
+                -self.source_bounds.minimum().y as f32,
+            )
+            .post_translate(
+                self.destination_bounds.minimum().x as f32,
+                self.destination_bounds.minimum().y as f32,
+            )
+            .post_translate(self.offset.x as f32, self.offset.y as f32)
+    }
+
+    pub fn world_to_screen(&self, p: Vec2<f64>) -> Vec2<f64> {
+        (p - self.source_bounds.minimum()) * self.scale
+            + self.destination_bounds.minimum()
+            + self.offset
+    }
+
+    pub fn screen_to_world(&self, p: Vec2<f64>) -> Vec2<f64> {
+        (p - self.destination_bounds.minimum() - self.offset) / self.scale
+            + self.source_bounds.minimum()
+    }
+}
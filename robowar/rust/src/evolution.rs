@@ -0,0 +1,1055 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use color_eyre::eyre::{Result, bail, eyre};
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::IndexedRandom};
+use tracing::*;
+
+use crate::{
+    assembler, headless,
+    simulation::{
+        language::{
+            DestinationF64, DestinationU64, Instruction, InterruptKind, Program, ProgramPointer,
+            ReadableRegisterF64, ReadableRegisterU64, SourceF64, SourceU64, WritableRegisterF64,
+            WritableRegisterU64,
+        },
+        simulation::{FaultPolicy, MatchConfig, RobotState},
+        telemetry::{MemoryCollector, TelemetryEvent},
+    },
+};
+
+pub const USAGE: &str = "usage: robowar evolve [--generations N] [--population N] [--ticks N] [--threads N] [--seed N] [--keep N] [--out DIRECTORY] [OPPONENT.s ...]";
+
+const MIN_RANDOM_INSTRUCTIONS: usize = 4;
+const MAX_RANDOM_INSTRUCTIONS: usize = 16;
+const MAX_MUTATIONS: usize = 3;
+const MAX_U64_LITERAL: u64 = 1000;
+const MAX_F64_LITERAL: f64 = 500.;
+
+pub struct EvolutionConfig {
+    pub population_size: usize,
+    pub generations: usize,
+    /// How many of the best candidates are copied unchanged into the next generation.
+    pub elite: usize,
+    pub tournament_size: usize,
+    /// Chance that a child is bred from two parents rather than copied from one before mutating.
+    pub crossover_rate: f64,
+    /// Programs are truncated to this many instructions so they don't grow without bound.
+    pub max_instructions: usize,
+    /// Every candidate plays this many matches against each opponent, since spawn positions are random.
+    pub matches_per_opponent: usize,
+    /// A match that hasn't finished after this many ticks is scored as it stands.
+    pub match_ticks: u64,
+    pub match_config: MatchConfig,
+    pub threads: usize,
+    /// Breeding starts from this, so the same seed breeds the same programs. `robowar evolve` picks a random one
+    /// unless it's given.
+    pub seed: u64,
+}
+
+impl Default for EvolutionConfig {
+    fn default() -> Self {
+        Self {
+            population_size: 32,
+            generations: 20,
+            elite: 2,
+            tournament_size: 3,
+            crossover_rate: 0.5,
+            max_instructions: 64,
+            matches_per_opponent: 2,
+            match_ticks: 600,
+            match_config: MatchConfig {
+                // a program that faults is out, otherwise random programs survive by doing nothing useful
+                fault_policy: FaultPolicy::Kill,
+                ..Default::default()
+            },
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            seed: 0,
+        }
+    }
+}
+
+/// How a candidate did across all of its matches.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Score {
+    /// Opponents the candidate destroyed. An opponent that dies of its own fault doesn't count.
+    pub kills: u32,
+    /// Matches the candidate didn't survive, for whatever reason.
+    pub deaths: u32,
+    pub damage_dealt: f64,
+    /// Health the candidate ended its matches down on, after any pickups.
+    pub health_lost: f64,
+    pub faults: u64,
+}
+
+impl Score {
+    pub fn fitness(&self) -> f64 {
+        100. * (self.kills as f64 - self.deaths as f64) + self.damage_dealt
+            - self.health_lost
+            - self.faults as f64
+    }
+
+    fn add(&mut self, other: &Score) {
+        self.kills += other.kills;
+        self.deaths += other.deaths;
+        self.damage_dealt += other.damage_dealt;
+        self.health_lost += other.health_lost;
+        self.faults += other.faults;
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub program: Program,
+    pub score: Score,
+}
+
+/// Breeds programs that do well against the opponents.
+///
+/// The initial population is mutated copies of `seeds` topped up with random programs. `on_generation` is called
+/// with every scored generation, best first. Returns the final generation, best first.
+pub fn evolve(
+    config: &EvolutionConfig,
+    opponents: &[Program],
+    seeds: &[Program],
+    mut on_generation: impl FnMut(usize, &[Candidate]),
+) -> Result<Vec<Candidate>> {
+    if opponents.is_empty() {
+        bail!("need at least one opponent");
+    }
+    if config.population_size == 0 || config.generations == 0 {
+        bail!("population size and generations must be at least 1");
+    }
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut population = Vec::with_capacity(config.population_size);
+    for seed in seeds.iter().take(config.population_size) {
+        let mut program = seed.clone();
+        mutate(&mut program, &mut rng, config.max_instructions);
+        population.push(program);
+    }
+    while population.len() < config.population_size {
+        population.push(random_program(&mut rng));
+    }
+
+    let mut generation = 0;
+    loop {
+        let mut ranked = evaluate(config, &population, opponents)?;
+        ranked.sort_by(|a, b| b.score.fitness().total_cmp(&a.score.fitness()));
+        on_generation(generation, &ranked);
+
+        generation += 1;
+        if generation == config.generations {
+            return Ok(ranked);
+        }
+
+        population = ranked
+            .iter()
+            .take(config.elite)
+            .map(|candidate| candidate.program.clone())
+            .collect();
+        while population.len() < config.population_size {
+            let parent = tournament(&ranked, config.tournament_size, &mut rng);
+            let mut child = if rng.random_bool(config.crossover_rate) {
+                let other = tournament(&ranked, config.tournament_size, &mut rng);
+                crossover(parent, other, &mut rng, config.max_instructions)
+            } else {
+                parent.clone()
+            };
+            for _ in 0..rng.random_range(1..=MAX_MUTATIONS) {
+                mutate(&mut child, &mut rng, config.max_instructions);
+            }
+            population.push(child);
+        }
+    }
+}
+
+/// Writes the given candidates to `best_00.s`, `best_01.s`, ... in the directory.
+pub fn write_programs(directory: &Path, candidates: &[Candidate]) -> Result<()> {
+    fs::create_dir_all(directory)?;
+    for (rank, candidate) in candidates.iter().enumerate() {
+        let text = assembler::disassemble(&candidate.program).map_err(|e| eyre!(e))?;
+        fs::write(directory.join(format!("best_{rank:02}.s")), text)?;
+    }
+    Ok(())
+}
+
+/// Scores every program, spreading the work over `config.threads` threads. Simulations aren't `Send`, so each thread
+/// builds its own.
+fn evaluate(
+    config: &EvolutionConfig,
+    population: &[Program],
+    opponents: &[Program],
+) -> Result<Vec<Candidate>> {
+    let chunk_size = population.len().div_ceil(config.threads.max(1));
+    thread::scope(|scope| {
+        let workers = population
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    chunk
+                        .iter()
+                        .map(|program| {
+                            let mut score = Score::default();
+                            for opponent in opponents {
+                                for _ in 0..config.matches_per_opponent {
+                                    score.add(&play_match(config, program, opponent)?);
+                                }
+                            }
+                            Ok(Candidate {
+                                program: program.clone(),
+                                score,
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut result = Vec::with_capacity(population.len());
+        for worker in workers {
+            result.extend(
+                worker
+                    .join()
+                    .map_err(|_| eyre!("evaluation thread panicked"))??,
+            );
+        }
+        Ok(result)
+    })
+}
+
+/// Plays one headless match and scores it from the point of view of `program`.
+fn play_match(config: &EvolutionConfig, program: &Program, opponent: &Program) -> Result<Score> {
+    let mut simulation = headless::new_match(&[program, opponent], config.match_config.clone())?;
+    let collector = MemoryCollector::new();
+    simulation.subscribe(collector.clone())?;
+    headless::run_match(&mut simulation, config.match_ticks)?;
+
+    // robots are spawned in the order their programs were given
+    let results = simulation.results();
+    let robots = simulation.robot_snapshots(0)?;
+    let ([candidate, opponent], [snapshot, _]) = (results.robots.as_slice(), robots.as_slice())
+    else {
+        bail!("expected 2 robots, found {}", results.robots.len());
+    };
+    let mut score = Score {
+        deaths: (candidate.state == RobotState::Dead) as u32,
+        health_lost: (snapshot.stats.max_health - snapshot.vm.health.max(0.)).max(0.),
+        faults: candidate.fault_count,
+        ..Default::default()
+    };
+    let events = collector.events();
+    for (i, event) in events.iter().enumerate() {
+        match event {
+            TelemetryEvent::DamageDealt {
+                source: Some(source),
+                amount,
+                ..
+            } if *source == candidate.id => score.damage_dealt += amount,
+            // a robot destroyed by damage dies straight after the damage is reported
+            TelemetryEvent::RobotDied { robot, .. } if *robot == opponent.id => {
+                if let Some(TelemetryEvent::DamageDealt {
+                    source: Some(source),
+                    ..
+                }) = i.checked_sub(1).map(|i| &events[i])
+                    && *source == candidate.id
+                {
+                    score.kills += 1;
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(score)
+}
+
+fn tournament<'a>(ranked: &'a [Candidate], size: usize, rng: &mut impl Rng) -> &'a Program {
+    (0..size.max(1))
+        .map(|_| &ranked[rng.random_range(0..ranked.len())])
+        .max_by(|a, b| a.score.fitness().total_cmp(&b.score.fitness()))
+        .map(|candidate| &candidate.program)
+        .expect("tournament has at least one entrant")
+}
+
+fn random_program(rng: &mut impl Rng) -> Program {
+    let length = rng.random_range(MIN_RANDOM_INSTRUCTIONS..=MAX_RANDOM_INSTRUCTIONS);
+    let instructions = (0..length)
+        .map(|_| random_instruction(rng, length))
+        .collect();
+    // same sizes the assembler uses
+    Program::new(instructions, HashMap::new(), 1024, 65536)
+}
+
+/// Applies one random mutation: inserting, deleting or replacing an instruction, tweaking an operand, or moving an
+/// interrupt handler.
+fn mutate(program: &mut Program, rng: &mut impl Rng, max_instructions: usize) {
+    let mut instructions = program.instructions().to_vec();
+    let mut vectors = program.interrupt_vectors().clone();
+    let length = instructions.len();
+
+    match rng.random_range(0..5) {
+        0 if length < max_instructions => {
+            let at = rng.random_range(0..=length);
+            shift_addresses(&mut instructions, &mut vectors, at, 1);
+            instructions.insert(at, random_instruction(rng, length + 1));
+        }
+        1 if length > 1 => {
+            let at = rng.random_range(0..length);
+            instructions.remove(at);
+            shift_addresses(&mut instructions, &mut vectors, at, -1);
+            // a handler that was the last instruction would now point past the end
+            vectors.retain(|_, address| address.0 < instructions.len());
+        }
+        2 if length > 0 => {
+            let at = rng.random_range(0..length);
+            instructions[at] = random_instruction(rng, length);
+        }
+        3 if length > 0 => {
+            let at = rng.random_range(0..length);
+            tweak_operand(&mut instructions[at], rng, length);
+        }
+        _ if length > 0 => {
+            let kind = *InterruptKind::ALL.choose(rng).expect("interrupt kinds");
+            if vectors.contains_key(&kind) && rng.random_bool(0.5) {
+                vectors.remove(&kind);
+            } else {
+                vectors.insert(kind, ProgramPointer(rng.random_range(0..length)));
+            }
+        }
+        _ => instructions.push(random_instruction(rng, 1)),
+    }
+
+    let loadout = program.loadout;
+    *program = Program::new(instructions, vectors, program.stack_size, program.heap_size);
+    program.loadout = loadout;
+}
+
+/// Splices the start of `a` onto the end of `b`, cutting each where a label could be: the start or end of the
+/// program, a jump target, or an interrupt handler.
+fn crossover(a: &Program, b: &Program, rng: &mut impl Rng, max_instructions: usize) -> Program {
+    let cut_a = random_boundary(a, rng);
+    let cut_b = random_boundary(b, rng);
+
+    let mut instructions = a.instructions()[..cut_a].to_vec();
+    let offset = cut_a as i64 - cut_b as i64;
+    for instruction in b.instructions()[cut_b..].iter() {
+        let mut instruction = instruction.clone();
+        if let Some(SourceU64::Literal(address)) = instruction.jump_address_mut() {
+            *address = (*address as i64 + offset).max(0) as u64;
+        }
+        instructions.push(instruction);
+    }
+    instructions.truncate(max_instructions);
+    if instructions.is_empty() {
+        instructions.push(random_instruction(rng, 1));
+    }
+
+    let mut vectors = HashMap::new();
+    for kind in InterruptKind::ALL {
+        if let Some(address) = a.interrupt_vector(kind)
+            && address.0 < cut_a
+        {
+            vectors.insert(kind, address);
+        } else if let Some(address) = b.interrupt_vector(kind)
+            && address.0 >= cut_b
+        {
+            vectors.insert(
+                kind,
+                ProgramPointer((address.0 as i64 + offset).max(0) as usize),
+            );
+        }
+    }
+
+    // anything pointing past the end after truncating now points at the end
+    let length = instructions.len();
+    for instruction in instructions.iter_mut() {
+        if let Some(SourceU64::Literal(address)) = instruction.jump_address_mut() {
+            *address = (*address).min(length as u64);
+        }
+    }
+    vectors.retain(|_, address| address.0 < length);
+
+    // the child is built on the start of `a`, so it keeps a's design too
+    let mut child = Program::new(instructions, vectors, a.stack_size, a.heap_size);
+    child.loadout = a.loadout;
+    child
+}
+
+fn random_boundary(program: &Program, rng: &mut impl Rng) -> usize {
+    let boundaries = boundaries(program).into_iter().collect::<Vec<_>>();
+    *boundaries
+        .choose(rng)
+        .expect("programs always have a start boundary")
+}
+
+fn boundaries(program: &Program) -> BTreeSet<usize> {
+    let length = program.instructions().len();
+    let mut result = BTreeSet::from([0, length]);
+    for instruction in program.instructions() {
+        if let Some(SourceU64::Literal(address)) = instruction.jump_address()
+            && *address <= length as u64
+        {
+            result.insert(*address as usize);
+        }
+    }
+    result.extend(
+        program
+            .interrupt_vectors()
+            .values()
+            .map(|address| address.0)
+            .filter(|address| *address <= length),
+    );
+    result
+}
+
+/// Keeps literal jump targets and interrupt handlers pointing at the same instructions after inserting (`delta` 1)
+/// or removing (`delta` -1) the instruction at `at`.
+fn shift_addresses(
+    instructions: &mut [Instruction],
+    vectors: &mut HashMap<InterruptKind, ProgramPointer>,
+    at: usize,
+    delta: i64,
+) {
+    let shift = |address: u64| {
+        if address > at as u64 {
+            (address as i64 + delta) as u64
+        } else {
+            address
+        }
+    };
+    for instruction in instructions.iter_mut() {
+        if let Some(SourceU64::Literal(address)) = instruction.jump_address_mut() {
+            *address = shift(*address);
+        }
+    }
+    for address in vectors.values_mut() {
+        address.0 = shift(address.0 as u64) as usize;
+    }
+}
+
+fn random_source_u64(rng: &mut impl Rng) -> SourceU64 {
+    if rng.random_bool(0.5) {
+        SourceU64::Register(
+            ReadableRegisterU64::ALL
+                .choose(rng)
+                .expect("registers")
+                .clone(),
+        )
+    } else {
+        SourceU64::Literal(rng.random_range(0..=MAX_U64_LITERAL))
+    }
+}
+
+fn random_source_f64(rng: &mut impl Rng) -> SourceF64 {
+    if rng.random_bool(0.5) {
+        SourceF64::Register(
+            ReadableRegisterF64::ALL
+                .choose(rng)
+                .expect("registers")
+                .clone(),
+        )
+    } else {
+        SourceF64::Literal(rng.random_range(-MAX_F64_LITERAL..=MAX_F64_LITERAL))
+    }
+}
+
+fn random_destination_u64(rng: &mut impl Rng) -> DestinationU64 {
+    DestinationU64::Register(
+        WritableRegisterU64::ALL
+            .choose(rng)
+            .expect("registers")
+            .clone(),
+    )
+}
+
+fn random_destination_f64(rng: &mut impl Rng) -> DestinationF64 {
+    DestinationF64::Register(
+        WritableRegisterF64::ALL
+            .choose(rng)
+            .expect("registers")
+            .clone(),
+    )
+}
+
+/// A literal address within a program of the given length. The end of the program is a valid target.
+fn random_address(rng: &mut impl Rng, program_length: usize) -> SourceU64 {
+    SourceU64::Literal(rng.random_range(0..=program_length as u64))
+}
+
+fn random_instruction(rng: &mut impl Rng, program_length: usize) -> Instruction {
+    let r = rng;
+    match r.random_range(0..37) {
+        0 => Instruction::SetU64 {
+            destination: random_destination_u64(r),
+            source: random_source_u64(r),
+        },
+        1 => Instruction::SetF64 {
+            destination: random_destination_f64(r),
+            source: random_source_f64(r),
+        },
+        2 => Instruction::AddU64 {
+            destination: random_destination_u64(r),
+            left: random_source_u64(r),
+            right: random_source_u64(r),
+        },
+        3 => Instruction::AddF64 {
+            destination: random_destination_f64(r),
+            left: random_source_f64(r),
+            right: random_source_f64(r),
+        },
+        4 => Instruction::SubU64 {
+            destination: random_destination_u64(r),
+            left: random_source_u64(r),
+            right: random_source_u64(r),
+        },
+        5 => Instruction::SubF64 {
+            destination: random_destination_f64(r),
+            left: random_source_f64(r),
+            right: random_source_f64(r),
+        },
+        6 => Instruction::MulU64 {
+            destination: random_destination_u64(r),
+            left: random_source_u64(r),
+            right: random_source_u64(r),
+        },
+        7 => Instruction::MulF64 {
+            destination: random_destination_f64(r),
+            left: random_source_f64(r),
+            right: random_source_f64(r),
+        },
+        8 => Instruction::DivU64 {
+            destination: random_destination_u64(r),
+            left: random_source_u64(r),
+            right: random_source_u64(r),
+        },
+        9 => Instruction::DivF64 {
+            destination: random_destination_f64(r),
+            left: random_source_f64(r),
+            right: random_source_f64(r),
+        },
+        10 => Instruction::Jump {
+            address: random_address(r, program_length),
+        },
+        11 => Instruction::JumpEqualU64 {
+            address: random_address(r, program_length),
+            left: random_source_u64(r),
+            right: random_source_u64(r),
+        },
+        12 => Instruction::JumpEqualF64 {
+            address: random_address(r, program_length),
+            left: random_source_f64(r),
+            right: random_source_f64(r),
+        },
+        13 => Instruction::JumpNotEqualU64 {
+            address: random_address(r, program_length),
+            left: random_source_u64(r),
+            right: random_source_u64(r),
+        },
+        14 => Instruction::JumpNotEqualF64 {
+            address: random_address(r, program_length),
+            left: random_source_f64(r),
+            right: random_source_f64(r),
+        },
+        15 => Instruction::JumpLessThanU64 {
+            address: random_address(r, program_length),
+            left: random_source_u64(r),
+            right: random_source_u64(r),
+        },
+        16 => Instruction::JumpLessThanF64 {
+            address: random_address(r, program_length),
+            left: random_source_f64(r),
+            right: random_source_f64(r),
+        },
+        17 => Instruction::JumpLessThanOrEqualToU64 {
+            address: random_address(r, program_length),
+            left: random_source_u64(r),
+            right: random_source_u64(r),
+        },
+        18 => Instruction::JumpLessThanOrEqualToF64 {
+            address: random_address(r, program_length),
+            left: random_source_f64(r),
+            right: random_source_f64(r),
+        },
+        19 => Instruction::JumpGreaterThanU64 {
+            address: random_address(r, program_length),
+            left: random_source_u64(r),
+            right: random_source_u64(r),
+        },
+        20 => Instruction::JumpGreaterThanF64 {
+            address: random_address(r, program_length),
+            left: random_source_f64(r),
+            right: random_source_f64(r),
+        },
+        21 => Instruction::JumpGreaterThanOrEqualToU64 {
+            address: random_address(r, program_length),
+            left: random_source_u64(r),
+            right: random_source_u64(r),
+        },
+        22 => Instruction::JumpGreaterThanOrEqualToF64 {
+            address: random_address(r, program_length),
+            left: random_source_f64(r),
+            right: random_source_f64(r),
+        },
+        23 => Instruction::ShiftLeft {
+            destination: random_destination_u64(r),
+            source: random_source_u64(r),
+        },
+        24 => Instruction::ShiftRight {
+            destination: random_destination_u64(r),
+            source: random_source_u64(r),
+        },
+        25 => Instruction::PushU64 {
+            source: random_source_u64(r),
+        },
+        26 => Instruction::PushF64 {
+            source: random_source_f64(r),
+        },
+        27 => Instruction::PopU64 {
+            destination: random_destination_u64(r),
+        },
+        28 => Instruction::PopF64 {
+            destination: random_destination_f64(r),
+        },
+        29 => Instruction::LoadU64 {
+            destination: random_destination_u64(r),
+            source_address: random_source_u64(r),
+        },
+        30 => Instruction::LoadF64 {
+            destination: random_destination_f64(r),
+            source_address: random_source_u64(r),
+        },
+        31 => Instruction::StoreU64 {
+            destination_address: random_source_u64(r),
+            source: random_source_u64(r),
+        },
+        32 => Instruction::StoreF64 {
+            destination_address: random_source_u64(r),
+            source: random_source_f64(r),
+        },
+        33 => Instruction::InterruptReturn,
+        // movement is what wins matches, so writes to the actuators are over-represented
+        34 => Instruction::SetF64 {
+            destination: DestinationF64::Register(WritableRegisterF64::VelocityX),
+            source: random_source_f64(r),
+        },
+        35 => Instruction::SetF64 {
+            destination: DestinationF64::Register(WritableRegisterF64::VelocityY),
+            source: random_source_f64(r),
+        },
+        _ => Instruction::SetF64 {
+            destination: DestinationF64::Register(WritableRegisterF64::TurretAngularVelocity),
+            source: random_source_f64(r),
+        },
+    }
+}
+
+enum Operand<'a> {
+    SourceU64(&'a mut SourceU64),
+    SourceF64(&'a mut SourceF64),
+    DestinationU64(&'a mut DestinationU64),
+    DestinationF64(&'a mut DestinationF64),
+    Address(&'a mut SourceU64),
+}
+
+fn operands_mut(instruction: &mut Instruction) -> Vec<Operand<'_>> {
+    match instruction {
+        Instruction::SetU64 {
+            destination,
+            source,
+        }
+        | Instruction::ShiftLeft {
+            destination,
+            source,
+        }
+        | Instruction::ShiftRight {
+            destination,
+            source,
+        } => vec![
+            Operand::DestinationU64(destination),
+            Operand::SourceU64(source),
+        ],
+        Instruction::SetF64 {
+            destination,
+            source,
+        } => vec![
+            Operand::DestinationF64(destination),
+            Operand::SourceF64(source),
+        ],
+        Instruction::AddU64 {
+            destination,
+            left,
+            right,
+        }
+        | Instruction::SubU64 {
+            destination,
+            left,
+            right,
+        }
+        | Instruction::MulU64 {
+            destination,
+            left,
+            right,
+        }
+        | Instruction::DivU64 {
+            destination,
+            left,
+            right,
+        } => vec![
+            Operand::DestinationU64(destination),
+            Operand::SourceU64(left),
+            Operand::SourceU64(right),
+        ],
+        Instruction::AddF64 {
+            destination,
+            left,
+            right,
+        }
+        | Instruction::SubF64 {
+            destination,
+            left,
+            right,
+        }
+        | Instruction::MulF64 {
+            destination,
+            left,
+            right,
+        }
+        | Instruction::DivF64 {
+            destination,
+            left,
+            right,
+        } => vec![
+            Operand::DestinationF64(destination),
+            Operand::SourceF64(left),
+            Operand::SourceF64(right),
+        ],
+        Instruction::Jump { address } => vec![Operand::Address(address)],
+        Instruction::JumpEqualU64 {
+            address,
+            left,
+            right,
+        }
+        | Instruction::JumpNotEqualU64 {
+            address,
+            left,
+            right,
+        }
+        | Instruction::JumpLessThanU64 {
+            address,
+            left,
+            right,
+        }
+        | Instruction::JumpLessThanOrEqualToU64 {
+            address,
+            left,
+            right,
+        }
+        | Instruction::JumpGreaterThanU64 {
+            address,
+            left,
+            right,
+        }
+        | Instruction::JumpGreaterThanOrEqualToU64 {
+            address,
+            left,
+            right,
+        } => vec![
+            Operand::Address(address),
+            Operand::SourceU64(left),
+            Operand::SourceU64(right),
+        ],
+        Instruction::JumpEqualF64 {
+            address,
+            left,
+            right,
+        }
+        | Instruction::JumpNotEqualF64 {
+            address,
+            left,
+            right,
+        }
+        | Instruction::JumpLessThanF64 {
+            address,
+            left,
+            right,
+        }
+        | Instruction::JumpLessThanOrEqualToF64 {
+            address,
+            left,
+            right,
+        }
+        | Instruction::JumpGreaterThanF64 {
+            address,
+            left,
+            right,
+        }
+        | Instruction::JumpGreaterThanOrEqualToF64 {
+            address,
+            left,
+            right,
+        } => vec![
+            Operand::Address(address),
+            Operand::SourceF64(left),
+            Operand::SourceF64(right),
+        ],
+        Instruction::PushU64 { source } => vec![Operand::SourceU64(source)],
+        Instruction::PushF64 { source } => vec![Operand::SourceF64(source)],
+        Instruction::PopU64 { destination } => vec![Operand::DestinationU64(destination)],
+        Instruction::PopF64 { destination } => vec![Operand::DestinationF64(destination)],
+        Instruction::LoadU64 {
+            destination,
+            source_address,
+        } => vec![
+            Operand::DestinationU64(destination),
+            Operand::SourceU64(source_address),
+        ],
+        Instruction::LoadF64 {
+            destination,
+            source_address,
+        } => vec![
+            Operand::DestinationF64(destination),
+            Operand::SourceU64(source_address),
+        ],
+        Instruction::StoreU64 {
+            destination_address,
+            source,
+        } => vec![
+            Operand::SourceU64(destination_address),
+            Operand::SourceU64(source),
+        ],
+        Instruction::StoreF64 {
+            destination_address,
+            source,
+        } => vec![
+            Operand::SourceU64(destination_address),
+            Operand::SourceF64(source),
+        ],
+        Instruction::InterruptReturn => vec![],
+    }
+}
+
+/// Nudges literals, swaps registers, or retargets jumps. Instructions without operands are replaced instead.
+fn tweak_operand(instruction: &mut Instruction, rng: &mut impl Rng, program_length: usize) {
+    let mut operands = operands_mut(instruction);
+    if operands.is_empty() {
+        drop(operands);
+        *instruction = random_instruction(rng, program_length);
+        return;
+    }
+    let index = rng.random_range(0..operands.len());
+    match &mut operands[index] {
+        Operand::SourceU64(SourceU64::Literal(value)) if rng.random_bool(0.8) => {
+            *value = value.saturating_add_signed(rng.random_range(-10..=10));
+        }
+        Operand::SourceU64(source) => **source = random_source_u64(rng),
+        Operand::SourceF64(SourceF64::Literal(value)) if rng.random_bool(0.8) => {
+            let nudged = *value + rng.random_range(-0.5..=0.5) * value.abs().max(1.);
+            *value = nudged.clamp(-MAX_F64_LITERAL, MAX_F64_LITERAL);
+        }
+        Operand::SourceF64(source) => **source = random_source_f64(rng),
+        Operand::DestinationU64(destination) => **destination = random_destination_u64(rng),
+        Operand::DestinationF64(destination) => **destination = random_destination_f64(rng),
+        Operand::Address(address) => **address = random_address(rng, program_length),
+    }
+}
+
+/// Command line options for `robowar evolve`.
+pub struct EvolveOptions {
+    pub config: EvolutionConfig,
+    pub opponents: Vec<PathBuf>,
+    pub output: PathBuf,
+    /// How many of the best programs to write out.
+    pub keep: usize,
+}
+
+impl EvolveOptions {
+    /// Parses the arguments following `evolve` on the command line.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut config = EvolutionConfig::default();
+        let mut opponents = Vec::new();
+        let mut output = PathBuf::from("evolved");
+        let mut keep = 5;
+        let mut seed = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(eyre!("missing value for {arg}\n{USAGE}"));
+            match arg.as_str() {
+                "--generations" => config.generations = value()?.parse()?,
+                "--population" => config.population_size = value()?.parse()?,
+                "--ticks" => config.match_ticks = value()?.parse()?,
+                "--threads" => config.threads = value()?.parse()?,
+                "--seed" => seed = Some(value()?.parse()?),
+                "--keep" => keep = value()?.parse()?,
+                "--out" => output = value()?.into(),
+                _ if arg.starts_with("--") => bail!("unknown argument {arg}\n{USAGE}"),
+                _ => opponents.push(arg.into()),
+            }
+        }
+        config.seed = seed.unwrap_or_else(|| rand::rng().random());
+
+        Ok(Self {
+            config,
+            opponents,
+            output,
+            keep,
+        })
+    }
+}
+
+/// Runs `robowar evolve`. The opponents are also used as seeds for the initial population.
+pub fn run(options: &EvolveOptions, default_opponent: &Program) -> Result<()> {
+    let opponents = if options.opponents.is_empty() {
+        vec![default_opponent.clone()]
+    } else {
+        options
+            .opponents
+            .iter()
+            .map(|path| {
+                let text = fs::read_to_string(path)?;
+                Ok(assembler::parse(&text)
+                    .map_err(|e| eyre!("{}: {e}", path.display()))?
+                    .runnable_program)
+            })
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    info!("evolving with seed {}", options.config.seed);
+    let best = evolve(
+        &options.config,
+        &opponents,
+        &opponents,
+        |generation, ranked| {
+            let best = &ranked[0];
+            info!(
+                "generation {generation}: best fitness {:.1} ({:?}), {} instructions",
+                best.score.fitness(),
+                best.score,
+                best.program.instructions().len()
+            );
+        },
+    )?;
+
+    let keep = options.keep.min(best.len());
+    write_programs(&options.output, &best[..keep])?;
+    info!(
+        "wrote the best {keep} programs to {}",
+        options.output.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn program(text: &str) -> Program {
+        assembler::parse(text).unwrap().runnable_program
+    }
+
+    fn jump_targets(program: &Program) -> Vec<u64> {
+        program
+            .instructions()
+            .iter()
+            .filter_map(|instruction| match instruction.jump_address() {
+                Some(SourceU64::Literal(address)) => Some(*address),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn shifting_keeps_targets() {
+        let program = program(
+            r"
+            .on hit handler
+            start:
+                add r0, r0, 1
+                jmp start
+            handler:
+                iret
+            ",
+        );
+        let mut instructions = program.instructions().to_vec();
+        let mut vectors = program.interrupt_vectors().clone();
+
+        shift_addresses(&mut instructions, &mut vectors, 0, 1);
+        instructions.insert(0, Instruction::InterruptReturn);
+        // inserting at a target puts the new instruction inside the labelled block
+        assert_eq!(
+            jump_targets(&Program::new(instructions.clone(), vectors.clone(), 0, 0)),
+            vec![0]
+        );
+        assert_eq!(vectors[&InterruptKind::Hit], ProgramPointer(3));
+
+        instructions.remove(1);
+        shift_addresses(&mut instructions, &mut vectors, 1, -1);
+        assert_eq!(vectors[&InterruptKind::Hit], ProgramPointer(2));
+    }
+
+    #[test]
+    fn mutations_stay_valid() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let seeds = [
+            program("loop:\nadd velocity_x, velocity_x, 1.5\njlt loop, r0, 3\niret"),
+            program(".chassis light\n.on collision bump\nset r1, 4\nbump:\niret"),
+        ];
+        let mut population = seeds.to_vec();
+        for _ in 0..200 {
+            let a = &population[rng.random_range(0..population.len())];
+            let b = &population[rng.random_range(0..population.len())];
+            let mut child = crossover(a, b, &mut rng, 16);
+            mutate(&mut child, &mut rng, 16);
+
+            let length = child.instructions().len();
+            assert!((1..=16).contains(&length));
+            assert!(
+                jump_targets(&child)
+                    .iter()
+                    .all(|address| *address <= length as u64)
+            );
+            assert!(
+                child
+                    .interrupt_vectors()
+                    .values()
+                    .all(|address| address.0 < length)
+            );
+
+            // whatever we breed can be written out and read back in
+            let text = assembler::disassemble(&child).unwrap();
+            let reassembled = program(&text);
+            assert_eq!(reassembled.instructions(), child.instructions());
+            assert_eq!(reassembled.loadout, child.loadout);
+            assert!(seeds.iter().any(|seed| seed.loadout == child.loadout));
+            population.push(child);
+        }
+    }
+
+    #[test]
+    fn opponent_faults_arent_kills() {
+        let config = EvolutionConfig {
+            match_ticks: 10,
+            ..EvolutionConfig::default()
+        };
+        let idle = program("loop:\njmp loop");
+        let faulting = program("div r0, r0, 0");
+
+        let score = play_match(&config, &idle, &faulting).unwrap();
+        assert_eq!(score, Score::default());
+        assert_eq!(score.fitness(), 0.);
+
+        let score = play_match(&config, &faulting, &idle).unwrap();
+        assert_eq!(
+            score,
+            Score {
+                deaths: 1,
+                faults: 1,
+                ..Score::default()
+            }
+        );
+        assert_eq!(score.fitness(), -101.);
+    }
+}
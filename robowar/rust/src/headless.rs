@@ -0,0 +1,431 @@
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
+
+use color_eyre::eyre::{Result, bail, eyre};
+use tiny_skia::Pixmap;
+use tracing::*;
+
+use crate::{
+    math::{Rect, Vec2},
+    render,
+    simulation::{
+        language::Program,
+        physics,
+        simulation::{MatchConfig, Simulation},
+    },
+};
+
+pub const USAGE: &str = "usage: robowar render [--ticks N] [--every N] [--size WIDTHxHEIGHT] (--frames DIRECTORY | --gif FILE | --apng FILE)";
+
+/// Width and height of the arena that evolution and tournaments play their matches in.
+const ARENA_SIZE: f64 = 500.;
+/// Simulated time per tick for matches that evolution and tournaments score.
+const TICK_DURATION: Duration = Duration::from_millis(16);
+
+/// Sets up a match between `programs` in the arena that evolution and tournaments share, so bots are scored under
+/// the same conditions wherever they play. Robots are spawned in the order their programs are given.
+pub fn new_match(programs: &[&Program], config: MatchConfig) -> Result<Simulation> {
+    Simulation::new(
+        physics::Environment::new_standard_rectangle(Rect::new_with_origin_size(
+            Vec2::new(0., 0.),
+            Vec2::new(ARENA_SIZE, ARENA_SIZE),
+        )),
+        programs
+            .iter()
+            .map(|program| Rc::new((*program).clone()))
+            .collect(),
+        config,
+    )
+}
+
+/// Runs a match set up by [`new_match`] until it's finished or has run for `max_ticks`.
+pub fn run_match(simulation: &mut Simulation, max_ticks: u64) -> Result<()> {
+    while simulation.tick() < max_ticks && !simulation.is_finished() {
+        simulation.update(TICK_DURATION)?;
+    }
+    Ok(())
+}
+
+pub enum Output {
+    /// One PNG per frame, named by tick, e.g. `tick_000120.png`.
+    Frames(PathBuf),
+    Gif(PathBuf),
+    Apng(PathBuf),
+}
+
+/// Options for running a match without a window and exporting what it looks like.
+pub struct HeadlessOptions {
+    /// Maximum number of ticks to run. The match stops early if it's finished.
+    pub ticks: u64,
+    /// Capture a frame every this many ticks.
+    pub every: u64,
+    pub width: u32,
+    pub height: u32,
+    /// Simulated time per tick.
+    pub tick_duration: Duration,
+    pub output: Output,
+}
+
+impl HeadlessOptions {
+    /// Parses the arguments following `render` on the command line.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut ticks = 600;
+        let mut every = 5;
+        let (mut width, mut height) = (512, 512);
+        let mut output = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(eyre!("missing value for {arg}\n{USAGE}"));
+            match arg.as_str() {
+                "--ticks" => ticks = value()?.parse()?,
+                "--every" => every = value()?.parse()?,
+                "--size" => {
+                    let size = value()?;
+                    let (w, h) = size
+                        .split_once('x')
+                        .ok_or(eyre!("expected size as WIDTHxHEIGHT, got {size}"))?;
+                    width = w.parse()?;
+                    height = h.parse()?;
+                }
+                "--frames" => output = Some(Output::Frames(value()?.into())),
+                "--gif" => output = Some(Output::Gif(value()?.into())),
+                "--apng" => output = Some(Output::Apng(value()?.into())),
+                _ => bail!("unknown argument {arg}\n{USAGE}"),
+            }
+        }
+
+        if every == 0 {
+            bail!("--every must be at least 1");
+        }
+        Ok(Self {
+            ticks,
+            every,
+            width,
+            height,
+            tick_duration: Duration::from_secs_f64(1. / 60.),
+            output: output.ok_or(eyre!("no output given\n{USAGE}"))?,
+        })
+    }
+
+    /// How long each captured frame should be shown for when played back in real time.
+    fn frame_delay(&self) -> Duration {
+        self.tick_duration * self.every as u32
+    }
+}
+
+/// Runs the match to completion, or until the tick limit, writing frames to the configured output.
+pub fn run(mut simulation: Simulation, options: &HeadlessOptions) -> Result<()> {
+    let mut sink: Box<dyn FrameSink> = match &options.output {
+        Output::Frames(directory) => Box::new(PngFrames::create(directory.clone())?),
+        Output::Gif(path) => Box::new(GifSink::create(path, options)?),
+        Output::Apng(path) => Box::new(ApngSink::create(path, options)?),
+    };
+
+    let mut last_captured = 0;
+    sink.write_frame(
+        0,
+        &render::render_frame(&simulation, options.width, options.height)?,
+    )?;
+    while simulation.tick() < options.ticks && !simulation.is_finished() {
+        simulation.update(options.tick_duration)?;
+        if simulation.tick().is_multiple_of(options.every) {
+            last_captured = simulation.tick();
+            sink.write_frame(
+                last_captured,
+                &render::render_frame(&simulation, options.width, options.height)?,
+            )?;
+        }
+    }
+    // always end on the final state
+    if last_captured != simulation.tick() {
+        sink.write_frame(
+            simulation.tick(),
+            &render::render_frame(&simulation, options.width, options.height)?,
+        )?;
+    }
+    sink.finish()?;
+
+    info!(
+        "match {} after {} ticks: {:?}",
+        if simulation.is_finished() {
+            "finished"
+        } else {
+            "stopped"
+        },
+        simulation.tick(),
+        simulation.results()
+    );
+    Ok(())
+}
+
+trait FrameSink {
+    fn write_frame(&mut self, tick: u64, pixmap: &Pixmap) -> Result<()>;
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// Pixmaps are premultiplied, image formats expect straight alpha.
+fn demultiplied_rgba(pixmap: &Pixmap) -> Vec<u8> {
+    pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect()
+}
+
+struct PngFrames {
+    directory: PathBuf,
+}
+
+impl PngFrames {
+    fn create(directory: PathBuf) -> Result<Self> {
+        fs::create_dir_all(&directory)?;
+        Ok(Self { directory })
+    }
+}
+
+impl FrameSink for PngFrames {
+    fn write_frame(&mut self, tick: u64, pixmap: &Pixmap) -> Result<()> {
+        pixmap.save_png(self.directory.join(format!("tick_{tick:06}.png")))?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+}
+
+struct GifSink {
+    encoder: gif::Encoder<BufWriter<File>>,
+    width: u16,
+    height: u16,
+    /// In hundredths of a second.
+    delay: u16,
+}
+
+impl GifSink {
+    fn create(path: &Path, options: &HeadlessOptions) -> Result<Self> {
+        let width = u16::try_from(options.width)?;
+        let height = u16::try_from(options.height)?;
+        let mut encoder =
+            gif::Encoder::new(BufWriter::new(File::create(path)?), width, height, &[])?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        Ok(Self {
+            encoder,
+            width,
+            height,
+            delay: ((options.frame_delay().as_secs_f64() * 100.).round() as u16).max(1),
+        })
+    }
+}
+
+impl FrameSink for GifSink {
+    fn write_frame(&mut self, _tick: u64, pixmap: &Pixmap) -> Result<()> {
+        let mut rgba = demultiplied_rgba(pixmap);
+        let mut frame = gif::Frame::from_rgba_speed(self.width, self.height, &mut rgba, 10);
+        frame.delay = self.delay;
+        self.encoder.write_frame(&frame)?;
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        self.encoder.into_inner()?.flush()?;
+        Ok(())
+    }
+}
+
+/// APNG needs the frame count up front, so frames are kept as PNGs until the match is over, then written out.
+struct ApngSink {
+    file: BufWriter<File>,
+    width: u32,
+    height: u32,
+    /// In milliseconds.
+    delay: u16,
+    frames: Vec<Vec<u8>>,
+}
+
+impl ApngSink {
+    fn create(path: &Path, options: &HeadlessOptions) -> Result<Self> {
+        Ok(Self {
+            file: BufWriter::new(File::create(path)?),
+            width: options.width,
+            height: options.height,
+            delay: u16::try_from(options.frame_delay().as_millis())?,
+            frames: Vec::new(),
+        })
+    }
+}
+
+impl FrameSink for ApngSink {
+    fn write_frame(&mut self, _tick: u64, pixmap: &Pixmap) -> Result<()> {
+        self.frames.push(pixmap.encode_png()?);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        let mut encoder = png::Encoder::new(self.file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_animated(u32::try_from(self.frames.len())?, 0)?;
+        encoder.set_frame_delay(self.delay, 1000)?;
+        let mut writer = encoder.write_header()?;
+        for frame in &self.frames {
+            writer.write_image_data(&demultiplied_rgba(&Pixmap::decode_png(frame)?))?;
+        }
+        writer.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{
+        assembler,
+        math::{Rect, Vec2},
+        simulation::{
+            physics,
+            simulation::{FaultPolicy, MatchConfig},
+        },
+    };
+
+    fn args(text: &str) -> Vec<String> {
+        text.split_whitespace().map(str::to_string).collect()
+    }
+
+    fn simulation(sources: &[&str]) -> Simulation {
+        let programs = sources
+            .iter()
+            .map(|source| Rc::new(assembler::parse(source).unwrap().runnable_program))
+            .collect();
+        let environment = physics::Environment::new_standard_rectangle(Rect::new_with_origin_size(
+            Vec2::new(0., 0.),
+            Vec2::new(200., 200.),
+        ));
+        let config = MatchConfig {
+            fault_policy: FaultPolicy::Kill,
+            ..MatchConfig::default()
+        };
+        Simulation::new(environment, programs, config).unwrap()
+    }
+
+    /// Frames written to each kind of output, for a match that runs to the tick limit and one that finishes on the
+    /// first tick.
+    fn frame_counts(name: &str, options: &str, sources: &[&str]) -> (usize, usize, u32) {
+        let directory =
+            std::env::temp_dir().join(format!("robowar_headless_{name}_{}", std::process::id()));
+        let gif = directory.with_extension("gif");
+        let apng = directory.with_extension("png");
+        for output in [
+            format!("--frames {}", directory.display()),
+            format!("--gif {}", gif.display()),
+            format!("--apng {}", apng.display()),
+        ] {
+            let options = HeadlessOptions::parse(args(&format!("{options} {output}"))).unwrap();
+            run(simulation(sources), &options).unwrap();
+        }
+
+        let pngs = fs::read_dir(&directory).unwrap().count();
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(File::open(&gif).unwrap())
+            .unwrap();
+        let mut gif_frames = 0;
+        while decoder.read_next_frame().unwrap().is_some() {
+            gif_frames += 1;
+        }
+        let mut reader = png::Decoder::new(File::open(&apng).unwrap())
+            .read_info()
+            .unwrap();
+        let apng_frames = reader.info().animation_control().unwrap().num_frames;
+        // every frame the header promises is there
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        for _ in 0..apng_frames {
+            reader.next_frame(&mut buffer).unwrap();
+        }
+
+        fs::remove_dir_all(&directory).unwrap();
+        fs::remove_file(&gif).unwrap();
+        fs::remove_file(&apng).unwrap();
+        (pngs, gif_frames, apng_frames)
+    }
+
+    #[test]
+    fn parse() {
+        let options =
+            HeadlessOptions::parse(args("--ticks 100 --every 10 --size 320x240 --gif out.gif"))
+                .unwrap();
+        assert_eq!(options.ticks, 100);
+        assert_eq!(options.every, 10);
+        assert_eq!((options.width, options.height), (320, 240));
+        assert!(matches!(options.output, Output::Gif(path) if path == Path::new("out.gif")));
+
+        let options = HeadlessOptions::parse(args("--frames frames")).unwrap();
+        assert_eq!((options.ticks, options.every), (600, 5));
+        assert_eq!((options.width, options.height), (512, 512));
+        assert!(matches!(options.output, Output::Frames(_)));
+        assert!(matches!(
+            HeadlessOptions::parse(args("--apng out.png"))
+                .unwrap()
+                .output,
+            Output::Apng(_)
+        ));
+
+        for bad in [
+            "",
+            "--ticks 10",
+            "--ticks --gif out.gif",
+            "--every 0 --gif out.gif",
+            "--size 320 --gif out.gif",
+            "--size 320xabc --gif out.gif",
+            "--loop --gif out.gif",
+            "--gif",
+        ] {
+            assert!(HeadlessOptions::parse(args(bad)).is_err(), "{bad:?}");
+        }
+    }
+
+    #[test]
+    fn frame_counts_at_the_tick_limit() {
+        // ticks 0, 5, 10 and the last one, 12
+        assert_eq!(
+            frame_counts(
+                "limit",
+                "--ticks 12 --every 5 --size 64x64",
+                &["loop:\njmp loop", "loop:\njmp loop"]
+            ),
+            (4, 4, 4)
+        );
+        // the last tick isn't captured twice
+        assert_eq!(
+            frame_counts(
+                "even",
+                "--ticks 10 --every 5 --size 64x64",
+                &["loop:\njmp loop", "loop:\njmp loop"]
+            ),
+            (3, 3, 3)
+        );
+    }
+
+    #[test]
+    fn frame_counts_when_the_match_finishes_early() {
+        // ticks 0 and 1
+        assert_eq!(
+            frame_counts(
+                "early",
+                "--ticks 12 --every 5 --size 64x64",
+                &["loop:\njmp loop", "div r0, r0, 0"]
+            ),
+            (2, 2, 2)
+        );
+    }
+}
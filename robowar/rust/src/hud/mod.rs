@@ -0,0 +1,406 @@
+mod font;
+
+use color_eyre::eyre::{Result, eyre};
+use tiny_skia::{Paint, PathBuilder, PixmapMut, Rect, Stroke, Transform};
+
+use crate::{
+    camera::Camera,
+    math::Vec2,
+    simulation::{
+        ecs,
+        simulation::{RobotSnapshot, RobotState},
+        vm::StackOrHeapValue,
+    },
+};
+
+use font::{GLYPH_ADVANCE, GLYPH_HEIGHT, draw_text, text_width};
+
+const TEXT_SCALE: f32 = 2.;
+const LINE_HEIGHT: f32 = (GLYPH_HEIGHT + 3.) * TEXT_SCALE;
+const BAR_WIDTH: f32 = 40.;
+const BAR_HEIGHT: f32 = 4.;
+/// How far to draw the scanner ray when it doesn't hit anything, in world units.
+const SCANNER_MAX_DRAW_DISTANCE: f64 = 10000.;
+const MIN_SPEED: f64 = 1. / 16.;
+const MAX_SPEED: f64 = 16.;
+
+/// Playback state shown in the status line.
+pub struct Status {
+    pub tick: u64,
+    pub paused: bool,
+    pub speed: f64,
+}
+
+/// Pause, single tick and speed controls for a match being watched.
+pub struct Playback {
+    paused: bool,
+    single_tick_requested: bool,
+    /// Simulation ticks per frame.
+    speed: f64,
+    pending_ticks: f64,
+}
+
+impl Default for Playback {
+    fn default() -> Self {
+        Self {
+            paused: false,
+            single_tick_requested: false,
+            speed: 1.,
+            pending_ticks: 0.,
+        }
+    }
+}
+
+impl Playback {
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Pauses, and runs a single tick on the next frame.
+    pub fn single_tick(&mut self) {
+        self.paused = true;
+        self.single_tick_requested = true;
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed * 2.).min(MAX_SPEED);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = (self.speed / 2.).max(MIN_SPEED);
+    }
+
+    /// How many ticks to run this frame. Below 1x, the fractions add up over several frames.
+    pub fn ticks_for_frame(&mut self) -> u32 {
+        if self.paused {
+            return std::mem::take(&mut self.single_tick_requested) as u32;
+        }
+        self.pending_ticks += self.speed;
+        let ticks = self.pending_ticks.floor();
+        self.pending_ticks -= ticks;
+        ticks as u32
+    }
+
+    pub fn status(&self, tick: u64) -> Status {
+        Status {
+            tick,
+            paused: self.paused,
+            speed: self.speed,
+        }
+    }
+}
+
+/// Draws labels, bars and scanner rays for every robot, plus a highlight around the selected one.
+pub fn draw_robot_overlays(
+    pixmap: &mut PixmapMut,
+    camera: &Camera,
+    robots: &[RobotSnapshot],
+    selected: Option<ecs::Id>,
+) -> Result<()> {
+    let mut paint = Paint {
+        anti_alias: true,
+        ..Paint::default()
+    };
+
+    for robot in robots.iter() {
+        // scanner ray, in world space
+        let scanner_end = robot.position
+            + robot.turret_angle.cos_sin_vec2()
+                * robot.scanner_distance.min(SCANNER_MAX_DRAW_DISTANCE);
+        let mut scanner = PathBuilder::new();
+        scanner.move_to(robot.position.x as f32, robot.position.y as f32);
+        scanner.line_to(scanner_end.x as f32, scanner_end.y as f32);
+        paint.set_color_rgba8(255, 255, 0, 128);
+        pixmap.stroke_path(
+            &scanner
+                .finish()
+                .ok_or(eyre!("error finishing scanner path"))?,
+            &paint,
+            &Stroke {
+                width: (1. / camera.scale()) as f32,
+                ..Default::default()
+            },
+            camera.tinyskia_transform(),
+            None,
+        );
+
+        if selected == Some(robot.id) {
+            let highlight = PathBuilder::from_circle(
+                robot.position.x as f32,
+                robot.position.y as f32,
+                (robot.radius + 4. / camera.scale()) as f32,
+            )
+            .ok_or(eyre!("error creating highlight path"))?;
+            paint.set_color_rgba8(255, 255, 0, 255);
+            pixmap.stroke_path(
+                &highlight,
+                &paint,
+                &Stroke {
+                    width: (2. / camera.scale()) as f32,
+                    ..Default::default()
+                },
+                camera.tinyskia_transform(),
+                None,
+            );
+        }
+
+        // label and bars, in screen space
+        let top = camera.world_to_screen(robot.position - Vec2::new(0., robot.radius));
+        let bottom = camera.world_to_screen(robot.position + Vec2::new(0., robot.radius));
+
+        let label = format!("#{} PC {}", robot.id.index(), robot.vm.program_counter.0);
+        let label_x = top.x as f32 - text_width(&label, 1.) * 0.5;
+        let label_y = top.y as f32 - GLYPH_HEIGHT - 4.;
+        paint.set_color_rgba8(0, 0, 0, 255);
+        draw_text(pixmap, label_x, label_y, 1., &label, &paint);
+
+        let bar_x = bottom.x as f32 - BAR_WIDTH * 0.5;
+        let bar_y = bottom.y as f32 + 4.;
+        draw_bar(
+            pixmap,
+            bar_x,
+            bar_y,
+            robot.vm.health / robot.stats.max_health,
+            (0, 200, 0),
+        );
+        draw_bar(
+            pixmap,
+            bar_x,
+            bar_y + BAR_HEIGHT + 2.,
+            robot.vm.energy / robot.stats.energy_capacity,
+            (0, 64, 255),
+        );
+    }
+
+    Ok(())
+}
+
+/// Draws a panel in the top right corner showing the registers and top of stack of the given robot.
+pub fn draw_inspector(pixmap: &mut PixmapMut, robot: &RobotSnapshot) {
+    let mut lines = vec![
+        format!("ROBOT #{} {}", robot.id.index(), state_name(robot.state)),
+        format!(
+            "PC {} CLOCK {}",
+            robot.vm.program_counter.0, robot.vm.clock.0
+        ),
+        format!("INSTRUCTIONS {}", robot.vm.instruction_count),
+        format!(
+            "HEALTH {:.1} ENERGY {:.1}",
+            robot.vm.health, robot.vm.energy
+        ),
+        format!("SCAN {}", format_distance(robot.scanner_distance)),
+    ];
+    if let Some(loadout) = robot.loadout {
+        lines.push(
+            format!(
+                "{} ARMOR {} BATTERY {} SCANNER {} {}",
+                loadout.chassis, loadout.armor, loadout.battery, loadout.scanner, loadout.weapon
+            )
+            .to_uppercase(),
+        );
+    }
+    for (i, (u, f)) in robot
+        .vm
+        .general_purpose_u64
+        .iter()
+        .zip(robot.vm.general_purpose_f64.iter())
+        .enumerate()
+    {
+        lines.push(format!("R{i} {u:<12} F{i} {f:.3}"));
+    }
+    lines.push("STACK".to_string());
+    if robot.vm.stack_top.is_empty() {
+        lines.push("  (EMPTY)".to_string());
+    }
+    for value in robot.vm.stack_top.iter() {
+        lines.push(match value {
+            StackOrHeapValue::U64(value) => format!("  U64 {value}"),
+            StackOrHeapValue::F64(value) => format!("  F64 {value:.3}"),
+        });
+    }
+
+    let padding = 8.;
+    let width = lines
+        .iter()
+        .map(|line| text_width(line, TEXT_SCALE))
+        .fold(0., f32::max)
+        + padding * 2.;
+    let height = lines.len() as f32 * LINE_HEIGHT + padding * 2.;
+    let x = pixmap.width() as f32 - width - padding;
+    let y = padding;
+
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(0, 0, 0, 192);
+    if let Some(rect) = Rect::from_xywh(x, y, width, height) {
+        pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+    }
+    paint.set_color_rgba8(255, 255, 255, 255);
+    for (i, line) in lines.iter().enumerate() {
+        draw_text(
+            pixmap,
+            x + padding,
+            y + padding + i as f32 * LINE_HEIGHT,
+            TEXT_SCALE,
+            line,
+            &paint,
+        );
+    }
+}
+
+/// Draws the playback state and key bindings along the bottom of the screen.
+pub fn draw_status(pixmap: &mut PixmapMut, status: &Status) {
+    let line = format!(
+        "TICK {} {} SPEED {}X   SPACE PAUSE  . STEP  +/- SPEED  CLICK INSPECT",
+        status.tick,
+        if status.paused { "PAUSED" } else { "RUNNING" },
+        status.speed
+    );
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(0, 0, 0, 255);
+    draw_text(
+        pixmap,
+        GLYPH_ADVANCE,
+        pixmap.height() as f32 - LINE_HEIGHT,
+        TEXT_SCALE,
+        &line,
+        &paint,
+    );
+}
+
+/// Finds the robot under the given screen position, if any.
+pub fn robot_at(
+    camera: &Camera,
+    robots: &[RobotSnapshot],
+    screen_position: Vec2<f64>,
+) -> Option<ecs::Id> {
+    let world_position = camera.screen_to_world(screen_position);
+    robots
+        .iter()
+        .find(|robot| {
+            (robot.position - world_position).magnitude_squared() <= robot.radius * robot.radius
+        })
+        .map(|robot| robot.id)
+}
+
+fn draw_bar(pixmap: &mut PixmapMut, x: f32, y: f32, fraction: f64, color: (u8, u8, u8)) {
+    let mut paint = Paint::default();
+    paint.set_color_rgba8(0, 0, 0, 160);
+    if let Some(rect) = Rect::from_xywh(x, y, BAR_WIDTH, BAR_HEIGHT) {
+        pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+    }
+    paint.set_color_rgba8(color.0, color.1, color.2, 255);
+    let filled = BAR_WIDTH * fraction.clamp(0., 1.) as f32;
+    if let Some(rect) = Rect::from_xywh(x, y, filled, BAR_HEIGHT) {
+        pixmap.fill_rect(rect, &paint, Transform::identity(), None);
+    }
+}
+
+fn state_name(state: RobotState) -> String {
+    match state {
+        RobotState::Running => "RUNNING".to_string(),
+        RobotState::Frozen { remaining_ticks } => format!("FROZEN ({remaining_ticks})"),
+        RobotState::Dead => "DEAD".to_string(),
+    }
+}
+
+fn format_distance(distance: f64) -> String {
+    if distance == f64::MAX {
+        "NONE".to_string()
+    } else {
+        format!("{distance:.1}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{
+        assembler, math,
+        simulation::{
+            physics,
+            simulation::{MatchConfig, Simulation},
+        },
+    };
+
+    #[test]
+    fn click_to_select() {
+        let arena = math::Rect::new_with_origin_size(Vec2::new(0., 0.), Vec2::new(100., 100.));
+        let program = Rc::new(
+            assembler::parse("loop:\njmp loop")
+                .unwrap()
+                .runnable_program,
+        );
+        let simulation = Simulation::new(
+            physics::Environment::new_standard_rectangle(arena),
+            vec![program.clone(), program],
+            MatchConfig::default(),
+        )
+        .unwrap();
+        let mut robots = simulation.robot_snapshots(0).unwrap();
+        robots[0].position = Vec2::new(20., 20.);
+        robots[0].radius = 10.;
+        robots[1].position = Vec2::new(70., 50.);
+        robots[1].radius = 15.;
+        // the arena drawn at twice the size, inside a 50 pixel border
+        let camera = Camera::new(
+            arena,
+            math::Rect::new_with_origin_size(Vec2::new(50., 50.), Vec2::new(200., 200.)),
+        );
+        let click = |x, y| robot_at(&camera, &robots, camera.world_to_screen(Vec2::new(x, y)));
+
+        assert_eq!(
+            robot_at(&camera, &robots, Vec2::new(90., 90.)),
+            Some(robots[0].id)
+        );
+        assert_eq!(click(27., 27.), Some(robots[0].id));
+        assert_eq!(click(28., 28.), None);
+        assert_eq!(click(84., 50.), Some(robots[1].id));
+        assert_eq!(click(50., 90.), None);
+    }
+
+    #[test]
+    fn playback() {
+        let mut playback = Playback::default();
+        assert_eq!(playback.ticks_for_frame(), 1);
+        playback.faster();
+        assert_eq!(playback.ticks_for_frame(), 2);
+        for _ in 0..10 {
+            playback.faster();
+        }
+        assert_eq!(playback.status(0).speed, MAX_SPEED);
+        assert_eq!(playback.ticks_for_frame(), 16);
+
+        let mut playback = Playback::default();
+        playback.slower();
+        playback.slower();
+        assert_eq!(
+            (0..8)
+                .map(|_| playback.ticks_for_frame())
+                .collect::<Vec<_>>(),
+            [0, 0, 0, 1, 0, 0, 0, 1]
+        );
+        for _ in 0..10 {
+            playback.slower();
+        }
+        assert_eq!(playback.status(0).speed, MIN_SPEED);
+
+        let mut playback = Playback::default();
+        playback.toggle_pause();
+        assert!(playback.status(0).paused);
+        assert_eq!(playback.ticks_for_frame(), 0);
+        playback.single_tick();
+        assert_eq!(playback.ticks_for_frame(), 1);
+        assert_eq!(playback.ticks_for_frame(), 0);
+        assert!(playback.status(0).paused);
+        playback.toggle_pause();
+        assert_eq!(playback.ticks_for_frame(), 1);
+
+        // stepping while running pauses
+        playback.single_tick();
+        assert!(playback.status(7).paused);
+        assert_eq!(playback.status(7).tick, 7);
+        assert_eq!(playback.ticks_for_frame(), 1);
+    }
+}
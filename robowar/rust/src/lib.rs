@@ -0,0 +1,16 @@
+pub mod assembler;
+pub mod camera;
+#[cfg(feature = "native")]
+pub mod evolution;
+#[cfg(feature = "native")]
+pub mod headless;
+pub mod hud;
+pub mod math;
+pub mod render;
+pub mod simulation;
+#[cfg(feature = "native")]
+pub mod tournament;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+#[cfg(feature = "native")]
+pub mod window;
@@ -1,59 +1,35 @@
-mod assembler;
-mod math;
-mod simulation;
-mod window;
-
 use std::{rc::Rc, time::Duration};
 
 use color_eyre::eyre::{Result, eyre};
-use tiny_skia::{FillRule, Paint, PathBuilder, PixmapMut, Stroke, Transform};
+use tiny_skia::PixmapMut;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+use winit::keyboard::{Key, NamedKey};
 
-use crate::{
+use robowar::{
+    assembler,
+    camera::Camera,
+    evolution, headless, hud,
     math::{Rect, Vec2},
-    simulation::physics,
+    render,
+    simulation::{
+        self, ecs,
+        items::{ItemConfig, ItemKind},
+        physics,
+        telemetry::JsonLinesSink,
+    },
+    tournament,
     window::{EventHandler, run},
 };
 
-// TODO move me
-struct Camera {
-    source_bounds: Rect<f64>,
-    destination_bounds: Rect<f64>,
-    scale: f64,
-    offset: Vec2<f64>,
-}
-
-impl Camera {
-    pub fn new(source_bounds: Rect<f64>, destination_bounds: Rect<f64>) -> Self {
-        let scale = (destination_bounds.width() / source_bounds.width())
-            .min(destination_bounds.height() / source_bounds.height());
-        let offset_x = (destination_bounds.width() - source_bounds.width() * scale) * 0.5;
-        let offset_y = (destination_bounds.height() - source_bounds.height() * scale) * 0.5;
-        Self {
-            source_bounds,
-            destination_bounds,
-            scale,
-            offset: Vec2::new(offset_x, offset_y),
-        }
-    }
-
-    pub fn tinyskia_transform(&self) -> Transform {
-        Transform::from_scale(self.scale as f32, self.scale as f32)
-            .pre_translate(
-                -self.source_bounds.minimum().x as f32,
-                -self.source_bounds.minimum().y as f32,
-            )
-            .post_translate(
-                self.destination_bounds.minimum().x as f32,
-                self.destination_bounds.minimum().y as f32,
-            )
-            .post_translate(self.offset.x as f32, self.offset.y as f32)
-    }
-}
+const INSPECTOR_STACK_DEPTH: usize = 8;
 
 struct Demo {
     simulation: simulation::simulation::Simulation,
     pixels_rgba: Vec<u8>,
+    // the camera from the last render, used to map mouse clicks back into the world
+    camera: Option<Camera>,
+    selected: Option<ecs::Id>,
+    playback: hud::Playback,
 }
 
 impl Demo {
@@ -61,6 +37,9 @@ impl Demo {
         Self {
             simulation,
             pixels_rgba: Vec::new(),
+            camera: None,
+            selected: None,
+            playback: hud::Playback::default(),
         }
     }
 }
@@ -72,100 +51,22 @@ impl EventHandler for Demo {
     }
 
     fn render(&mut self, buffer: &mut [u32], width: u32, height: u32) -> Result<()> {
-        let border = 50.;
-        let camera = Camera::new(
-            self.simulation
-                .physics_environment()
-                .bounding_box()?,
-            // Rect::new_with_origin_size(Vec2::new(0.0, 0.0), Vec2::new(width as f64, height as f64)),
-            Rect::new_with_points(&[
-                Vec2::new(border, border),
-                Vec2::new(width as f64 - border, height as f64 - border),
-            ])
-            .unwrap(),
-        );
+        let camera = render::camera_for(&self.simulation, width, height)?;
 
         let pixels_abgr: &mut [u8] = bytemuck::try_cast_slice_mut(buffer)
             .map_err(|e| eyre!("failed to cast buffer to u8 slice: {e:?}"))?;
 
         let mut pixmap = PixmapMut::from_bytes(&mut self.pixels_rgba, width, height)
             .ok_or(eyre!("error creating skia pixmap"))?;
-        let mut paint = Paint::default();
-
-        paint.anti_alias = true;
-
-        paint.set_color_rgba8(64, 128, 255, 255);
-        pixmap.fill_rect(
-            tiny_skia::Rect::from_xywh(0., 0., width as f32, height as f32).unwrap(),
-            &paint,
-            Transform::identity(),
-            None,
-        );
-
-        paint.set_color_rgba8(0, 0, 0, 255);
-        let mut path = PathBuilder::new();
-        for line in self.simulation.physics_environment().get_line_segments()? {
-            let a = *line.origin();
-            let b = *line.origin() + *line.delta();
-            path.move_to(a.x as f32, a.y as f32);
-            path.line_to(b.x as f32, b.y as f32);
-        }
-        pixmap.stroke_path(
-            &path.finish().ok_or(eyre!("error finishing path"))?,
-            &paint,
-            &Stroke {
-                width: 1.0,
-                ..Default::default()
-            },
-            camera.tinyskia_transform(),
-            None,
-        );
-
-        for actor in self.simulation.physics_environment().actors_iter() {
-            let actor = actor.borrow();
-            let position = actor.position()?;
-            let radius = actor.radius();
-            let circle =
-                PathBuilder::from_circle(position.x as f32, position.y as f32, radius as f32)
-                    .ok_or(eyre!("error creating circle path"))?;
-            paint.set_color_rgba8(255, 255, 255, 255);
-            pixmap.fill_path(
-                &circle,
-                &paint,
-                FillRule::Winding,
-                camera.tinyskia_transform(),
-                None,
-            );
-            paint.set_color_rgba8(0, 0, 0, 255);
-            pixmap.stroke_path(
-                &circle,
-                &paint,
-                &Stroke {
-                    width: 2.0,
-                    ..Default::default()
-                },
-                camera.tinyskia_transform(),
-                None,
-            );
-
-            let turret_end = position + actor.turret_angle().cos_sin_vec2() * radius;
-            let mut turret = PathBuilder::new();
-            turret.move_to(position.x as f32, position.y as f32);
-            turret.line_to(turret_end.x as f32, turret_end.y as f32);
-            paint.set_color_rgba8(255, 0, 0, 255);
-            pixmap.stroke_path(
-                &turret
-                    .finish()
-                    .ok_or(eyre!("error finishing turret path"))?,
-                &paint,
-                &Stroke {
-                    width: 1.0,
-                    ..Default::default()
-                },
-                camera.tinyskia_transform(),
-                None,
-            );
+        render::draw_scene(&mut pixmap, &self.simulation, &camera)?;
+
+        let robots = self.simulation.robot_snapshots(INSPECTOR_STACK_DEPTH)?;
+        hud::draw_robot_overlays(&mut pixmap, &camera, &robots, self.selected)?;
+        if let Some(robot) = robots.iter().find(|robot| Some(robot.id) == self.selected) {
+            hud::draw_inspector(&mut pixmap, robot);
         }
+        hud::draw_status(&mut pixmap, &self.playback.status(self.simulation.tick()));
+        self.camera = Some(camera);
 
         for i in (0..self.pixels_rgba.len()).step_by(4) {
             let r = self.pixels_rgba[i];
@@ -182,7 +83,28 @@ impl EventHandler for Demo {
     }
 
     fn update(&mut self, elapsed_time: Duration) -> Result<()> {
-        self.simulation.update(elapsed_time)?;
+        for _ in 0..self.playback.ticks_for_frame() {
+            self.simulation.update(elapsed_time)?;
+        }
+        Ok(())
+    }
+
+    fn key_pressed(&mut self, key: &Key) -> Result<()> {
+        match key.as_ref() {
+            Key::Named(NamedKey::Space) => self.playback.toggle_pause(),
+            Key::Named(NamedKey::ArrowRight) | Key::Character(".") => self.playback.single_tick(),
+            Key::Character("+") | Key::Character("=") => self.playback.faster(),
+            Key::Character("-") => self.playback.slower(),
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn mouse_pressed(&mut self, x: f64, y: f64) -> Result<()> {
+        if let Some(camera) = &self.camera {
+            let robots = self.simulation.robot_snapshots(0)?;
+            self.selected = hud::robot_at(camera, &robots, Vec2::new(x, y));
+        }
         Ok(())
     }
 }
@@ -212,17 +134,68 @@ fn main() -> Result<()> {
         .map_err(|e| eyre!("{e:?}"))?
         .runnable_program,
     );
+    let mut args = std::env::args().skip(1);
+    let command = args.next();
+    if command.as_deref() == Some("evolve") {
+        return evolution::run(&evolution::EvolveOptions::parse(args)?, &program);
+    }
+    if command.as_deref() == Some("tournament") {
+        return tournament::run(&tournament::TournamentOptions::parse(args)?);
+    }
+
     let mut robots = Vec::new();
     for _ in 0..10 {
         robots.push(program.clone());
     }
 
-    run(Demo::new(simulation::simulation::Simulation::new(
+    let mut simulation = simulation::simulation::Simulation::new(
         physics::Environment::new_standard_rectangle(Rect::new_with_origin_size(
             Vec2::new(0.0, 0.0),
             Vec2::new(500.0, 500.0),
         )),
         robots,
-        (10.0)..=20.0,
-    )?))
+        simulation::simulation::MatchConfig {
+            items: vec![
+                ItemConfig {
+                    kind: ItemKind::EnergyPickup { amount: 25. },
+                    position: Vec2::new(125., 125.),
+                    radius: 8.,
+                    respawn_ticks: Some(300),
+                },
+                ItemConfig {
+                    kind: ItemKind::HealthPickup { amount: 25. },
+                    position: Vec2::new(375., 375.),
+                    radius: 8.,
+                    respawn_ticks: Some(300),
+                },
+                ItemConfig {
+                    kind: ItemKind::Mine { damage: 40. },
+                    position: Vec2::new(375., 125.),
+                    radius: 6.,
+                    respawn_ticks: None,
+                },
+                ItemConfig {
+                    kind: ItemKind::SlowingZone { max_speed: 50. },
+                    position: Vec2::new(250., 250.),
+                    radius: 60.,
+                    respawn_ticks: None,
+                },
+            ],
+            ..Default::default()
+        },
+    )?;
+    if let Ok(path) = std::env::var("ROBOWAR_TELEMETRY") {
+        simulation.subscribe(JsonLinesSink::create(path)?)?;
+    }
+
+    match command.as_deref() {
+        None => run(Demo::new(simulation)),
+        Some("render") => headless::run(simulation, &headless::HeadlessOptions::parse(args)?),
+        Some(other) => Err(eyre!(
+            "unknown command {other}\n{}\n{}\n{}",
+            headless::USAGE,
+            evolution::USAGE,
+            tournament::USAGE
+        )),
+    }
 }
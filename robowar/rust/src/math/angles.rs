@@ -1,8 +1,10 @@
 use std::{
     fmt::Debug,
-    ops::{Add, Mul},
+    ops::{Add, Mul, Sub},
 };
 
+use num::Float;
+
 use crate::math::Vec2;
 
 pub trait Angle {
@@ -88,6 +90,30 @@ where
     }
 }
 
+impl<T> Radians<T>
+where
+    T: Angle + Float,
+{
+    /// The same angle, wrapped into `(-pi, pi]`.
+    pub fn normalized(&self) -> Self {
+        let pi = T::pi();
+        let two_pi = pi + pi;
+        let mut radians = self.0 % two_pi;
+        if radians > pi {
+            radians = radians - two_pi;
+        } else if radians <= -pi {
+            radians = radians + two_pi;
+        }
+        Self(radians)
+    }
+
+    /// The signed angle to turn through to get from `self` to `target` the short way round. Positive is
+    /// counter-clockwise.
+    pub fn shortest_difference(&self, target: Self) -> Self {
+        (target - *self).normalized()
+    }
+}
+
 impl<T> Add<Radians<T>> for Radians<T>
 where
     T: Add<T, Output = T>,
@@ -99,6 +125,17 @@ where
     }
 }
 
+impl<T> Sub<Radians<T>> for Radians<T>
+where
+    T: Sub<T, Output = T>,
+{
+    type Output = Radians<T>;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self(self.0 - rhs.0)
+    }
+}
+
 impl<T> Mul<T> for Radians<T>
 where
     T: Mul<T, Output = T>,
@@ -156,3 +193,55 @@ where
         Self(self.0 * rhs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::PI;
+
+    fn assert_close(a: Radians<f64>, b: f64) {
+        assert!((a.0 - b).abs() < 1e-9, "expected {b}, found {}", a.0);
+    }
+
+    #[test]
+    fn normalized() {
+        assert_close(Radians(0.).normalized(), 0.);
+        assert_close(Radians(1.).normalized(), 1.);
+        assert_close(Radians(-1.).normalized(), -1.);
+        assert_close(Radians(PI).normalized(), PI);
+        assert_close(Radians(-PI).normalized(), PI);
+        assert_close(Radians(3. * PI).normalized(), PI);
+        assert_close(Radians(2. * PI).normalized(), 0.);
+        assert_close(Radians(1.5 * PI).normalized(), -0.5 * PI);
+        assert_close(Radians(-1.5 * PI).normalized(), 0.5 * PI);
+        assert_close(Radians(100. * PI + 0.25).normalized(), 0.25);
+        assert_close(Radians(-100. * PI - 0.25).normalized(), -0.25);
+    }
+
+    #[test]
+    fn shortest_difference() {
+        let degrees = |d: f64| Radians::from_degrees(d);
+        assert_close(
+            degrees(10.).shortest_difference(degrees(30.)),
+            20f64.to_radians(),
+        );
+        assert_close(
+            degrees(30.).shortest_difference(degrees(10.)),
+            -20f64.to_radians(),
+        );
+        assert_close(
+            degrees(350.).shortest_difference(degrees(10.)),
+            20f64.to_radians(),
+        );
+        assert_close(
+            degrees(10.).shortest_difference(degrees(350.)),
+            -20f64.to_radians(),
+        );
+        assert_close(
+            degrees(-170.).shortest_difference(degrees(170.)),
+            -20f64.to_radians(),
+        );
+        assert_close(degrees(0.).shortest_difference(degrees(720.)), 0.);
+        assert_close(degrees(0.).shortest_difference(degrees(180.)), PI);
+    }
+}
@@ -0,0 +1,393 @@
+use num::Float;
+
+use crate::math::{Circle, Ray2, Rect, Vec2};
+
+/// Where two shapes meet.
+#[derive(Debug, Clone, Copy)]
+pub struct Hit<T> {
+    /// How far along the ray or first segment the hit is, in world units. For overlap tests, how deep the shapes
+    /// overlap instead.
+    pub distance: T,
+    pub point: Vec2<T>,
+    /// Unit length, pointing out of the surface that was hit, back towards whatever hit it.
+    pub normal: Vec2<T>,
+}
+
+impl<T> Ray2<T>
+where
+    T: Float,
+{
+    /// Treats `self` as a ray starting at the origin and heading along `delta` forever, and `segment` as the line
+    /// from its origin to `origin + delta`. Returns the first point where they touch.
+    pub fn cast_segment(&self, segment: &Ray2<T>) -> Option<Hit<T>> {
+        self.hit_segment(segment, T::infinity())
+    }
+
+    /// Like [`Ray2::cast_segment`], but `self` is a segment too, and stops at `origin + delta`.
+    pub fn intersect_segment(&self, segment: &Ray2<T>) -> Option<Hit<T>> {
+        self.hit_segment(segment, T::one())
+    }
+
+    /// Treats `self` as a ray heading along `delta` forever. A ray starting inside the circle hits at its origin,
+    /// with a normal facing back along the ray.
+    pub fn cast_circle(&self, circle: &Circle<T>) -> Option<Hit<T>> {
+        let direction = self.delta().normalize()?;
+        let radius = *circle.radius();
+        let offset = *self.origin() - *circle.center();
+        let b = offset.dot(&direction);
+        let c = offset.magnitude_squared() - radius * radius;
+        if c > T::zero() && b > T::zero() {
+            // outside and heading away
+            return None;
+        }
+        let discriminant = b * b - c;
+        if discriminant < T::zero() {
+            return None;
+        }
+        let distance = -b - discriminant.sqrt();
+        if distance < T::zero() {
+            return Some(Hit {
+                distance: T::zero(),
+                point: *self.origin(),
+                normal: -direction,
+            });
+        }
+        let point = *self.origin() + direction * distance;
+        Some(Hit {
+            distance,
+            point,
+            normal: (point - *circle.center()).normalize().unwrap_or(-direction),
+        })
+    }
+
+    /// Shared by the ray and segment tests, `max_fraction` is how far along `delta` counts as a hit.
+    fn hit_segment(&self, segment: &Ray2<T>, max_fraction: T) -> Option<Hit<T>> {
+        let d = *self.delta();
+        let e = *segment.delta();
+        let direction = d.normalize()?;
+        let length = d.magnitude_squared().sqrt();
+        let w = *segment.origin() - *self.origin();
+        let denominator = d.cross(&e);
+        let tolerance = T::epsilon() * length * e.magnitude_squared().sqrt().max(T::one());
+
+        if denominator.abs() <= tolerance {
+            // parallel, so only a hit if they lie on the same line
+            if w.cross(&direction).abs() > T::epsilon() * w.magnitude_squared().sqrt().max(T::one())
+            {
+                return None;
+            }
+            let squared_length = d.magnitude_squared();
+            let start = w.dot(&d) / squared_length;
+            let end = (w + e).dot(&d) / squared_length;
+            let fraction = start.min(end).max(T::zero());
+            if fraction > start.max(end) || fraction > max_fraction {
+                return None;
+            }
+            return Some(Hit {
+                distance: fraction * length,
+                point: *self.origin() + d * fraction,
+                normal: -direction,
+            });
+        }
+
+        let fraction = w.cross(&e) / denominator;
+        let along_segment = w.cross(&d) / denominator;
+        if fraction < T::zero()
+            || fraction > max_fraction
+            || along_segment < T::zero()
+            || along_segment > T::one()
+        {
+            return None;
+        }
+        let normal = e.perpendicular().normalize()?;
+        Some(Hit {
+            distance: fraction * length,
+            point: *self.origin() + d * fraction,
+            normal: if normal.dot(&d) > T::zero() {
+                -normal
+            } else {
+                normal
+            },
+        })
+    }
+}
+
+impl<T> Circle<T>
+where
+    T: Float,
+{
+    /// Overlap between the circle and a solid rectangle. The hit distance is how far the circle has to move along
+    /// the normal to stop overlapping, and the point is the closest point on the rectangle to the circle's center.
+    /// Circles that just touch the rectangle count, with a distance of zero.
+    pub fn intersect_rect(&self, rect: &Rect<T>) -> Option<Hit<T>> {
+        let center = *self.center();
+        let radius = *self.radius();
+        let (min, max) = (rect.minimum(), rect.maximum());
+        let closest = Vec2::new(
+            center.x.max(min.x).min(max.x),
+            center.y.max(min.y).min(max.y),
+        );
+        let offset = center - closest;
+        let squared_distance = offset.magnitude_squared();
+        if squared_distance > radius * radius {
+            return None;
+        }
+        if let Some(normal) = offset.normalize() {
+            return Some(Hit {
+                distance: radius - squared_distance.sqrt(),
+                point: closest,
+                normal,
+            });
+        }
+
+        // the center is inside, so push out through the nearest edge
+        let edges = [
+            (
+                center.x - min.x,
+                Vec2::new(min.x, center.y),
+                Vec2::new(-T::one(), T::zero()),
+            ),
+            (
+                max.x - center.x,
+                Vec2::new(max.x, center.y),
+                Vec2::new(T::one(), T::zero()),
+            ),
+            (
+                center.y - min.y,
+                Vec2::new(center.x, min.y),
+                Vec2::new(T::zero(), -T::one()),
+            ),
+            (
+                max.y - center.y,
+                Vec2::new(center.x, max.y),
+                Vec2::new(T::zero(), T::one()),
+            ),
+        ];
+        let (depth, point, normal) = edges
+            .into_iter()
+            .reduce(|nearest, edge| if edge.0 < nearest.0 { edge } else { nearest })?;
+        Some(Hit {
+            distance: radius + depth,
+            point,
+            normal,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn v(x: f64, y: f64) -> Vec2<f64> {
+        Vec2::new(x, y)
+    }
+
+    fn assert_close(a: Vec2<f64>, b: Vec2<f64>) {
+        assert!((a - b).magnitude() < 1e-9, "expected {b:?}, found {a:?}");
+    }
+
+    fn assert_hit(hit: Option<Hit<f64>>, distance: f64, point: Vec2<f64>, normal: Vec2<f64>) {
+        let hit = hit.expect("expected a hit");
+        assert!(
+            (hit.distance - distance).abs() < 1e-9,
+            "expected distance {distance}, found {}",
+            hit.distance
+        );
+        assert_close(hit.point, point);
+        assert_close(hit.normal, normal);
+    }
+
+    #[test]
+    fn ray_segment() {
+        let wall = Ray2::new_between_points(v(10., -5.), v(10., 5.));
+        // the ray's length doesn't matter, only its direction
+        let ray = Ray2::new(v(0., 0.), v(0.5, 0.));
+        assert_hit(ray.cast_segment(&wall), 10., v(10., 0.), v(-1., 0.));
+        // the normal faces the ray whichever way round the segment is
+        let reversed = Ray2::new_between_points(v(10., 5.), v(10., -5.));
+        assert_hit(ray.cast_segment(&reversed), 10., v(10., 0.), v(-1., 0.));
+        let from_behind = Ray2::new(v(20., 0.), v(-1., 0.));
+        assert_hit(from_behind.cast_segment(&wall), 10., v(10., 0.), v(1., 0.));
+
+        let diagonal = Ray2::new(v(0., 0.), v(4., 1.));
+        assert_hit(
+            diagonal.cast_segment(&wall),
+            106.25f64.sqrt(),
+            v(10., 2.5),
+            v(-1., 0.),
+        );
+        // misses past the end of the segment and behind the ray
+        assert!(
+            Ray2::new(v(0., 0.), v(1., 0.6))
+                .cast_segment(&wall)
+                .is_none()
+        );
+        assert!(
+            Ray2::new(v(0., 0.), v(-1., 0.))
+                .cast_segment(&wall)
+                .is_none()
+        );
+        // endpoints count
+        assert_hit(
+            Ray2::new(v(0., 0.), v(2., 1.)).cast_segment(&wall),
+            125f64.sqrt(),
+            v(10., 5.),
+            v(-1., 0.),
+        );
+        // zero length rays have no direction
+        assert!(
+            Ray2::new(v(0., 0.), v(0., 0.))
+                .cast_segment(&wall)
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn parallel_segments() {
+        let floor = Ray2::new_between_points(v(5., 0.), v(15., 0.));
+        assert!(
+            Ray2::new(v(0., 1.), v(1., 0.))
+                .cast_segment(&floor)
+                .is_none()
+        );
+        // collinear hits where the overlap starts, or at the origin if it's already on the segment
+        assert_hit(
+            Ray2::new(v(0., 0.), v(1., 0.)).cast_segment(&floor),
+            5.,
+            v(5., 0.),
+            v(-1., 0.),
+        );
+        assert_hit(
+            Ray2::new(v(7., 0.), v(1., 0.)).cast_segment(&floor),
+            0.,
+            v(7., 0.),
+            v(-1., 0.),
+        );
+        assert!(
+            Ray2::new(v(20., 0.), v(1., 0.))
+                .cast_segment(&floor)
+                .is_none()
+        );
+        assert_hit(
+            Ray2::new(v(20., 0.), v(-1., 0.)).cast_segment(&floor),
+            5.,
+            v(15., 0.),
+            v(1., 0.),
+        );
+    }
+
+    #[test]
+    fn segment_segment() {
+        let a = Ray2::new_between_points(v(0., 0.), v(4., 4.));
+        let b = Ray2::new_between_points(v(0., 4.), v(4., 0.));
+        assert_hit(
+            a.intersect_segment(&b),
+            8f64.sqrt(),
+            v(2., 2.),
+            v(-1., -1.) / 2f64.sqrt(),
+        );
+        // too short to reach
+        let short = Ray2::new_between_points(v(0., 0.), v(1., 1.));
+        assert!(short.intersect_segment(&b).is_none());
+        // the ray version doesn't stop
+        assert_hit(
+            short.cast_segment(&b),
+            8f64.sqrt(),
+            v(2., 2.),
+            v(-1., -1.) / 2f64.sqrt(),
+        );
+        // collinear segments that don't overlap
+        let left = Ray2::new_between_points(v(0., 0.), v(1., 0.));
+        let right = Ray2::new_between_points(v(2., 0.), v(3., 0.));
+        assert!(left.intersect_segment(&right).is_none());
+        let overlapping = Ray2::new_between_points(v(0.5, 0.), v(3., 0.));
+        assert_hit(
+            left.intersect_segment(&overlapping),
+            0.5,
+            v(0.5, 0.),
+            v(-1., 0.),
+        );
+        // touching at the very end
+        let touching = Ray2::new_between_points(v(1., -1.), v(1., 1.));
+        assert_hit(left.intersect_segment(&touching), 1., v(1., 0.), v(-1., 0.));
+    }
+
+    #[test]
+    fn ray_circle() {
+        let circle = Circle::new(v(10., 0.), 2.);
+        let ray = Ray2::new(v(0., 0.), v(3., 0.));
+        assert_hit(ray.cast_circle(&circle), 8., v(8., 0.), v(-1., 0.));
+        // grazing
+        assert_hit(
+            Ray2::new(v(0., 2.), v(1., 0.)).cast_circle(&circle),
+            10.,
+            v(10., 2.),
+            v(0., 1.),
+        );
+        assert!(
+            Ray2::new(v(0., 2.1), v(1., 0.))
+                .cast_circle(&circle)
+                .is_none()
+        );
+        assert!(
+            Ray2::new(v(0., 0.), v(-1., 0.))
+                .cast_circle(&circle)
+                .is_none()
+        );
+        assert!(
+            Ray2::new(v(20., 0.), v(1., 0.))
+                .cast_circle(&circle)
+                .is_none()
+        );
+        // starting inside
+        assert_hit(
+            Ray2::new(v(11., 0.), v(0., 1.)).cast_circle(&circle),
+            0.,
+            v(11., 0.),
+            v(0., -1.),
+        );
+        let diagonal = Ray2::new(v(10. - 5., -5.), v(1., 1.));
+        let hit = diagonal.cast_circle(&circle).unwrap();
+        assert!((hit.distance - (50f64.sqrt() - 2.)).abs() < 1e-9);
+        assert_close(hit.normal, v(-1., -1.) / 2f64.sqrt());
+    }
+
+    #[test]
+    fn circle_rect() {
+        let rect = Rect::new_with_origin_size(v(0., 0.), v(10., 4.));
+        assert!(Circle::new(v(-3., 2.), 2.).intersect_rect(&rect).is_none());
+        assert_hit(
+            Circle::new(v(-1., 2.), 2.).intersect_rect(&rect),
+            1.,
+            v(0., 2.),
+            v(-1., 0.),
+        );
+        assert_hit(
+            Circle::new(v(5., 5.), 1.).intersect_rect(&rect),
+            0.,
+            v(5., 4.),
+            v(0., 1.),
+        );
+        // corners push out diagonally
+        assert_hit(
+            Circle::new(v(11., 5.), 2.).intersect_rect(&rect),
+            2. - 2f64.sqrt(),
+            v(10., 4.),
+            v(1., 1.) / 2f64.sqrt(),
+        );
+        assert!(Circle::new(v(12., 6.), 2.).intersect_rect(&rect).is_none());
+        // centers inside leave through the nearest edge
+        assert_hit(
+            Circle::new(v(9., 2.), 1.).intersect_rect(&rect),
+            2.,
+            v(10., 2.),
+            v(1., 0.),
+        );
+        assert_hit(
+            Circle::new(v(5., 0.5), 1.).intersect_rect(&rect),
+            1.5,
+            v(5., 0.),
+            v(0., -1.),
+        );
+    }
+}
@@ -1,11 +1,14 @@
 mod angles;
 mod circle;
+mod intersection;
 mod ray2;
 mod rect;
 mod sqrt;
 mod vec2;
 
 pub use angles::*;
+pub use circle::*;
+pub use intersection::*;
 pub use ray2::*;
 pub use rect::*;
 pub use sqrt::*;
@@ -2,11 +2,14 @@ use std::ops::Sub;
 
 use crate::math::Vec2;
 
+#[derive(Debug, Clone)]
 pub struct Ray2<T> {
     origin: Vec2<T>,
     delta: Vec2<T>,
 }
 
+impl<T> Copy for Ray2<T> where T: Copy {}
+
 impl<T> Ray2<T> {
     pub fn new(origin: Vec2<T>, delta: Vec2<T>) -> Self {
         Self { origin, delta }
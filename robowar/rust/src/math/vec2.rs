@@ -1,5 +1,6 @@
-use crate::math::sqrt::Sqrt;
-use std::ops::{Add, Div, Mul, Sub};
+use crate::math::{Angle, Radians, sqrt::Sqrt};
+use num::Float;
+use std::ops::{Add, Div, Mul, Neg, Sub};
 
 #[derive(Debug, Clone)]
 pub struct Vec2<T> {
@@ -33,6 +34,56 @@ where
     }
 }
 
+impl<T> Vec2<T>
+where
+    T: Add<T, Output = T> + Sub<T, Output = T> + Mul<T, Output = T> + Copy,
+{
+    pub fn dot(&self, other: &Self) -> T {
+        self.x * other.x + self.y * other.y
+    }
+
+    /// The z component of the 3D cross product. Positive if `other` is counter-clockwise from `self`.
+    pub fn cross(&self, other: &Self) -> T {
+        self.x * other.y - self.y * other.x
+    }
+}
+
+impl<T> Vec2<T>
+where
+    T: Neg<Output = T> + Copy,
+{
+    /// Rotated a quarter turn counter-clockwise.
+    pub fn perpendicular(&self) -> Self {
+        Self::new(-self.y, self.x)
+    }
+}
+
+impl<T> Vec2<T>
+where
+    T: Float,
+{
+    /// The unit vector in the same direction, or `None` for a zero-length vector.
+    pub fn normalize(&self) -> Option<Self> {
+        let magnitude = self.x.hypot(self.y);
+        if magnitude > T::zero() && magnitude.is_finite() {
+            Some(Self::new(self.x / magnitude, self.y / magnitude))
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Vec2<T>
+where
+    T: Angle + Add<T, Output = T> + Sub<T, Output = T> + Mul<T, Output = T> + Copy,
+{
+    /// Rotated counter-clockwise by the given angle.
+    pub fn rotate(&self, angle: Radians<T>) -> Self {
+        let (cos, sin) = (angle.cos(), angle.sin());
+        Self::new(self.x * cos - self.y * sin, self.x * sin + self.y * cos)
+    }
+}
+
 impl<T> Add for Vec2<T>
 where
     T: Add<Output = T>,
@@ -61,6 +112,20 @@ where
     }
 }
 
+impl<T> Neg for Vec2<T>
+where
+    T: Neg<Output = T>,
+{
+    type Output = Self;
+
+    fn neg(self) -> Self::Output {
+        Self::Output {
+            x: -self.x,
+            y: -self.y,
+        }
+    }
+}
+
 impl<T> Mul<T> for Vec2<T>
 where
     T: Mul<Output = T> + Copy,
@@ -88,3 +153,49 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: Vec2<f64>, b: Vec2<f64>) {
+        assert!((a - b).magnitude() < 1e-9, "expected {b:?}, found {a:?}");
+    }
+
+    #[test]
+    fn dot_and_cross() {
+        let a = Vec2::new(1., 2.);
+        let b = Vec2::new(3., -4.);
+        assert_eq!(a.dot(&b), -5.);
+        assert_eq!(a.cross(&b), -10.);
+        assert_eq!(b.cross(&a), 10.);
+        assert_eq!(Vec2::new(1, 0).cross(&Vec2::new(0, 1)), 1);
+        assert_eq!(a.dot(&a.perpendicular()), 0.);
+    }
+
+    #[test]
+    fn normalize() {
+        assert_close(Vec2::new(3., 4.).normalize().unwrap(), Vec2::new(0.6, 0.8));
+        assert_close(Vec2::new(0., -2.).normalize().unwrap(), Vec2::new(0., -1.));
+        assert!(Vec2::new(0., 0.).normalize().is_none());
+        assert!(Vec2::new(f64::NAN, 1.).normalize().is_none());
+        // tiny vectors don't underflow to zero length
+        assert_close(
+            Vec2::new(1e-200, 1e-200).normalize().unwrap(),
+            Vec2::new(1., 1.) / 2f64.sqrt(),
+        );
+    }
+
+    #[test]
+    fn rotate() {
+        let v = Vec2::new(1., 0.);
+        assert_close(v.rotate(Radians::from_degrees(90.)), Vec2::new(0., 1.));
+        assert_close(v.rotate(Radians::from_degrees(-90.)), Vec2::new(0., -1.));
+        assert_close(v.rotate(Radians::from_degrees(180.)), Vec2::new(-1., 0.));
+        assert_close(
+            Vec2::new(2., 3.).rotate(Radians::from_degrees(360.)),
+            Vec2::new(2., 3.),
+        );
+        assert!((Vec2::new(3., 4.).rotate(Radians(1.234)).magnitude() - 5.).abs() < 1e-9);
+    }
+}
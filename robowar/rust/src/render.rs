@@ -0,0 +1,148 @@
+use color_eyre::eyre::{Result, eyre};
+use tiny_skia::{FillRule, Paint, PathBuilder, Pixmap, PixmapMut, Stroke, Transform};
+
+use crate::{
+    camera::Camera,
+    hud,
+    math::{Rect, Vec2},
+    simulation::{
+        items::{ItemKind, ItemState},
+        simulation::Simulation,
+    },
+};
+
+/// Screen space margin around the arena, in pixels.
+const BORDER: f64 = 50.;
+
+/// A camera that fits the simulation's arena into a `width` by `height` image.
+pub fn camera_for(simulation: &Simulation, width: u32, height: u32) -> Result<Camera> {
+    Ok(Camera::new(
+        simulation.physics_environment().bounding_box()?,
+        Rect::new_with_points(&[
+            Vec2::new(BORDER, BORDER),
+            Vec2::new(width as f64 - BORDER, height as f64 - BORDER),
+        ])
+        .ok_or(eyre!("error creating screen rect"))?,
+    ))
+}
+
+/// Draws the background, arena walls, items and robots. Anything interactive, like the inspector, is left to the caller.
+pub fn draw_scene(pixmap: &mut PixmapMut, simulation: &Simulation, camera: &Camera) -> Result<()> {
+    let width = pixmap.width();
+    let height = pixmap.height();
+    let mut paint = Paint {
+        anti_alias: true,
+        ..Paint::default()
+    };
+
+    paint.set_color_rgba8(64, 128, 255, 255);
+    pixmap.fill_rect(
+        tiny_skia::Rect::from_xywh(0., 0., width as f32, height as f32)
+            .ok_or(eyre!("error creating background rect"))?,
+        &paint,
+        Transform::identity(),
+        None,
+    );
+
+    paint.set_color_rgba8(0, 0, 0, 255);
+    let mut path = PathBuilder::new();
+    for line in simulation.physics_environment().get_line_segments()? {
+        let a = *line.origin();
+        let b = *line.origin() + *line.delta();
+        path.move_to(a.x as f32, a.y as f32);
+        path.line_to(b.x as f32, b.y as f32);
+    }
+    pixmap.stroke_path(
+        &path.finish().ok_or(eyre!("error finishing path"))?,
+        &paint,
+        &Stroke {
+            width: 1.0,
+            ..Default::default()
+        },
+        camera.tinyskia_transform(),
+        None,
+    );
+
+    for item in simulation.item_snapshots() {
+        if item.state != ItemState::Available {
+            continue;
+        }
+        let circle = PathBuilder::from_circle(
+            item.position.x as f32,
+            item.position.y as f32,
+            item.radius as f32,
+        )
+        .ok_or(eyre!("error creating item path"))?;
+        match item.kind {
+            ItemKind::EnergyPickup { .. } => paint.set_color_rgba8(255, 220, 0, 255),
+            ItemKind::HealthPickup { .. } => paint.set_color_rgba8(0, 200, 0, 255),
+            ItemKind::Mine { .. } => paint.set_color_rgba8(96, 0, 0, 255),
+            ItemKind::SlowingZone { .. } => paint.set_color_rgba8(0, 0, 64, 64),
+        }
+        pixmap.fill_path(
+            &circle,
+            &paint,
+            FillRule::Winding,
+            camera.tinyskia_transform(),
+            None,
+        );
+    }
+
+    for robot in simulation.robot_snapshots(0)? {
+        let position = robot.position;
+        let radius = robot.radius;
+        let circle = PathBuilder::from_circle(position.x as f32, position.y as f32, radius as f32)
+            .ok_or(eyre!("error creating circle path"))?;
+        paint.set_color_rgba8(255, 255, 255, 255);
+        pixmap.fill_path(
+            &circle,
+            &paint,
+            FillRule::Winding,
+            camera.tinyskia_transform(),
+            None,
+        );
+        paint.set_color_rgba8(0, 0, 0, 255);
+        pixmap.stroke_path(
+            &circle,
+            &paint,
+            &Stroke {
+                width: 2.0,
+                ..Default::default()
+            },
+            camera.tinyskia_transform(),
+            None,
+        );
+
+        let turret_end = position + robot.turret_angle.cos_sin_vec2() * radius;
+        let mut turret = PathBuilder::new();
+        turret.move_to(position.x as f32, position.y as f32);
+        turret.line_to(turret_end.x as f32, turret_end.y as f32);
+        paint.set_color_rgba8(255, 0, 0, 255);
+        pixmap.stroke_path(
+            &turret
+                .finish()
+                .ok_or(eyre!("error finishing turret path"))?,
+            &paint,
+            &Stroke {
+                width: 1.0,
+                ..Default::default()
+            },
+            camera.tinyskia_transform(),
+            None,
+        );
+    }
+
+    Ok(())
+}
+
+/// Renders the current state of the match, with robot overlays, into a new offscreen pixmap.
+pub fn render_frame(simulation: &Simulation, width: u32, height: u32) -> Result<Pixmap> {
+    let mut pixmap =
+        Pixmap::new(width, height).ok_or(eyre!("invalid frame size: {width}x{height}"))?;
+    let camera = camera_for(simulation, width, height)?;
+    let mut pixmap_mut = pixmap.as_mut();
+    draw_scene(&mut pixmap_mut, simulation, &camera)?;
+    let robots = simulation.robot_snapshots(0)?;
+    hud::draw_robot_overlays(&mut pixmap_mut, &camera, &robots, None)?;
+    Ok(pixmap)
+}
@@ -0,0 +1,562 @@
+use crate::simulation::language::*;
+
+/// Number of u64 registers at the start of the u64 register file, in [`ReadableRegisterU64::ALL`] order.
+pub const U64_REGISTER_COUNT: usize = ReadableRegisterU64::ALL.len();
+/// Number of f64 registers at the start of the f64 register file, in [`ReadableRegisterF64::ALL`] order.
+pub const F64_REGISTER_COUNT: usize = ReadableRegisterF64::ALL.len();
+
+/// What a decoded instruction does. Operands live in the [`DecodedInstruction`] slots.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    SetU64,
+    SetF64,
+    AddU64,
+    AddF64,
+    SubU64,
+    SubF64,
+    MulU64,
+    MulF64,
+    DivU64,
+    DivF64,
+    Jump,
+    JumpEqualU64,
+    JumpEqualF64,
+    JumpNotEqualU64,
+    JumpNotEqualF64,
+    JumpLessThanU64,
+    JumpLessThanF64,
+    JumpLessThanOrEqualToU64,
+    JumpLessThanOrEqualToF64,
+    JumpGreaterThanU64,
+    JumpGreaterThanF64,
+    JumpGreaterThanOrEqualToU64,
+    JumpGreaterThanOrEqualToF64,
+    ShiftLeft,
+    ShiftRight,
+    PushU64,
+    PushF64,
+    PopU64,
+    PopF64,
+    LoadU64,
+    LoadF64,
+    StoreU64,
+    StoreF64,
+    InterruptReturn,
+}
+
+/// An instruction with every operand turned into an index into a flat register file.
+///
+/// Literals are stored in the register file too, after the registers, so reading an operand never has to check
+/// whether it's a register or a literal. Which file each slot indexes depends on the operation: jump and memory
+/// addresses are always in the u64 file.
+#[derive(Debug, Clone, Copy)]
+pub struct DecodedInstruction {
+    pub operation: Operation,
+    /// The destination, or the address for jumps and stores.
+    pub a: u32,
+    pub b: u32,
+    pub c: u32,
+    /// Clock cost of the operands that are always read.
+    pub cost: u32,
+    /// Extra clock cost when a conditional jump is taken, for reading the address.
+    pub jump_cost: u32,
+    /// Whether any operand is the scanner, which has to be refreshed before the instruction runs.
+    pub reads_scanner: bool,
+}
+
+/// A program laid out for fast execution.
+#[derive(Debug, Clone)]
+pub struct DecodedProgram {
+    pub instructions: Vec<DecodedInstruction>,
+    /// Initial contents of the u64 register file: zeroed registers followed by the program's literals.
+    pub u64_file: Vec<u64>,
+    /// Initial contents of the f64 register file: zeroed registers followed by the program's literals.
+    pub f64_file: Vec<f64>,
+    /// Handler addresses, in [`InterruptKind::ALL`] order.
+    pub interrupt_vectors: [Option<ProgramPointer>; InterruptKind::ALL.len()],
+}
+
+/// Index of a register in the f64 register file.
+pub fn f64_index(register: &ReadableRegisterF64) -> usize {
+    register.clone() as usize
+}
+
+/// Index of a register in the u64 register file.
+pub fn u64_index(register: &ReadableRegisterU64) -> usize {
+    register.clone() as usize
+}
+
+fn writable_f64_index(register: &WritableRegisterF64) -> usize {
+    f64_index(&match register {
+        WritableRegisterF64::VelocityX => ReadableRegisterF64::VelocityX,
+        WritableRegisterF64::VelocityY => ReadableRegisterF64::VelocityY,
+        WritableRegisterF64::TurretAngularVelocity => ReadableRegisterF64::TurretAngularVelocity,
+        WritableRegisterF64::Fire => ReadableRegisterF64::Fire,
+        WritableRegisterF64::GeneralPurpose0 => ReadableRegisterF64::GeneralPurpose0,
+        WritableRegisterF64::GeneralPurpose1 => ReadableRegisterF64::GeneralPurpose1,
+        WritableRegisterF64::GeneralPurpose2 => ReadableRegisterF64::GeneralPurpose2,
+        WritableRegisterF64::GeneralPurpose3 => ReadableRegisterF64::GeneralPurpose3,
+        WritableRegisterF64::GeneralPurpose4 => ReadableRegisterF64::GeneralPurpose4,
+        WritableRegisterF64::GeneralPurpose5 => ReadableRegisterF64::GeneralPurpose5,
+        WritableRegisterF64::GeneralPurpose6 => ReadableRegisterF64::GeneralPurpose6,
+        WritableRegisterF64::GeneralPurpose7 => ReadableRegisterF64::GeneralPurpose7,
+    })
+}
+
+fn writable_u64_index(register: &WritableRegisterU64) -> usize {
+    // the writable registers are the same general purpose registers, declared in the same order
+    register.clone() as usize
+}
+
+/// Where an operand is and how long it takes to read.
+struct Slot {
+    index: u32,
+    cost: u32,
+    is_scanner: bool,
+}
+
+struct Decoder {
+    u64_file: Vec<u64>,
+    f64_file: Vec<f64>,
+}
+
+impl Decoder {
+    fn source_u64(&mut self, source: &SourceU64) -> Slot {
+        match source {
+            SourceU64::Register(register) => Slot {
+                index: u64_index(register) as u32,
+                cost: 2,
+                is_scanner: false,
+            },
+            SourceU64::Literal(value) => {
+                self.u64_file.push(*value);
+                Slot {
+                    index: (self.u64_file.len() - 1) as u32,
+                    cost: 1,
+                    is_scanner: false,
+                }
+            }
+        }
+    }
+
+    fn source_f64(&mut self, source: &SourceF64) -> Slot {
+        match source {
+            SourceF64::Register(register) => Slot {
+                index: f64_index(register) as u32,
+                cost: 4,
+                is_scanner: *register == ReadableRegisterF64::ScannerDistance,
+            },
+            SourceF64::Literal(value) => {
+                self.f64_file.push(*value);
+                Slot {
+                    index: (self.f64_file.len() - 1) as u32,
+                    cost: 2,
+                    is_scanner: false,
+                }
+            }
+        }
+    }
+
+    fn destination_u64(&self, destination: &DestinationU64) -> Slot {
+        match destination {
+            DestinationU64::Register(register) => Slot {
+                index: writable_u64_index(register) as u32,
+                cost: 0,
+                is_scanner: false,
+            },
+        }
+    }
+
+    fn destination_f64(&self, destination: &DestinationF64) -> Slot {
+        match destination {
+            DestinationF64::Register(register) => Slot {
+                index: writable_f64_index(register) as u32,
+                cost: 0,
+                is_scanner: false,
+            },
+        }
+    }
+
+    fn decode(&mut self, instruction: &Instruction) -> DecodedInstruction {
+        use Operation as O;
+        let (operation, a, b, c) = match instruction {
+            Instruction::SetU64 {
+                destination,
+                source,
+            } => (
+                O::SetU64,
+                self.destination_u64(destination),
+                self.source_u64(source),
+                Slot::none(),
+            ),
+            Instruction::SetF64 {
+                destination,
+                source,
+            } => (
+                O::SetF64,
+                self.destination_f64(destination),
+                self.source_f64(source),
+                Slot::none(),
+            ),
+            Instruction::AddU64 {
+                destination,
+                left,
+                right,
+            } => self.binary_u64(O::AddU64, destination, left, right),
+            Instruction::SubU64 {
+                destination,
+                left,
+                right,
+            } => self.binary_u64(O::SubU64, destination, left, right),
+            Instruction::MulU64 {
+                destination,
+                left,
+                right,
+            } => self.binary_u64(O::MulU64, destination, left, right),
+            Instruction::DivU64 {
+                destination,
+                left,
+                right,
+            } => self.binary_u64(O::DivU64, destination, left, right),
+            Instruction::AddF64 {
+                destination,
+                left,
+                right,
+            } => self.binary_f64(O::AddF64, destination, left, right),
+            Instruction::SubF64 {
+                destination,
+                left,
+                right,
+            } => self.binary_f64(O::SubF64, destination, left, right),
+            Instruction::MulF64 {
+                destination,
+                left,
+                right,
+            } => self.binary_f64(O::MulF64, destination, left, right),
+            Instruction::DivF64 {
+                destination,
+                left,
+                right,
+            } => self.binary_f64(O::DivF64, destination, left, right),
+            Instruction::Jump { address } => {
+                // unconditional, so the address is always read
+                let address = self.source_u64(address);
+                return DecodedInstruction {
+                    operation: O::Jump,
+                    a: address.index,
+                    b: 0,
+                    c: 0,
+                    cost: address.cost,
+                    jump_cost: 0,
+                    reads_scanner: false,
+                };
+            }
+            Instruction::JumpEqualU64 {
+                address,
+                left,
+                right,
+            } => return self.jump_u64(O::JumpEqualU64, address, left, right),
+            Instruction::JumpNotEqualU64 {
+                address,
+                left,
+                right,
+            } => return self.jump_u64(O::JumpNotEqualU64, address, left, right),
+            Instruction::JumpLessThanU64 {
+                address,
+                left,
+                right,
+            } => return self.jump_u64(O::JumpLessThanU64, address, left, right),
+            Instruction::JumpLessThanOrEqualToU64 {
+                address,
+                left,
+                right,
+            } => return self.jump_u64(O::JumpLessThanOrEqualToU64, address, left, right),
+            Instruction::JumpGreaterThanU64 {
+                address,
+                left,
+                right,
+            } => return self.jump_u64(O::JumpGreaterThanU64, address, left, right),
+            Instruction::JumpGreaterThanOrEqualToU64 {
+                address,
+                left,
+                right,
+            } => return self.jump_u64(O::JumpGreaterThanOrEqualToU64, address, left, right),
+            Instruction::JumpEqualF64 {
+                address,
+                left,
+                right,
+            } => return self.jump_f64(O::JumpEqualF64, address, left, right),
+            Instruction::JumpNotEqualF64 {
+                address,
+                left,
+                right,
+            } => return self.jump_f64(O::JumpNotEqualF64, address, left, right),
+            Instruction::JumpLessThanF64 {
+                address,
+                left,
+                right,
+            } => return self.jump_f64(O::JumpLessThanF64, address, left, right),
+            Instruction::JumpLessThanOrEqualToF64 {
+                address,
+                left,
+                right,
+            } => return self.jump_f64(O::JumpLessThanOrEqualToF64, address, left, right),
+            Instruction::JumpGreaterThanF64 {
+                address,
+                left,
+                right,
+            } => return self.jump_f64(O::JumpGreaterThanF64, address, left, right),
+            Instruction::JumpGreaterThanOrEqualToF64 {
+                address,
+                left,
+                right,
+            } => return self.jump_f64(O::JumpGreaterThanOrEqualToF64, address, left, right),
+            Instruction::ShiftLeft {
+                destination,
+                source,
+            } => (
+                O::ShiftLeft,
+                self.destination_u64(destination),
+                self.source_u64(source),
+                Slot::none(),
+            ),
+            Instruction::ShiftRight {
+                destination,
+                source,
+            } => (
+                O::ShiftRight,
+                self.destination_u64(destination),
+                self.source_u64(source),
+                Slot::none(),
+            ),
+            Instruction::PushU64 { source } => {
+                let source = self.source_u64(source);
+                (O::PushU64, source, Slot::none(), Slot::none())
+            }
+            Instruction::PushF64 { source } => {
+                let source = self.source_f64(source);
+                (O::PushF64, source, Slot::none(), Slot::none())
+            }
+            Instruction::PopU64 { destination } => (
+                O::PopU64,
+                self.destination_u64(destination),
+                Slot::fixed_cost(1),
+                Slot::none(),
+            ),
+            Instruction::PopF64 { destination } => (
+                O::PopF64,
+                self.destination_f64(destination),
+                Slot::fixed_cost(1),
+                Slot::none(),
+            ),
+            Instruction::LoadU64 {
+                destination,
+                source_address,
+            } => (
+                O::LoadU64,
+                self.destination_u64(destination),
+                self.source_u64(source_address),
+                Slot::none(),
+            ),
+            Instruction::LoadF64 {
+                destination,
+                source_address,
+            } => (
+                O::LoadF64,
+                self.destination_f64(destination),
+                self.source_u64(source_address),
+                Slot::none(),
+            ),
+            Instruction::StoreU64 {
+                destination_address,
+                source,
+            } => (
+                O::StoreU64,
+                self.source_u64(destination_address),
+                self.source_u64(source),
+                Slot::none(),
+            ),
+            Instruction::StoreF64 {
+                destination_address,
+                source,
+            } => (
+                O::StoreF64,
+                self.source_u64(destination_address),
+                self.source_f64(source),
+                Slot::none(),
+            ),
+            Instruction::InterruptReturn => (
+                O::InterruptReturn,
+                Slot::none(),
+                Slot::fixed_cost(2),
+                Slot::none(),
+            ),
+        };
+        DecodedInstruction {
+            operation,
+            a: a.index,
+            b: b.index,
+            c: c.index,
+            cost: a.cost + b.cost + c.cost,
+            jump_cost: 0,
+            reads_scanner: a.is_scanner || b.is_scanner || c.is_scanner,
+        }
+    }
+
+    fn binary_u64(
+        &mut self,
+        operation: Operation,
+        destination: &DestinationU64,
+        left: &SourceU64,
+        right: &SourceU64,
+    ) -> (Operation, Slot, Slot, Slot) {
+        (
+            operation,
+            self.destination_u64(destination),
+            self.source_u64(left),
+            self.source_u64(right),
+        )
+    }
+
+    fn binary_f64(
+        &mut self,
+        operation: Operation,
+        destination: &DestinationF64,
+        left: &SourceF64,
+        right: &SourceF64,
+    ) -> (Operation, Slot, Slot, Slot) {
+        (
+            operation,
+            self.destination_f64(destination),
+            self.source_f64(left),
+            self.source_f64(right),
+        )
+    }
+
+    fn jump_u64(
+        &mut self,
+        operation: Operation,
+        address: &SourceU64,
+        left: &SourceU64,
+        right: &SourceU64,
+    ) -> DecodedInstruction {
+        let address = self.source_u64(address);
+        let left = self.source_u64(left);
+        let right = self.source_u64(right);
+        DecodedInstruction {
+            operation,
+            a: address.index,
+            b: left.index,
+            c: right.index,
+            cost: left.cost + right.cost,
+            jump_cost: address.cost,
+            reads_scanner: false,
+        }
+    }
+
+    fn jump_f64(
+        &mut self,
+        operation: Operation,
+        address: &SourceU64,
+        left: &SourceF64,
+        right: &SourceF64,
+    ) -> DecodedInstruction {
+        let address = self.source_u64(address);
+        let left = self.source_f64(left);
+        let right = self.source_f64(right);
+        DecodedInstruction {
+            operation,
+            a: address.index,
+            b: left.index,
+            c: right.index,
+            cost: left.cost + right.cost,
+            jump_cost: address.cost,
+            reads_scanner: left.is_scanner || right.is_scanner,
+        }
+    }
+}
+
+impl Slot {
+    fn none() -> Self {
+        Self::fixed_cost(0)
+    }
+
+    /// An unused slot that still charges for the instruction, e.g. pops, which have no source operand.
+    fn fixed_cost(cost: u32) -> Self {
+        Self {
+            index: 0,
+            cost,
+            is_scanner: false,
+        }
+    }
+}
+
+impl DecodedProgram {
+    pub fn new(program: &Program) -> Self {
+        let mut decoder = Decoder {
+            u64_file: vec![0; U64_REGISTER_COUNT],
+            f64_file: vec![0.; F64_REGISTER_COUNT],
+        };
+        let instructions = program
+            .instructions()
+            .iter()
+            .map(|instruction| decoder.decode(instruction))
+            .collect();
+        Self {
+            instructions,
+            u64_file: decoder.u64_file,
+            f64_file: decoder.f64_file,
+            interrupt_vectors: InterruptKind::ALL.map(|kind| program.interrupt_vector(kind)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::parse;
+
+    #[test]
+    fn layout() {
+        assert_eq!(u64_index(&ReadableRegisterU64::GeneralPurpose3), 3);
+        for (i, register) in ReadableRegisterF64::ALL.iter().enumerate() {
+            assert_eq!(f64_index(register), i);
+        }
+        for register in WritableRegisterF64::ALL.iter() {
+            let readable = ReadableRegisterF64::try_from(register.to_string().as_str()).unwrap();
+            assert_eq!(writable_f64_index(register), f64_index(&readable));
+        }
+        for register in WritableRegisterU64::ALL.iter() {
+            let readable = ReadableRegisterU64::try_from(register.to_string().as_str()).unwrap();
+            assert_eq!(writable_u64_index(register), u64_index(&readable));
+        }
+
+        let program = parse(
+            r"
+            start:
+                set r1, 7
+                add f2, scanner_distance, 0.5
+                jlt start, r1, r2
+            ",
+        )
+        .unwrap()
+        .runnable_program;
+        let decoded = DecodedProgram::new(&program);
+        assert_eq!(decoded.u64_file.len(), U64_REGISTER_COUNT + 2);
+        assert_eq!(decoded.u64_file[U64_REGISTER_COUNT..], [7, 0]);
+        assert_eq!(decoded.f64_file[F64_REGISTER_COUNT..], [0.5]);
+
+        let set = decoded.instructions[0];
+        assert_eq!(set.operation, Operation::SetU64);
+        assert_eq!((set.a, set.b), (1, U64_REGISTER_COUNT as u32));
+        assert_eq!(set.cost, 1);
+
+        let add = decoded.instructions[1];
+        assert!(add.reads_scanner);
+        assert_eq!(add.cost, 6);
+
+        let jump = decoded.instructions[2];
+        assert_eq!(jump.a, U64_REGISTER_COUNT as u32 + 1);
+        assert_eq!((jump.cost, jump.jump_cost), (4, 1));
+    }
+}
@@ -1,69 +1,317 @@
-use std::collections::HashMap;
+use serde::Serialize;
 
-use color_eyre::eyre::Result;
+/// An entity. The generation is bumped every time an index is reused, so ids held on to after their entity is
+/// despawned never match whatever gets spawned into the same slot later.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct Id {
+    index: u32,
+    generation: u32,
+}
+
+impl Id {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    /// Packs the id into a single integer, e.g. for storing in a physics collider's user data.
+    pub fn to_bits(&self) -> u64 {
+        ((self.generation as u64) << 32) | self.index as u64
+    }
+
+    pub fn from_bits(bits: u64) -> Self {
+        Self {
+            index: bits as u32,
+            generation: (bits >> 32) as u32,
+        }
+    }
+}
+
+/// Hands out entity ids, reusing the indices of despawned entities.
+#[derive(Default)]
+pub struct Entities {
+    generations: Vec<u32>,
+    alive: Vec<bool>,
+    free: Vec<u32>,
+}
+
+impl Entities {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn spawn(&mut self) -> Id {
+        if let Some(index) = self.free.pop() {
+            let slot = index as usize;
+            self.generations[slot] += 1;
+            self.alive[slot] = true;
+            Id {
+                index,
+                generation: self.generations[slot],
+            }
+        } else {
+            let index = self.generations.len() as u32;
+            self.generations.push(0);
+            self.alive.push(true);
+            Id {
+                index,
+                generation: 0,
+            }
+        }
+    }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-pub struct Id(pub usize);
+    /// Returns false if the entity was already despawned. Components are not removed, that's up to the owner of
+    /// each storage.
+    pub fn despawn(&mut self, id: Id) -> bool {
+        if !self.is_alive(id) {
+            return false;
+        }
+        self.alive[id.index as usize] = false;
+        self.free.push(id.index);
+        true
+    }
+
+    pub fn is_alive(&self, id: Id) -> bool {
+        let slot = id.index as usize;
+        slot < self.generations.len() && self.alive[slot] && self.generations[slot] == id.generation
+    }
+}
 
 struct Component<T> {
     id: Id,
     data: T,
 }
 
+/// Storage for one kind of component, indexed by entity.
 pub struct ComponentSystem<T> {
-    data: HashMap<Id, Component<T>>,
-    next_id: Id,
+    data: Vec<Option<Component<T>>>,
+    len: usize,
+}
+
+impl<T> Default for ComponentSystem<T> {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl<T> ComponentSystem<T> {
     pub fn new() -> Self {
         Self {
-            data: HashMap::new(),
-            next_id: Id(0),
+            data: Vec::new(),
+            len: 0,
         }
     }
 
-    pub fn insert(&mut self, value: T) -> Id {
-        let id = self.next_id;
-        self.data.insert(id, Component { id, data: value });
-        self.next_id = Id(self.next_id.0 + 1);
-        id
+    /// Returns the component previously attached to the entity, if any.
+    pub fn insert(&mut self, id: Id, value: T) -> Option<T> {
+        let slot = id.index as usize;
+        if slot >= self.data.len() {
+            self.data.resize_with(slot + 1, || None);
+        }
+        let previous = self.data[slot].replace(Component { id, data: value });
+        match previous {
+            // a component left behind by an older generation of this index doesn't count
+            Some(component) if component.id == id => Some(component.data),
+            Some(_) => None,
+            None => {
+                self.len += 1;
+                None
+            }
+        }
     }
 
-    pub fn insert_factory(&mut self, factory: impl FnOnce(Id) -> Result<T>) -> Result<Id> {
-        let id = self.next_id;
-        self.next_id = Id(self.next_id.0 + 1);
-        self.data.insert(
-            id,
-            Component {
-                id,
-                data: factory(id)?,
-            },
-        );
-        Ok(id)
+    pub fn get(&self, id: Id) -> Option<&T> {
+        match self.data.get(id.index as usize) {
+            Some(Some(component)) if component.id == id => Some(&component.data),
+            _ => None,
+        }
     }
 
-    pub fn get(&self, index: Id) -> Option<&T> {
-        self.data.get(&index).map(|comp| &comp.data)
+    pub fn get_mut(&mut self, id: Id) -> Option<&mut T> {
+        match self.data.get_mut(id.index as usize) {
+            Some(Some(component)) if component.id == id => Some(&mut component.data),
+            _ => None,
+        }
     }
 
-    pub fn get_mut(&mut self, index: Id) -> Option<&mut T> {
-        self.data.get_mut(&index).map(|comp| &mut comp.data)
+    pub fn contains(&self, id: Id) -> bool {
+        self.get(id).is_some()
     }
 
-    pub fn remove(&mut self, index: Id) {
-        self.data.remove(&index);
+    pub fn remove(&mut self, id: Id) -> Option<T> {
+        let slot = self.data.get_mut(id.index as usize)?;
+        if slot.as_ref().is_some_and(|component| component.id == id) {
+            self.len -= 1;
+            slot.take().map(|component| component.data)
+        } else {
+            None
+        }
     }
 
     pub fn clear(&mut self) {
         self.data.clear();
+        self.len = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 
+    /// Iterates in entity index order.
     pub fn iter(&self) -> impl Iterator<Item = (Id, &T)> {
-        self.data.iter().map(|(id, comp)| (*id, &comp.data))
+        self.data
+            .iter()
+            .flatten()
+            .map(|component| (component.id, &component.data))
     }
 
     pub fn iter_mut(&mut self) -> impl Iterator<Item = (Id, &mut T)> {
-        self.data.iter_mut().map(|(id, comp)| (*id, &mut comp.data))
+        self.data
+            .iter_mut()
+            .flatten()
+            .map(|component| (component.id, &mut component.data))
+    }
+
+    /// Iterates over the entities that have this component and everything in `query`, e.g.
+    /// `robots.join((&actors, &teams))`.
+    pub fn join<'a, Q: Query<'a> + 'a>(
+        &'a self,
+        query: Q,
+    ) -> impl Iterator<Item = (Id, &'a T, Q::Item)> + 'a {
+        self.iter()
+            .filter_map(move |(id, data)| query.fetch(id).map(|item| (id, data, item)))
+    }
+
+    /// Like [`ComponentSystem::join`], but this component is mutable.
+    pub fn join_mut<'a, Q: Query<'a> + 'a>(
+        &'a mut self,
+        query: Q,
+    ) -> impl Iterator<Item = (Id, &'a mut T, Q::Item)> + 'a {
+        self.iter_mut()
+            .filter_map(move |(id, data)| query.fetch(id).map(|item| (id, data, item)))
+    }
+}
+
+/// Something that can look up components by entity, for use with [`ComponentSystem::join`].
+pub trait Query<'a> {
+    type Item;
+
+    fn fetch(&self, id: Id) -> Option<Self::Item>;
+}
+
+impl<'a, T> Query<'a> for &'a ComponentSystem<T> {
+    type Item = &'a T;
+
+    fn fetch(&self, id: Id) -> Option<Self::Item> {
+        (*self).get(id)
+    }
+}
+
+/// Matches every entity, yielding the component if it has one.
+pub struct Maybe<Q>(pub Q);
+
+impl<'a, Q: Query<'a>> Query<'a> for Maybe<Q> {
+    type Item = Option<Q::Item>;
+
+    fn fetch(&self, id: Id) -> Option<Self::Item> {
+        Some(self.0.fetch(id))
+    }
+}
+
+impl<'a, A: Query<'a>, B: Query<'a>> Query<'a> for (A, B) {
+    type Item = (A::Item, B::Item);
+
+    fn fetch(&self, id: Id) -> Option<Self::Item> {
+        Some((self.0.fetch(id)?, self.1.fetch(id)?))
+    }
+}
+
+impl<'a, A: Query<'a>, B: Query<'a>, C: Query<'a>> Query<'a> for (A, B, C) {
+    type Item = (A::Item, B::Item, C::Item);
+
+    fn fetch(&self, id: Id) -> Option<Self::Item> {
+        Some((self.0.fetch(id)?, self.1.fetch(id)?, self.2.fetch(id)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generations() {
+        let mut entities = Entities::new();
+        let a = entities.spawn();
+        let b = entities.spawn();
+        assert!(entities.despawn(a));
+        assert!(!entities.despawn(a));
+        let c = entities.spawn();
+        assert_eq!(c.index(), a.index());
+        assert_ne!(c, a);
+        assert!(!entities.is_alive(a));
+        assert!(entities.is_alive(b));
+        assert!(entities.is_alive(c));
+        assert_eq!(Id::from_bits(c.to_bits()), c);
+
+        let mut names = ComponentSystem::new();
+        names.insert(a, "a");
+        names.insert(c, "c");
+        assert_eq!(names.get(a), None);
+        assert_eq!(names.get(c), Some(&"c"));
+        assert_eq!(names.len(), 1);
+        assert_eq!(names.remove(a), None);
+        assert_eq!(names.remove(c), Some("c"));
+        assert!(names.is_empty());
+    }
+
+    #[test]
+    fn join() {
+        let mut entities = Entities::new();
+        let ids = (0..4).map(|_| entities.spawn()).collect::<Vec<_>>();
+        let mut healths = ComponentSystem::new();
+        let mut positions = ComponentSystem::new();
+        let mut teams = ComponentSystem::new();
+        for (i, id) in ids.iter().enumerate() {
+            healths.insert(*id, 100 * i);
+        }
+        positions.insert(ids[1], (1., 1.));
+        positions.insert(ids[2], (2., 2.));
+        positions.insert(ids[3], (3., 3.));
+        teams.insert(ids[2], "red");
+
+        assert_eq!(
+            healths
+                .join(&positions)
+                .map(|(id, _, _)| id)
+                .collect::<Vec<_>>(),
+            vec![ids[1], ids[2], ids[3]]
+        );
+        assert_eq!(
+            healths
+                .join((&positions, &teams))
+                .map(|(_, health, (position, team))| (*health, *position, *team))
+                .collect::<Vec<_>>(),
+            vec![(200, (2., 2.), "red")]
+        );
+        assert_eq!(
+            healths
+                .join((&positions, Maybe(&teams)))
+                .map(|(_, _, (_, team))| team.copied())
+                .collect::<Vec<_>>(),
+            vec![None, Some("red"), None]
+        );
+
+        for (_, health, _) in healths.join_mut(&teams) {
+            *health += 1;
+        }
+        assert_eq!(healths.get(ids[2]), Some(&201));
+        assert_eq!(healths.get(ids[3]), Some(&300));
     }
 }
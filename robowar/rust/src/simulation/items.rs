@@ -0,0 +1,219 @@
+use crate::{
+    math::{Radians, Vec2},
+    simulation::{ecs, physics},
+};
+
+/// What an arena item does to a robot that touches it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ItemKind {
+    EnergyPickup {
+        amount: f64,
+    },
+    HealthPickup {
+        amount: f64,
+    },
+    Mine {
+        damage: f64,
+    },
+    /// Robots inside the zone can't go faster than `max_speed`. Never used up.
+    SlowingZone {
+        max_speed: f64,
+    },
+}
+
+impl ItemKind {
+    /// Pickups show up in the `pickup_distance` and `pickup_bearing` registers, hazards don't.
+    pub fn is_pickup(&self) -> bool {
+        matches!(
+            self,
+            ItemKind::EnergyPickup { .. } | ItemKind::HealthPickup { .. }
+        )
+    }
+
+    /// Whether the item disappears once a robot touches it.
+    pub fn is_consumed(&self) -> bool {
+        !matches!(self, ItemKind::SlowingZone { .. })
+    }
+}
+
+/// An item placed in the arena at the start of a match.
+#[derive(Debug, Clone)]
+pub struct ItemConfig {
+    pub kind: ItemKind,
+    pub position: Vec2<f64>,
+    pub radius: f64,
+    /// Ticks before a used up item comes back, at least 1, or `None` if it's gone for the rest of the match.
+    pub respawn_ticks: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ItemState {
+    Available,
+    Respawning { remaining_ticks: u32 },
+    Gone,
+}
+
+/// Everything needed to draw an item at a point in time.
+#[derive(Debug, Clone)]
+pub struct ItemSnapshot {
+    pub id: ecs::Id,
+    pub kind: ItemKind,
+    pub position: Vec2<f64>,
+    pub radius: f64,
+    pub state: ItemState,
+}
+
+pub(crate) struct Item {
+    pub config: ItemConfig,
+    pub sensor: physics::SensorHandle,
+    pub state: ItemState,
+}
+
+impl Item {
+    /// Marks the item as used up. Returns true if the sensor should be disabled until it comes back.
+    pub fn consume(&mut self) -> bool {
+        if !self.config.kind.is_consumed() {
+            return false;
+        }
+        self.state = match self.config.respawn_ticks {
+            Some(ticks) => ItemState::Respawning {
+                remaining_ticks: ticks,
+            },
+            None => ItemState::Gone,
+        };
+        true
+    }
+
+    /// Counts down a respawning item. Returns true on the tick it becomes available again.
+    pub fn tick(&mut self) -> bool {
+        match self.state {
+            ItemState::Respawning { remaining_ticks } if remaining_ticks > 1 => {
+                self.state = ItemState::Respawning {
+                    remaining_ticks: remaining_ticks - 1,
+                };
+                false
+            }
+            ItemState::Respawning { .. } => {
+                self.state = ItemState::Available;
+                true
+            }
+            ItemState::Available | ItemState::Gone => false,
+        }
+    }
+
+    pub fn snapshot(&self, id: ecs::Id) -> ItemSnapshot {
+        ItemSnapshot {
+            id,
+            kind: self.config.kind,
+            position: self.config.position,
+            radius: self.config.radius,
+            state: self.state,
+        }
+    }
+}
+
+/// Distance and bearing from `position` to the closest available pickup, if there is one.
+pub(crate) fn nearest_pickup(
+    items: &ecs::ComponentSystem<Item>,
+    position: Vec2<f64>,
+) -> Option<(f64, Radians<f64>)> {
+    items
+        .iter()
+        .filter(|(_, item)| item.state == ItemState::Available && item.config.kind.is_pickup())
+        .map(|(_, item)| {
+            let delta = item.config.position - position;
+            (delta.magnitude(), Radians(delta.y.atan2(delta.x)))
+        })
+        .min_by(|a, b| a.0.total_cmp(&b.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(kind: ItemKind, position: Vec2<f64>, respawn_ticks: Option<u32>) -> ItemConfig {
+        ItemConfig {
+            kind,
+            position,
+            radius: 5.,
+            respawn_ticks,
+        }
+    }
+
+    #[test]
+    fn respawn() {
+        let mut environment = physics::Environment::<ecs::Id>::new_standard_rectangle(
+            crate::math::Rect::new_with_origin_size(Vec2::new(0., 0.), Vec2::new(100., 100.)),
+        );
+        let mut entities = ecs::Entities::new();
+        let mut item = Item {
+            config: config(
+                ItemKind::HealthPickup { amount: 10. },
+                Vec2::new(50., 50.),
+                Some(2),
+            ),
+            sensor: environment.add_sensor(Vec2::new(50., 50.), 5., entities.spawn()),
+            state: ItemState::Available,
+        };
+        assert!(item.consume());
+        assert_eq!(item.state, ItemState::Respawning { remaining_ticks: 2 });
+        assert!(!item.tick());
+        assert!(item.tick());
+        assert_eq!(item.state, ItemState::Available);
+
+        item.config.respawn_ticks = None;
+        assert!(item.consume());
+        assert_eq!(item.state, ItemState::Gone);
+        assert!(!item.tick());
+
+        item.config.kind = ItemKind::SlowingZone { max_speed: 1. };
+        item.state = ItemState::Available;
+        assert!(!item.consume());
+        assert_eq!(item.state, ItemState::Available);
+    }
+
+    #[test]
+    fn nearest() {
+        let mut environment = physics::Environment::<ecs::Id>::new_standard_rectangle(
+            crate::math::Rect::new_with_origin_size(Vec2::new(0., 0.), Vec2::new(100., 100.)),
+        );
+        let mut entities = ecs::Entities::new();
+        let mut items = ecs::ComponentSystem::new();
+        let mut add = |kind, position, state| {
+            let id = entities.spawn();
+            items.insert(
+                id,
+                Item {
+                    config: config(kind, position, None),
+                    sensor: environment.add_sensor(position, 5., id),
+                    state,
+                },
+            );
+        };
+        add(
+            ItemKind::Mine { damage: 10. },
+            Vec2::new(11., 10.),
+            ItemState::Available,
+        );
+        add(
+            ItemKind::EnergyPickup { amount: 10. },
+            Vec2::new(10., 12.),
+            ItemState::Gone,
+        );
+        add(
+            ItemKind::EnergyPickup { amount: 10. },
+            Vec2::new(10., 40.),
+            ItemState::Available,
+        );
+        add(
+            ItemKind::HealthPickup { amount: 10. },
+            Vec2::new(10., -25.),
+            ItemState::Available,
+        );
+
+        let (distance, bearing) = nearest_pickup(&items, Vec2::new(10., 10.)).unwrap();
+        assert_eq!(distance, 30.);
+        assert!((bearing.0 - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+        assert!(nearest_pickup(&ecs::ComponentSystem::new(), Vec2::new(0., 0.)).is_none());
+    }
+}
@@ -1,4 +1,10 @@
-use std::num::TryFromIntError;
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    num::TryFromIntError,
+};
+
+use crate::simulation::loadout::Loadout;
 
 const GENERAL_PURPOSE_REGISTER_U64_0: &str = "r0";
 const GENERAL_PURPOSE_REGISTER_U64_1: &str = "r1";
@@ -13,11 +19,16 @@ const POSITION_X_REGISTER_F64: &str = "position_x";
 const POSITION_Y_REGISTER_F64: &str = "position_y";
 const VELOCITY_X_REGISTER_F64: &str = "velocity_x";
 const VELOCITY_Y_REGISTER_F64: &str = "velocity_y";
+const ACTUAL_VELOCITY_X_REGISTER_F64: &str = "actual_velocity_x";
+const ACTUAL_VELOCITY_Y_REGISTER_F64: &str = "actual_velocity_y";
 const TURRET_ANGLE_REGISTER_F64: &str = "turret_angle";
 const TURRET_ANGULAR_VELOCITY_REGISTER_F64: &str = "turret_angular_velocity";
 const SCANNER_DISTANCE_REGISTER_F64: &str = "scanner_distance";
 const HEALTH_REGISTER_F64: &str = "health";
 const ENERGY_REGISTER_F64: &str = "energy";
+const PICKUP_DISTANCE_REGISTER_F64: &str = "pickup_distance";
+const PICKUP_BEARING_REGISTER_F64: &str = "pickup_bearing";
+const FIRE_REGISTER_F64: &str = "fire";
 const GENERAL_PURPOSE_REGISTER_F64_0: &str = "f0";
 const GENERAL_PURPOSE_REGISTER_F64_1: &str = "f1";
 const GENERAL_PURPOSE_REGISTER_F64_2: &str = "f2";
@@ -27,7 +38,11 @@ const GENERAL_PURPOSE_REGISTER_F64_5: &str = "f5";
 const GENERAL_PURPOSE_REGISTER_F64_6: &str = "f6";
 const GENERAL_PURPOSE_REGISTER_F64_7: &str = "f7";
 
-#[derive(Debug, Clone)]
+const COLLISION_INTERRUPT: &str = "collision";
+const HIT_INTERRUPT: &str = "hit";
+const SCAN_FOUND_INTERRUPT: &str = "scan_found";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ReadableRegisterU64 {
     GeneralPurpose0,
     GeneralPurpose1,
@@ -39,6 +54,34 @@ pub enum ReadableRegisterU64 {
     GeneralPurpose7,
 }
 
+impl ReadableRegisterU64 {
+    pub const ALL: [ReadableRegisterU64; 8] = [
+        ReadableRegisterU64::GeneralPurpose0,
+        ReadableRegisterU64::GeneralPurpose1,
+        ReadableRegisterU64::GeneralPurpose2,
+        ReadableRegisterU64::GeneralPurpose3,
+        ReadableRegisterU64::GeneralPurpose4,
+        ReadableRegisterU64::GeneralPurpose5,
+        ReadableRegisterU64::GeneralPurpose6,
+        ReadableRegisterU64::GeneralPurpose7,
+    ];
+}
+
+impl Display for ReadableRegisterU64 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReadableRegisterU64::GeneralPurpose0 => GENERAL_PURPOSE_REGISTER_U64_0,
+            ReadableRegisterU64::GeneralPurpose1 => GENERAL_PURPOSE_REGISTER_U64_1,
+            ReadableRegisterU64::GeneralPurpose2 => GENERAL_PURPOSE_REGISTER_U64_2,
+            ReadableRegisterU64::GeneralPurpose3 => GENERAL_PURPOSE_REGISTER_U64_3,
+            ReadableRegisterU64::GeneralPurpose4 => GENERAL_PURPOSE_REGISTER_U64_4,
+            ReadableRegisterU64::GeneralPurpose5 => GENERAL_PURPOSE_REGISTER_U64_5,
+            ReadableRegisterU64::GeneralPurpose6 => GENERAL_PURPOSE_REGISTER_U64_6,
+            ReadableRegisterU64::GeneralPurpose7 => GENERAL_PURPOSE_REGISTER_U64_7,
+        })
+    }
+}
+
 impl TryFrom<&str> for ReadableRegisterU64 {
     type Error = String;
 
@@ -57,7 +100,7 @@ impl TryFrom<&str> for ReadableRegisterU64 {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum WritableRegisterU64 {
     GeneralPurpose0,
     GeneralPurpose1,
@@ -69,6 +112,34 @@ pub enum WritableRegisterU64 {
     GeneralPurpose7,
 }
 
+impl WritableRegisterU64 {
+    pub const ALL: [WritableRegisterU64; 8] = [
+        WritableRegisterU64::GeneralPurpose0,
+        WritableRegisterU64::GeneralPurpose1,
+        WritableRegisterU64::GeneralPurpose2,
+        WritableRegisterU64::GeneralPurpose3,
+        WritableRegisterU64::GeneralPurpose4,
+        WritableRegisterU64::GeneralPurpose5,
+        WritableRegisterU64::GeneralPurpose6,
+        WritableRegisterU64::GeneralPurpose7,
+    ];
+}
+
+impl Display for WritableRegisterU64 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WritableRegisterU64::GeneralPurpose0 => GENERAL_PURPOSE_REGISTER_U64_0,
+            WritableRegisterU64::GeneralPurpose1 => GENERAL_PURPOSE_REGISTER_U64_1,
+            WritableRegisterU64::GeneralPurpose2 => GENERAL_PURPOSE_REGISTER_U64_2,
+            WritableRegisterU64::GeneralPurpose3 => GENERAL_PURPOSE_REGISTER_U64_3,
+            WritableRegisterU64::GeneralPurpose4 => GENERAL_PURPOSE_REGISTER_U64_4,
+            WritableRegisterU64::GeneralPurpose5 => GENERAL_PURPOSE_REGISTER_U64_5,
+            WritableRegisterU64::GeneralPurpose6 => GENERAL_PURPOSE_REGISTER_U64_6,
+            WritableRegisterU64::GeneralPurpose7 => GENERAL_PURPOSE_REGISTER_U64_7,
+        })
+    }
+}
+
 impl TryFrom<&str> for WritableRegisterU64 {
     type Error = String;
 
@@ -87,17 +158,25 @@ impl TryFrom<&str> for WritableRegisterU64 {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum ReadableRegisterF64 {
     PositionX,
     PositionY,
+    /// The velocity the robot is asking its drive for.
     VelocityX,
     VelocityY,
+    /// How fast the robot is really going, which lags behind the requested velocity.
+    ActualVelocityX,
+    ActualVelocityY,
     TurretAngle,
     TurretAngularVelocity,
     ScannerDistance,
     Health,
     Energy,
+    PickupDistance,
+    PickupBearing,
+    /// Writing a positive value fires the robot's weapon along its turret. Cleared once the shot is handled.
+    Fire,
     GeneralPurpose0,
     GeneralPurpose1,
     GeneralPurpose2,
@@ -108,6 +187,62 @@ pub enum ReadableRegisterF64 {
     GeneralPurpose7,
 }
 
+impl ReadableRegisterF64 {
+    pub const ALL: [ReadableRegisterF64; 22] = [
+        ReadableRegisterF64::PositionX,
+        ReadableRegisterF64::PositionY,
+        ReadableRegisterF64::VelocityX,
+        ReadableRegisterF64::VelocityY,
+        ReadableRegisterF64::ActualVelocityX,
+        ReadableRegisterF64::ActualVelocityY,
+        ReadableRegisterF64::TurretAngle,
+        ReadableRegisterF64::TurretAngularVelocity,
+        ReadableRegisterF64::ScannerDistance,
+        ReadableRegisterF64::Health,
+        ReadableRegisterF64::Energy,
+        ReadableRegisterF64::PickupDistance,
+        ReadableRegisterF64::PickupBearing,
+        ReadableRegisterF64::Fire,
+        ReadableRegisterF64::GeneralPurpose0,
+        ReadableRegisterF64::GeneralPurpose1,
+        ReadableRegisterF64::GeneralPurpose2,
+        ReadableRegisterF64::GeneralPurpose3,
+        ReadableRegisterF64::GeneralPurpose4,
+        ReadableRegisterF64::GeneralPurpose5,
+        ReadableRegisterF64::GeneralPurpose6,
+        ReadableRegisterF64::GeneralPurpose7,
+    ];
+}
+
+impl Display for ReadableRegisterF64 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ReadableRegisterF64::PositionX => POSITION_X_REGISTER_F64,
+            ReadableRegisterF64::PositionY => POSITION_Y_REGISTER_F64,
+            ReadableRegisterF64::VelocityX => VELOCITY_X_REGISTER_F64,
+            ReadableRegisterF64::VelocityY => VELOCITY_Y_REGISTER_F64,
+            ReadableRegisterF64::ActualVelocityX => ACTUAL_VELOCITY_X_REGISTER_F64,
+            ReadableRegisterF64::ActualVelocityY => ACTUAL_VELOCITY_Y_REGISTER_F64,
+            ReadableRegisterF64::TurretAngle => TURRET_ANGLE_REGISTER_F64,
+            ReadableRegisterF64::TurretAngularVelocity => TURRET_ANGULAR_VELOCITY_REGISTER_F64,
+            ReadableRegisterF64::ScannerDistance => SCANNER_DISTANCE_REGISTER_F64,
+            ReadableRegisterF64::Health => HEALTH_REGISTER_F64,
+            ReadableRegisterF64::Energy => ENERGY_REGISTER_F64,
+            ReadableRegisterF64::PickupDistance => PICKUP_DISTANCE_REGISTER_F64,
+            ReadableRegisterF64::PickupBearing => PICKUP_BEARING_REGISTER_F64,
+            ReadableRegisterF64::Fire => FIRE_REGISTER_F64,
+            ReadableRegisterF64::GeneralPurpose0 => GENERAL_PURPOSE_REGISTER_F64_0,
+            ReadableRegisterF64::GeneralPurpose1 => GENERAL_PURPOSE_REGISTER_F64_1,
+            ReadableRegisterF64::GeneralPurpose2 => GENERAL_PURPOSE_REGISTER_F64_2,
+            ReadableRegisterF64::GeneralPurpose3 => GENERAL_PURPOSE_REGISTER_F64_3,
+            ReadableRegisterF64::GeneralPurpose4 => GENERAL_PURPOSE_REGISTER_F64_4,
+            ReadableRegisterF64::GeneralPurpose5 => GENERAL_PURPOSE_REGISTER_F64_5,
+            ReadableRegisterF64::GeneralPurpose6 => GENERAL_PURPOSE_REGISTER_F64_6,
+            ReadableRegisterF64::GeneralPurpose7 => GENERAL_PURPOSE_REGISTER_F64_7,
+        })
+    }
+}
+
 impl TryFrom<&str> for ReadableRegisterF64 {
     type Error = String;
 
@@ -117,11 +252,16 @@ impl TryFrom<&str> for ReadableRegisterF64 {
             POSITION_Y_REGISTER_F64 => Ok(ReadableRegisterF64::PositionY),
             VELOCITY_X_REGISTER_F64 => Ok(ReadableRegisterF64::VelocityX),
             VELOCITY_Y_REGISTER_F64 => Ok(ReadableRegisterF64::VelocityY),
+            ACTUAL_VELOCITY_X_REGISTER_F64 => Ok(ReadableRegisterF64::ActualVelocityX),
+            ACTUAL_VELOCITY_Y_REGISTER_F64 => Ok(ReadableRegisterF64::ActualVelocityY),
             TURRET_ANGLE_REGISTER_F64 => Ok(ReadableRegisterF64::TurretAngle),
             TURRET_ANGULAR_VELOCITY_REGISTER_F64 => Ok(ReadableRegisterF64::TurretAngularVelocity),
             SCANNER_DISTANCE_REGISTER_F64 => Ok(ReadableRegisterF64::ScannerDistance),
             HEALTH_REGISTER_F64 => Ok(ReadableRegisterF64::Health),
             ENERGY_REGISTER_F64 => Ok(ReadableRegisterF64::Energy),
+            PICKUP_DISTANCE_REGISTER_F64 => Ok(ReadableRegisterF64::PickupDistance),
+            PICKUP_BEARING_REGISTER_F64 => Ok(ReadableRegisterF64::PickupBearing),
+            FIRE_REGISTER_F64 => Ok(ReadableRegisterF64::Fire),
             GENERAL_PURPOSE_REGISTER_F64_0 => Ok(ReadableRegisterF64::GeneralPurpose0),
             GENERAL_PURPOSE_REGISTER_F64_1 => Ok(ReadableRegisterF64::GeneralPurpose1),
             GENERAL_PURPOSE_REGISTER_F64_2 => Ok(ReadableRegisterF64::GeneralPurpose2),
@@ -135,11 +275,12 @@ impl TryFrom<&str> for ReadableRegisterF64 {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum WritableRegisterF64 {
     VelocityX,
     VelocityY,
     TurretAngularVelocity,
+    Fire,
     GeneralPurpose0,
     GeneralPurpose1,
     GeneralPurpose2,
@@ -150,6 +291,42 @@ pub enum WritableRegisterF64 {
     GeneralPurpose7,
 }
 
+impl WritableRegisterF64 {
+    pub const ALL: [WritableRegisterF64; 12] = [
+        WritableRegisterF64::VelocityX,
+        WritableRegisterF64::VelocityY,
+        WritableRegisterF64::TurretAngularVelocity,
+        WritableRegisterF64::Fire,
+        WritableRegisterF64::GeneralPurpose0,
+        WritableRegisterF64::GeneralPurpose1,
+        WritableRegisterF64::GeneralPurpose2,
+        WritableRegisterF64::GeneralPurpose3,
+        WritableRegisterF64::GeneralPurpose4,
+        WritableRegisterF64::GeneralPurpose5,
+        WritableRegisterF64::GeneralPurpose6,
+        WritableRegisterF64::GeneralPurpose7,
+    ];
+}
+
+impl Display for WritableRegisterF64 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WritableRegisterF64::VelocityX => VELOCITY_X_REGISTER_F64,
+            WritableRegisterF64::VelocityY => VELOCITY_Y_REGISTER_F64,
+            WritableRegisterF64::TurretAngularVelocity => TURRET_ANGULAR_VELOCITY_REGISTER_F64,
+            WritableRegisterF64::Fire => FIRE_REGISTER_F64,
+            WritableRegisterF64::GeneralPurpose0 => GENERAL_PURPOSE_REGISTER_F64_0,
+            WritableRegisterF64::GeneralPurpose1 => GENERAL_PURPOSE_REGISTER_F64_1,
+            WritableRegisterF64::GeneralPurpose2 => GENERAL_PURPOSE_REGISTER_F64_2,
+            WritableRegisterF64::GeneralPurpose3 => GENERAL_PURPOSE_REGISTER_F64_3,
+            WritableRegisterF64::GeneralPurpose4 => GENERAL_PURPOSE_REGISTER_F64_4,
+            WritableRegisterF64::GeneralPurpose5 => GENERAL_PURPOSE_REGISTER_F64_5,
+            WritableRegisterF64::GeneralPurpose6 => GENERAL_PURPOSE_REGISTER_F64_6,
+            WritableRegisterF64::GeneralPurpose7 => GENERAL_PURPOSE_REGISTER_F64_7,
+        })
+    }
+}
+
 impl TryFrom<&str> for WritableRegisterF64 {
     type Error = String;
 
@@ -158,6 +335,7 @@ impl TryFrom<&str> for WritableRegisterF64 {
             VELOCITY_X_REGISTER_F64 => Ok(WritableRegisterF64::VelocityX),
             VELOCITY_Y_REGISTER_F64 => Ok(WritableRegisterF64::VelocityY),
             TURRET_ANGULAR_VELOCITY_REGISTER_F64 => Ok(WritableRegisterF64::TurretAngularVelocity),
+            FIRE_REGISTER_F64 => Ok(WritableRegisterF64::Fire),
             GENERAL_PURPOSE_REGISTER_F64_0 => Ok(WritableRegisterF64::GeneralPurpose0),
             GENERAL_PURPOSE_REGISTER_F64_1 => Ok(WritableRegisterF64::GeneralPurpose1),
             GENERAL_PURPOSE_REGISTER_F64_2 => Ok(WritableRegisterF64::GeneralPurpose2),
@@ -171,29 +349,72 @@ impl TryFrom<&str> for WritableRegisterF64 {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Events that a program can register a handler for with `.on <kind> <label>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InterruptKind {
+    /// The robot bumped into another robot or a wall.
+    Collision,
+    /// The robot took damage, from a shot or a mine.
+    Hit,
+    /// A robot came into view of the scanner, within its range.
+    ScanFound,
+}
+
+impl InterruptKind {
+    /// All interrupt kinds, in the order they are delivered when several are pending at once.
+    pub const ALL: [InterruptKind; 3] = [
+        InterruptKind::Hit,
+        InterruptKind::Collision,
+        InterruptKind::ScanFound,
+    ];
+}
+
+impl Display for InterruptKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InterruptKind::Collision => COLLISION_INTERRUPT,
+            InterruptKind::Hit => HIT_INTERRUPT,
+            InterruptKind::ScanFound => SCAN_FOUND_INTERRUPT,
+        })
+    }
+}
+
+impl TryFrom<&str> for InterruptKind {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            COLLISION_INTERRUPT => Ok(InterruptKind::Collision),
+            HIT_INTERRUPT => Ok(InterruptKind::Hit),
+            SCAN_FOUND_INTERRUPT => Ok(InterruptKind::ScanFound),
+            _ => Err(format!("Invalid interrupt: {value}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum SourceU64 {
     Register(ReadableRegisterU64),
     Literal(u64),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum DestinationU64 {
     Register(WritableRegisterU64),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum SourceF64 {
     Register(ReadableRegisterF64),
     Literal(f64),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum DestinationF64 {
     Register(WritableRegisterF64),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Instruction {
     SetU64 {
         destination: DestinationU64,
@@ -342,9 +563,51 @@ pub enum Instruction {
         destination_address: SourceU64,
         source: SourceF64,
     },
+    InterruptReturn,
+}
+
+impl Instruction {
+    /// Where a jump instruction goes, or `None` if this isn't a jump.
+    pub fn jump_address(&self) -> Option<&SourceU64> {
+        match self {
+            Instruction::Jump { address }
+            | Instruction::JumpEqualU64 { address, .. }
+            | Instruction::JumpEqualF64 { address, .. }
+            | Instruction::JumpNotEqualU64 { address, .. }
+            | Instruction::JumpNotEqualF64 { address, .. }
+            | Instruction::JumpLessThanU64 { address, .. }
+            | Instruction::JumpLessThanF64 { address, .. }
+            | Instruction::JumpLessThanOrEqualToU64 { address, .. }
+            | Instruction::JumpLessThanOrEqualToF64 { address, .. }
+            | Instruction::JumpGreaterThanU64 { address, .. }
+            | Instruction::JumpGreaterThanF64 { address, .. }
+            | Instruction::JumpGreaterThanOrEqualToU64 { address, .. }
+            | Instruction::JumpGreaterThanOrEqualToF64 { address, .. } => Some(address),
+            _ => None,
+        }
+    }
+
+    pub fn jump_address_mut(&mut self) -> Option<&mut SourceU64> {
+        match self {
+            Instruction::Jump { address }
+            | Instruction::JumpEqualU64 { address, .. }
+            | Instruction::JumpEqualF64 { address, .. }
+            | Instruction::JumpNotEqualU64 { address, .. }
+            | Instruction::JumpNotEqualF64 { address, .. }
+            | Instruction::JumpLessThanU64 { address, .. }
+            | Instruction::JumpLessThanF64 { address, .. }
+            | Instruction::JumpLessThanOrEqualToU64 { address, .. }
+            | Instruction::JumpLessThanOrEqualToF64 { address, .. }
+            | Instruction::JumpGreaterThanU64 { address, .. }
+            | Instruction::JumpGreaterThanF64 { address, .. }
+            | Instruction::JumpGreaterThanOrEqualToU64 { address, .. }
+            | Instruction::JumpGreaterThanOrEqualToF64 { address, .. } => Some(address),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct ProgramPointer(pub usize);
 
 impl ProgramPointer {
@@ -387,20 +650,43 @@ impl TryInto<u64> for ProgramPointer {
 #[derive(Debug, Clone)]
 pub struct Program {
     instructions: Vec<Instruction>,
+    interrupt_vectors: HashMap<InterruptKind, ProgramPointer>,
     pub stack_size: usize,
     pub heap_size: usize,
+    /// The robot design the program declared, if it declared one.
+    pub loadout: Option<Loadout>,
 }
 
 impl Program {
-    pub fn new(instructions: Vec<Instruction>, stack_size: usize, heap_size: usize) -> Self {
+    pub fn new(
+        instructions: Vec<Instruction>,
+        interrupt_vectors: HashMap<InterruptKind, ProgramPointer>,
+        stack_size: usize,
+        heap_size: usize,
+    ) -> Self {
         Self {
             instructions,
+            interrupt_vectors,
             stack_size,
             heap_size,
+            loadout: None,
         }
     }
 
     pub fn get(&self, p: ProgramPointer) -> Option<&Instruction> {
         self.instructions.get(p.0)
     }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    pub fn interrupt_vectors(&self) -> &HashMap<InterruptKind, ProgramPointer> {
+        &self.interrupt_vectors
+    }
+
+    /// The address of the handler registered for the given interrupt, if any.
+    pub fn interrupt_vector(&self, kind: InterruptKind) -> Option<ProgramPointer> {
+        self.interrupt_vectors.get(&kind).copied()
+    }
 }
@@ -0,0 +1,295 @@
+use std::fmt::{self, Display, Formatter};
+
+const LIGHT_CHASSIS: &str = "light";
+const MEDIUM_CHASSIS: &str = "medium";
+const HEAVY_CHASSIS: &str = "heavy";
+
+const NO_WEAPON: &str = "none";
+const BLASTER_WEAPON: &str = "blaster";
+const CANNON_WEAPON: &str = "cannon";
+
+/// The highest level armor, battery and scanner upgrades go to.
+pub const MAX_LEVEL: u32 = 3;
+
+/// Points a loadout can spend unless the match says otherwise. Enough for a medium chassis, a blaster and a few
+/// upgrades, but not everything at once.
+pub const DEFAULT_BUDGET: u32 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Chassis {
+    Light,
+    #[default]
+    Medium,
+    Heavy,
+}
+
+impl Chassis {
+    pub const ALL: [Chassis; 3] = [Chassis::Light, Chassis::Medium, Chassis::Heavy];
+
+    pub fn radius(&self) -> f64 {
+        match self {
+            Chassis::Light => 10.,
+            Chassis::Medium => 15.,
+            Chassis::Heavy => 20.,
+        }
+    }
+
+    pub fn health(&self) -> f64 {
+        match self {
+            Chassis::Light => 70.,
+            Chassis::Medium => 100.,
+            Chassis::Heavy => 150.,
+        }
+    }
+
+    fn cost(&self) -> u32 {
+        match self {
+            Chassis::Light => 0,
+            Chassis::Medium => 1,
+            Chassis::Heavy => 2,
+        }
+    }
+}
+
+impl Display for Chassis {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Chassis::Light => LIGHT_CHASSIS,
+            Chassis::Medium => MEDIUM_CHASSIS,
+            Chassis::Heavy => HEAVY_CHASSIS,
+        })
+    }
+}
+
+impl TryFrom<&str> for Chassis {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            LIGHT_CHASSIS => Ok(Chassis::Light),
+            MEDIUM_CHASSIS => Ok(Chassis::Medium),
+            HEAVY_CHASSIS => Ok(Chassis::Heavy),
+            _ => Err(format!("Invalid chassis: {value}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum WeaponKind {
+    #[default]
+    None,
+    /// Cheap, weak and quick to fire.
+    Blaster,
+    /// Hits hard from further away, but slowly and at a high energy cost.
+    Cannon,
+}
+
+impl WeaponKind {
+    pub const ALL: [WeaponKind; 3] = [WeaponKind::None, WeaponKind::Blaster, WeaponKind::Cannon];
+
+    pub fn stats(&self) -> Option<WeaponStats> {
+        match self {
+            WeaponKind::None => None,
+            WeaponKind::Blaster => Some(WeaponStats {
+                damage: 5.,
+                range: 300.,
+                cooldown_ticks: 10,
+                energy_cost: 2.,
+            }),
+            WeaponKind::Cannon => Some(WeaponStats {
+                damage: 25.,
+                range: 600.,
+                cooldown_ticks: 60,
+                energy_cost: 15.,
+            }),
+        }
+    }
+
+    fn cost(&self) -> u32 {
+        match self {
+            WeaponKind::None => 0,
+            WeaponKind::Blaster => 1,
+            WeaponKind::Cannon => 2,
+        }
+    }
+}
+
+impl Display for WeaponKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            WeaponKind::None => NO_WEAPON,
+            WeaponKind::Blaster => BLASTER_WEAPON,
+            WeaponKind::Cannon => CANNON_WEAPON,
+        })
+    }
+}
+
+impl TryFrom<&str> for WeaponKind {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value.to_lowercase().as_str() {
+            NO_WEAPON => Ok(WeaponKind::None),
+            BLASTER_WEAPON => Ok(WeaponKind::Blaster),
+            CANNON_WEAPON => Ok(WeaponKind::Cannon),
+            _ => Err(format!("Invalid weapon: {value}")),
+        }
+    }
+}
+
+/// How a weapon behaves when the program writes to the `fire` register.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WeaponStats {
+    pub damage: f64,
+    /// Shots travel along the turret and hit the first robot within this distance.
+    pub range: f64,
+    /// Ticks after firing before the weapon can fire again.
+    pub cooldown_ticks: u32,
+    pub energy_cost: f64,
+}
+
+/// A robot's design, declared at the top of its program with `.chassis`, `.armor`, `.battery`, `.scanner` and
+/// `.weapon`. Every choice costs points, and the match rejects loadouts that cost more than its budget.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Loadout {
+    pub chassis: Chassis,
+    /// Each level takes a fifth off incoming damage, but makes the robot heavier and slower to accelerate.
+    pub armor: u32,
+    /// Each level adds half again to the energy the robot starts with and can hold.
+    pub battery: u32,
+    /// Each level doubles how far the scanner can see.
+    pub scanner: u32,
+    pub weapon: WeaponKind,
+}
+
+impl Loadout {
+    pub fn cost(&self) -> u32 {
+        self.chassis.cost() + self.armor + self.battery + self.scanner + self.weapon.cost()
+    }
+
+    /// Checks every upgrade is in range and the whole thing fits in `budget`.
+    pub fn validate(&self, budget: u32) -> Result<(), String> {
+        for (name, level) in [
+            ("armor", self.armor),
+            ("battery", self.battery),
+            ("scanner", self.scanner),
+        ] {
+            if level > MAX_LEVEL {
+                return Err(format!(
+                    "{name} level {level} is above the maximum of {MAX_LEVEL}"
+                ));
+            }
+        }
+        let cost = self.cost();
+        if cost > budget {
+            return Err(format!(
+                "loadout costs {cost} points, over the budget of {budget}"
+            ));
+        }
+        Ok(())
+    }
+
+    /// What the loadout does to the robot inside the simulation.
+    pub fn stats(&self) -> RobotStats {
+        RobotStats {
+            max_health: self.chassis.health(),
+            energy_capacity: 100. + 50. * self.battery as f64,
+            damage_taken: 1. - 0.2 * self.armor as f64,
+            scanner_range: 300. * 2f64.powi(self.scanner as i32),
+            weapon: self.weapon.stats(),
+        }
+    }
+
+    /// Mass per unit area of the robot's body. Rapier's default is 1.
+    pub fn density(&self) -> f64 {
+        1. + 0.25 * self.armor as f64
+    }
+}
+
+/// The numbers a robot plays by, whether they came from a [`Loadout`] or not.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RobotStats {
+    /// Starting health, and the most a health pickup can bring it back up to.
+    pub max_health: f64,
+    /// Starting energy, and the most the robot can hold.
+    pub energy_capacity: f64,
+    /// Multiplier for damage from weapons and mines.
+    pub damage_taken: f64,
+    /// Things further away than this don't show up on the scanner.
+    pub scanner_range: f64,
+    pub weapon: Option<WeaponStats>,
+}
+
+impl Default for RobotStats {
+    /// Robots that don't declare a loadout see everything, take full damage and can't fire.
+    fn default() -> Self {
+        Self {
+            max_health: 100.,
+            energy_capacity: 100.,
+            damage_taken: 1.,
+            scanner_range: f64::MAX,
+            weapon: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget() {
+        let loadout = Loadout::default();
+        assert_eq!(loadout.cost(), 1);
+        assert!(loadout.validate(1).is_ok());
+        assert!(loadout.validate(0).is_err());
+
+        let loadout = Loadout {
+            chassis: Chassis::Heavy,
+            armor: 2,
+            battery: 0,
+            scanner: 1,
+            weapon: WeaponKind::Cannon,
+        };
+        assert_eq!(loadout.cost(), 7);
+        assert!(loadout.validate(DEFAULT_BUDGET).is_err());
+        assert!(loadout.validate(7).is_ok());
+
+        let loadout = Loadout {
+            armor: MAX_LEVEL + 1,
+            ..Loadout::default()
+        };
+        assert!(loadout.validate(u32::MAX).is_err());
+    }
+
+    #[test]
+    fn stats() {
+        let stats = Loadout {
+            chassis: Chassis::Light,
+            armor: 1,
+            battery: 2,
+            scanner: 3,
+            weapon: WeaponKind::Blaster,
+        }
+        .stats();
+        assert_eq!(stats.max_health, 70.);
+        assert_eq!(stats.energy_capacity, 200.);
+        assert_eq!(stats.damage_taken, 0.8);
+        assert_eq!(stats.scanner_range, 2400.);
+        assert_eq!(stats.weapon, WeaponKind::Blaster.stats());
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for chassis in Chassis::ALL {
+            assert_eq!(Chassis::try_from(chassis.to_string().as_str()), Ok(chassis));
+        }
+        for weapon in WeaponKind::ALL {
+            assert_eq!(
+                WeaponKind::try_from(weapon.to_string().as_str()),
+                Ok(weapon)
+            );
+        }
+        assert!(Chassis::try_from("tank").is_err());
+    }
+}
@@ -1,5 +1,9 @@
+pub mod decoded;
 pub mod ecs;
+pub mod items;
 pub mod language;
+pub mod loadout;
 pub mod physics;
 pub mod simulation;
+pub mod telemetry;
 pub mod vm;
@@ -1,7 +1,7 @@
-use std::{cell::RefCell, ops::RangeInclusive, rc::Rc};
+use std::{cell::RefCell, collections::HashMap, ops::RangeInclusive, rc::Rc};
 
 use color_eyre::eyre::{Result, eyre};
-use rand::Rng;
+use rand::{Rng, SeedableRng, rngs::StdRng};
 use rapier2d_f64::{
     crossbeam,
     na::{Matrix2x1, Point2},
@@ -9,27 +9,118 @@ use rapier2d_f64::{
 };
 use tracing::*;
 
-use crate::{
-    math::*,
-    simulation::ecs::{self},
-};
+use crate::math::*;
 
-#[derive(Clone)]
+/// What a collider belongs to, identified by the user data it was added with.
+#[derive(Debug, Clone, PartialEq)]
 pub enum Collidable<ActorData> {
-    Actor(Rc<RefCell<Actor<ActorData>>>),
+    Actor(ActorData),
     Environment,
+    /// Something actors pass through, like a pickup. Overlaps are still reported as collision events.
+    Sensor(ActorData),
+}
+
+/// Identifies a sensor added with [`Environment::add_sensor`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SensorHandle(ColliderHandle);
+
+/// Limits on how robots drive, set per arena.
+///
+/// Speed and turn rate are given for a robot of `reference_radius` and shrink in proportion for bigger ones.
+/// Acceleration is the drive force over the robot's mass, so it falls off faster still.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriveConfig {
+    pub reference_radius: f64,
+    pub top_speed: f64,
+    /// Radians per second.
+    pub turn_rate: f64,
+    pub drive_force: f64,
+}
+
+impl Default for DriveConfig {
+    fn default() -> Self {
+        Self {
+            reference_radius: 15.,
+            top_speed: 200.,
+            turn_rate: std::f64::consts::PI,
+            // about 200 units per second squared for a robot of the reference radius
+            drive_force: 150_000.,
+        }
+    }
+}
+
+/// The limits for one robot, after scaling [`DriveConfig`] by its size and mass.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DriveLimits {
+    pub top_speed: f64,
+    pub turn_rate: f64,
+    pub acceleration: f64,
+}
+
+impl DriveConfig {
+    pub fn limits(&self, radius: f64, mass: f64) -> DriveLimits {
+        let scale = self.reference_radius / radius;
+        DriveLimits {
+            top_speed: self.top_speed * scale,
+            turn_rate: self.turn_rate * scale,
+            acceleration: self.drive_force / mass,
+        }
+    }
+}
+
+impl DriveLimits {
+    /// Moves `velocity` towards `target` over `dt` seconds without exceeding the limits.
+    ///
+    /// A moving robot turns towards the target direction at up to the turn rate, and slows down while it's facing more
+    /// than a right angle away. A stopped robot can set off in any direction.
+    pub fn drive(&self, velocity: Vec2<f64>, target: Vec2<f64>, dt: f64) -> Vec2<f64> {
+        let target_speed = target.magnitude().min(self.top_speed);
+        let speed = velocity.magnitude();
+        let max_speed_change = self.acceleration * dt;
+
+        let (Some(heading), Some(target_heading)) = (velocity.normalize(), target.normalize())
+        else {
+            // either stopped, or trying to stop, so there's no turning to do
+            let direction = velocity.normalize().or(target.normalize());
+            return match direction {
+                Some(direction) if speed > 0. => {
+                    direction * (speed - max_speed_change).max(0.).min(self.top_speed)
+                }
+                Some(direction) => direction * target_speed.min(max_speed_change),
+                None => Vec2::new(0., 0.),
+            };
+        };
+
+        let angle = Radians(heading.y.atan2(heading.x));
+        let target_angle = Radians(target_heading.y.atan2(target_heading.x));
+        let remaining = angle.shortest_difference(target_angle).0;
+        let max_turn = self.turn_rate * dt;
+        let turn = remaining.clamp(-max_turn, max_turn);
+        let remaining = remaining - turn;
+
+        // brake for sharp turns, there's no point speeding up while facing the wrong way
+        let desired_speed = target_speed * remaining.cos().max(0.);
+        let new_speed = if desired_speed > speed {
+            (speed + max_speed_change).min(desired_speed)
+        } else {
+            (speed - max_speed_change).max(desired_speed)
+        };
+        heading.rotate(Radians(turn)) * new_speed
+    }
 }
 
 pub struct Actor<T> {
     rigid_body_set: Rc<RefCell<RigidBodySet>>,
     rigid_body_handle: RigidBodyHandle,
     radius: f64,
+    /// What the robot asked its drive for. The real velocity chases it.
+    target_velocity: Vec2<f64>,
     turret_angle: Radians<f64>,
     turret_angular_velocity: Radians<f64>,
     user_data: T,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum CollisionEvent<ActorData> {
     Started(Collidable<ActorData>, Collidable<ActorData>),
     Stopped(Collidable<ActorData>, Collidable<ActorData>),
@@ -58,8 +149,10 @@ pub struct Environment<ActorData> {
     collision_recv: crossbeam::channel::Receiver<rapier2d_f64::geometry::CollisionEvent>,
     contact_force_recv: crossbeam::channel::Receiver<ContactForceEvent>,
     event_handler: ChannelEventCollector,
-    actors: Vec<Rc<RefCell<Actor<ActorData>>>>,
-    collidables: ecs::ComponentSystem<Collidable<ActorData>>,
+    drive: DriveConfig,
+    /// Where new actors' positions, sizes and turret angles come from.
+    rng: StdRng,
+    collidables: HashMap<ColliderHandle, Collidable<ActorData>>,
 }
 
 impl<T> Actor<T> {
@@ -97,6 +190,14 @@ impl<T> Actor<T> {
         self.radius
     }
 
+    pub fn target_velocity(&self) -> Vec2<f64> {
+        self.target_velocity
+    }
+
+    pub fn set_target_velocity(&mut self, value: Vec2<f64>) {
+        self.target_velocity = value;
+    }
+
     pub fn turret_angle(&self) -> Radians<f64> {
         self.turret_angle
     }
@@ -143,9 +244,6 @@ where
     ActorData: Clone,
 {
     pub fn new_standard_rectangle(bounding_box: Rect<f64>) -> Self {
-        let mut collidables = ecs::ComponentSystem::new();
-        let environment_id = collidables.insert(Collidable::Environment);
-
         let rigid_body_set = RigidBodySet::new();
         let mut collider_set = ColliderSet::new();
 
@@ -158,9 +256,9 @@ where
             ],
             Some(vec![[0, 1], [1, 2], [2, 3], [3, 0]]),
         )
-        .user_data(environment_id.0 as u128)
         .build();
         let polyline_handle = collider_set.insert(polyline);
+        let collidables = HashMap::from([(polyline_handle, Collidable::Environment)]);
 
         let gravity = vector![0., 0.];
         let integration_parameters = IntegrationParameters::default();
@@ -195,7 +293,8 @@ where
             collision_recv,
             contact_force_recv,
             event_handler,
-            actors: Vec::new(),
+            drive: DriveConfig::default(),
+            rng: StdRng::from_rng(&mut rand::rng()),
             collidables,
         }
     }
@@ -230,26 +329,65 @@ where
             .collect::<Vec<_>>())
     }
 
-    pub fn actors_iter(&self) -> impl Iterator<Item = &Rc<RefCell<Actor<ActorData>>>> {
-        self.actors.iter()
+    pub fn drive(&self) -> DriveConfig {
+        self.drive
+    }
+
+    pub fn set_drive(&mut self, drive: DriveConfig) {
+        self.drive = drive;
+    }
+
+    /// Makes actors added from now on spawn the same way every time for the same seed.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
     }
 
+    /// Removes the rigid bodies of every actor added so far. The [`Actor`]s themselves are owned by the caller and
+    /// shouldn't be used afterwards.
     pub fn clear_actors(&mut self) {
-        self.actors.clear();
-        // TODO clear actors from collidables
+        let mut rigid_body_set = self.rigid_body_set.borrow_mut();
+        let handles = rigid_body_set
+            .iter()
+            .map(|(handle, _)| handle)
+            .collect::<Vec<_>>();
+        for handle in handles {
+            rigid_body_set.remove(
+                handle,
+                &mut self.island_manager,
+                &mut self.collider_set,
+                &mut self.impulse_joint_set,
+                &mut self.multibody_joint_set,
+                true,
+            );
+        }
+        let collider_set = &self.collider_set;
+        self.collidables
+            .retain(|handle, _| collider_set.contains(*handle));
     }
 
     /// Creates a new actor with a random position and radius within the bounding box.
     ///
-    /// Adds actor to the internal list and also returns a reference to it.
+    /// The caller owns the actor and passes it back to [`Environment::step`] to drive it.
     pub fn add_random_actor(
         &mut self,
         actor_size: RangeInclusive<f64>,
         user_data: ActorData,
-    ) -> Result<Rc<RefCell<Actor<ActorData>>>> {
-        // TODO pick a position and radius such that we don't initially collide
+    ) -> Result<Actor<ActorData>> {
+        let radius = self.rng.random_range(actor_size);
+        self.add_actor(radius, 1., user_data)
+    }
 
-        let radius = rand::rng().random_range(actor_size);
+    /// Creates a new actor with the given radius and density at a random position within the bounding box. Its mass
+    /// is its area times its density.
+    ///
+    /// The caller owns the actor and passes it back to [`Environment::step`] to drive it.
+    pub fn add_actor(
+        &mut self,
+        radius: f64,
+        density: f64,
+        user_data: ActorData,
+    ) -> Result<Actor<ActorData>> {
+        // TODO pick a position such that we don't initially collide
 
         // find a random location by sampling within the bounding box
         let bbox = self.bounding_box()?;
@@ -258,11 +396,11 @@ where
         let x2 = bbox.maximum().x - radius;
         let y2 = bbox.maximum().y - radius;
         let position = Vec2::new(
-            rand::rng().random_range(x1..=x2),
-            rand::rng().random_range(y1..=y2),
+            self.rng.random_range(x1..=x2),
+            self.rng.random_range(y1..=y2),
         );
 
-        let turret_angle = Radians::from_degrees(rand::rng().random_range((0.)..360.0));
+        let turret_angle = Radians::from_degrees(self.rng.random_range((0.)..360.0));
 
         let turret_angular_velocity = Radians::from_degrees(0.);
 
@@ -272,33 +410,82 @@ where
         let mut rigid_body_set = self.rigid_body_set.borrow_mut();
         let rigid_body_handle = rigid_body_set.insert(rigid_body);
 
-        let result = Rc::new(RefCell::new(Actor {
+        let collider = ColliderBuilder::ball(radius)
+            .density(density)
+            .restitution(0.7)
+            .active_events(ActiveEvents::COLLISION_EVENTS)
+            .build();
+        let collider_handle =
+            self.collider_set
+                .insert_with_parent(collider, rigid_body_handle, &mut rigid_body_set);
+        self.collidables
+            .insert(collider_handle, Collidable::Actor(user_data.clone()));
+
+        Ok(Actor {
             rigid_body_set: self.rigid_body_set.clone(),
             rigid_body_handle,
             radius,
+            target_velocity: Vec2::new(0., 0.),
             turret_angle,
             turret_angular_velocity,
             user_data,
-        }));
-        let id = self.collidables.insert(Collidable::Actor(result.clone()));
-        self.actors.push(result.clone());
+        })
+    }
 
+    /// Adds a circular sensor at a fixed position. Actors move through it freely, but starting and stopping an overlap
+    /// with one is reported to the [`Environment::step`] callback.
+    pub fn add_sensor(
+        &mut self,
+        position: Vec2<f64>,
+        radius: f64,
+        user_data: ActorData,
+    ) -> SensorHandle {
         let collider = ColliderBuilder::ball(radius)
-            .restitution(0.7)
+            .translation(Matrix2x1::new(position.x, position.y))
+            .sensor(true)
             .active_events(ActiveEvents::COLLISION_EVENTS)
-            .user_data(id.0 as u128)
             .build();
-        self.collider_set
-            .insert_with_parent(collider, rigid_body_handle, &mut rigid_body_set);
+        let handle = self.collider_set.insert(collider);
+        self.collidables
+            .insert(handle, Collidable::Sensor(user_data));
+        SensorHandle(handle)
+    }
 
-        Ok(result)
+    /// A disabled sensor doesn't report overlaps. Re-enabling it reports a new overlap with anything already inside.
+    pub fn set_sensor_enabled(&mut self, handle: SensorHandle, enabled: bool) -> Result<()> {
+        self.collider_set
+            .get_mut(handle.0)
+            .ok_or(eyre!("sensor not found: {handle:?}"))?
+            .set_enabled(enabled);
+        Ok(())
     }
 
-    pub fn step<F>(&mut self, time: f64, collision_callback: F)
+    /// Moves `actors`, which should be every actor added to this environment, and everything else forward by one
+    /// physics step.
+    pub fn step<'a, F>(
+        &mut self,
+        time: f64,
+        actors: impl IntoIterator<Item = &'a mut Actor<ActorData>>,
+        mut collision_callback: F,
+    ) -> Result<()>
     where
-        F: Fn(CollisionEvent<ActorData>) -> Result<()>,
+        ActorData: 'a,
+        F: FnMut(CollisionEvent<ActorData>) -> Result<()>,
     {
-        // TODO needs some way to detect collisions, update robot health
+        let mut actors = actors.into_iter().collect::<Vec<_>>();
+
+        // drives push the actors towards where they want to go before the physics engine moves them
+        let dt = self.integration_parameters.dt;
+        for actor in actors.iter() {
+            let limits = self.drive.limits(actor.radius(), actor.mass()?);
+            let velocity = limits.drive(actor.velocity()?, actor.target_velocity(), dt);
+            actor
+                .rigid_body_mut(
+                    &mut self.rigid_body_set.borrow_mut(),
+                    actor.rigid_body_handle,
+                )?
+                .set_linvel(Matrix2x1::new(velocity.x, velocity.y), true);
+        }
 
         // update the physics engine
         let mut rigid_body_set = self.rigid_body_set.borrow_mut();
@@ -318,10 +505,11 @@ where
             &self.physics_hooks,
             &self.event_handler,
         );
+        // the callback is free to look at actors, which borrows the rigid body set again
+        drop(rigid_body_set);
 
         // turrets
-        for actor in self.actors.iter_mut() {
-            let mut actor = actor.borrow_mut();
+        for actor in actors.iter_mut() {
             let new_turret_angle = actor.turret_angle() + actor.turret_angular_velocity() * time;
             actor.set_turret_angle(new_turret_angle);
         }
@@ -329,52 +517,67 @@ where
         // collision events
         while let Ok(collision_event) = self.collision_recv.try_recv() {
             trace!("collision event: {:?}", collision_event);
-            if let Err(e) = self.handle_collision_event(&collision_event, &collision_callback) {
+            if let Err(e) = self.handle_collision_event(&collision_event, &mut collision_callback) {
                 error!("failed to handle collision event: {:?}", e);
             }
         }
         while let Ok(contact_force_event) = self.contact_force_recv.try_recv() {
             trace!("contact force event: {:?}", contact_force_event);
         }
+        Ok(())
     }
 
     /// Finds the first intersection with another actor or the world, starting from the actor's position and extending in the direction of
-    /// the actor's turret.
+    /// the actor's turret. Sensors are ignored.
+    ///
+    /// Returns the distance to that intersection, or `f64::MAX` if there is nothing in the way.
     pub fn actor_scan(&self, starting_actor: &Actor<ActorData>) -> f64 {
-        /*
-        TODO actor scan
-
-        let r = the ray starting from starting_actor.position and extending in the direction the turret is pointing
-
-        find first intersection:
-        - between r and each line segment
-        - between r and each other actor's collider
-
-        if no such intersections exist, return float max
-        */
+        self.actor_scan_target(starting_actor)
+            .map(|(distance, _)| distance)
+            .unwrap_or(f64::MAX)
+    }
 
-        todo!()
+    /// Like [`Environment::actor_scan`], but also says what was found: the user data of the actor in the way, or
+    /// `None` for the world.
+    pub fn actor_scan_target(
+        &self,
+        starting_actor: &Actor<ActorData>,
+    ) -> Option<(f64, Option<ActorData>)> {
+        let position = starting_actor.position().ok()?;
+        let direction = starting_actor.turret_angle().cos_sin_vec2();
+        let ray = Ray::new(
+            Point2::new(position.x, position.y),
+            vector![direction.x, direction.y],
+        );
+        let rigid_body_set = self.rigid_body_set.borrow();
+        let (handle, distance) = self.query_pipeline.cast_ray(
+            &rigid_body_set,
+            &self.collider_set,
+            &ray,
+            f64::MAX,
+            true,
+            QueryFilter::default()
+                .exclude_rigid_body(starting_actor.rigid_body_handle)
+                .exclude_sensors(),
+        )?;
+        let target = match self.collidables.get(&handle) {
+            Some(Collidable::Actor(user_data)) => Some(user_data.clone()),
+            _ => None,
+        };
+        Some((distance, target))
     }
 
     fn handle_collision_event<F>(
         &self,
         collision_event: &rapier2d_f64::geometry::CollisionEvent,
-        collision_callback: F,
+        collision_callback: &mut F,
     ) -> Result<()>
     where
-        F: Fn(CollisionEvent<ActorData>) -> Result<()>,
+        F: FnMut(CollisionEvent<ActorData>) -> Result<()>,
     {
-        // TODO de-duplicate collider exists checks?
-        let collider1 = self
-            .collider_set
-            .get(collision_event.collider1())
-            .ok_or(eyre!("failed to find collider1 in collision event"))?;
-        let collider2 = self
-            .collider_set
-            .get(collision_event.collider2())
-            .ok_or(eyre!("failed to find collider2 in collision event"))?;
-        if let Some(a) = self.collidables.get(ecs::Id(collider1.user_data as usize))
-            && let Some(b) = self.collidables.get(ecs::Id(collider2.user_data as usize))
+        // colliders removed since the step, like cleared actors, are skipped
+        if let Some(a) = self.collidables.get(&collision_event.collider1())
+            && let Some(b) = self.collidables.get(&collision_event.collider2())
         {
             let a = a.clone();
             let b = b.clone();
@@ -388,3 +591,99 @@ where
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: DriveLimits = DriveLimits {
+        top_speed: 100.,
+        turn_rate: std::f64::consts::PI,
+        acceleration: 200.,
+    };
+
+    fn assert_close(a: Vec2<f64>, b: Vec2<f64>) {
+        assert!((a - b).magnitude() < 1e-9, "expected {b:?}, found {a:?}");
+    }
+
+    #[test]
+    fn accelerates_to_top_speed() {
+        let target = Vec2::new(0., 500.);
+        let mut velocity = LIMITS.drive(Vec2::new(0., 0.), target, 0.1);
+        assert_close(velocity, Vec2::new(0., 20.));
+        for _ in 0..10 {
+            velocity = LIMITS.drive(velocity, target, 0.1);
+        }
+        assert_close(velocity, Vec2::new(0., 100.));
+    }
+
+    #[test]
+    fn turns_and_brakes() {
+        let velocity = LIMITS.drive(Vec2::new(100., 0.), Vec2::new(0., 100.), 0.1);
+        // a tenth of a half turn is 18 degrees, and there are still 72 to go, so it brakes as hard as it can
+        let angle = velocity.y.atan2(velocity.x);
+        assert!((angle - 18f64.to_radians()).abs() < 1e-9);
+        assert!((velocity.magnitude() - 80.).abs() < 1e-9);
+
+        // straight ahead, so no braking
+        assert_close(
+            LIMITS.drive(Vec2::new(50., 0.), Vec2::new(60., 0.), 0.1),
+            Vec2::new(60., 0.),
+        );
+    }
+
+    #[test]
+    fn stops() {
+        let velocity = LIMITS.drive(Vec2::new(30., 0.), Vec2::new(0., 0.), 0.1);
+        assert_close(velocity, Vec2::new(10., 0.));
+        assert_close(
+            LIMITS.drive(velocity, Vec2::new(0., 0.), 0.1),
+            Vec2::new(0., 0.),
+        );
+    }
+
+    #[test]
+    fn bigger_robots_are_slower() {
+        let drive = DriveConfig::default();
+        let small = drive.limits(10., 100.);
+        let big = drive.limits(20., 400.);
+        assert!(big.top_speed < small.top_speed);
+        assert!(big.turn_rate < small.turn_rate);
+        assert!(big.acceleration < small.acceleration);
+        assert_eq!(
+            drive.limits(drive.reference_radius, 1.).top_speed,
+            drive.top_speed
+        );
+    }
+
+    #[test]
+    fn scan_target() {
+        // positions are random, so retry until the actors start apart
+        let (mut environment, mut a, mut b) = loop {
+            let mut environment = Environment::new_standard_rectangle(Rect::new_with_origin_size(
+                Vec2::new(0., 0.),
+                Vec2::new(500., 500.),
+            ));
+            let a = environment.add_actor(10., 2., 'a').unwrap();
+            let b = environment.add_actor(10., 1., 'b').unwrap();
+            let distance = (b.position().unwrap() - a.position().unwrap()).magnitude();
+            if distance > 30. {
+                break (environment, a, b);
+            }
+        };
+        assert!((a.mass().unwrap() - 200. * std::f64::consts::PI).abs() < 1e-6);
+        // the query pipeline only knows about the actors once it has stepped
+        environment.step(0., [&mut a, &mut b], |_| Ok(())).unwrap();
+
+        let delta = b.position().unwrap() - a.position().unwrap();
+        let angle = delta.y.atan2(delta.x);
+        a.set_turret_angle(Radians(angle));
+        let (distance, target) = environment.actor_scan_target(&a).unwrap();
+        assert_eq!(target, Some('b'));
+        assert!((distance - (delta.magnitude() - 10.)).abs() < 1e-6);
+
+        a.set_turret_angle(Radians(angle + std::f64::consts::PI));
+        let (_, target) = environment.actor_scan_target(&a).unwrap();
+        assert_eq!(target, None);
+    }
+}
@@ -1,131 +1,1145 @@
-use std::{cell::RefCell, ops::RangeInclusive, rc::Rc, time::Duration};
+use std::{ops::RangeInclusive, rc::Rc, time::Duration};
 
 use color_eyre::eyre::{Result, eyre};
 use tracing::*;
 
-use crate::simulation::{
-    ecs,
-    language::Program,
-    physics,
-    vm::{StepError, VirtualMachine},
+use crate::{
+    math::{Radians, Vec2},
+    simulation::{
+        ecs,
+        items::{self, Item, ItemConfig, ItemKind, ItemSnapshot, ItemState},
+        language::{InterruptKind, Program, ProgramPointer},
+        loadout::{self, Loadout, RobotStats},
+        physics,
+        telemetry::{Telemetry, TelemetryEvent, TelemetrySubscriber},
+        vm::{ClockTime, StepError, VirtualMachine, VirtualMachineSnapshot},
+    },
 };
 
+/// What happens to a robot whose program faults, e.g. by dividing by zero or overflowing its stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultPolicy {
+    /// The robot stops running and stops moving for the rest of the match.
+    Kill,
+    /// The robot's program is paused and the robot stops moving for the given number of ticks, then the program
+    /// resumes after the faulting instruction.
+    Freeze { ticks: u32 },
+    /// The faulting instruction is skipped and the robot keeps running, including the rest of that tick's instructions.
+    Ignore,
+}
+
+impl FaultPolicy {
+    fn state_after_fault(&self) -> RobotState {
+        match self {
+            FaultPolicy::Kill => RobotState::Dead,
+            FaultPolicy::Freeze { ticks: 0 } | FaultPolicy::Ignore => RobotState::Running,
+            FaultPolicy::Freeze { ticks } => RobotState::Frozen {
+                remaining_ticks: *ticks,
+            },
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MatchConfig {
+    pub actor_size: RangeInclusive<f64>,
+    pub fault_policy: FaultPolicy,
+    /// Pickups and hazards placed in the arena when the match starts.
+    pub items: Vec<ItemConfig>,
+    pub drive: physics::DriveConfig,
+    /// Instructions each robot runs per tick. Dispatching an interrupt counts as one.
+    pub instructions_per_tick: u64,
+    /// Points each robot's loadout can spend.
+    pub loadout_budget: u32,
+    /// Used by programs that don't declare a loadout. If this is `None` too, the robot gets a random size from
+    /// `actor_size` and [`RobotStats::default`].
+    pub default_loadout: Option<Loadout>,
+    /// Spawns robots the same way every time. `None` picks random positions each match.
+    pub seed: Option<u64>,
+}
+
+impl Default for MatchConfig {
+    fn default() -> Self {
+        Self {
+            actor_size: (10.0)..=20.0,
+            fault_policy: FaultPolicy::Ignore,
+            items: Vec::new(),
+            drive: physics::DriveConfig::default(),
+            instructions_per_tick: 1,
+            loadout_budget: loadout::DEFAULT_BUDGET,
+            default_loadout: None,
+            seed: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RobotState {
+    Running,
+    Frozen { remaining_ticks: u32 },
+    Dead,
+}
+
+#[derive(Debug, Clone)]
+pub struct Fault {
+    pub error: StepError,
+    /// The address of the instruction that faulted.
+    pub program_counter: ProgramPointer,
+    pub clock: ClockTime,
+}
+
+#[derive(Debug, Clone)]
+pub struct RobotResult {
+    pub id: ecs::Id,
+    pub state: RobotState,
+    pub fault_count: u64,
+    pub last_fault: Option<Fault>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchResults {
+    pub total_time: Duration,
+    pub robots: Vec<RobotResult>,
+}
+
+/// Everything needed to draw or inspect a robot at a point in time.
+#[derive(Debug, Clone)]
+pub struct RobotSnapshot {
+    pub id: ecs::Id,
+    pub state: RobotState,
+    pub position: Vec2<f64>,
+    pub radius: f64,
+    pub turret_angle: Radians<f64>,
+    /// Distance to whatever the turret is pointing at, or `f64::MAX` if nothing.
+    pub scanner_distance: f64,
+    pub loadout: Option<Loadout>,
+    pub stats: RobotStats,
+    pub vm: VirtualMachineSnapshot,
+}
+
 struct Robot {
-    // TODO actors should have a better user data than just their index?
-    actor: Rc<RefCell<physics::Actor<ecs::Id>>>,
     vm: VirtualMachine,
+    state: RobotState,
+    fault_count: u64,
+    last_fault: Option<Fault>,
+    /// Slowing zones the robot is currently inside.
+    slowing_zones: Vec<ecs::Id>,
+    loadout: Option<Loadout>,
+    stats: RobotStats,
+    /// Ticks until the weapon can fire again.
+    weapon_cooldown: u32,
+    /// The robot the scanner saw on the robot's last tick, if any.
+    scanned: Option<ecs::Id>,
+}
+
+/// A shot that hit a robot, collected while the robots run and applied after.
+struct Shot {
+    source: ecs::Id,
+    target: ecs::Id,
+    damage: f64,
+}
+
+/// A robot starting or stopping an overlap with an item, collected during the physics step and applied after it.
+struct ItemOverlap {
+    robot: ecs::Id,
+    item: ecs::Id,
+    started: bool,
 }
 
 pub struct Simulation {
     physics_environment: physics::Environment<ecs::Id>,
+    actors: ecs::ComponentSystem<physics::Actor<ecs::Id>>,
     robots: ecs::ComponentSystem<Robot>,
+    items: ecs::ComponentSystem<Item>,
+    config: MatchConfig,
+    telemetry: Telemetry,
+    tick: u64,
     total_time: Duration,
+    ended: bool,
 }
 
 impl Simulation {
     pub fn new(
         mut physics_environment: physics::Environment<ecs::Id>,
         programs: Vec<Rc<Program>>,
-        actor_size: RangeInclusive<f64>,
+        config: MatchConfig,
     ) -> Result<Self> {
         physics_environment.clear_actors();
+        physics_environment.set_drive(config.drive);
+        if let Some(seed) = config.seed {
+            physics_environment.seed(seed);
+        }
+        let mut entities = ecs::Entities::new();
+        let mut actors = ecs::ComponentSystem::new();
         let mut robots = ecs::ComponentSystem::new();
-        for program in programs.iter() {
-            robots.insert_factory(|id| {
-                Ok(Robot {
-                    actor: physics_environment.add_random_actor(actor_size.clone(), id)?,
-                    vm: VirtualMachine::new(program.clone()),
-                })
-            })?;
+        for (i, program) in programs.iter().enumerate() {
+            let id = entities.spawn();
+            let loadout = program.loadout.or(config.default_loadout);
+            let (actor, stats) = match loadout {
+                Some(loadout) => {
+                    loadout
+                        .validate(config.loadout_budget)
+                        .map_err(|e| eyre!("robot {i}: {e}"))?;
+                    (
+                        physics_environment.add_actor(
+                            loadout.chassis.radius(),
+                            loadout.density(),
+                            id,
+                        )?,
+                        loadout.stats(),
+                    )
+                }
+                None => (
+                    physics_environment.add_random_actor(config.actor_size.clone(), id)?,
+                    RobotStats::default(),
+                ),
+            };
+            actors.insert(id, actor);
+            let mut vm = VirtualMachine::new(program.clone());
+            vm.set_health(stats.max_health);
+            vm.set_energy(stats.energy_capacity);
+            robots.insert(
+                id,
+                Robot {
+                    vm,
+                    state: RobotState::Running,
+                    fault_count: 0,
+                    last_fault: None,
+                    slowing_zones: Vec::new(),
+                    loadout,
+                    stats,
+                    weapon_cooldown: 0,
+                    scanned: None,
+                },
+            );
+        }
+        let mut items = ecs::ComponentSystem::new();
+        for (i, item) in config.items.iter().enumerate() {
+            if item.respawn_ticks == Some(0) {
+                return Err(eyre!("item {i}: respawn_ticks must be at least 1"));
+            }
+            let id = entities.spawn();
+            items.insert(
+                id,
+                Item {
+                    config: item.clone(),
+                    sensor: physics_environment.add_sensor(item.position, item.radius, id),
+                    state: ItemState::Available,
+                },
+            );
         }
         Ok(Self {
             physics_environment,
+            actors,
             robots,
+            items,
+            config,
+            telemetry: Telemetry::new(),
+            tick: 0,
             total_time: Duration::ZERO,
+            ended: false,
         })
     }
 
+    /// Adds a subscriber to the telemetry stream. It's immediately sent a spawn event for every robot already in the match.
+    pub fn subscribe(&mut self, mut subscriber: impl TelemetrySubscriber + 'static) -> Result<()> {
+        for (id, _, actor) in self.robots.join(&self.actors) {
+            let position = actor.position()?;
+            subscriber.on_event(&TelemetryEvent::RobotSpawned {
+                robot: id,
+                x: position.x,
+                y: position.y,
+                radius: actor.radius(),
+            })?;
+        }
+        self.telemetry.subscribe(Box::new(subscriber));
+        Ok(())
+    }
+
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    /// Snapshots of every robot, ordered by id.
+    pub fn robot_snapshots(&self, stack_depth: usize) -> Result<Vec<RobotSnapshot>> {
+        self.robots
+            .join(&self.actors)
+            .map(|(id, robot, actor)| {
+                Ok(RobotSnapshot {
+                    id,
+                    state: robot.state,
+                    position: actor.position()?,
+                    radius: actor.radius(),
+                    turret_angle: actor.turret_angle(),
+                    scanner_distance: within_range(
+                        self.physics_environment.actor_scan(actor),
+                        robot.stats.scanner_range,
+                    ),
+                    loadout: robot.loadout,
+                    stats: robot.stats,
+                    vm: robot.vm.snapshot(stack_depth),
+                })
+            })
+            .collect()
+    }
+
+    /// Snapshots of every item, ordered by id.
+    pub fn item_snapshots(&self) -> Vec<ItemSnapshot> {
+        self.items
+            .iter()
+            .map(|(id, item)| item.snapshot(id))
+            .collect()
+    }
+
+    /// The match is over once at most one robot is left alive.
+    pub fn is_finished(&self) -> bool {
+        self.ended
+    }
+
     pub fn physics_environment(&self) -> &physics::Environment<ecs::Id> {
         &self.physics_environment
     }
 
+    pub fn results(&self) -> MatchResults {
+        let robots = self
+            .robots
+            .iter()
+            .map(|(id, robot)| RobotResult {
+                id,
+                state: robot.state,
+                fault_count: robot.fault_count,
+                last_fault: robot.last_fault.clone(),
+            })
+            .collect::<Vec<_>>();
+        MatchResults {
+            total_time: self.total_time,
+            robots,
+        }
+    }
+
     pub fn update(&mut self, elapsed_time: Duration) -> Result<()> {
-        // update physics environment
+        if self.ended {
+            return Ok(());
+        }
+
+        self.tick += 1;
         self.total_time += elapsed_time;
-        self.physics_environment
-            .step(self.total_time.as_secs_f64(), |e| {
-                match e {
-                    physics::CollisionEvent::Started(actor1, actor2) => {
-                        match (actor1, actor2) {
-                            (physics::Collidable::Actor(a1), physics::Collidable::Actor(a2)) => {
-                                let actor1 = a1.borrow();
-                                let actor2 = a2.borrow();
-                                // TODO de-duplicate all the robot lookups
-                                let robot1 =
-                                    self.robots.get(*actor1.user_data()).ok_or_else(|| {
-                                        eyre!("Actor with id {:?} not found", actor1.user_data())
-                                    })?;
-                                let robot2 =
-                                    self.robots.get(*actor2.user_data()).ok_or_else(|| {
-                                        eyre!("Actor with id {:?} not found", actor2.user_data())
-                                    })?;
-                                info!(
-                                    "TODO collision STARTED between two robots {:?} and {:?}",
-                                    actor1.user_data(),
-                                    actor2.user_data()
-                                );
-
-                                let relative_velocity =
-                                    (actor1.velocity()? - actor2.velocity()?).magnitude();
-                                let damage_to_1 = relative_velocity * actor2.mass()?;
-                                let damage_to_2 = relative_velocity * actor1.mass()?;
-                                info!("TODO apply damage to robot 1 = {}", damage_to_1);
-                                info!("TODO apply damage to robot 2 = {}", damage_to_2);
-                            }
-                            (physics::Collidable::Actor(a), physics::Collidable::Environment)
-                            | (physics::Collidable::Environment, physics::Collidable::Actor(a)) => {
-                                let actor = a.borrow();
-                                let robot =
-                                    self.robots.get(*actor.user_data()).ok_or_else(|| {
-                                        eyre!("Actor with id {:?} not found", actor.user_data())
-                                    })?;
-                                info!(
-                                    "TODO collision STARTED between robot {:?} and environment",
-                                    actor.user_data()
-                                );
-
-                                let relative_velocity = actor.velocity()?.magnitude();
-                                // TODO what should the assumed mass be?
-                                let assumed_mass = 100.;
-                                let damage = relative_velocity * assumed_mass;
-                                info!("TODO apply damage to robot from environment = {}", damage);
-                            }
-                            // no robots involved, impossible?
-                            _ => (),
+        self.telemetry.emit(TelemetryEvent::Tick {
+            tick: self.tick,
+            total_time: self.total_time.as_secs_f64(),
+        });
+
+        for (_, item) in self.items.iter_mut() {
+            if item.tick() {
+                self.physics_environment
+                    .set_sensor_enabled(item.sensor, true)?;
+            }
+        }
+
+        // update physics environment
+        let mut collisions = Vec::new();
+        self.physics_environment.step(
+            self.total_time.as_secs_f64(),
+            self.actors.iter_mut().map(|(_, actor)| actor),
+            |e| {
+                collisions.push(e);
+                Ok(())
+            },
+        )?;
+        let mut item_overlaps = Vec::new();
+        for collision in collisions {
+            match collision {
+                physics::CollisionEvent::Started(a, b) => match (a, b) {
+                    (physics::Collidable::Actor(id1), physics::Collidable::Actor(id2)) => {
+                        for id in [id1, id2] {
+                            raise_interrupts(&mut self.robots, id, &[InterruptKind::Collision])?;
                         }
+                        info!(
+                            "TODO collision STARTED between two robots {:?} and {:?}",
+                            id1, id2
+                        );
+
+                        let actor1 = self.actor(id1)?;
+                        let actor2 = self.actor(id2)?;
+                        let relative_velocity =
+                            (actor1.velocity()? - actor2.velocity()?).magnitude();
+                        let damage_to_1 = relative_velocity * actor2.mass()?;
+                        let damage_to_2 = relative_velocity * actor1.mass()?;
+                        info!("TODO apply damage to robot 1 = {}", damage_to_1);
+                        info!("TODO apply damage to robot 2 = {}", damage_to_2);
                     }
-                    // TODO handle collision ends?
-                    physics::CollisionEvent::Stopped(actor1, actor2) => (),
-                };
-                Ok(())
-            });
+                    (physics::Collidable::Actor(id), physics::Collidable::Environment)
+                    | (physics::Collidable::Environment, physics::Collidable::Actor(id)) => {
+                        raise_interrupts(&mut self.robots, id, &[InterruptKind::Collision])?;
+                        info!(
+                            "TODO collision STARTED between robot {:?} and environment",
+                            id
+                        );
+
+                        let relative_velocity = self.actor(id)?.velocity()?.magnitude();
+                        // TODO what should the assumed mass be?
+                        let assumed_mass = 100.;
+                        let damage = relative_velocity * assumed_mass;
+                        info!("TODO apply damage to robot from environment = {}", damage);
+                    }
+                    (physics::Collidable::Actor(robot), physics::Collidable::Sensor(item))
+                    | (physics::Collidable::Sensor(item), physics::Collidable::Actor(robot)) => {
+                        item_overlaps.push(ItemOverlap {
+                            robot,
+                            item,
+                            started: true,
+                        });
+                    }
+                    // no robots involved, impossible?
+                    _ => (),
+                },
+                physics::CollisionEvent::Stopped(
+                    physics::Collidable::Actor(robot),
+                    physics::Collidable::Sensor(item),
+                )
+                | physics::CollisionEvent::Stopped(
+                    physics::Collidable::Sensor(item),
+                    physics::Collidable::Actor(robot),
+                ) => {
+                    item_overlaps.push(ItemOverlap {
+                        robot,
+                        item,
+                        started: false,
+                    });
+                }
+                // TODO handle collision ends?
+                physics::CollisionEvent::Stopped(_, _) => (),
+            }
+        }
+        for overlap in item_overlaps {
+            self.apply_item_overlap(overlap)?;
+        }
 
         // TODO need to update robots only until they match current time
-        for (_, robot) in self.robots.iter_mut() {
-            let mut actor = robot.actor.borrow_mut();
-            robot.vm.update_to_match_actor(&actor)?;
-            match robot.vm.step(&self.physics_environment, &actor) {
-                Ok(new_clock) => {
-                    // TODO what to do with new_clock?
+        let mut shots = Vec::new();
+        for (id, robot) in self.robots.iter_mut() {
+            let Some(actor) = self.actors.get_mut(id) else {
+                continue;
+            };
+            robot.weapon_cooldown = robot.weapon_cooldown.saturating_sub(1);
+            match robot.state {
+                RobotState::Running => (),
+                RobotState::Frozen { remaining_ticks } => {
+                    robot.state = if remaining_ticks > 1 {
+                        RobotState::Frozen {
+                            remaining_ticks: remaining_ticks - 1,
+                        }
+                    } else {
+                        RobotState::Running
+                    };
+                    continue;
+                }
+                RobotState::Dead => continue,
+            }
+            // scan_found is raised when a robot comes into view, not for as long as it stays there
+            let scanned = match self.physics_environment.actor_scan_target(actor) {
+                Some((distance, Some(target))) if distance <= robot.stats.scanner_range => {
+                    Some(target)
                 }
-                Err(StepError::Halted) => {
+                _ => None,
+            };
+            if scanned.is_some() && scanned != robot.scanned {
+                robot.vm.raise_interrupt(InterruptKind::ScanFound);
+            }
+            robot.scanned = scanned;
+            robot.vm.update_to_match_actor(actor)?;
+            robot
+                .vm
+                .set_nearest_pickup(items::nearest_pickup(&self.items, actor.position()?));
+            let scanner_range = robot.stats.scanner_range;
+            let physics_environment = &self.physics_environment;
+            let mut budget = self.config.instructions_per_tick;
+            while let Err(error) = robot.vm.run_within(&mut budget, &|| {
+                within_range(physics_environment.actor_scan(actor), scanner_range)
+            }) {
+                if error == StepError::Halted {
                     // TODO what to do about halted robot?
+                    break;
+                }
+                let program_counter = robot.vm.step_address();
+                warn!(
+                    "robot {:?} faulted at {:?}: {:?}",
+                    id, program_counter, error
+                );
+                robot.fault_count += 1;
+                robot.last_fault = Some(Fault {
+                    error: error.clone(),
+                    program_counter,
+                    clock: robot.vm.clock(),
+                });
+                robot.state = self.config.fault_policy.state_after_fault();
+                if robot.state == RobotState::Dead {
+                    self.telemetry.emit(TelemetryEvent::RobotDied {
+                        robot: id,
+                        reason: format!("fault at {program_counter:?}: {error:?}"),
+                    });
+                }
+                // a robot that's still running carries on with the rest of its instructions for the tick
+                if robot.state != RobotState::Running {
+                    break;
+                }
+            }
+            self.telemetry.emit(TelemetryEvent::InstructionCount {
+                robot: id,
+                count: robot.vm.instruction_count(),
+            });
+            if robot.vm.take_fire_request()
+                && robot.state != RobotState::Dead
+                && let Some(weapon) = robot.stats.weapon
+                && robot.weapon_cooldown == 0
+                && robot.vm.energy() >= weapon.energy_cost
+            {
+                robot.vm.set_energy(robot.vm.energy() - weapon.energy_cost);
+                robot.weapon_cooldown = weapon.cooldown_ticks;
+                let position = actor.position()?;
+                self.telemetry.emit(TelemetryEvent::ProjectileFired {
+                    robot: id,
+                    x: position.x,
+                    y: position.y,
+                    angle: actor.turret_angle().0,
+                });
+                if let Some((distance, Some(target))) =
+                    self.physics_environment.actor_scan_target(actor)
+                    && distance <= weapon.range
+                {
+                    shots.push(Shot {
+                        source: id,
+                        target,
+                        damage: weapon.damage,
+                    });
+                }
+            }
+            if robot.state != RobotState::Running {
+                actor.set_target_velocity(Vec2::new(0., 0.));
+            } else {
+                robot.vm.update_actor_match_vm(actor)?;
+                if let Some(max_speed) = max_speed_in_zones(&self.items, &robot.slowing_zones) {
+                    let target = actor.target_velocity();
+                    let speed = target.magnitude();
+                    if speed > max_speed {
+                        actor.set_target_velocity(target * (max_speed / speed));
+                    }
                 }
-                _ => (),
             }
-            robot.vm.update_actor_match_vm(&mut actor)?;
         }
 
+        for shot in shots {
+            self.damage_robot(shot.target, Some(shot.source), shot.damage, "a shot")?;
+        }
+
+        let alive = self
+            .robots
+            .iter()
+            .filter(|(_, robot)| robot.state != RobotState::Dead)
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        let robot_count = self.robots.len();
+        if alive.is_empty() || (alive.len() == 1 && robot_count > 1) {
+            self.ended = true;
+            self.telemetry.emit(TelemetryEvent::MatchEnded {
+                tick: self.tick,
+                total_time: self.total_time.as_secs_f64(),
+                winner: alive.first().copied(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn actor(&self, id: ecs::Id) -> Result<&physics::Actor<ecs::Id>> {
+        self.actors
+            .get(id)
+            .ok_or_else(|| eyre!("Actor with id {:?} not found", id))
+    }
+
+    fn apply_item_overlap(&mut self, overlap: ItemOverlap) -> Result<()> {
+        let item = self
+            .items
+            .get_mut(overlap.item)
+            .ok_or_else(|| eyre!("Item with id {:?} not found", overlap.item))?;
+        let robot = self
+            .robots
+            .get_mut(overlap.robot)
+            .ok_or_else(|| eyre!("Actor with id {:?} not found", overlap.robot))?;
+        if !overlap.started {
+            robot.slowing_zones.retain(|zone| *zone != overlap.item);
+            return Ok(());
+        }
+        // two robots can reach the same pickup in the same step, only the first gets it
+        if item.state != ItemState::Available || robot.state == RobotState::Dead {
+            return Ok(());
+        }
+
+        let mut mine_damage = None;
+        match item.config.kind {
+            ItemKind::EnergyPickup { amount } => robot
+                .vm
+                .set_energy((robot.vm.energy() + amount).min(robot.stats.energy_capacity)),
+            ItemKind::HealthPickup { amount } => robot
+                .vm
+                .set_health((robot.vm.health() + amount).min(robot.stats.max_health)),
+            ItemKind::Mine { damage } => mine_damage = Some(damage),
+            ItemKind::SlowingZone { .. } => robot.slowing_zones.push(overlap.item),
+        }
+        if item.config.kind.is_consumed() {
+            self.telemetry.emit(TelemetryEvent::ItemCollected {
+                robot: overlap.robot,
+                item: overlap.item,
+            });
+        }
+        if item.consume() {
+            self.physics_environment
+                .set_sensor_enabled(item.sensor, false)?;
+        }
+        if let Some(damage) = mine_damage {
+            self.damage_robot(overlap.robot, None, damage, "a mine")?;
+        }
         Ok(())
     }
+
+    /// Hurts a robot, less whatever its armor soaks up, and raises its hit interrupt. Kills it if its health runs out.
+    fn damage_robot(
+        &mut self,
+        id: ecs::Id,
+        source: Option<ecs::Id>,
+        damage: f64,
+        cause: &str,
+    ) -> Result<()> {
+        let robot = self
+            .robots
+            .get_mut(id)
+            .ok_or_else(|| eyre!("Actor with id {:?} not found", id))?;
+        if robot.state == RobotState::Dead {
+            return Ok(());
+        }
+        let amount = damage * robot.stats.damage_taken;
+        robot.vm.set_health(robot.vm.health() - amount);
+        robot.vm.raise_interrupt(InterruptKind::Hit);
+        self.telemetry.emit(TelemetryEvent::DamageDealt {
+            source,
+            target: id,
+            amount,
+        });
+        if robot.vm.health() <= 0. {
+            robot.state = RobotState::Dead;
+            self.telemetry.emit(TelemetryEvent::RobotDied {
+                robot: id,
+                reason: format!("destroyed by {cause}"),
+            });
+            if let Some(actor) = self.actors.get_mut(id) {
+                actor.set_target_velocity(Vec2::new(0., 0.));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Scanner readings beyond the scanner's range read as nothing found.
+fn within_range(distance: f64, range: f64) -> f64 {
+    if distance > range { f64::MAX } else { distance }
+}
+
+/// The tightest speed limit of all the zones a robot is in, if it's in any.
+fn max_speed_in_zones(items: &ecs::ComponentSystem<Item>, zones: &[ecs::Id]) -> Option<f64> {
+    zones
+        .iter()
+        .filter_map(|id| match items.get(*id)?.config.kind {
+            ItemKind::SlowingZone { max_speed } => Some(max_speed),
+            _ => None,
+        })
+        .reduce(f64::min)
+}
+
+fn raise_interrupts(
+    robots: &mut ecs::ComponentSystem<Robot>,
+    id: ecs::Id,
+    kinds: &[InterruptKind],
+) -> Result<()> {
+    let robot = robots
+        .get_mut(id)
+        .ok_or_else(|| eyre!("Actor with id {:?} not found", id))?;
+    for kind in kinds {
+        robot.vm.raise_interrupt(*kind);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{assembler, math::Rect, simulation::telemetry::MemoryCollector};
+
+    fn new_simulation(sources: &[&str], config: MatchConfig) -> Result<Simulation> {
+        let programs = sources
+            .iter()
+            .map(|source| {
+                Ok(Rc::new(
+                    assembler::parse(source)
+                        .map_err(|e| eyre!(e))?
+                        .runnable_program,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let environment = physics::Environment::new_standard_rectangle(Rect::new_with_origin_size(
+            Vec2::new(0., 0.),
+            Vec2::new(400., 400.),
+        ));
+        Simulation::new(environment, programs, config)
+    }
+
+    fn robot_ids(simulation: &Simulation) -> Vec<ecs::Id> {
+        simulation.robots.iter().map(|(id, _)| id).collect()
+    }
+
+    /// Points `shooter`'s turret straight at `target`, or straight away from it if `away` is set.
+    fn aim(simulation: &mut Simulation, shooter: ecs::Id, target: ecs::Id, away: bool) {
+        let target = simulation.actors.get(target).unwrap().position().unwrap();
+        let shooter = simulation.actors.get_mut(shooter).unwrap();
+        let delta = target - shooter.position().unwrap();
+        let angle = delta.y.atan2(delta.x);
+        shooter.set_turret_angle(Radians(if away {
+            angle + std::f64::consts::PI
+        } else {
+            angle
+        }));
+    }
+
+    fn tick(simulation: &mut Simulation) {
+        simulation
+            .update(Duration::from_secs_f64(1. / 60.))
+            .unwrap();
+    }
+
+    #[test]
+    fn loadouts() {
+        let config = MatchConfig {
+            default_loadout: Some(Loadout {
+                battery: 2,
+                ..Loadout::default()
+            }),
+            ..MatchConfig::default()
+        };
+        let simulation =
+            new_simulation(&[".chassis heavy\n.armor 2\niret", "iret"], config).unwrap();
+        let robots = simulation.robot_snapshots(0).unwrap();
+        assert_eq!(robots[0].radius, 20.);
+        assert_eq!(robots[0].vm.health, 150.);
+        assert_eq!(robots[0].vm.energy, 100.);
+        assert_eq!(robots[1].radius, 15.);
+        assert_eq!(robots[1].vm.energy, 200.);
+
+        assert!(
+            new_simulation(
+                &[".chassis heavy\n.armor 3\n.weapon cannon\niret"],
+                MatchConfig::default()
+            )
+            .is_err()
+        );
+        assert!(
+            new_simulation(
+                &[".armor 3\n.weapon cannon\niret"],
+                MatchConfig {
+                    loadout_budget: 6,
+                    ..MatchConfig::default()
+                }
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn shooting() {
+        let mut simulation = new_simulation(
+            &[
+                ".weapon cannon\nloop:\nset fire, 1.0\njmp loop",
+                ".chassis light\n.armor 1\nloop:\njmp loop",
+            ],
+            MatchConfig::default(),
+        )
+        .unwrap();
+        let ids = robot_ids(&simulation);
+        let (shooter, target) = (ids[0], ids[1]);
+
+        aim(&mut simulation, shooter, target, false);
+        tick(&mut simulation);
+        let robots = simulation.robot_snapshots(0).unwrap();
+        // a light chassis with one level of armor takes 20 of the cannon's 25
+        assert_eq!(robots[1].vm.health, 50.);
+        assert_eq!(robots[0].vm.energy, 85.);
+
+        // the cannon needs to cool down before it can fire again
+        for _ in 0..30 {
+            aim(&mut simulation, shooter, target, false);
+            tick(&mut simulation);
+        }
+        assert_eq!(simulation.robot_snapshots(0).unwrap()[1].vm.health, 50.);
+
+        for _ in 0..300 {
+            if simulation.is_finished() {
+                break;
+            }
+            aim(&mut simulation, shooter, target, false);
+            tick(&mut simulation);
+        }
+        assert!(simulation.is_finished());
+        let robots = simulation.robot_snapshots(0).unwrap();
+        assert_eq!(robots[0].state, RobotState::Running);
+        assert_eq!(robots[1].state, RobotState::Dead);
+    }
+
+    #[test]
+    fn scan_found() {
+        let mut simulation = new_simulation(
+            &[
+                ".on scan_found found\nloop:\njmp loop\nfound:\nadd r0, r0, 1\niret",
+                "loop:\njmp loop",
+            ],
+            MatchConfig::default(),
+        )
+        .unwrap();
+        let ids = robot_ids(&simulation);
+        let found = |simulation: &Simulation| {
+            simulation.robot_snapshots(0).unwrap()[0]
+                .vm
+                .general_purpose_u64[0]
+        };
+
+        // a wall isn't a robot
+        for _ in 0..10 {
+            aim(&mut simulation, ids[0], ids[1], true);
+            tick(&mut simulation);
+        }
+        assert_eq!(found(&simulation), 0);
+
+        // raised once when the robot comes into view, not again while it stays there
+        for _ in 0..10 {
+            aim(&mut simulation, ids[0], ids[1], false);
+            tick(&mut simulation);
+        }
+        assert_eq!(found(&simulation), 1);
+
+        aim(&mut simulation, ids[0], ids[1], true);
+        tick(&mut simulation);
+        for _ in 0..10 {
+            aim(&mut simulation, ids[0], ids[1], false);
+            tick(&mut simulation);
+        }
+        assert_eq!(found(&simulation), 2);
+    }
+
+    #[test]
+    fn telemetry() {
+        let mut simulation = new_simulation(
+            &[
+                ".weapon cannon\nloop:\nset fire, 1.0\njmp loop",
+                ".chassis light\n.armor 1\n.on collision bumped\nset velocity_x, 500.0\nloop:\njmp loop\nbumped:\nadd r0, r0, 1\niret",
+            ],
+            MatchConfig {
+                seed: Some(1),
+                ..MatchConfig::default()
+            },
+        )
+        .unwrap();
+        let ids = robot_ids(&simulation);
+        let (shooter, target) = (ids[0], ids[1]);
+        let collector = MemoryCollector::new();
+        simulation.subscribe(collector.clone()).unwrap();
+        let mut bumps = 0;
+        while !simulation.is_finished() && simulation.tick() < 600 {
+            aim(&mut simulation, shooter, target, false);
+            tick(&mut simulation);
+            bumps = bumps.max(
+                simulation.robot_snapshots(0).unwrap()[1]
+                    .vm
+                    .general_purpose_u64[0],
+            );
+        }
+        assert!(bumps > 0, "the target never hit a wall");
+
+        let events = collector.events();
+        assert!(matches!(
+            events[..3],
+            [
+                TelemetryEvent::RobotSpawned { robot: a, .. },
+                TelemetryEvent::RobotSpawned { robot: b, .. },
+                TelemetryEvent::Tick { tick: 1, .. },
+            ] if a == shooter && b == target
+        ));
+        let ticks = simulation.tick();
+        let count =
+            |matches: fn(&TelemetryEvent) -> bool| events.iter().filter(|e| matches(e)).count();
+        assert_eq!(
+            count(|e| matches!(e, TelemetryEvent::Tick { .. })),
+            ticks as usize
+        );
+        assert_eq!(
+            count(|e| matches!(e, TelemetryEvent::InstructionCount { .. })),
+            2 * ticks as usize
+        );
+
+        // only shots do damage, the wall and robots bumping into each other don't
+        let shots = count(|e| matches!(e, TelemetryEvent::ProjectileFired { .. }));
+        let damage = events
+            .iter()
+            .filter_map(|e| match e {
+                TelemetryEvent::DamageDealt {
+                    source,
+                    target,
+                    amount,
+                } => Some((*source, *target, *amount)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(damage.len(), shots);
+        assert!(
+            damage
+                .iter()
+                .all(|hit| *hit == (Some(shooter), target, 20.))
+        );
+
+        assert_eq!(
+            events[events.len() - 2..],
+            [
+                TelemetryEvent::RobotDied {
+                    robot: target,
+                    reason: "destroyed by a shot".to_string(),
+                },
+                TelemetryEvent::MatchEnded {
+                    tick: ticks,
+                    total_time: simulation.total_time.as_secs_f64(),
+                    winner: Some(shooter),
+                },
+            ]
+        );
+    }
+
+    /// Ticks until `done`, failing the test if it takes more than ten seconds.
+    fn run_until(simulation: &mut Simulation, done: impl Fn(&Simulation) -> bool) {
+        while !done(simulation) {
+            assert!(
+                simulation.tick() < 600,
+                "gave up at tick {}",
+                simulation.tick()
+            );
+            tick(simulation);
+        }
+    }
+
+    fn item_state(simulation: &Simulation, index: usize) -> ItemState {
+        simulation.item_snapshots()[index].state
+    }
+
+    #[test]
+    fn items() {
+        let config = MatchConfig {
+            seed: Some(1),
+            ..MatchConfig::default()
+        };
+        let source = |speed: f64| {
+            format!(".weapon cannon\nset fire, 1.0\nset velocity_x, {speed:.1}\nloop:\njmp loop")
+        };
+        let start = new_simulation(&[&source(0.)], config.clone())
+            .unwrap()
+            .robot_snapshots(0)
+            .unwrap()[0]
+            .position;
+        // head for whichever side wall is further away, which leaves at least 185 units to cross
+        let direction = if start.x < 200. { 1. } else { -1. };
+        let at = |distance: f64| start + Vec2::new(direction * distance, 0.);
+        let item = |kind, distance, radius| ItemConfig {
+            kind,
+            position: at(distance),
+            radius,
+            respawn_ticks: None,
+        };
+        let mut simulation = new_simulation(
+            &[&source(direction * 100.)],
+            MatchConfig {
+                items: vec![
+                    item(ItemKind::Mine { damage: 30. }, 30., 5.),
+                    item(ItemKind::HealthPickup { amount: 50. }, 55., 5.),
+                    item(ItemKind::EnergyPickup { amount: 50. }, 80., 5.),
+                    item(ItemKind::SlowingZone { max_speed: 20. }, 130., 40.),
+                    item(ItemKind::Mine { damage: 1000. }, 150., 5.),
+                ],
+                ..config
+            },
+        )
+        .unwrap();
+        let id = robot_ids(&simulation)[0];
+        let robot = |simulation: &Simulation| simulation.robot_snapshots(0).unwrap()[0].clone();
+        let stats = robot(&simulation).stats;
+
+        run_until(&mut simulation, |s| item_state(s, 0) == ItemState::Gone);
+        assert_eq!(robot(&simulation).vm.health, stats.max_health - 30.);
+        assert_eq!(
+            robot(&simulation).vm.energy,
+            stats.energy_capacity - stats.weapon.unwrap().energy_cost
+        );
+
+        run_until(&mut simulation, |s| item_state(s, 1) == ItemState::Gone);
+        assert_eq!(robot(&simulation).vm.health, stats.max_health);
+        run_until(&mut simulation, |s| item_state(s, 2) == ItemState::Gone);
+        assert_eq!(robot(&simulation).vm.energy, stats.energy_capacity);
+
+        // the zone isn't used up, so wait until the robot is well inside it
+        let speed = |simulation: &Simulation| {
+            simulation
+                .actor(id)
+                .unwrap()
+                .velocity()
+                .unwrap()
+                .magnitude()
+        };
+        assert!(speed(&simulation) > 90.);
+        run_until(&mut simulation, |s| {
+            (robot(s).position - at(130.)).magnitude() < 20.
+        });
+        assert!(speed(&simulation) <= 20. + 1e-9);
+        assert_eq!(item_state(&simulation, 3), ItemState::Available);
+
+        run_until(&mut simulation, |s| robot(s).state == RobotState::Dead);
+        assert_eq!(item_state(&simulation, 4), ItemState::Gone);
+        assert!(simulation.is_finished());
+    }
+
+    #[test]
+    fn items_respawn() {
+        let config = MatchConfig {
+            seed: Some(1),
+            ..MatchConfig::default()
+        };
+        let start = new_simulation(&["loop:\njmp loop"], config.clone())
+            .unwrap()
+            .robot_snapshots(0)
+            .unwrap()[0]
+            .position;
+        let mut simulation = new_simulation(
+            &["loop:\njmp loop"],
+            MatchConfig {
+                items: vec![ItemConfig {
+                    kind: ItemKind::HealthPickup { amount: 10. },
+                    position: start,
+                    radius: 5.,
+                    respawn_ticks: Some(10),
+                }],
+                ..config
+            },
+        )
+        .unwrap();
+        let collector = MemoryCollector::new();
+        simulation.subscribe(collector.clone()).unwrap();
+
+        // the robot never moves, so each pickup only comes from the sensor being turned back on
+        let mut collected = Vec::new();
+        for _ in 0..25 {
+            tick(&mut simulation);
+            if let ItemState::Respawning { remaining_ticks } = item_state(&simulation, 0) {
+                assert!(remaining_ticks <= 10);
+            }
+            let count = collector
+                .events()
+                .iter()
+                .filter(|e| matches!(e, TelemetryEvent::ItemCollected { .. }))
+                .count();
+            if count > collected.len() {
+                collected.push(simulation.tick());
+            }
+        }
+        assert_eq!(collected, [1, 11, 21]);
+
+        // an item that came straight back would be picked up again every time a robot touched it
+        let config = MatchConfig {
+            items: vec![ItemConfig {
+                respawn_ticks: Some(0),
+                ..simulation.items.iter().next().unwrap().1.config.clone()
+            }],
+            ..MatchConfig::default()
+        };
+        assert!(new_simulation(&["loop:\njmp loop"], config).is_err());
+    }
+
+    /// A robot that drives off, then divides by zero every time round its loop, counting in `r1` how often it gets
+    /// past that. It runs three instructions a tick, so once a tick if faults don't get in the way.
+    fn faulting(fault_policy: FaultPolicy) -> Simulation {
+        new_simulation(
+            &["set velocity_x, 100.0\nloop:\ndiv r0, r0, 0\nadd r1, r1, 1\njmp loop"],
+            MatchConfig {
+                fault_policy,
+                instructions_per_tick: 3,
+                ..MatchConfig::default()
+            },
+        )
+        .unwrap()
+    }
+
+    fn assert_faulted(simulation: &Simulation, state: RobotState, fault_count: u64) {
+        let result = &simulation.results().robots[0];
+        assert_eq!(result.state, state);
+        assert_eq!(result.fault_count, fault_count);
+        let fault = result.last_fault.as_ref().unwrap();
+        assert_eq!(fault.error, StepError::DivideByZero);
+        assert_eq!(fault.program_counter, ProgramPointer(1));
+    }
+
+    fn passes(simulation: &Simulation) -> u64 {
+        simulation.robot_snapshots(0).unwrap()[0]
+            .vm
+            .general_purpose_u64[1]
+    }
+
+    fn target_speed(simulation: &Simulation) -> f64 {
+        let id = robot_ids(simulation)[0];
+        simulation.actor(id).unwrap().target_velocity().magnitude()
+    }
+
+    #[test]
+    fn fault_policy_kill() {
+        let mut simulation = faulting(FaultPolicy::Kill);
+        tick(&mut simulation);
+        assert_faulted(&simulation, RobotState::Dead, 1);
+        assert!(simulation.is_finished());
+        tick(&mut simulation);
+        assert_faulted(&simulation, RobotState::Dead, 1);
+        assert_eq!(passes(&simulation), 0);
+        assert_eq!(target_speed(&simulation), 0.);
+    }
+
+    #[test]
+    fn fault_policy_freeze() {
+        let mut simulation = faulting(FaultPolicy::Freeze { ticks: 3 });
+        tick(&mut simulation);
+        assert_faulted(&simulation, RobotState::Frozen { remaining_ticks: 3 }, 1);
+        assert_eq!(target_speed(&simulation), 0.);
+        tick(&mut simulation);
+        tick(&mut simulation);
+        assert_faulted(&simulation, RobotState::Frozen { remaining_ticks: 1 }, 1);
+        assert_eq!(target_speed(&simulation), 0.);
+        tick(&mut simulation);
+        assert_faulted(&simulation, RobotState::Running, 1);
+        assert_eq!(passes(&simulation), 0);
+
+        // it picks up after the faulting instruction, and faults again on the way round
+        tick(&mut simulation);
+        assert_faulted(&simulation, RobotState::Frozen { remaining_ticks: 3 }, 2);
+        assert_eq!(passes(&simulation), 1);
+    }
+
+    #[test]
+    fn fault_policy_ignore() {
+        let mut simulation = faulting(FaultPolicy::Ignore);
+        for _ in 0..5 {
+            tick(&mut simulation);
+        }
+        // the rest of the tick's instructions still run after a fault, so it gets round the loop once a tick
+        assert_faulted(&simulation, RobotState::Running, 5);
+        assert_eq!(passes(&simulation), 5);
+        assert_eq!(target_speed(&simulation), 100.);
+        assert!(!simulation.is_finished());
+    }
 }
@@ -0,0 +1,182 @@
+use std::{
+    cell::RefCell,
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use color_eyre::eyre::Result;
+use serde::Serialize;
+use tracing::*;
+
+use crate::simulation::ecs;
+
+/// Something that happened during a match, in the order it happened.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TelemetryEvent {
+    Tick {
+        tick: u64,
+        total_time: f64,
+    },
+    RobotSpawned {
+        robot: ecs::Id,
+        x: f64,
+        y: f64,
+        radius: f64,
+    },
+    /// Total number of instructions a robot has executed so far.
+    InstructionCount {
+        robot: ecs::Id,
+        count: u64,
+    },
+    /// `source` is the robot that caused the damage, or `None` for the arena itself.
+    DamageDealt {
+        source: Option<ecs::Id>,
+        target: ecs::Id,
+        amount: f64,
+    },
+    ProjectileFired {
+        robot: ecs::Id,
+        x: f64,
+        y: f64,
+        angle: f64,
+    },
+    /// A robot used up a pickup or set off a mine.
+    ItemCollected {
+        robot: ecs::Id,
+        item: ecs::Id,
+    },
+    RobotDied {
+        robot: ecs::Id,
+        reason: String,
+    },
+    MatchEnded {
+        tick: u64,
+        total_time: f64,
+        winner: Option<ecs::Id>,
+    },
+}
+
+pub trait TelemetrySubscriber {
+    fn on_event(&mut self, event: &TelemetryEvent) -> Result<()>;
+}
+
+/// Fans events out to all subscribers. A subscriber that fails is logged and doesn't stop the others or the match.
+#[derive(Default)]
+pub struct Telemetry {
+    subscribers: Vec<Box<dyn TelemetrySubscriber>>,
+}
+
+impl Telemetry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn subscribe(&mut self, subscriber: Box<dyn TelemetrySubscriber>) {
+        self.subscribers.push(subscriber);
+    }
+
+    pub fn emit(&mut self, event: TelemetryEvent) {
+        for subscriber in self.subscribers.iter_mut() {
+            if let Err(e) = subscriber.on_event(&event) {
+                error!("telemetry subscriber failed to handle {event:?}: {e:?}");
+            }
+        }
+    }
+}
+
+/// Writes each event as a single line of JSON.
+pub struct JsonLinesSink<W: Write> {
+    writer: W,
+}
+
+impl JsonLinesSink<BufWriter<File>> {
+    pub fn create(path: impl AsRef<Path>) -> Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> TelemetrySubscriber for JsonLinesSink<W> {
+    fn on_event(&mut self, event: &TelemetryEvent) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, event)?;
+        self.writer.write_all(b"\n")?;
+        if let TelemetryEvent::MatchEnded { .. } = event {
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// Keeps every event in memory. Clones share the same list, so keep one to inspect after handing the other to a simulation.
+#[derive(Clone, Default)]
+pub struct MemoryCollector {
+    events: Rc<RefCell<Vec<TelemetryEvent>>>,
+}
+
+impl MemoryCollector {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn events(&self) -> Vec<TelemetryEvent> {
+        self.events.borrow().clone()
+    }
+}
+
+impl TelemetrySubscriber for MemoryCollector {
+    fn on_event(&mut self, event: &TelemetryEvent) -> Result<()> {
+        self.events.borrow_mut().push(event.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn json_lines() {
+        let mut sink = JsonLinesSink::new(Vec::new());
+        sink.on_event(&TelemetryEvent::Tick {
+            tick: 1,
+            total_time: 0.5,
+        })
+        .unwrap();
+        sink.on_event(&TelemetryEvent::DamageDealt {
+            source: None,
+            target: ecs::Id::from_bits(3),
+            amount: 12.5,
+        })
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(sink.writer).unwrap(),
+            "{\"type\":\"tick\",\"tick\":1,\"total_time\":0.5}\n{\"type\":\"damage_dealt\",\"source\":null,\"target\":{\"index\":3,\"generation\":0},\"amount\":12.5}\n"
+        );
+    }
+
+    #[test]
+    fn memory_collector_shares_events() {
+        let collector = MemoryCollector::new();
+        let mut telemetry = Telemetry::new();
+        telemetry.subscribe(Box::new(collector.clone()));
+        telemetry.emit(TelemetryEvent::RobotDied {
+            robot: ecs::Id::from_bits(0),
+            reason: "fault".to_string(),
+        });
+        assert_eq!(
+            collector.events(),
+            vec![TelemetryEvent::RobotDied {
+                robot: ecs::Id::from_bits(0),
+                reason: "fault".to_string(),
+            }]
+        );
+    }
+}
@@ -9,11 +9,15 @@ use color_eyre::eyre::Result;
 
 use crate::{
     math::*,
-    simulation::{language::*, physics},
+    simulation::{
+        decoded::{DecodedInstruction, DecodedProgram, Operation, f64_index, u64_index},
+        language::*,
+        physics,
+    },
 };
 
-#[derive(Debug, Clone, Copy)]
-pub struct ClockTime(u64);
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ClockTime(pub u64);
 
 impl Add for ClockTime {
     type Output = Self;
@@ -29,74 +33,229 @@ impl AddAssign for ClockTime {
     }
 }
 
-struct ResolvedValue<T> {
-    value: T,
-    clock_cost: ClockTime,
-}
-
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum StepError {
     Halted,
     TryFromIntError(TryFromIntError),
     StackUnderflow,
+    StackOverflow,
     AddressOutOfBounds,
+    DivideByZero,
+    /// `iret` outside an interrupt handler, where there's no return address on the stack.
+    ReturnOutsideInterrupt,
 }
 
-#[derive(Debug, Clone, Copy)]
-enum StackOrHeapValue {
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StackOrHeapValue {
     U64(u64),
     F64(f64),
 }
 
+/// A copy of the state of a virtual machine, for display and debugging.
+#[derive(Debug, Clone)]
+pub struct VirtualMachineSnapshot {
+    pub program_counter: ProgramPointer,
+    pub clock: ClockTime,
+    pub instruction_count: u64,
+    pub health: f64,
+    pub energy: f64,
+    pub general_purpose_u64: [u64; 8],
+    pub general_purpose_f64: [f64; 8],
+    /// The top of the stack, most recently pushed first.
+    pub stack_top: Vec<StackOrHeapValue>,
+}
+
+/// Where reads of the `scanner_distance` register come from.
+pub trait Scanner {
+    /// Distance to whatever the turret is pointing at, or `f64::MAX` if nothing.
+    fn scan(&self) -> f64;
+}
+
+impl<F> Scanner for F
+where
+    F: Fn() -> f64,
+{
+    fn scan(&self) -> f64 {
+        self()
+    }
+}
+
+const POSITION_X: usize = ReadableRegisterF64::PositionX as usize;
+const POSITION_Y: usize = ReadableRegisterF64::PositionY as usize;
+const VELOCITY_X: usize = ReadableRegisterF64::VelocityX as usize;
+const VELOCITY_Y: usize = ReadableRegisterF64::VelocityY as usize;
+const ACTUAL_VELOCITY_X: usize = ReadableRegisterF64::ActualVelocityX as usize;
+const ACTUAL_VELOCITY_Y: usize = ReadableRegisterF64::ActualVelocityY as usize;
+const TURRET_ANGLE: usize = ReadableRegisterF64::TurretAngle as usize;
+const TURRET_ANGULAR_VELOCITY: usize = ReadableRegisterF64::TurretAngularVelocity as usize;
+const SCANNER_DISTANCE: usize = ReadableRegisterF64::ScannerDistance as usize;
+const HEALTH: usize = ReadableRegisterF64::Health as usize;
+const ENERGY: usize = ReadableRegisterF64::Energy as usize;
+const PICKUP_DISTANCE: usize = ReadableRegisterF64::PickupDistance as usize;
+const PICKUP_BEARING: usize = ReadableRegisterF64::PickupBearing as usize;
+const FIRE: usize = ReadableRegisterF64::Fire as usize;
+const GENERAL_PURPOSE_F64: usize = ReadableRegisterF64::GeneralPurpose0 as usize;
+
 pub struct VirtualMachine {
-    program: Rc<Program>,
+    instructions: Box<[DecodedInstruction]>,
+    interrupt_vectors: [Option<ProgramPointer>; InterruptKind::ALL.len()],
+    stack_size: usize,
 
     stack: Vec<StackOrHeapValue>,
     heap: Vec<StackOrHeapValue>,
 
     program_counter: ProgramPointer,
+    /// Where the last step started, which is what a fault part way through a batch is reported at.
+    step_address: ProgramPointer,
     clock: ClockTime,
+    instruction_count: u64,
     halted: bool,
 
-    health: f64,
-    energy: f64,
+    /// One bit per interrupt kind, in [`InterruptKind::ALL`] order.
+    pending_interrupts: u8,
+    in_interrupt: bool,
 
-    position: Vec2<f64>,
-    velocity: Vec2<f64>,
-    turret_angle: Radians<f64>,
-    turrent_angular_velocity: Radians<f64>,
-
-    register_general_purpose_u64: [u64; 8],
-    register_general_purpose_f64: [f64; 8],
+    /// Every u64 register followed by the program's u64 literals.
+    u64_file: Box<[u64]>,
+    /// Every f64 register, including the robot's sensors and controls, followed by the program's f64 literals.
+    f64_file: Box<[f64]>,
 }
 
 impl VirtualMachine {
     pub fn new(program: Rc<Program>) -> Self {
-        let stack = vec![StackOrHeapValue::U64(0); program.stack_size];
+        let decoded = DecodedProgram::new(&program);
+        let stack = Vec::with_capacity(program.stack_size);
         let heap = vec![StackOrHeapValue::U64(0); program.heap_size];
+        let mut f64_file = decoded.f64_file.into_boxed_slice();
+        // TODO health should be configurable?
+        f64_file[HEALTH] = 100.0;
+        // TODO energy should be configurable?
+        f64_file[ENERGY] = 100.0;
+        f64_file[PICKUP_DISTANCE] = f64::MAX;
         Self {
-            program,
+            instructions: decoded.instructions.into_boxed_slice(),
+            interrupt_vectors: decoded.interrupt_vectors,
+            stack_size: program.stack_size,
             stack,
             heap,
 
             program_counter: 0.into(),
+            step_address: 0.into(),
             clock: ClockTime(0),
+            instruction_count: 0,
             halted: false,
 
-            // TODO health should be configurable?
-            health: 100.0,
-            // TODO energy should be configurable?
-            energy: 100.0,
+            pending_interrupts: 0,
+            in_interrupt: false,
+
+            u64_file: decoded.u64_file.into_boxed_slice(),
+            f64_file,
+        }
+    }
+
+    pub fn program_counter(&self) -> ProgramPointer {
+        self.program_counter
+    }
+
+    /// The address of the instruction the last step ran, or of the one it was interrupted before.
+    pub fn step_address(&self) -> ProgramPointer {
+        self.step_address
+    }
+
+    pub fn clock(&self) -> ClockTime {
+        self.clock
+    }
+
+    pub fn instruction_count(&self) -> u64 {
+        self.instruction_count
+    }
+
+    pub fn register_u64(&self, register: &ReadableRegisterU64) -> u64 {
+        self.u64_file[u64_index(register)]
+    }
+
+    /// Sets any register, including ones the program can only read, e.g. for setting up tests.
+    pub fn set_register_u64(&mut self, register: &ReadableRegisterU64, value: u64) {
+        self.u64_file[u64_index(register)] = value;
+    }
+
+    pub fn register_f64(&self, register: &ReadableRegisterF64) -> f64 {
+        self.f64_file[f64_index(register)]
+    }
+
+    /// Sets any register, including ones the program can only read, e.g. for setting up tests.
+    pub fn set_register_f64(&mut self, register: &ReadableRegisterF64, value: f64) {
+        self.f64_file[f64_index(register)] = value;
+    }
+
+    /// The whole stack, bottom first.
+    pub fn stack(&self) -> &[StackOrHeapValue] {
+        &self.stack
+    }
+
+    pub fn heap(&self) -> &[StackOrHeapValue] {
+        &self.heap
+    }
+
+    pub fn heap_mut(&mut self) -> &mut [StackOrHeapValue] {
+        &mut self.heap
+    }
+
+    pub fn health(&self) -> f64 {
+        self.f64_file[HEALTH]
+    }
+
+    pub fn set_health(&mut self, value: f64) {
+        self.f64_file[HEALTH] = value;
+    }
+
+    pub fn energy(&self) -> f64 {
+        self.f64_file[ENERGY]
+    }
+
+    pub fn set_energy(&mut self, value: f64) {
+        self.f64_file[ENERGY] = value;
+    }
 
-            position: Vec2::new(0., 0.),
-            velocity: Vec2::new(0., 0.),
-            turret_angle: Radians(0.),
-            turrent_angular_velocity: Radians(0.),
+    /// Whether the program has asked to fire since the last call. Clears the request, so each write fires at most once.
+    pub fn take_fire_request(&mut self) -> bool {
+        let requested = self.f64_file[FIRE] > 0.;
+        self.f64_file[FIRE] = 0.;
+        requested
+    }
 
-            register_general_purpose_u64: [0; 8],
-            register_general_purpose_f64: [0.; 8],
+    /// Distance and bearing to the nearest pickup, for the `pickup_distance` and `pickup_bearing` registers.
+    ///
+    /// With no pickup the distance reads as `f64::MAX`, like the scanner, and the bearing is left alone.
+    pub fn set_nearest_pickup(&mut self, pickup: Option<(f64, Radians<f64>)>) {
+        match pickup {
+            Some((distance, bearing)) => {
+                self.f64_file[PICKUP_DISTANCE] = distance;
+                self.f64_file[PICKUP_BEARING] = bearing.0;
+            }
+            None => self.f64_file[PICKUP_DISTANCE] = f64::MAX,
         }
     }
 
+    pub fn snapshot(&self, stack_depth: usize) -> VirtualMachineSnapshot {
+        let mut general_purpose_u64 = [0; 8];
+        general_purpose_u64.copy_from_slice(&self.u64_file[..8]);
+        let mut general_purpose_f64 = [0.; 8];
+        general_purpose_f64
+            .copy_from_slice(&self.f64_file[GENERAL_PURPOSE_F64..GENERAL_PURPOSE_F64 + 8]);
+        VirtualMachineSnapshot {
+            program_counter: self.program_counter,
+            clock: self.clock,
+            instruction_count: self.instruction_count,
+            health: self.f64_file[HEALTH],
+            energy: self.f64_file[ENERGY],
+            general_purpose_u64,
+            general_purpose_f64,
+            stack_top: self.stack.iter().rev().take(stack_depth).copied().collect(),
+        }
+    }
+
+    /// Copies the actor's sensors into the registers. The requested velocity is left alone, it's up to the program.
     pub fn update_to_match_actor<ActorData>(
         &mut self,
         actor: &physics::Actor<ActorData>,
@@ -104,13 +263,18 @@ impl VirtualMachine {
     where
         ActorData: Clone,
     {
-        self.position = actor.position()?;
-        self.velocity = actor.velocity()?;
-        self.turret_angle = actor.turret_angle();
-        self.turrent_angular_velocity = actor.turret_angular_velocity();
+        let position = actor.position()?;
+        let velocity = actor.velocity()?;
+        self.f64_file[POSITION_X] = position.x;
+        self.f64_file[POSITION_Y] = position.y;
+        self.f64_file[ACTUAL_VELOCITY_X] = velocity.x;
+        self.f64_file[ACTUAL_VELOCITY_Y] = velocity.y;
+        self.f64_file[TURRET_ANGLE] = actor.turret_angle().0;
+        self.f64_file[TURRET_ANGULAR_VELOCITY] = actor.turret_angular_velocity().0;
         Ok(())
     }
 
+    /// Passes the program's requested velocity to the actor's drive, and its turret controls to the turret.
     pub fn update_actor_match_vm<ActorData>(
         &self,
         actor: &mut physics::Actor<ActorData>,
@@ -118,11 +282,27 @@ impl VirtualMachine {
     where
         ActorData: Clone,
     {
-        actor.set_velocity(self.velocity)?;
-        actor.set_turret_angular_velocity(self.turrent_angular_velocity);
+        actor.set_target_velocity(Vec2::new(
+            self.f64_file[VELOCITY_X],
+            self.f64_file[VELOCITY_Y],
+        ));
+        actor.set_turret_angular_velocity(Radians::from_radians(
+            self.f64_file[TURRET_ANGULAR_VELOCITY],
+        ));
         Ok(())
     }
 
+    /// Marks an interrupt as pending, to be delivered at the start of the next step.
+    ///
+    /// Interrupts the program has no handler for are ignored. Raising an interrupt that is already pending has no further effect.
+    pub fn raise_interrupt(&mut self, kind: InterruptKind) {
+        let index = interrupt_index(kind);
+        if self.interrupt_vectors[index].is_some() {
+            self.pending_interrupts |= 1 << index;
+        }
+    }
+
+    /// Runs a single step, either dispatching a pending interrupt or executing one instruction.
     pub fn step<ActorData>(
         &mut self,
         environment: &physics::Environment<ActorData>,
@@ -131,530 +311,192 @@ impl VirtualMachine {
     where
         ActorData: Clone,
     {
+        self.run(1, &|| environment.actor_scan(actor))
+    }
+
+    /// Runs up to `budget` steps, stopping at the first fault.
+    ///
+    /// On a fault the program counter has already moved past the faulting instruction, as with [`VirtualMachine::step`].
+    pub fn run(&mut self, mut budget: u64, scanner: &impl Scanner) -> Result<ClockTime, StepError> {
+        self.run_within(&mut budget, scanner)
+    }
+
+    /// Like [`VirtualMachine::run`], but takes the steps it uses out of `budget`, so that the rest of it can still be
+    /// run after a fault.
+    pub fn run_within(
+        &mut self,
+        budget: &mut u64,
+        scanner: &impl Scanner,
+    ) -> Result<ClockTime, StepError> {
         if self.halted {
             return Err(StepError::Halted);
         }
-        match self.read_next_instruction() {
-            Some(Instruction::SetU64 {
-                destination,
-                source,
-            }) => {
-                let source = self.resolve_source_u64(source);
-                self.write_destination_u64(destination, source.value);
-                self.clock += source.clock_cost;
+        while *budget > 0 {
+            *budget -= 1;
+            self.step_address = self.program_counter;
+            if self.pending_interrupts != 0 && self.dispatch_pending_interrupt()? {
+                continue;
             }
-            Some(Instruction::SetF64 {
-                destination,
-                source,
-            }) => {
-                let source = self.resolve_source_f64(source, environment, actor);
-                self.write_destination_f64(destination, source.value);
-                self.clock += source.clock_cost;
+            let Some(&instruction) = self.instructions.get(self.program_counter.0) else {
+                return Err(StepError::Halted);
+            };
+            self.program_counter.advance();
+            self.instruction_count += 1;
+            if instruction.reads_scanner {
+                self.read_scanner(scanner);
             }
-            Some(Instruction::AddU64 {
-                destination,
-                left,
-                right,
-            }) => self
-                .binary_operator_common_u64(destination, left, right, |left, right| left + right)?,
-            Some(Instruction::AddF64 {
-                destination,
-                left,
-                right,
-            }) => self.binary_operator_common_f64(
-                destination,
-                left,
-                right,
-                |left, right| left + right,
-                environment,
-                actor,
-            )?,
-            Some(Instruction::SubU64 {
-                destination,
-                left,
-                right,
-            }) => self
-                .binary_operator_common_u64(destination, left, right, |left, right| left - right)?,
-            Some(Instruction::SubF64 {
-                destination,
-                left,
-                right,
-            }) => self.binary_operator_common_f64(
-                destination,
-                left,
-                right,
-                |left, right| left - right,
-                environment,
-                actor,
-            )?,
-            Some(Instruction::MulU64 {
-                destination,
-                left,
-                right,
-            }) => self
-                .binary_operator_common_u64(destination, left, right, |left, right| left * right)?,
-            Some(Instruction::MulF64 {
-                destination,
-                left,
-                right,
-            }) => self.binary_operator_common_f64(
-                destination,
-                left,
-                right,
-                |left, right| left * right,
-                environment,
-                actor,
-            )?,
-            Some(Instruction::DivU64 {
-                destination,
-                left,
-                right,
-            }) => self
-                .binary_operator_common_u64(destination, left, right, |left, right| left / right)?,
-            Some(Instruction::DivF64 {
-                destination,
-                left,
-                right,
-            }) => self.binary_operator_common_f64(
-                destination,
-                left,
-                right,
-                |left, right| left / right,
-                environment,
-                actor,
-            )?,
-            Some(Instruction::Jump { address }) => {
-                let address = self.resolve_source_u64(address);
-                self.program_counter = address
-                    .value
-                    .try_into()
-                    .map_err(StepError::TryFromIntError)?;
-                self.clock += address.clock_cost;
+            self.execute(instruction)?;
+        }
+        Ok(self.clock)
+    }
+
+    fn execute(&mut self, instruction: DecodedInstruction) -> Result<(), StepError> {
+        let a = instruction.a as usize;
+        let b = instruction.b as usize;
+        let c = instruction.c as usize;
+        let u = &mut self.u64_file;
+        let f = &mut self.f64_file;
+        match instruction.operation {
+            Operation::SetU64 => u[a] = u[b],
+            Operation::SetF64 => f[a] = f[b],
+            Operation::AddU64 => u[a] = u[b].wrapping_add(u[c]),
+            Operation::AddF64 => f[a] = f[b] + f[c],
+            Operation::SubU64 => u[a] = u[b].wrapping_sub(u[c]),
+            Operation::SubF64 => f[a] = f[b] - f[c],
+            Operation::MulU64 => u[a] = u[b].wrapping_mul(u[c]),
+            Operation::MulF64 => f[a] = f[b] * f[c],
+            Operation::DivU64 => u[a] = u[b].checked_div(u[c]).ok_or(StepError::DivideByZero)?,
+            Operation::DivF64 => f[a] = f[b] / f[c],
+            Operation::Jump => self.jump(a)?,
+            Operation::JumpEqualU64 => {
+                let taken = u[b] == u[c];
+                self.jump_if(taken, a, instruction.jump_cost)?
             }
-            Some(Instruction::JumpEqualU64 {
-                address,
-                left,
-                right,
-            }) => self.jump_comparison_u64(address, left, right, |left, right| left == right)?,
-            Some(Instruction::JumpEqualF64 {
-                address,
-                left,
-                right,
-            }) => self.jump_comparison_f64(
-                address,
-                left,
-                right,
-                |left, right| left != right,
-                environment,
-                actor,
-            )?,
-            Some(Instruction::JumpNotEqualU64 {
-                address,
-                left,
-                right,
-            }) => self.jump_comparison_u64(address, left, right, |left, right| left != right)?,
-            Some(Instruction::JumpNotEqualF64 {
-                address,
-                left,
-                right,
-            }) => self.jump_comparison_f64(
-                address,
-                left,
-                right,
-                |left, right| left != right,
-                environment,
-                actor,
-            )?,
-            Some(Instruction::JumpLessThanU64 {
-                address,
-                left,
-                right,
-            }) => self.jump_comparison_u64(address, left, right, |left, right| left < right)?,
-            Some(Instruction::JumpLessThanF64 {
-                address,
-                left,
-                right,
-            }) => self.jump_comparison_f64(
-                address,
-                left,
-                right,
-                |left, right| left < right,
-                environment,
-                actor,
-            )?,
-            Some(Instruction::JumpLessThanOrEqualToU64 {
-                address,
-                left,
-                right,
-            }) => self.jump_comparison_u64(address, left, right, |left, right| left <= right)?,
-            Some(Instruction::JumpLessThanOrEqualToF64 {
-                address,
-                left,
-                right,
-            }) => self.jump_comparison_f64(
-                address,
-                left,
-                right,
-                |left, right| left <= right,
-                environment,
-                actor,
-            )?,
-            Some(Instruction::JumpGreaterThanU64 {
-                address,
-                left,
-                right,
-            }) => self.jump_comparison_u64(address, left, right, |left, right| left > right)?,
-            Some(Instruction::JumpGreaterThanF64 {
-                address,
-                left,
-                right,
-            }) => self.jump_comparison_f64(
-                address,
-                left,
-                right,
-                |left, right| left > right,
-                environment,
-                actor,
-            )?,
-            Some(Instruction::JumpGreaterThanOrEqualToU64 {
-                address,
-                left,
-                right,
-            }) => self.jump_comparison_u64(address, left, right, |left, right| left >= right)?,
-            Some(Instruction::JumpGreaterThanOrEqualToF64 {
-                address,
-                left,
-                right,
-            }) => self.jump_comparison_f64(
-                address,
-                left,
-                right,
-                |left, right| left >= right,
-                environment,
-                actor,
-            )?,
-            Some(Instruction::ShiftLeft {
-                destination,
-                source,
-            }) => self.unary_operator_common_u64(destination, source, |source| source << 1)?,
-            Some(Instruction::ShiftRight {
-                destination,
-                source,
-            }) => self.unary_operator_common_u64(destination, source, |source| source >> 1)?,
-            Some(Instruction::PushU64 { source }) => {
-                let source = self.resolve_source_u64(source);
-                self.push_u64(source.value);
-                self.clock += source.clock_cost;
+            Operation::JumpEqualF64 => {
+                let taken = f[b] == f[c];
+                self.jump_if(taken, a, instruction.jump_cost)?
             }
-            Some(Instruction::PushF64 { source }) => {
-                let source = self.resolve_source_f64(source, environment, actor);
-                self.push_f64(source.value);
-                self.clock += source.clock_cost;
+            Operation::JumpNotEqualU64 => {
+                let taken = u[b] != u[c];
+                self.jump_if(taken, a, instruction.jump_cost)?
             }
-            Some(Instruction::PopU64 { destination }) => {
-                let value = self.pop_u64()?;
-                self.write_destination_u64(destination, value);
-                self.clock += ClockTime(1);
+            Operation::JumpNotEqualF64 => {
+                let taken = f[b] != f[c];
+                self.jump_if(taken, a, instruction.jump_cost)?
             }
-            Some(Instruction::PopF64 { destination }) => {
-                let value = self.pop_f64()?;
-                self.write_destination_f64(destination, value);
-                self.clock += ClockTime(1);
+            Operation::JumpLessThanU64 => {
+                let taken = u[b] < u[c];
+                self.jump_if(taken, a, instruction.jump_cost)?
             }
-            Some(Instruction::LoadU64 {
-                destination,
-                source_address,
-            }) => {
-                let source_address = self.resolve_source_u64(source_address);
-                let value = self.load_u64(source_address.value)?;
-                self.write_destination_u64(destination, value);
-                self.clock += source_address.clock_cost;
+            Operation::JumpLessThanF64 => {
+                let taken = f[b] < f[c];
+                self.jump_if(taken, a, instruction.jump_cost)?
             }
-            Some(Instruction::LoadF64 {
-                destination,
-                source_address,
-            }) => {
-                let source_address = self.resolve_source_u64(source_address);
-                let value = self.load_f64(source_address.value)?;
-                self.write_destination_f64(destination, value);
-                self.clock += source_address.clock_cost;
+            Operation::JumpLessThanOrEqualToU64 => {
+                let taken = u[b] <= u[c];
+                self.jump_if(taken, a, instruction.jump_cost)?
             }
-            Some(Instruction::StoreU64 {
-                source,
-                destination_address,
-            }) => {
-                let destination_address = self.resolve_source_u64(destination_address);
-                let source = self.resolve_source_u64(source);
-                self.store_u64(destination_address.value, source.value)
-                    .map_err(|e| {
-                        self.halted = true;
-                        e
-                    })?;
-                self.clock += destination_address.clock_cost;
-                self.clock += source.clock_cost;
+            Operation::JumpLessThanOrEqualToF64 => {
+                let taken = f[b] <= f[c];
+                self.jump_if(taken, a, instruction.jump_cost)?
             }
-            Some(Instruction::StoreF64 {
-                source,
-                destination_address,
-            }) => {
-                let destination_address = self.resolve_source_u64(destination_address);
-                let source = self.resolve_source_f64(source, environment, actor);
-                self.store_f64(destination_address.value, source.value)
-                    .map_err(|e| {
-                        self.halted = true;
-                        e
-                    })?;
-                self.clock += destination_address.clock_cost;
-                self.clock += source.clock_cost;
+            Operation::JumpGreaterThanU64 => {
+                let taken = u[b] > u[c];
+                self.jump_if(taken, a, instruction.jump_cost)?
             }
-            None => Err(StepError::Halted)?,
-        };
-        Ok(self.clock)
-    }
-
-    fn unary_operator_common_u64<F>(
-        &mut self,
-        destination: DestinationU64,
-        source: SourceU64,
-        f: F,
-    ) -> Result<(), StepError>
-    where
-        F: FnOnce(u64) -> u64,
-    {
-        let source = self.resolve_source_u64(source);
-        self.write_destination_u64(destination, f(source.value));
-        self.clock += source.clock_cost;
-        Ok(())
-    }
-
-    fn binary_operator_common_u64<F>(
-        &mut self,
-        destination: DestinationU64,
-        left: SourceU64,
-        right: SourceU64,
-        f: F,
-    ) -> Result<(), StepError>
-    where
-        F: FnOnce(u64, u64) -> u64,
-    {
-        let left = self.resolve_source_u64(left);
-        let right = self.resolve_source_u64(right);
-        self.write_destination_u64(destination, f(left.value, right.value));
-        self.clock += left.clock_cost + right.clock_cost;
-        Ok(())
-    }
-
-    fn binary_operator_common_f64<F, ActorData>(
-        &mut self,
-        destination: DestinationF64,
-        left: SourceF64,
-        right: SourceF64,
-        f: F,
-        environment: &physics::Environment<ActorData>,
-        actor: &physics::Actor<ActorData>,
-    ) -> Result<(), StepError>
-    where
-        F: FnOnce(f64, f64) -> f64,
-        ActorData: Clone,
-    {
-        let left = self.resolve_source_f64(left, environment, actor);
-        let right = self.resolve_source_f64(right, environment, actor);
-        self.write_destination_f64(destination, f(left.value, right.value));
-        self.clock += left.clock_cost + right.clock_cost;
+            Operation::JumpGreaterThanF64 => {
+                let taken = f[b] > f[c];
+                self.jump_if(taken, a, instruction.jump_cost)?
+            }
+            Operation::JumpGreaterThanOrEqualToU64 => {
+                let taken = u[b] >= u[c];
+                self.jump_if(taken, a, instruction.jump_cost)?
+            }
+            Operation::JumpGreaterThanOrEqualToF64 => {
+                let taken = f[b] >= f[c];
+                self.jump_if(taken, a, instruction.jump_cost)?
+            }
+            Operation::ShiftLeft => u[a] = u[b] << 1,
+            Operation::ShiftRight => u[a] = u[b] >> 1,
+            Operation::PushU64 => self.push_u64(self.u64_file[a])?,
+            Operation::PushF64 => self.push_f64(self.f64_file[a])?,
+            Operation::PopU64 => self.u64_file[a] = self.pop_u64()?,
+            Operation::PopF64 => self.f64_file[a] = self.pop_f64()?,
+            Operation::LoadU64 => self.u64_file[a] = self.load_u64(self.u64_file[b])?,
+            Operation::LoadF64 => self.f64_file[a] = self.load_f64(self.u64_file[b])?,
+            Operation::StoreU64 => self.store_u64(self.u64_file[a], self.u64_file[b])?,
+            Operation::StoreF64 => self.store_f64(self.u64_file[a], self.f64_file[b])?,
+            Operation::InterruptReturn => {
+                if !self.in_interrupt {
+                    return Err(StepError::ReturnOutsideInterrupt);
+                }
+                let address = self.pop_u64()?;
+                self.program_counter = address.try_into().map_err(StepError::TryFromIntError)?;
+                self.in_interrupt = false;
+            }
+        }
+        self.clock += ClockTime(instruction.cost as u64);
         Ok(())
     }
 
-    fn jump_comparison_u64<F>(
-        &mut self,
-        address: SourceU64,
-        left: SourceU64,
-        right: SourceU64,
-        f: F,
-    ) -> Result<(), StepError>
-    where
-        F: FnOnce(u64, u64) -> bool,
-    {
-        let left = self.resolve_source_u64(left);
-        let right = self.resolve_source_u64(right);
-        if f(left.value, right.value) {
-            let address = self.resolve_source_u64(address);
-            self.program_counter = address
-                .value
-                .try_into()
-                .map_err(StepError::TryFromIntError)?;
-            self.clock += address.clock_cost;
-        }
-        self.clock += left.clock_cost + right.clock_cost;
+    fn jump(&mut self, address: usize) -> Result<(), StepError> {
+        self.program_counter = self.u64_file[address]
+            .try_into()
+            .map_err(StepError::TryFromIntError)?;
         Ok(())
     }
 
-    fn jump_comparison_f64<F, ActorData>(
-        &mut self,
-        address: SourceU64,
-        left: SourceF64,
-        right: SourceF64,
-        f: F,
-        environment: &physics::Environment<ActorData>,
-        actor: &physics::Actor<ActorData>,
-    ) -> Result<(), StepError>
-    where
-        F: FnOnce(f64, f64) -> bool,
-        ActorData: Clone,
-    {
-        let left = self.resolve_source_f64(left, environment, actor);
-        let right = self.resolve_source_f64(right, environment, actor);
-        if f(left.value, right.value) {
-            let address = self.resolve_source_u64(address);
-            self.program_counter = address
-                .value
-                .try_into()
-                .map_err(StepError::TryFromIntError)?;
-            self.clock += address.clock_cost;
+    fn jump_if(&mut self, condition: bool, address: usize, cost: u32) -> Result<(), StepError> {
+        if condition {
+            self.jump(address)?;
+            self.clock += ClockTime(cost as u64);
         }
-        self.clock += left.clock_cost + right.clock_cost;
         Ok(())
     }
 
-    fn read_next_instruction(&mut self) -> Option<Instruction> {
-        self.program.get(self.program_counter).map(|result| {
-            self.program_counter.advance();
-            result.clone()
-        })
-    }
-
-    fn resolve_source_u64(&self, source: SourceU64) -> ResolvedValue<u64> {
-        match source {
-            SourceU64::Register(r) => ResolvedValue {
-                value: self.read_register_u64(r),
-                clock_cost: ClockTime(2),
-            },
-            SourceU64::Literal(value) => ResolvedValue {
-                value,
-                clock_cost: ClockTime(1),
-            },
-        }
+    fn read_scanner(&mut self, scanner: &impl Scanner) {
+        self.f64_file[SCANNER_DISTANCE] = scanner.scan();
     }
 
-    fn resolve_source_f64<ActorData>(
-        &self,
-        source: SourceF64,
-        environment: &physics::Environment<ActorData>,
-        actor: &physics::Actor<ActorData>,
-    ) -> ResolvedValue<f64>
-    where
-        ActorData: Clone,
-    {
-        match source {
-            SourceF64::Register(r) => ResolvedValue {
-                value: self.read_register_f64(r, environment, actor),
-                clock_cost: ClockTime(4),
-            },
-            SourceF64::Literal(value) => ResolvedValue {
-                value,
-                clock_cost: ClockTime(2),
-            },
+    /// If an interrupt is pending and we aren't already inside a handler, pushes the return address and jumps to the handler.
+    ///
+    /// Returns whether an interrupt was dispatched. Nested interrupts are masked, they stay pending until the current handler returns.
+    fn dispatch_pending_interrupt(&mut self) -> Result<bool, StepError> {
+        if self.in_interrupt {
+            return Ok(false);
         }
+        // lowest bit first, which is the order of InterruptKind::ALL
+        let index = self.pending_interrupts.trailing_zeros() as usize;
+        self.pending_interrupts &= !(1 << index);
+        let Some(handler) = self.interrupt_vectors[index] else {
+            return Ok(false);
+        };
+        let return_address = self
+            .program_counter
+            .try_into()
+            .map_err(StepError::TryFromIntError)?;
+        self.push_u64(return_address)?;
+        self.program_counter = handler;
+        self.in_interrupt = true;
+        self.clock += ClockTime(4);
+        Ok(true)
     }
 
-    fn write_destination_u64(&mut self, destination: DestinationU64, value: u64) {
-        match destination {
-            DestinationU64::Register(r) => self.write_register_u64(r, value),
-        }
+    fn push_u64(&mut self, value: u64) -> Result<(), StepError> {
+        self.push(StackOrHeapValue::U64(value))
     }
 
-    fn write_destination_f64(&mut self, destination: DestinationF64, value: f64) {
-        match destination {
-            DestinationF64::Register(r) => self.write_register_f64(r, value),
-        }
+    fn push_f64(&mut self, value: f64) -> Result<(), StepError> {
+        self.push(StackOrHeapValue::F64(value))
     }
 
-    fn read_register_u64(&self, r: ReadableRegisterU64) -> u64 {
-        match r {
-            ReadableRegisterU64::GeneralPurpose0 => self.register_general_purpose_u64[0],
-            ReadableRegisterU64::GeneralPurpose1 => self.register_general_purpose_u64[1],
-            ReadableRegisterU64::GeneralPurpose2 => self.register_general_purpose_u64[2],
-            ReadableRegisterU64::GeneralPurpose3 => self.register_general_purpose_u64[3],
-            ReadableRegisterU64::GeneralPurpose4 => self.register_general_purpose_u64[4],
-            ReadableRegisterU64::GeneralPurpose5 => self.register_general_purpose_u64[5],
-            ReadableRegisterU64::GeneralPurpose6 => self.register_general_purpose_u64[6],
-            ReadableRegisterU64::GeneralPurpose7 => self.register_general_purpose_u64[7],
+    /// Fails with [`StepError::StackOverflow`] if the stack is already at the program's stack size.
+    pub fn push(&mut self, value: StackOrHeapValue) -> Result<(), StepError> {
+        if self.stack.len() >= self.stack_size {
+            return Err(StepError::StackOverflow);
         }
-    }
-
-    fn write_register_u64(&mut self, r: WritableRegisterU64, value: u64) {
-        *match r {
-            WritableRegisterU64::GeneralPurpose0 => &mut self.register_general_purpose_u64[0],
-            WritableRegisterU64::GeneralPurpose1 => &mut self.register_general_purpose_u64[1],
-            WritableRegisterU64::GeneralPurpose2 => &mut self.register_general_purpose_u64[2],
-            WritableRegisterU64::GeneralPurpose3 => &mut self.register_general_purpose_u64[3],
-            WritableRegisterU64::GeneralPurpose4 => &mut self.register_general_purpose_u64[4],
-            WritableRegisterU64::GeneralPurpose5 => &mut self.register_general_purpose_u64[5],
-            WritableRegisterU64::GeneralPurpose6 => &mut self.register_general_purpose_u64[6],
-            WritableRegisterU64::GeneralPurpose7 => &mut self.register_general_purpose_u64[7],
-        } = value;
-    }
-
-    fn read_register_f64<ActorData>(
-        &self,
-        r: ReadableRegisterF64,
-        environment: &physics::Environment<ActorData>,
-        actor: &physics::Actor<ActorData>,
-    ) -> f64
-    where
-        ActorData: Clone,
-    {
-        match r {
-            ReadableRegisterF64::PositionX => self.position.x,
-            ReadableRegisterF64::PositionY => self.position.y,
-            ReadableRegisterF64::VelocityX => self.velocity.x,
-            ReadableRegisterF64::VelocityY => self.velocity.y,
-            ReadableRegisterF64::TurretAngle => self.turret_angle.0,
-            ReadableRegisterF64::TurretAngularVelocity => self.turrent_angular_velocity.0,
-            ReadableRegisterF64::ScannerDistance => environment.actor_scan(actor),
-            ReadableRegisterF64::Health => self.health,
-            ReadableRegisterF64::Energy => self.energy,
-            ReadableRegisterF64::GeneralPurpose0 => self.register_general_purpose_f64[0],
-            ReadableRegisterF64::GeneralPurpose1 => self.register_general_purpose_f64[1],
-            ReadableRegisterF64::GeneralPurpose2 => self.register_general_purpose_f64[2],
-            ReadableRegisterF64::GeneralPurpose3 => self.register_general_purpose_f64[3],
-            ReadableRegisterF64::GeneralPurpose4 => self.register_general_purpose_f64[4],
-            ReadableRegisterF64::GeneralPurpose5 => self.register_general_purpose_f64[5],
-            ReadableRegisterF64::GeneralPurpose6 => self.register_general_purpose_f64[6],
-            ReadableRegisterF64::GeneralPurpose7 => self.register_general_purpose_f64[7],
-        }
-    }
-
-    fn write_register_f64(&mut self, r: WritableRegisterF64, value: f64) {
-        *match r {
-            WritableRegisterF64::VelocityX => {
-                self.velocity.x = value;
-                return;
-            }
-            WritableRegisterF64::VelocityY => {
-                self.velocity.y = value;
-                return;
-            }
-            WritableRegisterF64::TurretAngularVelocity => {
-                self.turrent_angular_velocity = Radians::from_radians(value);
-                return;
-            }
-            WritableRegisterF64::GeneralPurpose0 => &mut self.register_general_purpose_f64[0],
-            WritableRegisterF64::GeneralPurpose1 => &mut self.register_general_purpose_f64[1],
-            WritableRegisterF64::GeneralPurpose2 => &mut self.register_general_purpose_f64[2],
-            WritableRegisterF64::GeneralPurpose3 => &mut self.register_general_purpose_f64[3],
-            WritableRegisterF64::GeneralPurpose4 => &mut self.register_general_purpose_f64[4],
-            WritableRegisterF64::GeneralPurpose5 => &mut self.register_general_purpose_f64[5],
-            WritableRegisterF64::GeneralPurpose6 => &mut self.register_general_purpose_f64[6],
-            WritableRegisterF64::GeneralPurpose7 => &mut self.register_general_purpose_f64[7],
-        } = value;
-    }
-
-    fn push_u64(&mut self, value: u64) {
-        self.stack.push(StackOrHeapValue::U64(value));
-    }
-
-    fn push_f64(&mut self, value: f64) {
-        self.stack.push(StackOrHeapValue::F64(value));
+        self.stack.push(value);
+        Ok(())
     }
 
     fn pop_u64(&mut self) -> Result<u64, StepError> {
@@ -705,3 +547,166 @@ impl VirtualMachine {
         Ok(())
     }
 }
+
+fn interrupt_index(kind: InterruptKind) -> usize {
+    InterruptKind::ALL
+        .iter()
+        .position(|other| *other == kind)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::parse;
+
+    fn vm(source: &str) -> VirtualMachine {
+        VirtualMachine::new(Rc::new(parse(source).unwrap().runnable_program))
+    }
+
+    fn nothing_in_sight() -> f64 {
+        f64::MAX
+    }
+
+    #[test]
+    fn arithmetic_and_clock() {
+        let mut vm = vm(r"
+            set r0, 6
+            mul r1, r0, 7
+            sub r2, r1, 50
+            div r3, r1, r0
+            set f0, 1.5
+            mul f1, f0, f0
+            sl r4, r0
+            sr r5, r0
+        ");
+        vm.run(8, &nothing_in_sight).unwrap();
+        let snapshot = vm.snapshot(0);
+        assert_eq!(
+            snapshot.general_purpose_u64[..6],
+            [6, 42, 42u64.wrapping_sub(50), 7, 12, 3]
+        );
+        assert_eq!(snapshot.general_purpose_f64[..2], [1.5, 2.25]);
+        // literals cost 1 and registers 2, doubled for f64
+        assert_eq!(vm.clock(), ClockTime(1 + 3 + 3 + 4 + 2 + 8 + 2 + 2));
+        assert_eq!(vm.instruction_count(), 8);
+        assert_eq!(
+            vm.run(1, &nothing_in_sight),
+            Err(StepError::Halted),
+            "running off the end halts"
+        );
+    }
+
+    #[test]
+    fn jumps() {
+        let mut vm = vm(r"
+            loop:
+                add r0, r0, 1
+                jlt loop, r0, 10
+                set f0, 2.0
+                jeq equal, f0, 2.0
+                set r1, 1
+            equal:
+                set r2, 1
+        ");
+        assert_eq!(vm.run(100, &nothing_in_sight), Err(StepError::Halted));
+        let snapshot = vm.snapshot(0);
+        assert_eq!(snapshot.general_purpose_u64[..3], [10, 0, 1]);
+        assert_eq!(vm.instruction_count(), 20 + 3);
+    }
+
+    #[test]
+    fn faults_skip_the_instruction() {
+        let mut vm = vm(r"
+            set r0, 5
+            div r1, r0, 0
+            set r2, 1
+            pop r3
+        ");
+        vm.run(1, &nothing_in_sight).unwrap();
+        let clock = vm.clock();
+        assert_eq!(vm.run(10, &nothing_in_sight), Err(StepError::DivideByZero));
+        assert_eq!(vm.program_counter(), ProgramPointer(2));
+        assert_eq!(vm.step_address(), ProgramPointer(1));
+        assert_eq!(
+            vm.clock(),
+            clock,
+            "faulting instructions don't take any time"
+        );
+        assert_eq!(
+            vm.run(10, &nothing_in_sight),
+            Err(StepError::StackUnderflow)
+        );
+        assert_eq!(vm.step_address(), ProgramPointer(3));
+        assert_eq!(vm.snapshot(0).general_purpose_u64[2], 1);
+    }
+
+    #[test]
+    fn scanner_interrupts() {
+        let mut vm = vm(r"
+            .on scan_found found
+            loop:
+                set f0, scanner_distance
+                jmp loop
+            found:
+                add r0, r0, 1
+                iret
+        ");
+        vm.run(2, &nothing_in_sight).unwrap();
+        assert_eq!(vm.snapshot(0).general_purpose_f64[0], f64::MAX);
+        assert_eq!(vm.snapshot(0).general_purpose_u64[0], 0);
+
+        // reading the scanner is just a read, the simulation decides when a robot has been found
+        vm.run(1, &|| 12.5).unwrap();
+        assert_eq!(vm.snapshot(0).general_purpose_f64[0], 12.5);
+        assert_eq!(vm.program_counter(), ProgramPointer(1));
+        vm.raise_interrupt(InterruptKind::ScanFound);
+        // dispatch, then the handler
+        vm.run(1, &nothing_in_sight).unwrap();
+        assert_eq!(vm.program_counter(), ProgramPointer(2));
+        assert_eq!(vm.snapshot(4).stack_top.len(), 1);
+        vm.run(2, &nothing_in_sight).unwrap();
+        assert_eq!(vm.snapshot(0).general_purpose_u64[0], 1);
+        assert_eq!(vm.program_counter(), ProgramPointer(1));
+        assert!(vm.snapshot(4).stack_top.is_empty());
+    }
+
+    #[test]
+    fn batches_match_single_steps() {
+        let source = r"
+            .on scan_found found
+            loop:
+                add r0, r0, 3
+                mul r1, r0, r0
+                jgt reset, f0, 0.5
+                add f0, f0, 0.125
+                store r0, f0
+                load f1, r0
+                sub f2, scanner_distance, f1
+                jmp loop
+            reset:
+                set f0, 0.0
+                jmp loop
+            found:
+                sr r1, r1
+                iret
+        ";
+        let scanner = || 100.;
+        let mut batched = vm(source);
+        let mut stepped = vm(source);
+        for _ in 0..10 {
+            batched.raise_interrupt(InterruptKind::ScanFound);
+            batched.run(100, &scanner).unwrap();
+            stepped.raise_interrupt(InterruptKind::ScanFound);
+            for _ in 0..100 {
+                stepped.run(1, &scanner).unwrap();
+            }
+        }
+        let (batched, stepped) = (batched.snapshot(8), stepped.snapshot(8));
+        assert_eq!(batched.general_purpose_u64, stepped.general_purpose_u64);
+        assert_eq!(batched.general_purpose_f64, stepped.general_purpose_f64);
+        assert_eq!(batched.clock, stepped.clock);
+        assert_eq!(batched.program_counter, stepped.program_counter);
+        assert_eq!(batched.instruction_count, stepped.instruction_count);
+    }
+}
@@ -0,0 +1,700 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    f64::consts::PI,
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+    thread,
+};
+
+use color_eyre::eyre::{Result, bail, eyre};
+use rand::{Rng, SeedableRng, rngs::StdRng};
+use serde::{Deserialize, Serialize};
+use tracing::*;
+
+use crate::{
+    assembler, headless,
+    simulation::{
+        language::Program,
+        simulation::{MatchConfig, RobotState},
+    },
+};
+
+pub const USAGE: &str = "usage: robowar tournament [--format round-robin|swiss] [--rounds N] [--games N] [--ticks N] [--threads N] [--seed N] [--rating elo|glicko] [--ratings FILE] BOT.s BOT.s ...";
+
+const INITIAL_RATING: f64 = 1500.;
+/// Glicko's deviation for a player nothing is known about, and the most it can grow back to.
+const INITIAL_DEVIATION: f64 = 350.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Every entrant plays every other entrant once per round.
+    RoundRobin,
+    /// Each round pairs entrants with similar scores who haven't met yet. Needs far fewer games than a round robin
+    /// once the library gets big.
+    Swiss,
+}
+
+impl TryFrom<&str> for Format {
+    type Error = String;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "round-robin" => Ok(Format::RoundRobin),
+            "swiss" => Ok(Format::Swiss),
+            _ => Err(format!("Invalid tournament format: {value}")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RatingSystem {
+    /// Classic Elo. `k` is the most a rating can move in one game.
+    Elo { k: f64 },
+    /// Glicko-1, which also tracks how sure it is of each rating. `c` is how much the deviation grows back each
+    /// round, so ratings of bots that haven't played in a while can move quickly again.
+    Glicko { c: f64 },
+}
+
+impl RatingSystem {
+    /// A player's new rating and deviation after one round of results, given as each opponent's rating before the
+    /// round and the player's score against them: 1 for a win, 0.5 for a draw, 0 for a loss.
+    pub fn update(&self, player: &Rating, results: &[(Rating, f64)]) -> (f64, f64) {
+        match self {
+            RatingSystem::Elo { k } => {
+                let change = results
+                    .iter()
+                    .map(|(opponent, score)| {
+                        score - expected_score(player.rating, opponent.rating, 1.)
+                    })
+                    .sum::<f64>();
+                (player.rating + k * change, player.deviation)
+            }
+            RatingSystem::Glicko { c } => {
+                let deviation = (player.deviation.powi(2) + c.powi(2))
+                    .sqrt()
+                    .min(INITIAL_DEVIATION);
+                if results.is_empty() {
+                    return (player.rating, deviation);
+                }
+                let q = 10f64.ln() / 400.;
+                let mut variance_inverse = 0.;
+                let mut improvement = 0.;
+                for (opponent, score) in results {
+                    let g = glicko_g(opponent.deviation);
+                    let expected = expected_score(player.rating, opponent.rating, g);
+                    variance_inverse += q.powi(2) * g.powi(2) * expected * (1. - expected);
+                    improvement += g * (score - expected);
+                }
+                let precision = 1. / deviation.powi(2) + variance_inverse;
+                (
+                    player.rating + q / precision * improvement,
+                    (1. / precision).sqrt(),
+                )
+            }
+        }
+    }
+}
+
+fn glicko_g(deviation: f64) -> f64 {
+    let q = 10f64.ln() / 400.;
+    1. / (1. + 3. * q.powi(2) * deviation.powi(2) / PI.powi(2)).sqrt()
+}
+
+/// Chance of a player rated `rating` beating one rated `opponent`, with the difference scaled by `g`.
+fn expected_score(rating: f64, opponent: f64, g: f64) -> f64 {
+    1. / (1. + 10f64.powf(-g * (rating - opponent) / 400.))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rating {
+    pub rating: f64,
+    /// Only used by Glicko. Elo leaves it alone.
+    pub deviation: f64,
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Self {
+            rating: INITIAL_RATING,
+            deviation: INITIAL_DEVIATION,
+            wins: 0,
+            draws: 0,
+            losses: 0,
+        }
+    }
+}
+
+impl Rating {
+    pub fn games(&self) -> u32 {
+        self.wins + self.draws + self.losses
+    }
+}
+
+/// Ratings by bot name, saved between runs so the library's rankings build up over many tournaments.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Ratings {
+    pub players: BTreeMap<String, Rating>,
+}
+
+impl Ratings {
+    /// Reads ratings from a JSON file, or starts afresh if it doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| eyre!("failed to read ratings from {}: {e}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Rating {
+        self.players.get(name).copied().unwrap_or_default()
+    }
+
+    /// Every player, highest rated first.
+    pub fn standings(&self) -> Vec<(&str, &Rating)> {
+        let mut standings = self
+            .players
+            .iter()
+            .map(|(name, rating)| (name.as_str(), rating))
+            .collect::<Vec<_>>();
+        standings.sort_by(|a, b| b.1.rating.total_cmp(&a.1.rating));
+        standings
+    }
+}
+
+pub struct Entrant {
+    /// What the entrant's rating is saved under.
+    pub name: String,
+    pub program: Program,
+}
+
+pub struct TournamentConfig {
+    pub format: Format,
+    /// For a round robin, how many times every pair meets. For Swiss, how many rounds are paired.
+    pub rounds: usize,
+    /// Games played by each pairing in a round, each with different spawn positions.
+    pub games_per_pairing: usize,
+    /// A match that hasn't finished after this many ticks is a draw.
+    pub match_ticks: u64,
+    pub match_config: MatchConfig,
+    pub rating_system: RatingSystem,
+    pub threads: usize,
+    /// Every match's spawn seed comes from this, so the same tournament plays out the same way on any number of
+    /// threads. `robowar tournament` picks a random one unless it's given.
+    pub seed: u64,
+}
+
+impl Default for TournamentConfig {
+    fn default() -> Self {
+        Self {
+            format: Format::RoundRobin,
+            rounds: 1,
+            games_per_pairing: 2,
+            match_ticks: 1800,
+            match_config: MatchConfig::default(),
+            rating_system: RatingSystem::Glicko { c: 30. },
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    FirstWins,
+    SecondWins,
+    Draw,
+}
+
+impl Outcome {
+    /// The first robot's score: 1 for a win, 0.5 for a draw, 0 for a loss.
+    pub fn score(&self) -> f64 {
+        match self {
+            Outcome::FirstWins => 1.,
+            Outcome::SecondWins => 0.,
+            Outcome::Draw => 0.5,
+        }
+    }
+}
+
+/// One match, with entrants given by their index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Game {
+    pub round: usize,
+    /// The first entrant's robot is spawned first.
+    pub first: usize,
+    pub second: usize,
+    pub seed: u64,
+    pub outcome: Outcome,
+}
+
+/// Plays a tournament, updating `ratings` after every round. `on_round` is called with each round's games.
+///
+/// Returns every game played.
+pub fn run_tournament(
+    config: &TournamentConfig,
+    entrants: &[Entrant],
+    ratings: &mut Ratings,
+    mut on_round: impl FnMut(usize, &[Game]),
+) -> Result<Vec<Game>> {
+    if entrants.len() < 2 {
+        bail!("need at least two entrants");
+    }
+    if config.rounds == 0 || config.games_per_pairing == 0 {
+        bail!("rounds and games per pairing must be at least 1");
+    }
+    let mut names = HashSet::new();
+    for entrant in entrants {
+        if !names.insert(&entrant.name) {
+            bail!("two entrants are called {}", entrant.name);
+        }
+    }
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    // tournament points, as opposed to ratings, used for Swiss pairings
+    let mut points = vec![0.; entrants.len()];
+    let mut met = HashSet::new();
+    let mut had_bye = vec![false; entrants.len()];
+    let mut games = Vec::new();
+    for round in 0..config.rounds {
+        let pairs = match config.format {
+            Format::RoundRobin => round_robin_pairs(entrants.len()),
+            Format::Swiss => {
+                let ranking = (0..entrants.len())
+                    .map(|i| (points[i], ratings.get(&entrants[i].name).rating))
+                    .collect::<Vec<_>>();
+                let (pairs, bye) = swiss_pairs(&ranking, &met, &had_bye);
+                if let Some(bye) = bye {
+                    had_bye[bye] = true;
+                    points[bye] += 1.;
+                }
+                pairs
+            }
+        };
+
+        let mut scheduled = Vec::new();
+        for (a, b) in pairs {
+            met.insert((a.min(b), a.max(b)));
+            for game in 0..config.games_per_pairing {
+                // take turns at being spawned first
+                let (first, second) = if game % 2 == 0 { (a, b) } else { (b, a) };
+                scheduled.push((first, second, rng.random()));
+            }
+        }
+
+        let outcomes = play_games(config, entrants, &scheduled)?;
+        let round_games = scheduled
+            .iter()
+            .zip(outcomes)
+            .map(|(&(first, second, seed), outcome)| Game {
+                round,
+                first,
+                second,
+                seed,
+                outcome,
+            })
+            .collect::<Vec<_>>();
+        for game in round_games.iter() {
+            points[game.first] += game.outcome.score();
+            points[game.second] += 1. - game.outcome.score();
+        }
+        update_ratings(config.rating_system, ratings, entrants, &round_games);
+        on_round(round, &round_games);
+        games.extend(round_games);
+    }
+    Ok(games)
+}
+
+fn round_robin_pairs(count: usize) -> Vec<(usize, usize)> {
+    (0..count)
+        .flat_map(|a| (a + 1..count).map(move |b| (a, b)))
+        .collect()
+}
+
+/// Pairs entrants ranked by `(points, rating)`, each with the best ranked entrant below it that it hasn't met yet, or
+/// the next one down if it's met them all. With an odd number, the lowest ranked entrant that hasn't had a bye sits
+/// the round out.
+fn swiss_pairs(
+    ranking: &[(f64, f64)],
+    met: &HashSet<(usize, usize)>,
+    had_bye: &[bool],
+) -> (Vec<(usize, usize)>, Option<usize>) {
+    let mut order = (0..ranking.len()).collect::<Vec<_>>();
+    order.sort_by(|a, b| {
+        ranking[*b]
+            .0
+            .total_cmp(&ranking[*a].0)
+            .then(ranking[*b].1.total_cmp(&ranking[*a].1))
+            .then(a.cmp(b))
+    });
+
+    let bye = if order.len() % 2 == 1 {
+        let index = order
+            .iter()
+            .rposition(|entrant| !had_bye[*entrant])
+            .unwrap_or(order.len() - 1);
+        Some(order.remove(index))
+    } else {
+        None
+    };
+
+    let mut pairs = Vec::new();
+    while let Some(a) = order.first().copied() {
+        order.remove(0);
+        let index = order
+            .iter()
+            .position(|b| !met.contains(&(a.min(*b), a.max(*b))))
+            .unwrap_or(0);
+        pairs.push((a, order.remove(index)));
+    }
+    (pairs, bye)
+}
+
+/// Treats the round as one rating period: everyone's new rating is worked out from the ratings at the start of it.
+fn update_ratings(
+    system: RatingSystem,
+    ratings: &mut Ratings,
+    entrants: &[Entrant],
+    games: &[Game],
+) {
+    let before = entrants
+        .iter()
+        .map(|entrant| ratings.get(&entrant.name))
+        .collect::<Vec<_>>();
+    for (i, entrant) in entrants.iter().enumerate() {
+        let results = games
+            .iter()
+            .filter_map(|game| {
+                if game.first == i {
+                    Some((before[game.second], game.outcome.score()))
+                } else if game.second == i {
+                    Some((before[game.first], 1. - game.outcome.score()))
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+        let (rating, deviation) = system.update(&before[i], &results);
+        let player = ratings.players.entry(entrant.name.clone()).or_default();
+        player.rating = rating;
+        player.deviation = deviation;
+        for (_, score) in results {
+            if score == 1. {
+                player.wins += 1;
+            } else if score == 0. {
+                player.losses += 1;
+            } else {
+                player.draws += 1;
+            }
+        }
+    }
+}
+
+/// Plays every game, spread over `config.threads` threads. Simulations aren't `Send`, so each thread builds its own,
+/// and threads take the next unplayed game as they finish so one long match doesn't hold up a whole batch.
+fn play_games(
+    config: &TournamentConfig,
+    entrants: &[Entrant],
+    scheduled: &[(usize, usize, u64)],
+) -> Result<Vec<Outcome>> {
+    let next = AtomicUsize::new(0);
+    let threads = config.threads.clamp(1, scheduled.len().max(1));
+    thread::scope(|scope| {
+        let workers = (0..threads)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut played = Vec::new();
+                    loop {
+                        let index = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(&(first, second, seed)) = scheduled.get(index) else {
+                            break;
+                        };
+                        let outcome = play_match(
+                            config,
+                            &entrants[first].program,
+                            &entrants[second].program,
+                            seed,
+                        )?;
+                        played.push((index, outcome));
+                    }
+                    Ok::<_, color_eyre::Report>(played)
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut outcomes = vec![None; scheduled.len()];
+        for worker in workers {
+            for (index, outcome) in worker
+                .join()
+                .map_err(|_| eyre!("tournament thread panicked"))??
+            {
+                outcomes[index] = Some(outcome);
+            }
+        }
+        outcomes
+            .into_iter()
+            .map(|outcome| outcome.ok_or(eyre!("a game was never played")))
+            .collect()
+    })
+}
+
+/// Plays one headless match.
+fn play_match(
+    config: &TournamentConfig,
+    first: &Program,
+    second: &Program,
+    seed: u64,
+) -> Result<Outcome> {
+    let mut simulation = headless::new_match(
+        &[first, second],
+        MatchConfig {
+            seed: Some(seed),
+            ..config.match_config.clone()
+        },
+    )?;
+    headless::run_match(&mut simulation, config.match_ticks)?;
+
+    // robots are spawned in the order their programs were given
+    let results = simulation.results();
+    let [first, second] = results.robots.as_slice() else {
+        bail!("expected 2 robots, found {}", results.robots.len());
+    };
+    Ok(match (first.state, second.state) {
+        (RobotState::Dead, RobotState::Dead) => Outcome::Draw,
+        (RobotState::Dead, _) => Outcome::SecondWins,
+        (_, RobotState::Dead) => Outcome::FirstWins,
+        _ => Outcome::Draw,
+    })
+}
+
+/// Command line options for `robowar tournament`.
+pub struct TournamentOptions {
+    pub config: TournamentConfig,
+    pub bots: Vec<PathBuf>,
+    pub ratings: PathBuf,
+}
+
+impl TournamentOptions {
+    /// Parses the arguments following `tournament` on the command line.
+    pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut config = TournamentConfig::default();
+        let mut bots = Vec::new();
+        let mut ratings = PathBuf::from("ratings.json");
+        let mut seed = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(eyre!("missing value for {arg}\n{USAGE}"));
+            match arg.as_str() {
+                "--format" => {
+                    config.format = Format::try_from(value()?.as_str()).map_err(|e| eyre!(e))?
+                }
+                "--rounds" => config.rounds = value()?.parse()?,
+                "--games" => config.games_per_pairing = value()?.parse()?,
+                "--ticks" => config.match_ticks = value()?.parse()?,
+                "--threads" => config.threads = value()?.parse()?,
+                "--seed" => seed = Some(value()?.parse()?),
+                "--rating" => {
+                    config.rating_system = match value()?.as_str() {
+                        "elo" => RatingSystem::Elo { k: 32. },
+                        "glicko" => RatingSystem::Glicko { c: 30. },
+                        other => bail!("unknown rating system {other}\n{USAGE}"),
+                    }
+                }
+                "--ratings" => ratings = value()?.into(),
+                _ if arg.starts_with("--") => bail!("unknown argument {arg}\n{USAGE}"),
+                _ => bots.push(arg.into()),
+            }
+        }
+        config.seed = seed.unwrap_or_else(|| rand::rng().random());
+
+        Ok(Self {
+            config,
+            bots,
+            ratings,
+        })
+    }
+}
+
+/// Runs `robowar tournament`. Bots are named after their file names, without the extension.
+pub fn run(options: &TournamentOptions) -> Result<()> {
+    let entrants = options
+        .bots
+        .iter()
+        .map(|path| {
+            let text = fs::read_to_string(path)?;
+            let program = assembler::parse(&text)
+                .map_err(|e| eyre!("{}: {e}", path.display()))?
+                .runnable_program;
+            let name = path
+                .file_stem()
+                .ok_or(eyre!("{} has no file name", path.display()))?
+                .to_string_lossy()
+                .to_string();
+            Ok(Entrant { name, program })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut ratings = Ratings::load(&options.ratings)?;
+    info!("playing a tournament with seed {}", options.config.seed);
+    run_tournament(&options.config, &entrants, &mut ratings, |round, games| {
+        let decided = games
+            .iter()
+            .filter(|game| game.outcome != Outcome::Draw)
+            .count();
+        info!("round {round}: {} games, {decided} decided", games.len());
+    })?;
+    ratings.save(&options.ratings)?;
+
+    for (rank, (name, rating)) in ratings.standings().iter().enumerate() {
+        info!(
+            "{:>3}. {name}: {:.0} ± {:.0} ({}W {}D {}L)",
+            rank + 1,
+            rating.rating,
+            2. * rating.deviation,
+            rating.wins,
+            rating.draws,
+            rating.losses
+        );
+    }
+    info!("saved ratings to {}", options.ratings.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(rating: f64, deviation: f64) -> Rating {
+        Rating {
+            rating,
+            deviation,
+            ..Rating::default()
+        }
+    }
+
+    #[test]
+    fn glicko_matches_the_paper() {
+        // the worked example from Glickman's description of the system
+        let (rating, deviation) = RatingSystem::Glicko { c: 0. }.update(
+            &player(1500., 200.),
+            &[
+                (player(1400., 30.), 1.),
+                (player(1550., 100.), 0.),
+                (player(1700., 300.), 0.),
+            ],
+        );
+        assert!((rating - 1464.06).abs() < 0.1, "{rating}");
+        assert!((deviation - 151.4).abs() < 0.1, "{deviation}");
+
+        // sitting out a round makes a rating less certain, up to the initial deviation
+        let (_, deviation) = RatingSystem::Glicko { c: 30. }.update(&player(1500., 40.), &[]);
+        assert_eq!(deviation, 50.);
+        let (_, deviation) = RatingSystem::Glicko { c: 30. }.update(&player(1500., 349.), &[]);
+        assert_eq!(deviation, INITIAL_DEVIATION);
+    }
+
+    #[test]
+    fn elo() {
+        let elo = RatingSystem::Elo { k: 32. };
+        let (rating, _) = elo.update(&player(1500., 350.), &[(player(1500., 350.), 1.)]);
+        assert_eq!(rating, 1516.);
+        let (rating, _) = elo.update(&player(1500., 350.), &[(player(1500., 350.), 0.5)]);
+        assert_eq!(rating, 1500.);
+    }
+
+    #[test]
+    fn swiss_pairing() {
+        // 0 and 1 lead, but have already met
+        let ranking = [
+            (2., 1500.),
+            (2., 1600.),
+            (1., 1500.),
+            (0., 1500.),
+            (1., 1400.),
+        ];
+        let met = HashSet::from([(0, 1)]);
+        let (pairs, bye) = swiss_pairs(&ranking, &met, &[false, false, false, false, false]);
+        assert_eq!(bye, Some(3));
+        assert_eq!(pairs, vec![(1, 2), (0, 4)]);
+
+        // the bottom entrant already sat one out
+        let (_, bye) = swiss_pairs(&ranking, &met, &[false, false, false, true, false]);
+        assert_eq!(bye, Some(4));
+    }
+
+    #[test]
+    fn tournament() {
+        let program = |text: &str| assembler::parse(text).unwrap().runnable_program;
+        let entrants = vec![
+            Entrant {
+                name: "shooter".to_string(),
+                program: program(
+                    ".weapon cannon\nset turret_angular_velocity, 0.01\nloop:\nset fire, 1.0\njmp loop",
+                ),
+            },
+            Entrant {
+                name: "sitter".to_string(),
+                program: program("loop:\njmp loop"),
+            },
+            Entrant {
+                name: "crasher".to_string(),
+                program: program("div r0, r0, 0"),
+            },
+        ];
+        let config = TournamentConfig {
+            format: Format::Swiss,
+            rounds: 2,
+            match_ticks: 300,
+            match_config: MatchConfig {
+                fault_policy: crate::simulation::simulation::FaultPolicy::Kill,
+                ..MatchConfig::default()
+            },
+            threads: 3,
+            seed: 7,
+            ..TournamentConfig::default()
+        };
+
+        let mut ratings = Ratings::default();
+        let mut rounds = 0;
+        let games = run_tournament(&config, &entrants, &mut ratings, |_, _| rounds += 1).unwrap();
+        assert_eq!(rounds, 2);
+        // one pairing of two games a round, with a bye for the third entrant
+        assert_eq!(games.len(), 4);
+        let crasher = ratings.get("crasher");
+        assert_eq!(crasher.wins, 0);
+        assert!(crasher.rating < INITIAL_RATING);
+
+        // the same seed plays out the same way, whatever the number of threads
+        let mut again = Ratings::default();
+        let replayed = run_tournament(
+            &TournamentConfig {
+                threads: 1,
+                ..config
+            },
+            &entrants,
+            &mut again,
+            |_, _| (),
+        )
+        .unwrap();
+        assert_eq!(games, replayed);
+        assert_eq!(ratings, again);
+
+        let path =
+            std::env::temp_dir().join(format!("robowar_ratings_{}.json", std::process::id()));
+        ratings.save(&path).unwrap();
+        assert_eq!(Ratings::load(&path).unwrap(), ratings);
+        fs::remove_file(&path).unwrap();
+    }
+}
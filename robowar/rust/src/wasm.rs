@@ -0,0 +1,200 @@
+//! Bindings for running matches in a browser. Snapshots come back as flat arrays of numbers, which wasm-bindgen turns
+//! into typed arrays, so a viewer can read every robot each frame without allocating JS objects.
+
+use std::{rc::Rc, time::Duration};
+
+use color_eyre::eyre::{Report, Result, eyre};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    assembler,
+    math::{Rect, Vec2},
+    simulation::{
+        items::{ItemKind, ItemState},
+        physics,
+        simulation::{MatchConfig, RobotState, Simulation},
+    },
+};
+
+/// Numbers per robot in [`WasmSimulation::robot_snapshots`]: id, x, y, radius, turret angle, scanner distance, health,
+/// energy and state, which is 0 for running, 1 for frozen and 2 for dead.
+pub const ROBOT_SNAPSHOT_STRIDE: usize = 9;
+
+/// Numbers per item in [`WasmSimulation::item_snapshots`]: id, kind, x, y, radius and whether it's available. Kinds are
+/// 0 for energy, 1 for health, 2 for mines and 3 for slowing zones.
+pub const ITEM_SNAPSHOT_STRIDE: usize = 6;
+
+#[wasm_bindgen(js_name = robotSnapshotStride)]
+pub fn robot_snapshot_stride() -> usize {
+    ROBOT_SNAPSHOT_STRIDE
+}
+
+#[wasm_bindgen(js_name = itemSnapshotStride)]
+pub fn item_snapshot_stride() -> usize {
+    ITEM_SNAPSHOT_STRIDE
+}
+
+/// Checks that a program assembles, returning the number of instructions.
+#[wasm_bindgen]
+pub fn assemble(source: &str) -> Result<usize, JsError> {
+    assembler::parse(source)
+        .map(|program| program.runnable_program.instructions().len())
+        .map_err(|e| JsError::new(&e))
+}
+
+/// A match running in the browser.
+#[wasm_bindgen]
+pub struct WasmSimulation {
+    simulation: Simulation,
+}
+
+#[wasm_bindgen]
+impl WasmSimulation {
+    /// Assembles every program and starts a match between them in a `width` by `height` arena.
+    #[wasm_bindgen(constructor)]
+    pub fn new(sources: Vec<String>, width: f64, height: f64) -> Result<WasmSimulation, JsError> {
+        Self::try_new(sources, width, height).map_err(js_error)
+    }
+
+    /// Runs up to `ticks` ticks of `tick_seconds` each, stopping early if the match finishes. Returns whether it has.
+    pub fn step(&mut self, ticks: u32, tick_seconds: f64) -> Result<bool, JsError> {
+        let elapsed_time = Duration::from_secs_f64(tick_seconds);
+        for _ in 0..ticks {
+            if self.simulation.is_finished() {
+                break;
+            }
+            self.simulation.update(elapsed_time).map_err(js_error)?;
+        }
+        Ok(self.simulation.is_finished())
+    }
+
+    /// Number of ticks run so far.
+    pub fn tick(&self) -> f64 {
+        self.simulation.tick() as f64
+    }
+
+    #[wasm_bindgen(js_name = isFinished)]
+    pub fn is_finished(&self) -> bool {
+        self.simulation.is_finished()
+    }
+
+    /// Every robot, ordered by id, [`ROBOT_SNAPSHOT_STRIDE`] numbers each.
+    #[wasm_bindgen(js_name = robotSnapshots)]
+    pub fn robot_snapshots(&self) -> Result<Vec<f64>, JsError> {
+        self.try_robot_snapshots().map_err(js_error)
+    }
+
+    /// Every item, ordered by id, [`ITEM_SNAPSHOT_STRIDE`] numbers each.
+    #[wasm_bindgen(js_name = itemSnapshots)]
+    pub fn item_snapshots(&self) -> Vec<f64> {
+        self.simulation
+            .item_snapshots()
+            .iter()
+            .flat_map(|item| {
+                [
+                    item.id.index() as f64,
+                    match item.kind {
+                        ItemKind::EnergyPickup { .. } => 0.,
+                        ItemKind::HealthPickup { .. } => 1.,
+                        ItemKind::Mine { .. } => 2.,
+                        ItemKind::SlowingZone { .. } => 3.,
+                    },
+                    item.position.x,
+                    item.position.y,
+                    item.radius,
+                    (item.state == ItemState::Available) as u8 as f64,
+                ]
+            })
+            .collect()
+    }
+
+    /// The arena walls as line segments, 4 numbers each: x1, y1, x2, y2.
+    #[wasm_bindgen(js_name = arenaSegments)]
+    pub fn arena_segments(&self) -> Result<Vec<f64>, JsError> {
+        Ok(self
+            .simulation
+            .physics_environment()
+            .get_line_segments()
+            .map_err(js_error)?
+            .iter()
+            .flat_map(|line| {
+                let a = *line.origin();
+                let b = a + *line.delta();
+                [a.x, a.y, b.x, b.y]
+            })
+            .collect())
+    }
+}
+
+impl WasmSimulation {
+    fn try_new(sources: Vec<String>, width: f64, height: f64) -> Result<Self> {
+        let programs = sources
+            .iter()
+            .enumerate()
+            .map(|(i, source)| {
+                assembler::parse(source)
+                    .map(|program| Rc::new(program.runnable_program))
+                    .map_err(|e| eyre!("program {i}: {e}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let environment = physics::Environment::new_standard_rectangle(Rect::new_with_origin_size(
+            Vec2::new(0., 0.),
+            Vec2::new(width, height),
+        ));
+        Ok(Self {
+            simulation: Simulation::new(environment, programs, MatchConfig::default())?,
+        })
+    }
+
+    fn try_robot_snapshots(&self) -> Result<Vec<f64>> {
+        Ok(self
+            .simulation
+            .robot_snapshots(0)?
+            .iter()
+            .flat_map(|robot| {
+                [
+                    robot.id.index() as f64,
+                    robot.position.x,
+                    robot.position.y,
+                    robot.radius,
+                    robot.turret_angle.0,
+                    robot.scanner_distance,
+                    robot.vm.health,
+                    robot.vm.energy,
+                    match robot.state {
+                        RobotState::Running => 0.,
+                        RobotState::Frozen { .. } => 1.,
+                        RobotState::Dead => 2.,
+                    },
+                ]
+            })
+            .collect())
+    }
+}
+
+fn js_error(e: Report) -> JsError {
+    JsError::new(&format!("{e:?}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots() {
+        let source = "set velocity_x, 10.0\nloop:\njmp loop".to_string();
+        let mut simulation = WasmSimulation::new(vec![source.clone(), source], 200., 100.).unwrap();
+        assert!(!simulation.step(3, 1. / 60.).unwrap());
+        assert_eq!(simulation.tick(), 3.);
+
+        let robots = simulation.robot_snapshots().unwrap();
+        assert_eq!(robots.len(), 2 * ROBOT_SNAPSHOT_STRIDE);
+        let first = &robots[..ROBOT_SNAPSHOT_STRIDE];
+        assert!((0. ..=200.).contains(&first[1]) && (0. ..=100.).contains(&first[2]));
+        assert_eq!(first[6], 100.);
+        assert_eq!(first[8], 0.);
+
+        assert!(simulation.item_snapshots().is_empty());
+        assert_eq!(simulation.arena_segments().unwrap().len(), 4 * 4);
+    }
+}
@@ -11,7 +11,7 @@ use tracing::*;
 use winit::{
     application::ApplicationHandler,
     dpi::{LogicalSize, PhysicalPosition, PhysicalSize},
-    event::{ElementState, KeyEvent},
+    event::{ElementState, KeyEvent, MouseButton},
     event_loop::EventLoop,
     keyboard::{Key, NamedKey},
     window::{Window, WindowAttributes},
@@ -23,6 +23,15 @@ pub trait EventHandler {
     fn resize(&mut self, width: u32, height: u32) -> Result<()>;
     fn render(&mut self, buffer: &mut [u32], width: u32, height: u32) -> Result<()>;
     fn update(&mut self, elapsed_time: Duration) -> Result<()>;
+
+    fn key_pressed(&mut self, _key: &Key) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called when the left mouse button is pressed, with the cursor position in physical pixels.
+    fn mouse_pressed(&mut self, _x: f64, _y: f64) -> Result<()> {
+        Ok(())
+    }
 }
 
 struct WindowState<EH: EventHandler> {
@@ -30,6 +39,7 @@ struct WindowState<EH: EventHandler> {
     window: Arc<Window>,
     surface: Surface<Arc<Window>, Arc<Window>>,
     last_update: Option<Instant>,
+    cursor_position: Option<PhysicalPosition<f64>>,
 }
 
 impl<EH: EventHandler> WindowState<EH> {
@@ -62,6 +72,7 @@ impl<EH: EventHandler> WindowState<EH> {
             window,
             surface,
             last_update: None,
+            cursor_position: None,
         })
     }
 
@@ -169,6 +180,45 @@ impl<EH: EventHandler> ApplicationHandler for App<EH> {
                 event_loop.exit();
             }
 
+            winit::event::WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        logical_key,
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(window_state) = &mut self.window_state
+                    && let Err(e) = window_state.event_handler.key_pressed(&logical_key)
+                {
+                    error!("error handling key press: {e:?}");
+                    exit(1);
+                }
+            }
+
+            winit::event::WindowEvent::CursorMoved { position, .. } => {
+                if let Some(window_state) = &mut self.window_state {
+                    window_state.cursor_position = Some(position);
+                }
+            }
+
+            winit::event::WindowEvent::MouseInput {
+                state: ElementState::Pressed,
+                button: MouseButton::Left,
+                ..
+            } => {
+                if let Some(window_state) = &mut self.window_state
+                    && let Some(position) = window_state.cursor_position
+                    && let Err(e) = window_state
+                        .event_handler
+                        .mouse_pressed(position.x, position.y)
+                {
+                    error!("error handling mouse press: {e:?}");
+                    exit(1);
+                }
+            }
+
             winit::event::WindowEvent::Resized(physical_size) => {
                 if let Some(window_state) = &mut self.window_state
                     && let Err(e) = window_state.resize(physical_size)
@@ -0,0 +1,270 @@
+//! Runs the language-neutral VM conformance cases in `robowar/conformance`.
+
+use std::{collections::BTreeMap, fs, path::Path, rc::Rc};
+
+use robowar::{
+    assembler,
+    simulation::{
+        language::{InterruptKind, ReadableRegisterF64, ReadableRegisterU64},
+        vm::{StackOrHeapValue, StepError, VirtualMachine},
+    },
+};
+use serde::Deserialize;
+use serde_json::Number;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Case {
+    name: String,
+    source: String,
+    #[serde(default = "default_assembles")]
+    assembles: bool,
+    #[serde(default)]
+    initial: Initial,
+    #[serde(default)]
+    steps: u64,
+    #[serde(default)]
+    expected: Expected,
+}
+
+fn default_assembles() -> bool {
+    true
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Initial {
+    #[serde(default)]
+    registers: BTreeMap<String, Number>,
+    #[serde(default)]
+    stack: Vec<Value>,
+    #[serde(default)]
+    heap: BTreeMap<usize, Value>,
+    #[serde(default)]
+    interrupts: Vec<String>,
+    scanner: Option<f64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct Expected {
+    #[serde(default)]
+    registers: BTreeMap<String, Number>,
+    stack: Option<Vec<Value>>,
+    #[serde(default)]
+    heap: BTreeMap<usize, Value>,
+    program_counter: Option<usize>,
+    clock: Option<u64>,
+    instruction_count: Option<u64>,
+    /// `Some(None)` means the case expects no error, `None` means it isn't checked.
+    #[serde(default, deserialize_with = "present")]
+    error: Option<Option<String>>,
+}
+
+fn present<'de, D>(deserializer: D) -> Result<Option<Option<String>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Option::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum Value {
+    U64(u64),
+    F64(f64),
+}
+
+impl From<Value> for StackOrHeapValue {
+    fn from(value: Value) -> Self {
+        match value {
+            Value::U64(value) => StackOrHeapValue::U64(value),
+            Value::F64(value) => StackOrHeapValue::F64(value),
+        }
+    }
+}
+
+enum Register {
+    U64(ReadableRegisterU64),
+    F64(ReadableRegisterF64),
+}
+
+fn register(name: &str) -> Result<Register, String> {
+    ReadableRegisterU64::try_from(name)
+        .map(Register::U64)
+        .or_else(|_| ReadableRegisterF64::try_from(name).map(Register::F64))
+}
+
+fn error_name(error: &StepError) -> &'static str {
+    match error {
+        StepError::Halted => "halted",
+        StepError::TryFromIntError(_) => "invalid_address",
+        StepError::StackUnderflow => "stack_underflow",
+        StepError::StackOverflow => "stack_overflow",
+        StepError::AddressOutOfBounds => "address_out_of_bounds",
+        StepError::DivideByZero => "divide_by_zero",
+        StepError::ReturnOutsideInterrupt => "return_outside_interrupt",
+    }
+}
+
+fn set_up(vm: &mut VirtualMachine, initial: &Initial) -> Result<(), String> {
+    for (name, value) in initial.registers.iter() {
+        match register(name)? {
+            Register::U64(register) => vm.set_register_u64(
+                &register,
+                value
+                    .as_u64()
+                    .ok_or(format!("{name} needs a u64, found {value}"))?,
+            ),
+            Register::F64(register) => vm.set_register_f64(
+                &register,
+                value
+                    .as_f64()
+                    .ok_or(format!("{name} needs an f64, found {value}"))?,
+            ),
+        }
+    }
+    for value in initial.stack.iter() {
+        vm.push((*value).into())
+            .map_err(|e| format!("initial stack: {e:?}"))?;
+    }
+    for (address, value) in initial.heap.iter() {
+        *vm.heap_mut()
+            .get_mut(*address)
+            .ok_or(format!("initial heap address {address} out of bounds"))? = (*value).into();
+    }
+    for name in initial.interrupts.iter() {
+        vm.raise_interrupt(InterruptKind::try_from(name.as_str())?);
+    }
+    Ok(())
+}
+
+fn check(vm: &VirtualMachine, expected: &Expected, error: Option<&StepError>) -> Vec<String> {
+    let mut failures = Vec::new();
+    let mut compare = |what: String, actual: String, expected: String| {
+        if actual != expected {
+            failures.push(format!("{what}: expected {expected}, found {actual}"));
+        }
+    };
+
+    for (name, value) in expected.registers.iter() {
+        match register(name) {
+            Ok(Register::U64(register)) => compare(
+                name.clone(),
+                vm.register_u64(&register).to_string(),
+                value.to_string(),
+            ),
+            // compare as f64s, so that e.g. 2 and 2.0 match
+            Ok(Register::F64(register)) => compare(
+                name.clone(),
+                format!("{:?}", vm.register_f64(&register)),
+                format!("{:?}", value.as_f64().unwrap_or(f64::NAN)),
+            ),
+            Err(e) => compare(name.clone(), e, "a register".to_string()),
+        }
+    }
+    if let Some(stack) = &expected.stack {
+        let stack = stack
+            .iter()
+            .map(|value| StackOrHeapValue::from(*value))
+            .collect::<Vec<_>>();
+        compare(
+            "stack".to_string(),
+            format!("{:?}", vm.stack()),
+            format!("{stack:?}"),
+        );
+    }
+    for (address, value) in expected.heap.iter() {
+        compare(
+            format!("heap[{address}]"),
+            format!("{:?}", vm.heap().get(*address)),
+            format!("{:?}", Some(StackOrHeapValue::from(*value))),
+        );
+    }
+    if let Some(program_counter) = expected.program_counter {
+        compare(
+            "program counter".to_string(),
+            vm.program_counter().0.to_string(),
+            program_counter.to_string(),
+        );
+    }
+    if let Some(clock) = expected.clock {
+        compare(
+            "clock".to_string(),
+            vm.clock().0.to_string(),
+            clock.to_string(),
+        );
+    }
+    if let Some(instruction_count) = expected.instruction_count {
+        compare(
+            "instruction count".to_string(),
+            vm.instruction_count().to_string(),
+            instruction_count.to_string(),
+        );
+    }
+    if let Some(expected_error) = &expected.error {
+        compare(
+            "error".to_string(),
+            format!("{:?}", error.map(error_name)),
+            format!("{:?}", expected_error.as_deref()),
+        );
+    }
+    failures
+}
+
+fn run(case: &Case) -> Vec<String> {
+    let program = match (assembler::parse(&case.source), case.assembles) {
+        (Ok(program), true) => program.runnable_program,
+        (Err(_), false) => return Vec::new(),
+        (Ok(_), false) => return vec!["expected the source to be rejected".to_string()],
+        (Err(e), true) => return vec![format!("failed to assemble: {e}")],
+    };
+    let mut vm = VirtualMachine::new(Rc::new(program));
+    if let Err(e) = set_up(&mut vm, &case.initial) {
+        return vec![e];
+    }
+    let scanner = case.initial.scanner.unwrap_or(f64::MAX);
+    let mut error = None;
+    for _ in 0..case.steps {
+        if let Err(e) = vm.run(1, &|| scanner) {
+            error = Some(e);
+            break;
+        }
+    }
+    check(&vm, &case.expected, error.as_ref())
+}
+
+#[test]
+fn conformance() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("../conformance");
+    let mut paths = fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "json")
+        })
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "no cases in {directory:?}");
+
+    let mut failures = Vec::new();
+    let mut count = 0;
+    for path in paths {
+        let file = path.file_name().unwrap().to_string_lossy().to_string();
+        let cases: Vec<Case> = serde_json::from_str(&fs::read_to_string(&path).unwrap())
+            .unwrap_or_else(|e| panic!("failed to read {file}: {e}"));
+        for case in cases.iter() {
+            count += 1;
+            for failure in run(case) {
+                failures.push(format!("{file}: {}: {failure}", case.name));
+            }
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {count} cases failed:\n{}",
+        failures.len(),
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,200 @@
+//! Property tests that throw generated programs at the assembler and VM, looking for panics.
+
+use std::rc::Rc;
+
+use proptest::prelude::*;
+use robowar::{
+    assembler::{self, disassemble},
+    simulation::{
+        language::{InterruptKind, ReadableRegisterF64, ReadableRegisterU64, WritableRegisterF64},
+        vm::{StepError, VirtualMachine},
+    },
+};
+
+const LABELS: [&str; 3] = ["a", "b", "c"];
+
+fn u64_register() -> impl Strategy<Value = String> {
+    prop::sample::select(ReadableRegisterU64::ALL.to_vec()).prop_map(|r| r.to_string())
+}
+
+fn f64_register() -> impl Strategy<Value = String> {
+    prop::sample::select(ReadableRegisterF64::ALL.to_vec()).prop_map(|r| r.to_string())
+}
+
+fn writable_f64_register() -> impl Strategy<Value = String> {
+    prop::sample::select(WritableRegisterF64::ALL.to_vec()).prop_map(|r| r.to_string())
+}
+
+fn u64_source() -> impl Strategy<Value = String> {
+    prop_oneof![
+        u64_register(),
+        // mostly small values, so that addresses sometimes land inside the program and heap
+        (0u64..80).prop_map(|n| n.to_string()),
+        any::<u64>().prop_map(|n| n.to_string()),
+    ]
+}
+
+fn f64_source() -> impl Strategy<Value = String> {
+    prop_oneof![
+        f64_register(),
+        (-1e6f64..1e6).prop_map(|n| format!("{n:?}")),
+    ]
+}
+
+fn address() -> impl Strategy<Value = String> {
+    prop_oneof![
+        prop::sample::select(LABELS.to_vec()).prop_map(str::to_string),
+        u64_source(),
+    ]
+}
+
+/// One line of assembler that should always assemble, given that every label in [`LABELS`] is defined.
+fn instruction() -> impl Strategy<Value = String> {
+    let arithmetic = prop::sample::select(vec!["add", "sub", "mul", "div"]);
+    let comparison = prop::sample::select(vec!["jeq", "jne", "jlt", "jle", "jgt", "jge"]);
+    prop_oneof![
+        (u64_register(), u64_source()).prop_map(|(d, s)| format!("set {d}, {s}")),
+        (writable_f64_register(), f64_source()).prop_map(|(d, s)| format!("set {d}, {s}")),
+        (
+            arithmetic.clone(),
+            u64_register(),
+            u64_source(),
+            u64_source()
+        )
+            .prop_map(|(op, d, l, r)| format!("{op} {d}, {l}, {r}")),
+        (
+            arithmetic,
+            writable_f64_register(),
+            f64_source(),
+            f64_source()
+        )
+            .prop_map(|(op, d, l, r)| format!("{op} {d}, {l}, {r}")),
+        address().prop_map(|a| format!("jmp {a}")),
+        (comparison.clone(), address(), u64_source(), u64_source())
+            .prop_map(|(op, a, l, r)| format!("{op} {a}, {l}, {r}")),
+        (comparison, address(), f64_source(), f64_source())
+            .prop_map(|(op, a, l, r)| format!("{op} {a}, {l}, {r}")),
+        (
+            prop::sample::select(vec!["sl", "sr"]),
+            u64_register(),
+            u64_source()
+        )
+            .prop_map(|(op, d, s)| format!("{op} {d}, {s}")),
+        u64_source().prop_map(|s| format!("push {s}")),
+        f64_source().prop_map(|s| format!("push {s}")),
+        u64_register().prop_map(|d| format!("pop {d}")),
+        writable_f64_register().prop_map(|d| format!("pop {d}")),
+        (u64_register(), u64_source()).prop_map(|(d, a)| format!("load {d}, {a}")),
+        (writable_f64_register(), u64_source()).prop_map(|(d, a)| format!("load {d}, {a}")),
+        (u64_source(), u64_source()).prop_map(|(a, s)| format!("store {a}, {s}")),
+        (u64_source(), f64_source()).prop_map(|(a, s)| format!("store {a}, {s}")),
+        Just("iret".to_string()),
+    ]
+}
+
+/// A program with every label defined somewhere and handlers for some of the interrupts.
+fn program() -> impl Strategy<Value = String> {
+    (
+        prop::collection::vec(instruction(), 1..40),
+        prop::collection::vec(any::<prop::sample::Index>(), LABELS.len()),
+        prop::collection::vec(any::<bool>(), InterruptKind::ALL.len()),
+    )
+        .prop_map(|(mut lines, positions, handled)| {
+            for (label, position) in LABELS.iter().zip(positions) {
+                let index = position.index(lines.len() + 1);
+                lines.insert(index, format!("{label}:"));
+            }
+            for ((kind, label), handled) in InterruptKind::ALL.iter().zip(LABELS).zip(handled) {
+                if handled {
+                    lines.insert(0, format!(".on {kind} {label}"));
+                }
+            }
+            lines.join("\n")
+        })
+}
+
+fn vm(source: &str) -> VirtualMachine {
+    let program = assembler::parse(source)
+        .unwrap_or_else(|e| panic!("failed to assemble:\n{source}\n{e}"))
+        .runnable_program;
+    VirtualMachine::new(Rc::new(program))
+}
+
+/// Steps until halted or out of budget, carrying on past faults the way the simulation does.
+fn run_to_completion(vm: &mut VirtualMachine, budget: u64, scanner: f64) {
+    for _ in 0..budget {
+        if vm.run(1, &|| scanner) == Err(StepError::Halted) {
+            break;
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn assembler_never_panics(source in "\\PC{0,200}") {
+        let _ = assembler::parse(&source);
+    }
+
+    #[test]
+    fn assembler_never_panics_on_almost_valid_programs(
+        source in program(),
+        cut in any::<prop::sample::Index>(),
+        junk in "[a-z0-9,.:=+*/() \n-]{0,8}",
+    ) {
+        // splice some junk into an otherwise valid program
+        let mut index = cut.index(source.len() + 1);
+        while !source.is_char_boundary(index) {
+            index -= 1;
+        }
+        let _ = assembler::parse(&format!("{}{junk}{}", &source[..index], &source[index..]));
+    }
+
+    #[test]
+    fn vm_never_panics(
+        source in program(),
+        registers in prop::collection::vec(any::<u64>(), ReadableRegisterU64::ALL.len()),
+        interrupts in prop::collection::vec(any::<bool>(), InterruptKind::ALL.len()),
+        scanner in prop_oneof![Just(f64::MAX), 0f64..1000.],
+    ) {
+        let mut vm = vm(&source);
+        for (register, value) in ReadableRegisterU64::ALL.iter().zip(registers) {
+            vm.set_register_u64(register, value);
+        }
+        for (kind, raised) in InterruptKind::ALL.iter().zip(interrupts) {
+            if raised {
+                vm.raise_interrupt(*kind);
+            }
+        }
+        run_to_completion(&mut vm, 2000, scanner);
+    }
+
+    #[test]
+    fn batches_match_single_steps(source in program(), budget in 0u64..500) {
+        let mut batched = vm(&source);
+        let mut stepped = vm(&source);
+        let batched_result = batched.run(budget, &|| 10.);
+        let mut stepped_result = Ok(stepped.clock());
+        for _ in 0..budget {
+            stepped_result = stepped.run(1, &|| 10.);
+            if stepped_result.is_err() {
+                break;
+            }
+        }
+        prop_assert_eq!(batched_result, stepped_result);
+        prop_assert_eq!(batched.program_counter(), stepped.program_counter());
+        prop_assert_eq!(batched.clock(), stepped.clock());
+        prop_assert_eq!(batched.instruction_count(), stepped.instruction_count());
+        // compared as text so that NaNs from dividing zero by zero match each other
+        prop_assert_eq!(format!("{:?}", batched.stack()), format!("{:?}", stepped.stack()));
+    }
+
+    #[test]
+    fn disassembly_round_trips(source in program()) {
+        let program = assembler::parse(&source).unwrap().runnable_program;
+        let disassembled = disassemble(&program).unwrap();
+        let reassembled = assembler::parse(&disassembled)
+            .unwrap_or_else(|e| panic!("failed to reassemble:\n{disassembled}\n{e}"))
+            .runnable_program;
+        prop_assert_eq!(program.instructions(), reassembled.instructions());
+    }
+}
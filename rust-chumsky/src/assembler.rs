@@ -0,0 +1,62 @@
+use std::collections::HashMap;
+
+use crate::{
+    encoding::encode,
+    instruction_set::{Item, Program, Target},
+};
+
+/// An assembled program, loaded at address 0 and run from there.
+#[derive(Debug)]
+pub struct Image<'a> {
+    pub bytes: Vec<u8>,
+    /// The address of every label.
+    pub symbols: HashMap<&'a str, u32>,
+}
+
+/// Lays the program out in source order, then encodes it with every label replaced by its address.
+pub fn assemble<'a>(program: &Program<'a>) -> Result<Image<'a>, String> {
+    // first pass: instruction sizes don't depend on where their targets are, so encoding with placeholder addresses
+    // gives each item's address
+    let mut addresses = Vec::with_capacity(program.items.len() + 1);
+    let mut scratch = Vec::new();
+    for item in &program.items {
+        addresses.push(scratch.len() as u32);
+        match item {
+            Item::Instruction(instruction) => {
+                let mut instruction = instruction.clone();
+                if let Some(target) = instruction.target_mut() {
+                    *target = Target::Address(0);
+                }
+                encode(&instruction, &mut scratch)?;
+            }
+            Item::Data(data) => scratch.extend(data),
+        }
+    }
+    addresses.push(scratch.len() as u32);
+    let symbols = program
+        .labels
+        .iter()
+        .map(|(label, index)| (*label, addresses[*index]))
+        .collect::<HashMap<_, _>>();
+
+    // second pass: encode for real, now every label has an address
+    let mut bytes = Vec::with_capacity(scratch.len());
+    for item in &program.items {
+        match item {
+            Item::Instruction(instruction) => {
+                let mut instruction = instruction.clone();
+                if let Some(target) = instruction.target_mut()
+                    && let Target::Label(label) = target
+                {
+                    let address = symbols
+                        .get(label.as_str())
+                        .ok_or_else(|| format!("undefined label: {}", label))?;
+                    *target = Target::Address(*address);
+                }
+                encode(&instruction, &mut bytes)?;
+            }
+            Item::Data(data) => bytes.extend(data),
+        }
+    }
+    Ok(Image { bytes, symbols })
+}
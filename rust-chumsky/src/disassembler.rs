@@ -0,0 +1,204 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use crate::{encoding::decode, instruction_set::Target};
+
+/// Decodes `bytes` as a program loaded at address 0, one instruction after another, into assembly the parser accepts.
+///
+/// Labels come from `symbols` where there are any, and are made up for any other address that's jumped to, called or
+/// loaded with `la`. Bytes that don't decode come out as `.byte` directives. Data that happens to decode comes out as
+/// instructions, which still reassemble to the same bytes, except for jumps, calls and `la`s pointing somewhere a label
+/// can't go, such as the middle of an instruction or past the end. Those come out as `.byte` directives too.
+pub fn disassemble(bytes: &[u8], symbols: &HashMap<&str, u32>) -> String {
+    let mut lines = Vec::new();
+    let mut address = 0;
+    while address < bytes.len() {
+        match decode(&bytes[address..]) {
+            Ok((instruction, length)) => {
+                lines.push((address as u32, Ok((instruction, length))));
+                address += length;
+            }
+            Err(_) => {
+                lines.push((address as u32, Err(bytes[address])));
+                address += 1;
+            }
+        }
+    }
+
+    // labels can only go between lines, so a target in the middle of an instruction stays an address
+    let boundaries = lines
+        .iter()
+        .map(|(address, _)| *address)
+        .chain([bytes.len() as u32])
+        .collect::<HashSet<_>>();
+    let mut labels = BTreeMap::<u32, Vec<String>>::new();
+    for (label, address) in symbols
+        .iter()
+        .filter(|(_, address)| boundaries.contains(address))
+    {
+        labels.entry(*address).or_default().push(label.to_string());
+    }
+    for names in labels.values_mut() {
+        names.sort();
+    }
+    for (_, line) in &lines {
+        if let Ok((instruction, _)) = line
+            && let Some(Target::Address(address)) = instruction.target()
+            && boundaries.contains(address)
+        {
+            labels
+                .entry(*address)
+                .or_insert_with(|| vec![format!("label_{:04x}", address)]);
+        }
+    }
+
+    let mut output = String::new();
+    for (address, line) in lines {
+        for name in labels.get(&address).into_iter().flatten() {
+            output.push_str(&format!("{}:\n", name));
+        }
+        let text = match line {
+            Ok((mut instruction, length)) => {
+                if let Some(target) = instruction.target_mut()
+                    && let Target::Address(target_address) = target
+                    && let Some(names) = labels.get(target_address)
+                {
+                    *target = Target::Label(names[0].clone());
+                }
+                // the parser only takes labels as targets, so a target that can't have one is left as bytes
+                if let Some(Target::Address(_)) = instruction.target() {
+                    byte_directive(&bytes[address as usize..][..length])
+                } else {
+                    instruction.to_string()
+                }
+            }
+            Err(byte) => byte_directive(&[byte]),
+        };
+        output.push_str(&format!("    {:<32} ; {:#06x}\n", text, address));
+    }
+    for name in labels.get(&(bytes.len() as u32)).into_iter().flatten() {
+        output.push_str(&format!("{}:\n", name));
+    }
+    output
+}
+
+fn byte_directive(bytes: &[u8]) -> String {
+    let bytes = bytes
+        .iter()
+        .map(|byte| format!("{:#04x}", byte))
+        .collect::<Vec<_>>()
+        .join(", ");
+    format!(".byte {}", bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        assembler::assemble,
+        disassembler::disassemble,
+        encoding::encode,
+        instruction_set::{Condition, Instruction, Target},
+        parser::parse,
+    };
+
+    fn assemble_source(source: &str) -> Vec<u8> {
+        assemble(&parse(source).unwrap()).unwrap().bytes
+    }
+
+    /// Uses every kind of target, and data where some bytes decode as instructions and some don't.
+    const SOURCE: &str = r#"
+        main:
+            la r1, message
+            mov r12, -1i64
+        loop:
+            load8s r2, [r1 + 1]
+            cmp r3, r2, 0
+            jz done
+            store16 r2, [r1 - 2]
+            call function
+            jmp loop
+        done:
+            halt
+        function:
+            add r34, r34, 0x100000000
+            ret
+        message:
+            .ascii "hi"
+            .byte 0xff, 0x0e, 0
+            .word 0xdeadbeef
+        end:
+    "#;
+
+    #[test]
+    fn reassembles_to_the_same_bytes() {
+        let image = assemble(&parse(SOURCE).unwrap()).unwrap();
+        let with_symbols = disassemble(&image.bytes, &image.symbols);
+        assert_eq!(
+            assemble_source(&with_symbols),
+            image.bytes,
+            "{}",
+            with_symbols
+        );
+        let without_symbols = disassemble(&image.bytes, &HashMap::new());
+        assert_eq!(
+            assemble_source(&without_symbols),
+            image.bytes,
+            "{}",
+            without_symbols
+        );
+    }
+
+    #[test]
+    fn labels() {
+        let image = assemble(&parse(SOURCE).unwrap()).unwrap();
+        let with_symbols = disassemble(&image.bytes, &image.symbols);
+        for label in ["main:", "loop:", "done:", "function:", "message:", "end:"] {
+            assert!(with_symbols.contains(label), "{}", with_symbols);
+        }
+        assert!(with_symbols.contains("call function"), "{}", with_symbols);
+
+        let without_symbols = disassemble(&image.bytes, &HashMap::new());
+        let done = image.symbols["done"];
+        assert!(
+            without_symbols.contains(&format!("jz label_{:04x}", done)),
+            "{}",
+            without_symbols
+        );
+    }
+
+    #[test]
+    fn targets_that_cant_be_labelled() {
+        let mut bytes = Vec::new();
+        for instruction in [
+            // into the middle of itself
+            Instruction::Jump {
+                condition: Condition::Always,
+                target: Target::Address(1),
+            },
+            // past the end of the image
+            Instruction::Call {
+                target: Target::Address(0x1000),
+            },
+            Instruction::Halt,
+        ] {
+            encode(&instruction, &mut bytes).unwrap();
+        }
+        let text = disassemble(&bytes, &HashMap::new());
+        assert!(!text.contains("jmp"), "{}", text);
+        assert!(!text.contains("call"), "{}", text);
+        assert!(text.contains("halt"), "{}", text);
+        assert_eq!(assemble_source(&text), bytes, "{}", text);
+    }
+
+    #[test]
+    fn undecodable_bytes() {
+        assert_eq!(
+            disassemble(&[0xff, 0x0e], &HashMap::new()),
+            format!(
+                "    {:<32} ; 0x0000\n    {:<32} ; 0x0001\n",
+                ".byte 0xff", "ret"
+            )
+        );
+    }
+}
@@ -1,57 +1,242 @@
 use std::{collections::HashMap, fmt::Debug};
 
-use crate::instruction_set::{
-    Instruction, Program, Register32, Register64, RegisterOrLiteral32, RegisterOrLiteral64,
+use crate::{
+    encoding::{DecodeError, decode},
+    instruction_set::{
+        Address, BinaryOperation, Condition, Instruction, MemoryWidth, Overflow, Register32,
+        Register64, RegisterOrLiteral32, RegisterOrLiteral64, Signedness, Target,
+    },
+    memory::Memory,
 };
 
-pub struct Emulator<'a> {
+/// How many nested calls can be made before the emulator traps.
+pub const MAX_CALL_DEPTH: usize = 1024;
+
+pub struct Emulator {
     registers: [u32; 8],
-    program: Program<'a>,
-    program_counter: usize,
+    flags: Flags,
+    memory: Memory,
+    /// The address of the next instruction to fetch from memory.
+    program_counter: u32,
+    call_stack: Vec<u32>,
+    halted: bool,
+    trap: Option<Trap>,
+}
+
+/// Set by arithmetic, logical and compare instructions, and read by conditional jumps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Flags {
+    pub zero: bool,
+    /// Unsigned overflow, or a borrow out of a subtraction.
+    pub carry: bool,
+    /// Signed overflow.
+    pub overflow: bool,
+    pub negative: bool,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum StepResult {
     NotHalted,
     Halted,
+    /// The last instruction trapped. The emulator won't run any further.
+    Trapped(Trap),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trap {
+    Overflow,
+    DivideByZero,
+    StackOverflow,
+    StackUnderflow,
+    /// The bytes at `program_counter` don't decode to an instruction.
+    InvalidInstruction,
+    AddressOutOfBounds,
+    /// A memory-mapped device failed to handle a read or write.
+    DeviceError,
 }
 
-impl<'a> Emulator<'a> {
-    pub fn new(program: Program<'a>) -> Self {
-        Self {
+impl Emulator {
+    /// Loads an assembled image at the start of `memory`, ready to run from address 0.
+    pub fn new(image: &[u8], mut memory: Memory) -> Result<Self, String> {
+        memory.load_image(0, image)?;
+        Ok(Self {
             registers: [0; 8],
-            program,
+            flags: Flags::default(),
+            memory,
             program_counter: 0,
-        }
+            call_stack: Vec::new(),
+            halted: false,
+            trap: None,
+        })
+    }
+
+    /// `r1` through `r8`, in order.
+    pub fn registers(&self) -> [u32; 8] {
+        self.registers
+    }
+
+    pub fn flags(&self) -> Flags {
+        self.flags
+    }
+
+    pub fn program_counter(&self) -> u32 {
+        self.program_counter
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Whether a `halt` has run, which `step` only reports on the call after it.
+    pub fn halted(&self) -> bool {
+        self.halted
     }
 
     pub fn step(&mut self) -> StepResult {
-        match self.next_instruction() {
-            Some(Instruction::Add32 {
+        if let Some(trap) = self.trap {
+            return StepResult::Trapped(trap);
+        }
+        if self.halted {
+            return StepResult::Halted;
+        }
+        if let Err(trap) = self
+            .next_instruction()
+            .and_then(|instruction| self.execute(instruction))
+        {
+            self.trap = Some(trap);
+            return StepResult::Trapped(trap);
+        }
+        StepResult::NotHalted
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<(), Trap> {
+        match instruction {
+            Instruction::Binary32 {
+                operation,
                 destination,
                 left,
                 right,
-            }) => {
-                self.set_register_32(
-                    destination,
-                    self.get_register_or_literal_32(&left)
-                        + self.get_register_or_literal_32(&right),
-                );
+            } => {
+                let (value, flags) = binary_32(
+                    operation,
+                    self.get_register_or_literal_32(&left),
+                    self.get_register_or_literal_32(&right),
+                )?;
+                self.set_register_32(destination, value);
+                self.flags = flags;
             }
-            Some(Instruction::Add64 {
+            Instruction::Binary64 {
+                operation,
                 destination,
                 left,
                 right,
-            }) => {
-                self.set_register_64(
-                    destination,
-                    self.get_register_or_literal_64(&left)
-                        + self.get_register_or_literal_64(&right),
-                );
+            } => {
+                let (value, flags) = binary_64(
+                    operation,
+                    self.get_register_or_literal_64(&left),
+                    self.get_register_or_literal_64(&right),
+                )?;
+                self.set_register_64(destination, value);
+                self.flags = flags;
+            }
+            Instruction::Not32 {
+                destination,
+                source,
+            } => {
+                let value = !self.get_register_or_literal_32(&source);
+                self.set_register_32(destination, value);
+                self.flags = Flags {
+                    zero: value == 0,
+                    negative: (value as i32) < 0,
+                    ..Flags::default()
+                };
+            }
+            Instruction::Not64 {
+                destination,
+                source,
+            } => {
+                let value = !self.get_register_or_literal_64(&source);
+                self.set_register_64(destination, value);
+                self.flags = Flags {
+                    zero: value == 0,
+                    negative: (value as i64) < 0,
+                    ..Flags::default()
+                };
+            }
+            Instruction::Mov32 {
+                destination,
+                source,
+            } => self.set_register_32(destination, self.get_register_or_literal_32(&source)),
+            Instruction::Mov64 {
+                destination,
+                source,
+            } => self.set_register_64(destination, self.get_register_or_literal_64(&source)),
+            Instruction::Load32 {
+                width,
+                signedness,
+                destination,
+                address,
+            } => {
+                let value = self
+                    .memory
+                    .read(self.effective_address(address), width.bytes())?
+                    as u32;
+                let value = match (signedness, width) {
+                    (Signedness::Signed, MemoryWidth::Byte) => value as u8 as i8 as u32,
+                    (Signedness::Signed, MemoryWidth::Half) => value as u16 as i16 as u32,
+                    _ => value,
+                };
+                self.set_register_32(destination, value);
+            }
+            Instruction::Load64 {
+                destination,
+                address,
+            } => {
+                let value = self.memory.read(self.effective_address(address), 8)?;
+                self.set_register_64(destination, value);
+            }
+            Instruction::Store32 {
+                width,
+                source,
+                address,
+            } => self.memory.write(
+                self.effective_address(address),
+                width.bytes(),
+                self.get_register_or_literal_32(&source) as u64,
+            )?,
+            Instruction::Store64 { source, address } => self.memory.write(
+                self.effective_address(address),
+                8,
+                self.get_register_or_literal_64(&source),
+            )?,
+            Instruction::LoadAddress {
+                destination,
+                target,
+            } => self.set_register_32(destination, target_address(&target)?),
+            Instruction::Jump { condition, target } => {
+                if self.flags.satisfy(condition) {
+                    self.program_counter = target_address(&target)?;
+                }
+            }
+            Instruction::Call { target } => {
+                if self.call_stack.len() >= MAX_CALL_DEPTH {
+                    return Err(Trap::StackOverflow);
+                }
+                let target = target_address(&target)?;
+                self.call_stack.push(self.program_counter);
+                self.program_counter = target;
+            }
+            Instruction::Ret => {
+                self.program_counter = self.call_stack.pop().ok_or(Trap::StackUnderflow)?;
             }
-            None => return StepResult::Halted,
+            Instruction::Halt => self.halted = true,
         }
-        StepResult::NotHalted
+        Ok(())
+    }
+
+    fn effective_address(&self, address: Address) -> u32 {
+        self.get_register_32(address.base)
+            .wrapping_add(address.offset as u32)
     }
 
     fn get_register_32(&self, r: Register32) -> u32 {
@@ -91,25 +276,167 @@ impl<'a> Emulator<'a> {
         }
     }
 
-    fn next_instruction(&mut self) -> Option<Instruction> {
-        let result = self.program.instructions.get(self.program_counter).cloned();
-        if result.is_some() {
-            self.program_counter += 1;
-        }
-        result
+    /// Decodes the instruction at `program_counter`, and how long it is, without running it. Instructions are only
+    /// fetched from RAM.
+    pub fn peek_instruction(&self) -> Result<(Instruction, u32), Trap> {
+        let bytes = self
+            .memory
+            .ram_from(self.program_counter)
+            .ok_or(Trap::AddressOutOfBounds)?;
+        let (instruction, length) = decode(bytes).map_err(|e| match e {
+            DecodeError::Truncated => Trap::AddressOutOfBounds,
+            DecodeError::InvalidOpcode(_) | DecodeError::InvalidOperand(_) => {
+                Trap::InvalidInstruction
+            }
+        })?;
+        Ok((instruction, length as u32))
+    }
+
+    fn next_instruction(&mut self) -> Result<Instruction, Trap> {
+        let (instruction, length) = self.peek_instruction()?;
+        self.program_counter += length;
+        Ok(instruction)
     }
 }
 
-impl<'a> Debug for Emulator<'a> {
+impl Debug for Emulator {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         // TODO better debug for emulator?
         f.debug_struct("Emulator")
             .field("registers", &self.registers)
+            .field("flags", &self.flags)
             .field("program_counter", &self.program_counter)
+            .field("call_stack", &self.call_stack)
+            .field("memory", &self.memory)
             .finish()
     }
 }
 
+/// Decoded instructions always have addresses, but one built by hand might still have a label.
+fn target_address(target: &Target) -> Result<u32, Trap> {
+    match target {
+        Target::Address(address) => Ok(*address),
+        Target::Label(_) => Err(Trap::InvalidInstruction),
+    }
+}
+
+impl Flags {
+    fn satisfy(&self, condition: Condition) -> bool {
+        match condition {
+            Condition::Always => true,
+            Condition::Zero => self.zero,
+            Condition::NotZero => !self.zero,
+            Condition::Carry => self.carry,
+            Condition::NotCarry => !self.carry,
+            Condition::Overflow => self.overflow,
+            Condition::NotOverflow => !self.overflow,
+            Condition::Negative => self.negative,
+            Condition::NotNegative => !self.negative,
+            Condition::Less => self.negative != self.overflow,
+            Condition::GreaterOrEqual => self.negative == self.overflow,
+            Condition::LessOrEqual => self.zero || self.negative != self.overflow,
+            Condition::Greater => !self.zero && self.negative == self.overflow,
+            Condition::Above => !self.carry && !self.zero,
+            Condition::BelowOrEqual => self.carry || self.zero,
+        }
+    }
+}
+
+fn check_overflow(mode: Overflow, carry: bool, overflow: bool) -> Result<(), Trap> {
+    match mode {
+        Overflow::TrappingSigned if overflow => Err(Trap::Overflow),
+        Overflow::TrappingUnsigned if carry => Err(Trap::Overflow),
+        _ => Ok(()),
+    }
+}
+
+macro_rules! binary_operation {
+    ($name:ident, $unsigned:ty, $signed:ty) => {
+        fn $name(
+            operation: BinaryOperation,
+            left: $unsigned,
+            right: $unsigned,
+        ) -> Result<($unsigned, Flags), Trap> {
+            let signed_left = left as $signed;
+            let signed_right = right as $signed;
+            let (value, carry, overflow) = match operation {
+                BinaryOperation::Add(mode) => {
+                    let (value, carry) = left.overflowing_add(right);
+                    let (_, overflow) = signed_left.overflowing_add(signed_right);
+                    check_overflow(mode, carry, overflow)?;
+                    (value, carry, overflow)
+                }
+                BinaryOperation::Sub(mode) => {
+                    let (value, carry) = left.overflowing_sub(right);
+                    let (_, overflow) = signed_left.overflowing_sub(signed_right);
+                    check_overflow(mode, carry, overflow)?;
+                    (value, carry, overflow)
+                }
+                BinaryOperation::Mul(mode) => {
+                    let (value, carry) = left.overflowing_mul(right);
+                    let (_, overflow) = signed_left.overflowing_mul(signed_right);
+                    check_overflow(mode, carry, overflow)?;
+                    (value, carry, overflow)
+                }
+                BinaryOperation::Div(_) | BinaryOperation::Rem(_) if right == 0 => {
+                    return Err(Trap::DivideByZero);
+                }
+                BinaryOperation::Div(Signedness::Signed) => {
+                    let value = signed_left
+                        .checked_div(signed_right)
+                        .ok_or(Trap::Overflow)?;
+                    (value as $unsigned, false, false)
+                }
+                BinaryOperation::Div(Signedness::Unsigned) => (left / right, false, false),
+                BinaryOperation::Rem(Signedness::Signed) => {
+                    let value = signed_left
+                        .checked_rem(signed_right)
+                        .ok_or(Trap::Overflow)?;
+                    (value as $unsigned, false, false)
+                }
+                BinaryOperation::Rem(Signedness::Unsigned) => (left % right, false, false),
+                BinaryOperation::And => (left & right, false, false),
+                BinaryOperation::Or => (left | right, false, false),
+                BinaryOperation::Xor => (left ^ right, false, false),
+                BinaryOperation::ShiftLeft => (left.wrapping_shl(right as u32), false, false),
+                BinaryOperation::ShiftRightLogical => {
+                    (left.wrapping_shr(right as u32), false, false)
+                }
+                BinaryOperation::ShiftRightArithmetic => (
+                    signed_left.wrapping_shr(right as u32) as $unsigned,
+                    false,
+                    false,
+                ),
+                BinaryOperation::Compare(signedness) => {
+                    let (difference, carry) = left.overflowing_sub(right);
+                    let (_, overflow) = signed_left.overflowing_sub(signed_right);
+                    let ordering = match signedness {
+                        Signedness::Signed => signed_left.cmp(&signed_right),
+                        Signedness::Unsigned => left.cmp(&right),
+                    };
+                    let flags = Flags {
+                        zero: difference == 0,
+                        carry,
+                        overflow,
+                        negative: (difference as $signed) < 0,
+                    };
+                    return Ok((ordering as $signed as $unsigned, flags));
+                }
+            };
+            let flags = Flags {
+                zero: value == 0,
+                carry,
+                overflow,
+                negative: (value as $signed) < 0,
+            };
+            Ok((value, flags))
+        }
+    };
+}
+
+binary_operation!(binary_32, u32, i32);
+binary_operation!(binary_64, u64, i64);
+
 fn register_32_to_index(r: Register32) -> usize {
     match r {
         Register32::R1 => 0,
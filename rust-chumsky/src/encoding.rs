@@ -0,0 +1,578 @@
+//! The machine code format.
+//!
+//! Every instruction starts with a one-byte opcode, followed by its operands in the order they're written in assembly:
+//!
+//! - registers are one byte, their index in [`Register32::ALL`] or [`Register64::ALL`]
+//! - register-or-literal operands are a tag byte, then a register byte, or a little-endian literal the width of the
+//!   register
+//! - addresses are a base register byte and a little-endian `i32` offset
+//! - jump, call and `la` targets are a little-endian `u32` absolute address
+//! - binary operations and conditions are one byte, their index in [`BINARY_OPERATIONS`] or [`CONDITIONS`]
+//!
+//! Opcode 0 is `halt`, so running into zeroed memory stops the emulator.
+
+use crate::instruction_set::{
+    Address, BINARY_OPERATIONS, CONDITIONS, Instruction, MemoryWidth, Register32, Register64,
+    RegisterOrLiteral32, RegisterOrLiteral64, Signedness, Target,
+};
+
+const HALT: u8 = 0x00;
+const BINARY_32: u8 = 0x01;
+const BINARY_64: u8 = 0x02;
+const NOT_32: u8 = 0x03;
+const NOT_64: u8 = 0x04;
+const MOV_32: u8 = 0x05;
+const MOV_64: u8 = 0x06;
+const LOAD_32: u8 = 0x07;
+const LOAD_64: u8 = 0x08;
+const STORE_32: u8 = 0x09;
+const STORE_64: u8 = 0x0a;
+const LOAD_ADDRESS: u8 = 0x0b;
+const JUMP: u8 = 0x0c;
+const CALL: u8 = 0x0d;
+const RET: u8 = 0x0e;
+
+const REGISTER_OPERAND: u8 = 0;
+const UNSIGNED_OPERAND: u8 = 1;
+const SIGNED_OPERAND: u8 = 2;
+
+const MEMORY_WIDTHS: [MemoryWidth; 3] = [MemoryWidth::Byte, MemoryWidth::Half, MemoryWidth::Word];
+const SIGNEDNESSES: [Signedness; 2] = [Signedness::Unsigned, Signedness::Signed];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The bytes ran out partway through an instruction.
+    Truncated,
+    InvalidOpcode(u8),
+    InvalidOperand(u8),
+}
+
+/// Appends the encoding of `instruction` to `bytes`. Targets have to be addresses by now.
+pub fn encode(instruction: &Instruction, bytes: &mut Vec<u8>) -> Result<(), String> {
+    match instruction {
+        Instruction::Halt => bytes.push(HALT),
+        Instruction::Binary32 {
+            operation,
+            destination,
+            left,
+            right,
+        } => {
+            bytes.push(BINARY_32);
+            bytes.push(index_of(&BINARY_OPERATIONS.map(|(_, o)| o), operation));
+            encode_register_32(*destination, bytes);
+            encode_register_or_literal_32(left, bytes);
+            encode_register_or_literal_32(right, bytes);
+        }
+        Instruction::Binary64 {
+            operation,
+            destination,
+            left,
+            right,
+        } => {
+            bytes.push(BINARY_64);
+            bytes.push(index_of(&BINARY_OPERATIONS.map(|(_, o)| o), operation));
+            encode_register_64(*destination, bytes);
+            encode_register_or_literal_64(left, bytes);
+            encode_register_or_literal_64(right, bytes);
+        }
+        Instruction::Not32 {
+            destination,
+            source,
+        } => {
+            bytes.push(NOT_32);
+            encode_register_32(*destination, bytes);
+            encode_register_or_literal_32(source, bytes);
+        }
+        Instruction::Not64 {
+            destination,
+            source,
+        } => {
+            bytes.push(NOT_64);
+            encode_register_64(*destination, bytes);
+            encode_register_or_literal_64(source, bytes);
+        }
+        Instruction::Mov32 {
+            destination,
+            source,
+        } => {
+            bytes.push(MOV_32);
+            encode_register_32(*destination, bytes);
+            encode_register_or_literal_32(source, bytes);
+        }
+        Instruction::Mov64 {
+            destination,
+            source,
+        } => {
+            bytes.push(MOV_64);
+            encode_register_64(*destination, bytes);
+            encode_register_or_literal_64(source, bytes);
+        }
+        Instruction::Load32 {
+            width,
+            signedness,
+            destination,
+            address,
+        } => {
+            bytes.push(LOAD_32);
+            bytes.push(index_of(&MEMORY_WIDTHS, width));
+            bytes.push(index_of(&SIGNEDNESSES, signedness));
+            encode_register_32(*destination, bytes);
+            encode_address(*address, bytes);
+        }
+        Instruction::Load64 {
+            destination,
+            address,
+        } => {
+            bytes.push(LOAD_64);
+            encode_register_64(*destination, bytes);
+            encode_address(*address, bytes);
+        }
+        Instruction::Store32 {
+            width,
+            source,
+            address,
+        } => {
+            bytes.push(STORE_32);
+            bytes.push(index_of(&MEMORY_WIDTHS, width));
+            encode_register_or_literal_32(source, bytes);
+            encode_address(*address, bytes);
+        }
+        Instruction::Store64 { source, address } => {
+            bytes.push(STORE_64);
+            encode_register_or_literal_64(source, bytes);
+            encode_address(*address, bytes);
+        }
+        Instruction::LoadAddress {
+            destination,
+            target,
+        } => {
+            bytes.push(LOAD_ADDRESS);
+            encode_register_32(*destination, bytes);
+            encode_target(target, bytes)?;
+        }
+        Instruction::Jump { condition, target } => {
+            bytes.push(JUMP);
+            bytes.push(index_of(&CONDITIONS.map(|(_, c)| c), condition));
+            encode_target(target, bytes)?;
+        }
+        Instruction::Call { target } => {
+            bytes.push(CALL);
+            encode_target(target, bytes)?;
+        }
+        Instruction::Ret => bytes.push(RET),
+    }
+    Ok(())
+}
+
+/// Decodes the instruction at the start of `bytes`, returning it and how many bytes it took up.
+pub fn decode(bytes: &[u8]) -> Result<(Instruction, usize), DecodeError> {
+    let mut reader = Reader { bytes, position: 0 };
+    let instruction = match reader.byte()? {
+        HALT => Instruction::Halt,
+        BINARY_32 => Instruction::Binary32 {
+            operation: reader.choice(&BINARY_OPERATIONS.map(|(_, o)| o))?,
+            destination: reader.register_32()?,
+            left: reader.register_or_literal_32()?,
+            right: reader.register_or_literal_32()?,
+        },
+        BINARY_64 => Instruction::Binary64 {
+            operation: reader.choice(&BINARY_OPERATIONS.map(|(_, o)| o))?,
+            destination: reader.register_64()?,
+            left: reader.register_or_literal_64()?,
+            right: reader.register_or_literal_64()?,
+        },
+        NOT_32 => Instruction::Not32 {
+            destination: reader.register_32()?,
+            source: reader.register_or_literal_32()?,
+        },
+        NOT_64 => Instruction::Not64 {
+            destination: reader.register_64()?,
+            source: reader.register_or_literal_64()?,
+        },
+        MOV_32 => Instruction::Mov32 {
+            destination: reader.register_32()?,
+            source: reader.register_or_literal_32()?,
+        },
+        MOV_64 => Instruction::Mov64 {
+            destination: reader.register_64()?,
+            source: reader.register_or_literal_64()?,
+        },
+        LOAD_32 => Instruction::Load32 {
+            width: reader.choice(&MEMORY_WIDTHS)?,
+            signedness: reader.choice(&SIGNEDNESSES)?,
+            destination: reader.register_32()?,
+            address: reader.address()?,
+        },
+        LOAD_64 => Instruction::Load64 {
+            destination: reader.register_64()?,
+            address: reader.address()?,
+        },
+        STORE_32 => Instruction::Store32 {
+            width: reader.choice(&MEMORY_WIDTHS)?,
+            source: reader.register_or_literal_32()?,
+            address: reader.address()?,
+        },
+        STORE_64 => Instruction::Store64 {
+            source: reader.register_or_literal_64()?,
+            address: reader.address()?,
+        },
+        LOAD_ADDRESS => Instruction::LoadAddress {
+            destination: reader.register_32()?,
+            target: Target::Address(reader.u32()?),
+        },
+        JUMP => Instruction::Jump {
+            condition: reader.choice(&CONDITIONS.map(|(_, c)| c))?,
+            target: Target::Address(reader.u32()?),
+        },
+        CALL => Instruction::Call {
+            target: Target::Address(reader.u32()?),
+        },
+        RET => Instruction::Ret,
+        opcode => return Err(DecodeError::InvalidOpcode(opcode)),
+    };
+    Ok((instruction, reader.position))
+}
+
+fn index_of<T: PartialEq>(values: &[T], value: &T) -> u8 {
+    values
+        .iter()
+        .position(|v| v == value)
+        .expect("every value is in its encoding table") as u8
+}
+
+fn encode_register_32(register: Register32, bytes: &mut Vec<u8>) {
+    bytes.push(index_of(&Register32::ALL, &register));
+}
+
+fn encode_register_64(register: Register64, bytes: &mut Vec<u8>) {
+    bytes.push(index_of(&Register64::ALL, &register));
+}
+
+fn encode_register_or_literal_32(value: &RegisterOrLiteral32, bytes: &mut Vec<u8>) {
+    match value {
+        RegisterOrLiteral32::Register(register) => {
+            bytes.push(REGISTER_OPERAND);
+            encode_register_32(*register, bytes);
+        }
+        RegisterOrLiteral32::U32(value) => {
+            bytes.push(UNSIGNED_OPERAND);
+            bytes.extend(value.to_le_bytes());
+        }
+        RegisterOrLiteral32::I32(value) => {
+            bytes.push(SIGNED_OPERAND);
+            bytes.extend(value.to_le_bytes());
+        }
+    }
+}
+
+fn encode_register_or_literal_64(value: &RegisterOrLiteral64, bytes: &mut Vec<u8>) {
+    match value {
+        RegisterOrLiteral64::Register(register) => {
+            bytes.push(REGISTER_OPERAND);
+            encode_register_64(*register, bytes);
+        }
+        RegisterOrLiteral64::U64(value) => {
+            bytes.push(UNSIGNED_OPERAND);
+            bytes.extend(value.to_le_bytes());
+        }
+        RegisterOrLiteral64::I64(value) => {
+            bytes.push(SIGNED_OPERAND);
+            bytes.extend(value.to_le_bytes());
+        }
+    }
+}
+
+fn encode_address(address: Address, bytes: &mut Vec<u8>) {
+    encode_register_32(address.base, bytes);
+    bytes.extend(address.offset.to_le_bytes());
+}
+
+fn encode_target(target: &Target, bytes: &mut Vec<u8>) -> Result<(), String> {
+    match target {
+        Target::Address(address) => {
+            bytes.extend(address.to_le_bytes());
+            Ok(())
+        }
+        Target::Label(label) => Err(format!("unresolved label: {}", label)),
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, DecodeError> {
+        let byte = *self
+            .bytes
+            .get(self.position)
+            .ok_or(DecodeError::Truncated)?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let mut array = [0; N];
+        for byte in &mut array {
+            *byte = self.byte()?;
+        }
+        Ok(array)
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn choice<T: Copy>(&mut self, values: &[T]) -> Result<T, DecodeError> {
+        let byte = self.byte()?;
+        values
+            .get(byte as usize)
+            .copied()
+            .ok_or(DecodeError::InvalidOperand(byte))
+    }
+
+    fn register_32(&mut self) -> Result<Register32, DecodeError> {
+        self.choice(&Register32::ALL)
+    }
+
+    fn register_64(&mut self) -> Result<Register64, DecodeError> {
+        self.choice(&Register64::ALL)
+    }
+
+    fn register_or_literal_32(&mut self) -> Result<RegisterOrLiteral32, DecodeError> {
+        match self.byte()? {
+            REGISTER_OPERAND => Ok(RegisterOrLiteral32::Register(self.register_32()?)),
+            UNSIGNED_OPERAND => Ok(RegisterOrLiteral32::U32(u32::from_le_bytes(self.array()?))),
+            SIGNED_OPERAND => Ok(RegisterOrLiteral32::I32(i32::from_le_bytes(self.array()?))),
+            tag => Err(DecodeError::InvalidOperand(tag)),
+        }
+    }
+
+    fn register_or_literal_64(&mut self) -> Result<RegisterOrLiteral64, DecodeError> {
+        match self.byte()? {
+            REGISTER_OPERAND => Ok(RegisterOrLiteral64::Register(self.register_64()?)),
+            UNSIGNED_OPERAND => Ok(RegisterOrLiteral64::U64(u64::from_le_bytes(self.array()?))),
+            SIGNED_OPERAND => Ok(RegisterOrLiteral64::I64(i64::from_le_bytes(self.array()?))),
+            tag => Err(DecodeError::InvalidOperand(tag)),
+        }
+    }
+
+    fn address(&mut self) -> Result<Address, DecodeError> {
+        Ok(Address {
+            base: self.register_32()?,
+            offset: i32::from_le_bytes(self.array()?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instruction_set::{BinaryOperation, Condition, Overflow};
+
+    /// At least one of every instruction, operand tag, width, signedness, operation and condition, with literals at
+    /// the edges of their ranges.
+    fn instructions() -> Vec<Instruction> {
+        let mut instructions = vec![
+            Instruction::Halt,
+            Instruction::Ret,
+            Instruction::Not32 {
+                destination: Register32::R8,
+                source: RegisterOrLiteral32::I32(i32::MIN),
+            },
+            Instruction::Not64 {
+                destination: Register64::R78,
+                source: RegisterOrLiteral64::U64(u64::MAX),
+            },
+            Instruction::Mov32 {
+                destination: Register32::R1,
+                source: RegisterOrLiteral32::U32(u32::MAX),
+            },
+            Instruction::Mov64 {
+                destination: Register64::R12,
+                source: RegisterOrLiteral64::I64(i64::MIN),
+            },
+            Instruction::Load64 {
+                destination: Register64::R56,
+                address: Address {
+                    base: Register32::R4,
+                    offset: -8,
+                },
+            },
+            Instruction::Store64 {
+                source: RegisterOrLiteral64::Register(Register64::R34),
+                address: Address {
+                    base: Register32::R5,
+                    offset: i32::MAX,
+                },
+            },
+            Instruction::LoadAddress {
+                destination: Register32::R2,
+                target: Target::Address(0x1234_5678),
+            },
+            Instruction::Call {
+                target: Target::Address(u32::MAX),
+            },
+        ];
+        for (i, (_, operation)) in BINARY_OPERATIONS.into_iter().enumerate() {
+            let register_32 = Register32::ALL[i % Register32::ALL.len()];
+            let register_64 = Register64::ALL[i % Register64::ALL.len()];
+            instructions.push(Instruction::Binary32 {
+                operation,
+                destination: register_32,
+                left: RegisterOrLiteral32::Register(register_32),
+                right: [
+                    RegisterOrLiteral32::Register(Register32::R7),
+                    RegisterOrLiteral32::U32(i as u32),
+                    RegisterOrLiteral32::I32(-(i as i32)),
+                ][i % 3]
+                    .clone(),
+            });
+            instructions.push(Instruction::Binary64 {
+                operation,
+                destination: register_64,
+                left: RegisterOrLiteral64::Register(register_64),
+                right: [
+                    RegisterOrLiteral64::Register(Register64::R56),
+                    RegisterOrLiteral64::U64(1 << 40),
+                    RegisterOrLiteral64::I64(-(1 << 40)),
+                ][i % 3]
+                    .clone(),
+            });
+        }
+        for (i, (_, condition)) in CONDITIONS.into_iter().enumerate() {
+            instructions.push(Instruction::Jump {
+                condition,
+                target: Target::Address(i as u32 * 4),
+            });
+        }
+        for width in MEMORY_WIDTHS {
+            for signedness in SIGNEDNESSES {
+                instructions.push(Instruction::Load32 {
+                    width,
+                    signedness,
+                    destination: Register32::R3,
+                    address: Address {
+                        base: Register32::R6,
+                        offset: i32::MIN,
+                    },
+                });
+            }
+            for source in [
+                RegisterOrLiteral32::Register(Register32::R2),
+                RegisterOrLiteral32::U32(0xff),
+                RegisterOrLiteral32::I32(-1),
+            ] {
+                instructions.push(Instruction::Store32 {
+                    width,
+                    source,
+                    address: Address {
+                        base: Register32::R1,
+                        offset: 0,
+                    },
+                });
+            }
+        }
+        instructions
+    }
+
+    fn encoded(instruction: &Instruction) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        encode(instruction, &mut bytes).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn round_trip() {
+        for instruction in instructions() {
+            let mut bytes = encoded(&instruction);
+            // anything after the instruction isn't part of it
+            let length = bytes.len();
+            bytes.push(0xff);
+            assert_eq!(
+                decode(&bytes),
+                Ok((instruction.clone(), length)),
+                "{}",
+                instruction
+            );
+        }
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(decode(&[]), Err(DecodeError::Truncated));
+        for instruction in instructions() {
+            let bytes = encoded(&instruction);
+            for length in 1..bytes.len() {
+                assert_eq!(
+                    decode(&bytes[..length]),
+                    Err(DecodeError::Truncated),
+                    "{} cut to {} bytes",
+                    instruction,
+                    length
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_opcode() {
+        assert_eq!(decode(&[0x0f]), Err(DecodeError::InvalidOpcode(0x0f)));
+        assert_eq!(decode(&[0xff]), Err(DecodeError::InvalidOpcode(0xff)));
+    }
+
+    #[test]
+    fn invalid_operands() {
+        let add = Instruction::Binary32 {
+            operation: BinaryOperation::Add(Overflow::Wrapping),
+            destination: Register32::R1,
+            left: RegisterOrLiteral32::Register(Register32::R2),
+            right: RegisterOrLiteral32::U32(1),
+        };
+        let jump = Instruction::Jump {
+            condition: Condition::Always,
+            target: Target::Address(0),
+        };
+        let load = Instruction::Load32 {
+            width: MemoryWidth::Byte,
+            signedness: Signedness::Unsigned,
+            destination: Register32::R1,
+            address: Address {
+                base: Register32::R2,
+                offset: 0,
+            },
+        };
+        // (instruction, index of the byte to replace, replacement)
+        let cases = [
+            (&add, 1, BINARY_OPERATIONS.len() as u8),
+            (&add, 2, Register32::ALL.len() as u8),
+            (&add, 3, 3),
+            (&add, 4, 0xff),
+            (&jump, 1, CONDITIONS.len() as u8),
+            (&load, 1, MEMORY_WIDTHS.len() as u8),
+            (&load, 2, SIGNEDNESSES.len() as u8),
+            (&load, 4, Register32::ALL.len() as u8),
+        ];
+        for (instruction, index, byte) in cases {
+            let mut bytes = encoded(instruction);
+            bytes[index] = byte;
+            assert_eq!(
+                decode(&bytes),
+                Err(DecodeError::InvalidOperand(byte)),
+                "{} with byte {} set to {:#x}",
+                instruction,
+                index,
+                byte
+            );
+        }
+    }
+
+    #[test]
+    fn unresolved_label() {
+        let call = Instruction::Call {
+            target: Target::Label("main".to_string()),
+        };
+        assert!(encode(&call, &mut Vec::new()).is_err());
+    }
+}
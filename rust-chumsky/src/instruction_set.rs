@@ -1,4 +1,8 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    str::FromStr,
+};
 
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 pub enum Register32 {
@@ -20,41 +24,289 @@ pub enum Register64 {
     R78,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RegisterOrLiteral32 {
     Register(Register32),
     U32(u32),
     I32(i32),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RegisterOrLiteral64 {
     Register(Register64),
     U64(u64),
     I64(i64),
 }
 
-#[derive(Debug, Clone)]
+/// What happens when an arithmetic result doesn't fit in the destination.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Overflow {
+    Wrapping,
+    /// Traps if the result overflows when the operands are read as two's complement.
+    TrappingSigned,
+    /// Traps if the result overflows when the operands are read as unsigned.
+    TrappingUnsigned,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signedness {
+    Signed,
+    Unsigned,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOperation {
+    Add(Overflow),
+    Sub(Overflow),
+    Mul(Overflow),
+    /// Traps on division by zero, and on `MIN / -1` when signed.
+    Div(Signedness),
+    Rem(Signedness),
+    And,
+    Or,
+    Xor,
+    /// Shift amounts are taken modulo the register width.
+    ShiftLeft,
+    ShiftRightLogical,
+    ShiftRightArithmetic,
+    /// Writes -1, 0 or 1 depending on whether the left operand is less than, equal to or greater than the right.
+    /// Flags are set as if the right operand had been subtracted from the left.
+    Compare(Signedness),
+}
+
+/// How many bytes a load or store into a 32-bit register moves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryWidth {
+    Byte,
+    Half,
+    Word,
+}
+
+/// A register holding a base address plus a constant byte offset, written `[r2 + 8]`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Address {
+    pub base: Register32,
+    pub offset: i32,
+}
+
+/// Where a jump, call or `la` points. The parser produces labels, and the assembler swaps them for addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    Label(String),
+    Address(u32),
+}
+
+/// Which flags a jump looks at. The signed and unsigned orderings assume the flags came from a `cmp` or `sub`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    Always,
+    Zero,
+    NotZero,
+    Carry,
+    NotCarry,
+    Overflow,
+    NotOverflow,
+    Negative,
+    NotNegative,
+    Less,
+    GreaterOrEqual,
+    LessOrEqual,
+    Greater,
+    Above,
+    BelowOrEqual,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Instruction {
-    Add32 {
+    Binary32 {
+        operation: BinaryOperation,
         destination: Register32,
         left: RegisterOrLiteral32,
         right: RegisterOrLiteral32,
     },
-    Add64 {
+    Binary64 {
+        operation: BinaryOperation,
         destination: Register64,
         left: RegisterOrLiteral64,
         right: RegisterOrLiteral64,
     },
+    Not32 {
+        destination: Register32,
+        source: RegisterOrLiteral32,
+    },
+    Not64 {
+        destination: Register64,
+        source: RegisterOrLiteral64,
+    },
+    Mov32 {
+        destination: Register32,
+        source: RegisterOrLiteral32,
+    },
+    Mov64 {
+        destination: Register64,
+        source: RegisterOrLiteral64,
+    },
+    Jump {
+        condition: Condition,
+        target: Target,
+    },
+    /// Narrower loads are zero or sign extended to fill the register.
+    Load32 {
+        width: MemoryWidth,
+        signedness: Signedness,
+        destination: Register32,
+        address: Address,
+    },
+    Load64 {
+        destination: Register64,
+        address: Address,
+    },
+    /// Narrower stores take the low bytes of `source`.
+    Store32 {
+        width: MemoryWidth,
+        source: RegisterOrLiteral32,
+        address: Address,
+    },
+    Store64 {
+        source: RegisterOrLiteral64,
+        address: Address,
+    },
+    /// Loads the address of a label.
+    LoadAddress {
+        destination: Register32,
+        target: Target,
+    },
+    /// Pushes the address of the next instruction and jumps to `target`.
+    Call {
+        target: Target,
+    },
+    /// Pops the address pushed by the last `Call` and jumps back to it.
+    Ret,
+    Halt,
+}
+
+/// Something that takes up space in the assembled image.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    Instruction(Instruction),
+    /// Bytes from `.byte`, `.word` and `.ascii` directives.
+    Data(Vec<u8>),
 }
 
 #[derive(Debug)]
 pub struct Program<'a> {
+    /// Each label is the index of the item that follows it, or `items.len()` if nothing does.
     pub labels: HashMap<&'a str, usize>,
-    pub instructions: Vec<Instruction>,
+    pub items: Vec<Item>,
+}
+
+/// Mnemonics for every binary operation. An operation's index here is also its encoding.
+pub const BINARY_OPERATIONS: [(&str, BinaryOperation); 21] = [
+    ("add", BinaryOperation::Add(Overflow::Wrapping)),
+    ("adds", BinaryOperation::Add(Overflow::TrappingSigned)),
+    ("addu", BinaryOperation::Add(Overflow::TrappingUnsigned)),
+    ("sub", BinaryOperation::Sub(Overflow::Wrapping)),
+    ("subs", BinaryOperation::Sub(Overflow::TrappingSigned)),
+    ("subu", BinaryOperation::Sub(Overflow::TrappingUnsigned)),
+    ("mul", BinaryOperation::Mul(Overflow::Wrapping)),
+    ("muls", BinaryOperation::Mul(Overflow::TrappingSigned)),
+    ("mulu", BinaryOperation::Mul(Overflow::TrappingUnsigned)),
+    ("div", BinaryOperation::Div(Signedness::Signed)),
+    ("divu", BinaryOperation::Div(Signedness::Unsigned)),
+    ("rem", BinaryOperation::Rem(Signedness::Signed)),
+    ("remu", BinaryOperation::Rem(Signedness::Unsigned)),
+    ("and", BinaryOperation::And),
+    ("or", BinaryOperation::Or),
+    ("xor", BinaryOperation::Xor),
+    ("shl", BinaryOperation::ShiftLeft),
+    ("shr", BinaryOperation::ShiftRightLogical),
+    ("sar", BinaryOperation::ShiftRightArithmetic),
+    ("cmp", BinaryOperation::Compare(Signedness::Signed)),
+    ("cmpu", BinaryOperation::Compare(Signedness::Unsigned)),
+];
+
+/// Mnemonics for every jump. A condition's index here is also its encoding.
+pub const CONDITIONS: [(&str, Condition); 15] = [
+    ("jmp", Condition::Always),
+    ("jz", Condition::Zero),
+    ("jnz", Condition::NotZero),
+    ("jc", Condition::Carry),
+    ("jnc", Condition::NotCarry),
+    ("jo", Condition::Overflow),
+    ("jno", Condition::NotOverflow),
+    ("jn", Condition::Negative),
+    ("jnn", Condition::NotNegative),
+    ("jl", Condition::Less),
+    ("jge", Condition::GreaterOrEqual),
+    ("jle", Condition::LessOrEqual),
+    ("jg", Condition::Greater),
+    ("ja", Condition::Above),
+    ("jbe", Condition::BelowOrEqual),
+];
+
+impl Instruction {
+    pub fn target(&self) -> Option<&Target> {
+        match self {
+            Instruction::Jump {
+                condition: _,
+                target,
+            }
+            | Instruction::Call { target }
+            | Instruction::LoadAddress {
+                destination: _,
+                target,
+            } => Some(target),
+            _ => None,
+        }
+    }
+
+    pub fn target_mut(&mut self) -> Option<&mut Target> {
+        match self {
+            Instruction::Jump {
+                condition: _,
+                target,
+            }
+            | Instruction::Call { target }
+            | Instruction::LoadAddress {
+                destination: _,
+                target,
+            } => Some(target),
+            _ => None,
+        }
+    }
+
+    /// The label this instruction refers to, if it hasn't been assembled yet.
+    pub fn label(&self) -> Option<&str> {
+        match self.target() {
+            Some(Target::Label(label)) => Some(label),
+            _ => None,
+        }
+    }
+}
+
+impl Register32 {
+    /// Every register, in encoding order.
+    pub const ALL: [Register32; 8] = [
+        Register32::R1,
+        Register32::R2,
+        Register32::R3,
+        Register32::R4,
+        Register32::R5,
+        Register32::R6,
+        Register32::R7,
+        Register32::R8,
+    ];
 }
 
 impl Register64 {
+    /// Every register pair, in encoding order.
+    pub const ALL: [Register64; 4] = [
+        Register64::R12,
+        Register64::R34,
+        Register64::R56,
+        Register64::R78,
+    ];
+
     pub fn low(self) -> Register32 {
         match self {
             Register64::R12 => Register32::R2,
@@ -74,6 +326,16 @@ impl Register64 {
     }
 }
 
+impl MemoryWidth {
+    pub fn bytes(self) -> u32 {
+        match self {
+            MemoryWidth::Byte => 1,
+            MemoryWidth::Half => 2,
+            MemoryWidth::Word => 4,
+        }
+    }
+}
+
 impl<'a> FromStr for Register32 {
     type Err = String;
 
@@ -105,3 +367,177 @@ impl<'a> FromStr for Register64 {
         }
     }
 }
+
+impl FromStr for BinaryOperation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BINARY_OPERATIONS
+            .iter()
+            .find(|(mnemonic, _)| *mnemonic == s)
+            .map(|(_, operation)| *operation)
+            .ok_or_else(|| format!("not a valid binary operation: {}", s))
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        CONDITIONS
+            .iter()
+            .find(|(mnemonic, _)| *mnemonic == s)
+            .map(|(_, condition)| *condition)
+            .ok_or_else(|| format!("not a valid jump: {}", s))
+    }
+}
+
+// Instructions display as assembly the parser accepts, as long as their targets are labels.
+
+impl Display for Register32 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl Display for Register64 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", format!("{:?}", self).to_lowercase())
+    }
+}
+
+impl Display for RegisterOrLiteral32 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterOrLiteral32::Register(register) => write!(f, "{}", register),
+            RegisterOrLiteral32::U32(value) => write!(f, "{}", value),
+            // unsuffixed non-negative literals parse as unsigned
+            RegisterOrLiteral32::I32(value) if *value >= 0 => write!(f, "{}i32", value),
+            RegisterOrLiteral32::I32(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl Display for RegisterOrLiteral64 {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RegisterOrLiteral64::Register(register) => write!(f, "{}", register),
+            RegisterOrLiteral64::U64(value) => write!(f, "{}", value),
+            RegisterOrLiteral64::I64(value) if *value >= 0 => write!(f, "{}i64", value),
+            RegisterOrLiteral64::I64(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.offset {
+            0 => write!(f, "[{}]", self.base),
+            offset if offset < 0 => write!(f, "[{} - {}]", self.base, offset.unsigned_abs()),
+            offset => write!(f, "[{} + {}]", self.base, offset),
+        }
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Target::Label(label) => write!(f, "{}", label),
+            Target::Address(address) => write!(f, "{:#x}", address),
+        }
+    }
+}
+
+impl Display for BinaryOperation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (mnemonic, _) = BINARY_OPERATIONS
+            .iter()
+            .find(|(_, operation)| operation == self)
+            .expect("every binary operation has a mnemonic");
+        write!(f, "{}", mnemonic)
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (mnemonic, _) = CONDITIONS
+            .iter()
+            .find(|(_, condition)| condition == self)
+            .expect("every condition has a mnemonic");
+        write!(f, "{}", mnemonic)
+    }
+}
+
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Instruction::Binary32 {
+                operation,
+                destination,
+                left,
+                right,
+            } => write!(f, "{} {}, {}, {}", operation, destination, left, right),
+            Instruction::Binary64 {
+                operation,
+                destination,
+                left,
+                right,
+            } => write!(f, "{} {}, {}, {}", operation, destination, left, right),
+            Instruction::Not32 {
+                destination,
+                source,
+            } => write!(f, "not {}, {}", destination, source),
+            Instruction::Not64 {
+                destination,
+                source,
+            } => write!(f, "not {}, {}", destination, source),
+            Instruction::Mov32 {
+                destination,
+                source,
+            } => write!(f, "mov {}, {}", destination, source),
+            Instruction::Mov64 {
+                destination,
+                source,
+            } => write!(f, "mov {}, {}", destination, source),
+            Instruction::Load32 {
+                width,
+                signedness,
+                destination,
+                address,
+            } => {
+                let suffix = match (width, signedness) {
+                    (MemoryWidth::Word, _) | (_, Signedness::Unsigned) => "",
+                    (_, Signedness::Signed) => "s",
+                };
+                write!(
+                    f,
+                    "load{}{} {}, {}",
+                    width.bytes() * 8,
+                    suffix,
+                    destination,
+                    address
+                )
+            }
+            Instruction::Load64 {
+                destination,
+                address,
+            } => write!(f, "load64 {}, {}", destination, address),
+            Instruction::Store32 {
+                width,
+                source,
+                address,
+            } => write!(f, "store{} {}, {}", width.bytes() * 8, source, address),
+            Instruction::Store64 { source, address } => {
+                write!(f, "store64 {}, {}", source, address)
+            }
+            Instruction::LoadAddress {
+                destination,
+                target,
+            } => write!(f, "la {}, {}", destination, target),
+            Instruction::Jump { condition, target } => write!(f, "{} {}", condition, target),
+            Instruction::Call { target } => write!(f, "call {}", target),
+            Instruction::Ret => write!(f, "ret"),
+            Instruction::Halt => write!(f, "halt"),
+        }
+    }
+}
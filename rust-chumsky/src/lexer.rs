@@ -0,0 +1,160 @@
+use std::fmt::{self, Display, Formatter};
+
+use chumsky::{extra::Err, prelude::*};
+
+pub type Spanned<T> = (T, SimpleSpan);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IntegerSuffix {
+    U32,
+    I32,
+    U64,
+    I64,
+}
+
+/// An integer as written, before a sign is applied. The parser picks the type from the suffix, or from the value if
+/// there isn't one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IntegerLiteral {
+    pub value: u64,
+    pub suffix: Option<IntegerSuffix>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token<'a> {
+    Identifier(&'a str),
+    /// `.byte`, `.word` and so on, without the dot.
+    Directive(&'a str),
+    /// Decimal, `0x` hex, `0b` binary or a `'c'` character literal.
+    Integer(IntegerLiteral),
+    String(String),
+    Comma,
+    Colon,
+    Plus,
+    Minus,
+    LeftBracket,
+    RightBracket,
+    /// Instructions end at the end of the line, so newlines are tokens rather than whitespace.
+    Newline,
+}
+
+impl Display for Token<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Identifier(identifier) => write!(f, "{}", identifier),
+            Token::Directive(directive) => write!(f, ".{}", directive),
+            Token::Integer(IntegerLiteral { value, suffix }) => {
+                write!(f, "{}", value)?;
+                match suffix {
+                    Some(IntegerSuffix::U32) => write!(f, "u32"),
+                    Some(IntegerSuffix::I32) => write!(f, "i32"),
+                    Some(IntegerSuffix::U64) => write!(f, "u64"),
+                    Some(IntegerSuffix::I64) => write!(f, "i64"),
+                    None => Ok(()),
+                }
+            }
+            Token::String(string) => write!(f, "{:?}", string),
+            Token::Comma => write!(f, ","),
+            Token::Colon => write!(f, ":"),
+            Token::Plus => write!(f, "+"),
+            Token::Minus => write!(f, "-"),
+            Token::LeftBracket => write!(f, "["),
+            Token::RightBracket => write!(f, "]"),
+            Token::Newline => write!(f, "newline"),
+        }
+    }
+}
+
+pub fn lexer<'a>() -> impl Parser<'a, &'a str, Vec<Spanned<Token<'a>>>, Err<Rich<'a, char>>> {
+    let identifier = regex("[a-zA-Z_][a-zA-Z0-9_]*");
+
+    let token = choice((
+        identifier.clone().map(Token::Identifier),
+        just('.').ignore_then(identifier).map(Token::Directive),
+        integer().map(Token::Integer),
+        character().map(|c| {
+            Token::Integer(IntegerLiteral {
+                value: c as u64,
+                suffix: None,
+            })
+        }),
+        string().map(Token::String),
+        just(',').to(Token::Comma),
+        just(':').to(Token::Colon),
+        just('+').to(Token::Plus),
+        just('-').to(Token::Minus),
+        just('[').to(Token::LeftBracket),
+        just(']').to(Token::RightBracket),
+        text::newline().to(Token::Newline),
+    ));
+
+    // `;` and `#` comment out the rest of the line, but not the newline itself
+    let comment = one_of(";#")
+        .then(any().and_is(text::newline().not()).repeated())
+        .ignored();
+    let padding = text::inline_whitespace().then(comment.or_not());
+
+    padding.ignore_then(
+        token
+            .map_with(|token, e| (token, e.span()))
+            .then_ignore(padding)
+            .recover_with(skip_then_retry_until(any().ignored(), end()))
+            .repeated()
+            .collect(),
+    )
+}
+
+/// Takes everything that could be part of the number, so that `1u8` is an error rather than `1` followed by `u8`.
+fn integer<'a>() -> impl Parser<'a, &'a str, IntegerLiteral, Err<Rich<'a, char>>> {
+    regex("[0-9][0-9a-zA-Z_]*").validate(|text: &str, e, emitter| {
+        let (digits, suffix) = [
+            ("u32", IntegerSuffix::U32),
+            ("i32", IntegerSuffix::I32),
+            ("u64", IntegerSuffix::U64),
+            ("i64", IntegerSuffix::I64),
+        ]
+        .into_iter()
+        .find_map(|(name, suffix)| text.strip_suffix(name).map(|digits| (digits, Some(suffix))))
+        .unwrap_or((text, None));
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            u64::from_str_radix(hex, 16)
+        } else if let Some(binary) = digits.strip_prefix("0b") {
+            u64::from_str_radix(binary, 2)
+        } else {
+            digits.parse()
+        };
+        let value = value.unwrap_or_else(|error| {
+            emitter.emit(Rich::custom(
+                e.span(),
+                format!("invalid integer literal {}: {}", text, error),
+            ));
+            0
+        });
+        IntegerLiteral { value, suffix }
+    })
+}
+
+fn escape<'a>() -> impl Parser<'a, &'a str, char, Err<Rich<'a, char>>> {
+    just('\\').ignore_then(choice((
+        just('n').to('\n'),
+        just('t').to('\t'),
+        just('0').to('\0'),
+        just('\\'),
+        just('"'),
+        just('\''),
+    )))
+}
+
+fn character<'a>() -> impl Parser<'a, &'a str, char, Err<Rich<'a, char>>> {
+    none_of("\\'")
+        .or(escape())
+        .delimited_by(just('\''), just('\''))
+}
+
+fn string<'a>() -> impl Parser<'a, &'a str, String, Err<Rich<'a, char>>> {
+    none_of("\\\"")
+        .or(escape())
+        .repeated()
+        .collect::<String>()
+        .delimited_by(just('"'), just('"'))
+}
@@ -0,0 +1,8 @@
+pub mod assembler;
+pub mod disassembler;
+pub mod emulator;
+pub mod encoding;
+pub mod instruction_set;
+pub mod lexer;
+pub mod memory;
+pub mod parser;
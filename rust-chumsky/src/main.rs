@@ -1,31 +1,316 @@
-use crate::{
-    emulator::{Emulator, StepResult},
-    parser::program,
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, Write},
+    process::ExitCode,
+    str::FromStr,
 };
-use chumsky::prelude::*;
-
-mod emulator;
-mod instruction_set;
-mod parser;
-
-fn main() {
-    let input = r"
-        main:
-            add r1, r2, 42
-        foo:
-        bar:
-            add r34, r34, -1
-    "
-    .to_string();
-
-    let parser = program();
-
-    let program = parser.parse(&input).into_result().unwrap();
-    println!("program: {:?}", program);
-
-    let mut emulator = Emulator::new(program);
-    println!("before execution: {:?}", emulator);
-    while emulator.step() != StepResult::Halted {
-        println!("after stepping: {:?}", emulator);
+
+use rust::{
+    assembler::{Image, assemble},
+    disassembler::disassemble,
+    emulator::{Emulator, Flags, StepResult},
+    instruction_set::Register32,
+    memory::{CONSOLE_OUTPUT_ADDRESS, ConsoleOutput, DEFAULT_MEMORY_SIZE, Memory},
+    parser,
+};
+
+const USAGE: &str = "usage:
+    rust-chumsky assemble <file.s> -o <out.bin>
+    rust-chumsky disassemble <file>
+    rust-chumsky run <file> [options]
+    rust-chumsky trace <file> [options]
+
+files ending in .bin are loaded as they are, anything else is assembled first.
+
+options for run and trace:
+    --max-steps <n>        give up after n instructions, exiting with 124 (default 10000000)
+    --memory-size <bytes>  size of RAM (default 65536)
+    --exit-register <r>    exit with the value of this register on halt (default r1)
+    --dump-registers       print the registers and flags in hex when the program stops
+    --dump-memory          print the non-zero parts of RAM in hex when the program stops
+
+exit codes for run and trace:
+    0-123  the program halted with this value in the exit register
+    124    the step limit was reached
+    125    the program trapped
+    126    the program halted with more than 123 in the exit register
+    2      bad arguments, or a file that can't be read or assembled, before anything runs";
+
+const DEFAULT_MAX_STEPS: u64 = 10_000_000;
+
+/// What `timeout` exits with, for the same reason.
+const STEP_LIMIT_EXIT_CODE: u8 = 124;
+
+/// Kept apart from the exit values a program can halt with, so a trap can't pass for one.
+const TRAP_EXIT_CODE: u8 = 125;
+
+/// For a halt whose exit value is too big to be told apart from the codes above.
+const EXIT_VALUE_TOO_BIG_EXIT_CODE: u8 = 126;
+
+/// The largest exit value a halted program can exit with, below the reserved codes.
+const MAX_EXIT_VALUE: u32 = STEP_LIMIT_EXIT_CODE as u32 - 1;
+
+struct RunOptions {
+    path: String,
+    trace: bool,
+    max_steps: u64,
+    memory_size: u32,
+    exit_register: Register32,
+    dump_registers: bool,
+    dump_memory: bool,
+}
+
+fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let result = match args.first().map(String::as_str) {
+        Some("assemble") => assemble_command(&args[1..]),
+        Some("disassemble") => disassemble_command(&args[1..]),
+        Some("run") => RunOptions::parse(&args[1..], false).and_then(|options| run(&options)),
+        Some("trace") => RunOptions::parse(&args[1..], true).and_then(|options| run(&options)),
+        _ => Err(USAGE.to_string()),
+    };
+    result.unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        ExitCode::from(2)
+    })
+}
+
+fn assemble_command(args: &[String]) -> Result<ExitCode, String> {
+    let [input, flag, output] = args else {
+        return Err(USAGE.to_string());
+    };
+    if flag != "-o" {
+        return Err(USAGE.to_string());
+    }
+    let image = assemble_file(input)?;
+    fs::write(output, image).map_err(|e| format!("{}: {}", output, e))?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn disassemble_command(args: &[String]) -> Result<ExitCode, String> {
+    let [input] = args else {
+        return Err(USAGE.to_string());
+    };
+    if input.ends_with(".bin") {
+        let image = fs::read(input).map_err(|e| format!("{}: {}", input, e))?;
+        print!("{}", disassemble(&image, &HashMap::new()));
+    } else {
+        // assembling here rather than in `assemble_file` keeps the labels, which borrow from the source
+        let source = read_source(input)?;
+        let image = assemble_source(input, &source)?;
+        print!("{}", disassemble(&image.bytes, &image.symbols));
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+impl RunOptions {
+    fn parse(args: &[String], trace: bool) -> Result<Self, String> {
+        let mut path = None;
+        let mut options = RunOptions {
+            path: String::new(),
+            trace,
+            max_steps: DEFAULT_MAX_STEPS,
+            memory_size: DEFAULT_MEMORY_SIZE,
+            exit_register: Register32::R1,
+            dump_registers: false,
+            dump_memory: false,
+        };
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--max-steps" => options.max_steps = option_value(arg, args.next())?,
+                "--memory-size" => options.memory_size = option_value(arg, args.next())?,
+                "--exit-register" => options.exit_register = option_value(arg, args.next())?,
+                "--dump-registers" => options.dump_registers = true,
+                "--dump-memory" => options.dump_memory = true,
+                _ if arg.starts_with("--") || path.is_some() => {
+                    return Err(format!("unexpected argument: {}\n{}", arg, USAGE));
+                }
+                _ => path = Some(arg.clone()),
+            }
+        }
+        options.path = path.ok_or_else(|| USAGE.to_string())?;
+        Ok(options)
+    }
+}
+
+fn option_value<T: FromStr>(option: &str, value: Option<&String>) -> Result<T, String> {
+    let value = value.ok_or_else(|| format!("{} needs a value", option))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", option, value))
+}
+
+fn run(options: &RunOptions) -> Result<ExitCode, String> {
+    let image = if options.path.ends_with(".bin") {
+        fs::read(&options.path).map_err(|e| format!("{}: {}", options.path, e))?
+    } else {
+        assemble_file(&options.path)?
+    };
+
+    let mut memory = Memory::new(options.memory_size);
+    memory.map(
+        CONSOLE_OUTPUT_ADDRESS,
+        1,
+        Box::new(ConsoleOutput::new(io::stdout())),
+    )?;
+    let mut emulator = Emulator::new(&image, memory)?;
+
+    let mut steps = 0;
+    let exit_code = loop {
+        if steps == options.max_steps {
+            eprintln!("gave up after {} steps", steps);
+            break ExitCode::from(STEP_LIMIT_EXIT_CODE);
+        }
+        // stepping moves the program counter past the instruction, even one that traps
+        let address = emulator.program_counter();
+        // a trap is reported below, so only instructions that decode are traced
+        let before = match emulator.peek_instruction() {
+            Ok((instruction, _)) if options.trace => {
+                Some((instruction, emulator.registers(), emulator.flags()))
+            }
+            _ => None,
+        };
+        let result = emulator.step();
+        steps += 1;
+        if let Some((instruction, registers, flags)) = before {
+            eprintln!(
+                "{:#06x}: {:<32} {}",
+                address,
+                instruction.to_string(),
+                changes(&registers, &flags, &emulator)
+            );
+        }
+        match result {
+            // `step` only reports the halt on the call after it, which would trace and count an extra step
+            StepResult::NotHalted if !emulator.halted() => {}
+            StepResult::NotHalted | StepResult::Halted => {
+                let value = Register32::ALL
+                    .iter()
+                    .zip(emulator.registers())
+                    .find(|(register, _)| **register == options.exit_register)
+                    .map_or(0, |(_, value)| value);
+                if value > MAX_EXIT_VALUE {
+                    eprintln!(
+                        "halted with {} in {}, more than the largest exit value, {}",
+                        value, options.exit_register, MAX_EXIT_VALUE
+                    );
+                    break ExitCode::from(EXIT_VALUE_TOO_BIG_EXIT_CODE);
+                }
+                break ExitCode::from(value as u8);
+            }
+            StepResult::Trapped(trap) => {
+                eprintln!("trapped at {:#06x}: {:?}", address, trap);
+                break ExitCode::from(TRAP_EXIT_CODE);
+            }
+        }
+    };
+    io::stdout().flush().map_err(|e| e.to_string())?;
+
+    if options.dump_registers {
+        dump_registers(&emulator);
+    }
+    if options.dump_memory {
+        dump_memory(emulator.memory().ram());
+    }
+    Ok(exit_code)
+}
+
+fn read_source(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+fn assemble_file(path: &str) -> Result<Vec<u8>, String> {
+    let source = read_source(path)?;
+    Ok(assemble_source(path, &source)?.bytes)
+}
+
+/// Parses and assembles `source`, with any errors prefixed by where they are in `path`.
+fn assemble_source<'a>(path: &str, source: &'a str) -> Result<Image<'a>, String> {
+    let program = parser::parse(source).map_err(|errors| {
+        errors
+            .iter()
+            .map(|error| {
+                let (line, column) = line_and_column(source, error.span().start);
+                format!("{}:{}:{}: {}", path, line, column, error)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    })?;
+    assemble(&program).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// 1-based line and column of the byte at `offset`.
+fn line_and_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count() + 1,
+        before[line_start..].chars().count() + 1,
+    )
+}
+
+/// The registers and flags that differ from before the step, like `r1: 0x0 -> 0x2a`.
+fn changes(registers: &[u32; 8], flags: &Flags, emulator: &Emulator) -> String {
+    let mut changes = Register32::ALL
+        .iter()
+        .zip(registers.iter().zip(emulator.registers()))
+        .filter(|(_, (before, after))| **before != *after)
+        .map(|(register, (before, after))| format!("{}: {:#x} -> {:#x}", register, before, after))
+        .collect::<Vec<_>>();
+    if *flags != emulator.flags() {
+        changes.push(format!(
+            "flags: {} -> {}",
+            format_flags(flags),
+            format_flags(&emulator.flags())
+        ));
+    }
+    changes.join(", ")
+}
+
+/// One letter per flag that's set and `-` for each that isn't, in the order `zcvn`.
+fn format_flags(flags: &Flags) -> String {
+    [
+        (flags.zero, 'z'),
+        (flags.carry, 'c'),
+        (flags.overflow, 'v'),
+        (flags.negative, 'n'),
+    ]
+    .iter()
+    .map(|(set, letter)| if *set { *letter } else { '-' })
+    .collect()
+}
+
+fn dump_registers(emulator: &Emulator) {
+    for (register, value) in Register32::ALL.iter().zip(emulator.registers()) {
+        eprintln!("{}: {:#010x}", register, value);
+    }
+    eprintln!("pc: {:#010x}", emulator.program_counter());
+    eprintln!("flags: {}", format_flags(&emulator.flags()));
+}
+
+/// 16 bytes a line with their address and ASCII, leaving out lines that are all zero.
+fn dump_memory(ram: &[u8]) {
+    for (line, bytes) in ram.chunks(16).enumerate() {
+        if bytes.iter().all(|byte| *byte == 0) {
+            continue;
+        }
+        let hex = bytes
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let ascii = bytes
+            .iter()
+            .map(|byte| {
+                if byte.is_ascii_graphic() || *byte == b' ' {
+                    *byte as char
+                } else {
+                    '.'
+                }
+            })
+            .collect::<String>();
+        eprintln!("{:#010x}: {:<47}  {}", line * 16, hex, ascii);
     }
 }
@@ -0,0 +1,172 @@
+use std::{fmt::Debug, io::Write, ops::Range};
+
+use crate::emulator::Trap;
+
+/// How much memory the emulator gets unless the host asks for something else.
+pub const DEFAULT_MEMORY_SIZE: u32 = 64 * 1024;
+
+/// Where `main` maps the console. Every byte stored here is written to standard output.
+pub const CONSOLE_OUTPUT_ADDRESS: u32 = 0xffff_0000;
+
+/// Something the host maps into the address space. Offsets are relative to the start of the mapping, and wider
+/// accesses arrive one byte at a time, lowest address first.
+pub trait Device {
+    fn read(&mut self, offset: u32) -> Result<u8, Trap>;
+    fn write(&mut self, offset: u32, value: u8) -> Result<(), Trap>;
+}
+
+/// Byte-addressable little-endian memory, with RAM starting at address 0 and devices mapped anywhere RAM isn't.
+pub struct Memory {
+    ram: Vec<u8>,
+    devices: Vec<(Range<u32>, Box<dyn Device>)>,
+}
+
+/// Writes every byte stored to it out to `W`. Reads return 0.
+pub struct ConsoleOutput<W: Write> {
+    writer: W,
+}
+
+impl Memory {
+    pub fn new(size: u32) -> Self {
+        Self {
+            ram: vec![0; size as usize],
+            devices: Vec::new(),
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.ram.len() as u32
+    }
+
+    /// Maps `length` bytes starting at `address` to `device`.
+    pub fn map(
+        &mut self,
+        address: u32,
+        length: u32,
+        device: Box<dyn Device>,
+    ) -> Result<(), String> {
+        let end = address
+            .checked_add(length)
+            .ok_or_else(|| format!("device at {:#x} runs past the end of memory", address))?;
+        let range = address..end;
+        if range.start < self.size() {
+            return Err(format!("device at {:#x} overlaps RAM", address));
+        }
+        if self
+            .devices
+            .iter()
+            .any(|(other, _)| range.start < other.end && other.start < range.end)
+        {
+            return Err(format!("device at {:#x} overlaps another device", address));
+        }
+        self.devices.push((range, device));
+        Ok(())
+    }
+
+    /// Copies `bytes` into RAM without going through any devices.
+    pub fn load_image(&mut self, address: u32, bytes: &[u8]) -> Result<(), String> {
+        let start = address as usize;
+        match self.ram.get_mut(start..start + bytes.len()) {
+            Some(destination) => {
+                destination.copy_from_slice(bytes);
+                Ok(())
+            }
+            None => Err(format!(
+                "{} bytes at {:#x} don't fit in {} bytes of memory",
+                bytes.len(),
+                address,
+                self.ram.len()
+            )),
+        }
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    /// Everything in RAM from `address` to the end, or `None` if `address` isn't in RAM.
+    pub fn ram_from(&self, address: u32) -> Option<&[u8]> {
+        self.ram.get(address as usize..)
+    }
+
+    /// Reads `width` bytes (at most 8) starting at `address`, little-endian.
+    pub fn read(&mut self, address: u32, width: u32) -> Result<u64, Trap> {
+        let mut value = 0;
+        for i in 0..width {
+            let byte = self.read_byte(address.wrapping_add(i))?;
+            value |= (byte as u64) << (8 * i);
+        }
+        Ok(value)
+    }
+
+    /// Writes the low `width` bytes (at most 8) of `value` starting at `address`, little-endian.
+    pub fn write(&mut self, address: u32, width: u32, value: u64) -> Result<(), Trap> {
+        for i in 0..width {
+            self.write_byte(address.wrapping_add(i), (value >> (8 * i)) as u8)?;
+        }
+        Ok(())
+    }
+
+    fn read_byte(&mut self, address: u32) -> Result<u8, Trap> {
+        if let Some(byte) = self.ram.get(address as usize) {
+            return Ok(*byte);
+        }
+        match self.device_at(address) {
+            Some((offset, device)) => device.read(offset),
+            None => Err(Trap::AddressOutOfBounds),
+        }
+    }
+
+    fn write_byte(&mut self, address: u32, value: u8) -> Result<(), Trap> {
+        if let Some(byte) = self.ram.get_mut(address as usize) {
+            *byte = value;
+            return Ok(());
+        }
+        match self.device_at(address) {
+            Some((offset, device)) => device.write(offset, value),
+            None => Err(Trap::AddressOutOfBounds),
+        }
+    }
+
+    fn device_at(&mut self, address: u32) -> Option<(u32, &mut Box<dyn Device>)> {
+        self.devices
+            .iter_mut()
+            .find(|(range, _)| range.contains(&address))
+            .map(|(range, device)| (address - range.start, device))
+    }
+}
+
+impl Debug for Memory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Memory")
+            .field("size", &self.ram.len())
+            .field(
+                "devices",
+                &self
+                    .devices
+                    .iter()
+                    .map(|(range, _)| range)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl<W: Write> ConsoleOutput<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+}
+
+impl<W: Write> Device for ConsoleOutput<W> {
+    fn read(&mut self, _offset: u32) -> Result<u8, Trap> {
+        Ok(0)
+    }
+
+    fn write(&mut self, _offset: u32, value: u8) -> Result<(), Trap> {
+        self.writer
+            .write_all(&[value])
+            .and_then(|_| self.writer.flush())
+            .map_err(|_| Trap::DeviceError)
+    }
+}
@@ -1,13 +1,11 @@
-use std::{collections::HashMap, num::ParseIntError, str::FromStr};
+use std::collections::HashMap;
 
-use chumsky::{
-    container::Seq,
-    error::RichReason,
-    extra::{Err, Full, ParserExtra},
-    prelude::*,
-};
+use chumsky::{extra::Err, input::ValueInput, prelude::*};
 
-use crate::instruction_set::*;
+use crate::{
+    instruction_set::*,
+    lexer::{IntegerLiteral, IntegerSuffix, Token, lexer},
+};
 
 #[derive(Debug)]
 pub struct NumberLiteral<T> {
@@ -22,6 +20,8 @@ pub enum Argument<'a> {
     U32(NumberLiteral<u32>),
     I64(NumberLiteral<i64>),
     U64(NumberLiteral<u64>),
+    String(String),
+    Address { base: &'a str, offset: i32 },
 }
 
 #[derive(Debug)]
@@ -35,110 +35,366 @@ pub enum InstructionOrUnknown<'a> {
 
 #[derive(Debug)]
 pub enum Statement<'a> {
-    Instruction(InstructionOrUnknown<'a>),
-    Label(&'a str),
+    Instruction(InstructionOrUnknown<'a>, SimpleSpan),
+    Label(&'a str, SimpleSpan),
+    Data(Vec<u8>),
 }
 
-pub fn program<'a>() -> impl Parser<'a, &'a str, Program<'a>, Err<Rich<'a, char>>> {
-    statement()
+/// Lexes and parses `source`, with lexer and parser errors both turned into strings.
+pub fn parse(source: &str) -> Result<Program<'_>, Vec<Rich<'static, String>>> {
+    let (tokens, lexer_errors) = lexer().parse(source).into_output_errors();
+    let mut errors = lexer_errors
+        .into_iter()
+        .map(|e| e.map_token(|c| c.to_string()).into_owned())
+        .collect::<Vec<_>>();
+    let Some(tokens) = tokens else {
+        return Err(errors);
+    };
+    let (program, parser_errors) = program()
+        .parse(
+            tokens
+                .as_slice()
+                .map((source.len()..source.len()).into(), |(token, span)| {
+                    (token, span)
+                }),
+        )
+        .into_output_errors();
+    errors.extend(
+        parser_errors
+            .into_iter()
+            .map(|e| e.map_token(|token| token.to_string()).into_owned()),
+    );
+    match program {
+        Some(program) if errors.is_empty() => Ok(program),
+        _ => Err(errors),
+    }
+}
+
+pub fn program<'tokens, 'src: 'tokens, I>()
+-> impl Parser<'tokens, I, Program<'src>, Err<Rich<'tokens, Token<'src>>>>
+where
+    I: ValueInput<'tokens, Token = Token<'src>, Span = SimpleSpan>,
+{
+    just(Token::Newline)
         .repeated()
-        .collect::<Vec<_>>()
-        .validate(|instructions, e, emitter| {
-            let mut valid_instruction_count = 0;
+        .ignore_then(
+            statement()
+                .then_ignore(just(Token::Newline).repeated())
+                .repeated()
+                .collect::<Vec<_>>(),
+        )
+        .validate(|statements, _, emitter| {
+            let mut items = Vec::new();
             let mut labels = HashMap::new();
-            let instructions = instructions
-                .into_iter()
-                .filter_map(|statement| match statement {
-                    Statement::Instruction(InstructionOrUnknown::Valid(instruction)) => {
-                        valid_instruction_count += 1;
-                        Some(instruction)
+            // labels belong to whatever comes after them, an instruction or data
+            let mut pending_labels = Vec::new();
+            let mut references = Vec::new();
+            for statement in statements {
+                let item = match statement {
+                    Statement::Instruction(InstructionOrUnknown::Valid(instruction), span) => {
+                        if let Some(label) = instruction.label() {
+                            references.push((label.to_string(), span));
+                        }
+                        Item::Instruction(instruction)
                     }
-                    Statement::Instruction(InstructionOrUnknown::Unknown {
-                        command: _,
-                        arguments: _,
-                    }) => None,
-                    Statement::Label(label) => {
-                        if labels.insert(label, valid_instruction_count).is_some() {
-                            emitter.emit(Rich::custom(
-                                e.span(),
-                                format!("duplicate label: {}", label),
-                            ));
+                    Statement::Instruction(
+                        InstructionOrUnknown::Unknown {
+                            command: _,
+                            arguments: _,
+                        },
+                        _,
+                    ) => continue,
+                    Statement::Label(label, span) => {
+                        if pending_labels.contains(&label) || labels.contains_key(label) {
+                            emitter.emit(Rich::custom(span, format!("duplicate label: {}", label)));
+                        } else {
+                            pending_labels.push(label);
                         }
-                        None
+                        continue;
                     }
-                })
-                .collect::<Vec<_>>();
-            Program {
-                labels,
-                instructions,
+                    Statement::Data(bytes) => Item::Data(bytes),
+                };
+                for label in pending_labels.drain(..) {
+                    labels.insert(label, items.len());
+                }
+                items.push(item);
+            }
+            for label in pending_labels {
+                labels.insert(label, items.len());
             }
+            for (label, span) in references {
+                if !labels.contains_key(label.as_str()) {
+                    emitter.emit(Rich::custom(span, format!("undefined label: {}", label)));
+                }
+            }
+            Program { labels, items }
         })
 }
 
-fn statement<'a>() -> impl Parser<'a, &'a str, Statement<'a>, Err<Rich<'a, char>>> {
+fn statement<'tokens, 'src: 'tokens, I>()
+-> impl Parser<'tokens, I, Statement<'src>, Err<Rich<'tokens, Token<'src>>>>
+where
+    I: ValueInput<'tokens, Token = Token<'src>, Span = SimpleSpan>,
+{
+    // a label can share its line, but everything else has to end it
+    let line_end = just(Token::Newline).ignored().or(end()).rewind();
     choice((
-        label().map(Statement::Label),
-        instruction().map(Statement::Instruction),
+        label().map_with(|label, e| Statement::Label(label, e.span())),
+        directive()
+            .then_ignore(line_end.clone())
+            .map(Statement::Data),
+        instruction()
+            .then_ignore(line_end)
+            .map_with(|instruction, e| Statement::Instruction(instruction, e.span())),
     ))
 }
 
-fn label<'a>() -> impl Parser<'a, &'a str, &'a str, Err<Rich<'a, char>>> {
-    identifier()
-        .then(just(":"))
-        .map(|(result, _)| result)
-        .padded()
+fn label<'tokens, 'src: 'tokens, I>()
+-> impl Parser<'tokens, I, &'src str, Err<Rich<'tokens, Token<'src>>>>
+where
+    I: ValueInput<'tokens, Token = Token<'src>, Span = SimpleSpan>,
+{
+    identifier().then_ignore(just(Token::Colon))
 }
 
-fn instruction<'a>() -> impl Parser<'a, &'a str, InstructionOrUnknown<'a>, Err<Rich<'a, char>>> {
-    identifier()
-        .padded()
-        .then(
-            argument()
-                .padded()
-                .separated_by(just(","))
-                .collect::<Vec<_>>()
-                .or_not(),
-        )
-        .map(|(command, arguments)| (command, arguments.unwrap_or(vec![])))
-        .validate(
-            |(command, arguments), e, emitter| match (command, arguments.as_slice()) {
-                ("add", [destination, left, right]) => {
-                    if let Ok(destination) = destination.try_into()
-                        && let Ok(left) = left.try_into()
-                        && let Ok(right) = right.try_into()
-                    {
-                        InstructionOrUnknown::Valid(Instruction::Add32 {
-                            destination,
-                            left,
-                            right,
-                        })
-                    } else if let Ok(destination) = destination.try_into()
-                        && let Ok(left) = left.try_into()
-                        && let Ok(right) = right.try_into()
-                    {
-                        InstructionOrUnknown::Valid(Instruction::Add64 {
-                            destination,
-                            left,
-                            right,
-                        })
-                    } else {
-                        emitter.emit(Rich::custom(
-                            e.span(),
-                            format!("invalid arguments to {}: {:?}", command, arguments),
-                        ));
-                        InstructionOrUnknown::Unknown { command, arguments }
-                    }
+fn directive<'tokens, 'src: 'tokens, I>()
+-> impl Parser<'tokens, I, Vec<u8>, Err<Rich<'tokens, Token<'src>>>>
+where
+    I: ValueInput<'tokens, Token = Token<'src>, Span = SimpleSpan>,
+{
+    select! { Token::Directive(directive) => directive }
+        .then(arguments())
+        .validate(|(directive, arguments), e, emitter| {
+            match data_from_arguments(directive, &arguments) {
+                Ok(data) => data,
+                Err(message) => {
+                    emitter.emit(Rich::custom(e.span(), message));
+                    Vec::new()
+                }
+            }
+        })
+}
+
+fn data_from_arguments(directive: &str, arguments: &[Argument]) -> Result<Vec<u8>, String> {
+    let mut data = Vec::new();
+    for argument in arguments {
+        match (directive, argument) {
+            ("byte", _) => match integer_argument(argument) {
+                Some(value) if (i8::MIN as i128..=u8::MAX as i128).contains(&value) => {
+                    data.push(value as u8)
+                }
+                _ => return Err(format!("not a valid byte: {:?}", argument)),
+            },
+            ("word", _) => match integer_argument(argument) {
+                Some(value) if (i32::MIN as i128..=u32::MAX as i128).contains(&value) => {
+                    data.extend((value as u32).to_le_bytes())
                 }
-                // TODO more commands
-                _ => {
-                    emitter.emit(Rich::custom(
-                        e.span(),
-                        format!("invalid command: {}", command),
-                    ));
+                _ => return Err(format!("not a valid word: {:?}", argument)),
+            },
+            ("ascii", Argument::String(string)) if string.is_ascii() => {
+                data.extend(string.as_bytes())
+            }
+            ("ascii", _) => return Err(format!("not a valid ascii string: {:?}", argument)),
+            _ => return Err(format!("invalid directive: {}", directive)),
+        }
+    }
+    Ok(data)
+}
+
+fn integer_argument(argument: &Argument) -> Option<i128> {
+    match argument {
+        Argument::I32(literal) => Some(literal.value as i128),
+        Argument::U32(literal) => Some(literal.value as i128),
+        Argument::I64(literal) => Some(literal.value as i128),
+        Argument::U64(literal) => Some(literal.value as i128),
+        _ => None,
+    }
+}
+
+fn instruction<'tokens, 'src: 'tokens, I>()
+-> impl Parser<'tokens, I, InstructionOrUnknown<'src>, Err<Rich<'tokens, Token<'src>>>>
+where
+    I: ValueInput<'tokens, Token = Token<'src>, Span = SimpleSpan>,
+{
+    identifier()
+        .then(arguments())
+        .validate(|(command, arguments), e, emitter| {
+            match instruction_from_arguments(command, &arguments) {
+                Ok(instruction) => InstructionOrUnknown::Valid(instruction),
+                Err(message) => {
+                    emitter.emit(Rich::custom(e.span(), message));
                     InstructionOrUnknown::Unknown { command, arguments }
                 }
+            }
+        })
+}
+
+fn instruction_from_arguments(
+    command: &str,
+    arguments: &[Argument],
+) -> Result<Instruction, String> {
+    if let Ok(operation) = command.parse::<BinaryOperation>() {
+        if let [destination, left, right] = arguments {
+            if let Ok(destination) = destination.try_into()
+                && let Ok(left) = left.try_into()
+                && let Ok(right) = right.try_into()
+            {
+                return Ok(Instruction::Binary32 {
+                    operation,
+                    destination,
+                    left,
+                    right,
+                });
+            } else if let Ok(destination) = destination.try_into()
+                && let Ok(left) = left.try_into()
+                && let Ok(right) = right.try_into()
+            {
+                return Ok(Instruction::Binary64 {
+                    operation,
+                    destination,
+                    left,
+                    right,
+                });
+            }
+        }
+        return Err(format!("invalid arguments to {}: {:?}", command, arguments));
+    }
+    if let Ok(condition) = command.parse::<Condition>() {
+        return match arguments {
+            [Argument::Identifier(label)] => Ok(Instruction::Jump {
+                condition,
+                target: Target::Label(label.to_string()),
+            }),
+            _ => Err(format!("invalid arguments to {}: {:?}", command, arguments)),
+        };
+    }
+    match (command, arguments) {
+        ("load8" | "load8s" | "load16" | "load16s" | "load32", [destination, address]) => {
+            let (width, signedness) = match command {
+                "load8" => (MemoryWidth::Byte, Signedness::Unsigned),
+                "load8s" => (MemoryWidth::Byte, Signedness::Signed),
+                "load16" => (MemoryWidth::Half, Signedness::Unsigned),
+                "load16s" => (MemoryWidth::Half, Signedness::Signed),
+                _ => (MemoryWidth::Word, Signedness::Unsigned),
+            };
+            if let Ok(destination) = destination.try_into()
+                && let Ok(address) = address.try_into()
+            {
+                Ok(Instruction::Load32 {
+                    width,
+                    signedness,
+                    destination,
+                    address,
+                })
+            } else {
+                Err(format!("invalid arguments to {}: {:?}", command, arguments))
+            }
+        }
+        ("load64", [destination, address]) => {
+            if let Ok(destination) = destination.try_into()
+                && let Ok(address) = address.try_into()
+            {
+                Ok(Instruction::Load64 {
+                    destination,
+                    address,
+                })
+            } else {
+                Err(format!("invalid arguments to {}: {:?}", command, arguments))
+            }
+        }
+        ("store8" | "store16" | "store32", [source, address]) => {
+            let width = match command {
+                "store8" => MemoryWidth::Byte,
+                "store16" => MemoryWidth::Half,
+                _ => MemoryWidth::Word,
+            };
+            if let Ok(source) = source.try_into()
+                && let Ok(address) = address.try_into()
+            {
+                Ok(Instruction::Store32 {
+                    width,
+                    source,
+                    address,
+                })
+            } else {
+                Err(format!("invalid arguments to {}: {:?}", command, arguments))
+            }
+        }
+        ("store64", [source, address]) => {
+            if let Ok(source) = source.try_into()
+                && let Ok(address) = address.try_into()
+            {
+                Ok(Instruction::Store64 { source, address })
+            } else {
+                Err(format!("invalid arguments to {}: {:?}", command, arguments))
+            }
+        }
+        ("la", [destination, Argument::Identifier(label)]) => match destination.try_into() {
+            Ok(destination) => Ok(Instruction::LoadAddress {
+                destination,
+                target: Target::Label(label.to_string()),
+            }),
+            Err(e) => Err(e),
+        },
+        (
+            "load8" | "load8s" | "load16" | "load16s" | "load32" | "load64" | "store8" | "store16"
+            | "store32" | "store64" | "la",
+            _,
+        ) => Err(format!("invalid arguments to {}: {:?}", command, arguments)),
+        ("call", [Argument::Identifier(label)]) => Ok(Instruction::Call {
+            target: Target::Label(label.to_string()),
+        }),
+        ("ret", []) => Ok(Instruction::Ret),
+        ("halt", []) => Ok(Instruction::Halt),
+        ("call" | "ret" | "halt", _) => {
+            Err(format!("invalid arguments to {}: {:?}", command, arguments))
+        }
+        ("not", _) => unary_instruction(
+            command,
+            arguments,
+            |destination, source| Instruction::Not32 {
+                destination,
+                source,
             },
-        )
+            |destination, source| Instruction::Not64 {
+                destination,
+                source,
+            },
+        ),
+        ("mov", _) => unary_instruction(
+            command,
+            arguments,
+            |destination, source| Instruction::Mov32 {
+                destination,
+                source,
+            },
+            |destination, source| Instruction::Mov64 {
+                destination,
+                source,
+            },
+        ),
+        _ => Err(format!("invalid command: {}", command)),
+    }
+}
+
+fn unary_instruction(
+    command: &str,
+    arguments: &[Argument],
+    instruction_32: fn(Register32, RegisterOrLiteral32) -> Instruction,
+    instruction_64: fn(Register64, RegisterOrLiteral64) -> Instruction,
+) -> Result<Instruction, String> {
+    if let [destination, source] = arguments {
+        if let Ok(destination) = destination.try_into()
+            && let Ok(source) = source.try_into()
+        {
+            return Ok(instruction_32(destination, source));
+        } else if let Ok(destination) = destination.try_into()
+            && let Ok(source) = source.try_into()
+        {
+            return Ok(instruction_64(destination, source));
+        }
+    }
+    Err(format!("invalid arguments to {}: {:?}", command, arguments))
 }
 
 impl<'a> TryFrom<&Argument<'a>> for RegisterOrLiteral32 {
@@ -200,6 +456,7 @@ impl<'a> TryFrom<&Argument<'a>> for RegisterOrLiteral64 {
                 value,
                 had_type_suffix: _,
             }) => Ok(RegisterOrLiteral64::U64(*value)),
+            _ => Err(format!("not a valid 64-bit argument: {:?}", value)),
         }
     }
 }
@@ -215,6 +472,20 @@ impl<'a> TryFrom<&Argument<'a>> for Register32 {
     }
 }
 
+impl<'a> TryFrom<&Argument<'a>> for Address {
+    type Error = String;
+
+    fn try_from(value: &Argument<'a>) -> Result<Self, Self::Error> {
+        match value {
+            Argument::Address { base, offset } => Ok(Address {
+                base: base.parse()?,
+                offset: *offset,
+            }),
+            _ => Err(format!("not a valid address: {:?}", value)),
+        }
+    }
+}
+
 impl<'a> TryFrom<&Argument<'a>> for Register64 {
     type Error = String;
 
@@ -226,91 +497,157 @@ impl<'a> TryFrom<&Argument<'a>> for Register64 {
     }
 }
 
-fn argument<'a>() -> impl Parser<'a, &'a str, Argument<'a>, Err<Rich<'a, char>>> {
-    // TODO can end up with stuff like I64(-1) followed by a command with no args ("u32"), so the suffix isn't correctly being respected in order
+fn arguments<'tokens, 'src: 'tokens, I>()
+-> impl Parser<'tokens, I, Vec<Argument<'src>>, Err<Rich<'tokens, Token<'src>>>>
+where
+    I: ValueInput<'tokens, Token = Token<'src>, Span = SimpleSpan>,
+{
+    argument()
+        .separated_by(just(Token::Comma))
+        .collect::<Vec<_>>()
+}
+
+fn argument<'tokens, 'src: 'tokens, I>()
+-> impl Parser<'tokens, I, Argument<'src>, Err<Rich<'tokens, Token<'src>>>>
+where
+    I: ValueInput<'tokens, Token = Token<'src>, Span = SimpleSpan>,
+{
     choice((
+        address(),
+        select! { Token::String(string) => Argument::String(string) },
         identifier().map(Argument::Identifier),
-        unsigned_integer_with_suffix("u32".to_owned()).map(Argument::U32),
-        signed_integer_with_suffix("i32".to_owned()).map(Argument::I32),
-        unsigned_integer_with_suffix("u64".to_owned()).map(Argument::U64),
-        signed_integer_with_suffix("i64".to_owned()).map(Argument::I64),
-        unsigned_integer().map(Argument::U32),
-        signed_integer().map(Argument::I32),
-        unsigned_integer().map(Argument::U64),
-        signed_integer().map(Argument::I64),
+        just(Token::Minus)
+            .or_not()
+            .then(integer())
+            .validate(|(minus, literal), e, emitter| {
+                number_argument(minus.is_some(), literal).unwrap_or_else(|message| {
+                    emitter.emit(Rich::custom(e.span(), message));
+                    // fits anywhere a literal does, so the instruction doesn't report it again
+                    Argument::U32(NumberLiteral {
+                        value: 0,
+                        had_type_suffix: false,
+                    })
+                })
+            }),
     ))
 }
 
-fn identifier<'a>() -> impl Parser<'a, &'a str, &'a str, Err<Rich<'a, char>>> {
-    regex("[a-zA-Z_][a-zA-Z0-9_]*")
-}
-
-fn signed_integer<'a, T>() -> impl Parser<'a, &'a str, NumberLiteral<T>, Err<Rich<'a, char>>>
+fn address<'tokens, 'src: 'tokens, I>()
+-> impl Parser<'tokens, I, Argument<'src>, Err<Rich<'tokens, Token<'src>>>>
 where
-    T: FromStr,
-    <T as FromStr>::Err: std::fmt::Display,
+    I: ValueInput<'tokens, Token = Token<'src>, Span = SimpleSpan>,
 {
-    regex(r"\-?[0-9]+").try_map(move |input: &str, span| match input.parse() {
-        Ok(result) => Ok(NumberLiteral {
-            value: result,
-            had_type_suffix: false,
-        }),
-        Err(e) => Err(Rich::custom(span, e)),
-    })
+    identifier()
+        .then(
+            choice((just(Token::Plus).to(1), just(Token::Minus).to(-1)))
+                .then(integer())
+                .or_not(),
+        )
+        .delimited_by(just(Token::LeftBracket), just(Token::RightBracket))
+        .validate(|(base, offset), e, emitter| {
+            let offset = match offset {
+                Some((sign, literal)) => i32::try_from(sign * literal.value as i128)
+                    .unwrap_or_else(|_| {
+                        emitter.emit(Rich::custom(
+                            e.span(),
+                            format!("offset out of range: {}", literal.value),
+                        ));
+                        0
+                    }),
+                None => 0,
+            };
+            Argument::Address { base, offset }
+        })
 }
 
-fn signed_integer_with_suffix<'a, T>(
-    suffix: String,
-) -> impl Parser<'a, &'a str, NumberLiteral<T>, Err<Rich<'a, char>>>
+fn identifier<'tokens, 'src: 'tokens, I>()
+-> impl Parser<'tokens, I, &'src str, Err<Rich<'tokens, Token<'src>>>>
 where
-    T: FromStr,
-    <T as FromStr>::Err: std::fmt::Display,
+    I: ValueInput<'tokens, Token = Token<'src>, Span = SimpleSpan>,
 {
-    signed_integer().then(just(suffix)).map(
-        |(
-            NumberLiteral {
-                value,
-                had_type_suffix: _,
-            },
-            _,
-        ): (NumberLiteral<T>, _)| NumberLiteral {
-            value,
-            had_type_suffix: true,
-        },
-    )
+    select! { Token::Identifier(identifier) => identifier }
 }
 
-fn unsigned_integer<'a, T>() -> impl Parser<'a, &'a str, NumberLiteral<T>, Err<Rich<'a, char>>>
+fn integer<'tokens, 'src: 'tokens, I>()
+-> impl Parser<'tokens, I, IntegerLiteral, Err<Rich<'tokens, Token<'src>>>>
 where
-    T: FromStr,
-    <T as FromStr>::Err: std::fmt::Display,
+    I: ValueInput<'tokens, Token = Token<'src>, Span = SimpleSpan>,
 {
-    regex(r"[0-9]+").try_map(move |input: &str, span| match input.parse() {
-        Ok(result) => Ok(NumberLiteral {
-            value: result,
-            had_type_suffix: false,
-        }),
-        Err(e) => Err(Rich::custom(span, e)),
-    })
+    select! { Token::Integer(literal) => literal }
 }
 
-fn unsigned_integer_with_suffix<'a, T>(
-    suffix: String,
-) -> impl Parser<'a, &'a str, NumberLiteral<T>, Err<Rich<'a, char>>>
-where
-    T: FromStr,
-    <T as FromStr>::Err: std::fmt::Display,
-{
-    unsigned_integer().then(just(suffix)).map(
-        |(
-            NumberLiteral {
+/// A suffix fixes the type. Without one, the literal is 32-bit if it fits and 64-bit otherwise, and signed only if it's
+/// negative.
+fn number_argument<'a>(negative: bool, literal: IntegerLiteral) -> Result<Argument<'a>, String> {
+    let value = if negative {
+        -(literal.value as i128)
+    } else {
+        literal.value as i128
+    };
+    let had_type_suffix = literal.suffix.is_some();
+    let argument = match literal.suffix {
+        Some(IntegerSuffix::U32) => u32::try_from(value).ok().map(|value| {
+            Argument::U32(NumberLiteral {
                 value,
-                had_type_suffix: _,
-            },
-            _,
-        ): (NumberLiteral<T>, _)| NumberLiteral {
-            value,
-            had_type_suffix: true,
-        },
-    )
+                had_type_suffix,
+            })
+        }),
+        Some(IntegerSuffix::I32) => i32::try_from(value).ok().map(|value| {
+            Argument::I32(NumberLiteral {
+                value,
+                had_type_suffix,
+            })
+        }),
+        Some(IntegerSuffix::U64) => u64::try_from(value).ok().map(|value| {
+            Argument::U64(NumberLiteral {
+                value,
+                had_type_suffix,
+            })
+        }),
+        Some(IntegerSuffix::I64) => i64::try_from(value).ok().map(|value| {
+            Argument::I64(NumberLiteral {
+                value,
+                had_type_suffix,
+            })
+        }),
+        None if negative => i32::try_from(value)
+            .map(|value| {
+                Argument::I32(NumberLiteral {
+                    value,
+                    had_type_suffix,
+                })
+            })
+            .or_else(|_| {
+                i64::try_from(value).map(|value| {
+                    Argument::I64(NumberLiteral {
+                        value,
+                        had_type_suffix,
+                    })
+                })
+            })
+            .ok(),
+        None => u32::try_from(value)
+            .map(|value| {
+                Argument::U32(NumberLiteral {
+                    value,
+                    had_type_suffix,
+                })
+            })
+            .or_else(|_| {
+                u64::try_from(value).map(|value| {
+                    Argument::U64(NumberLiteral {
+                        value,
+                        had_type_suffix,
+                    })
+                })
+            })
+            .ok(),
+    };
+    argument.ok_or_else(|| {
+        format!(
+            "integer literal out of range: {}{}",
+            if negative { "-" } else { "" },
+            Token::Integer(literal)
+        )
+    })
 }
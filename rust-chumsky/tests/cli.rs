@@ -0,0 +1,193 @@
+//! Runs the `rust-chumsky` binary (the package's `rust` target) on small programs written to a scratch directory, checking its output and exit codes.
+
+use std::{
+    fs,
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+/// Writes `contents` to `name` in a directory of its own for `test`, returning the path.
+fn scratch_file(test: &str, name: &str, contents: &str) -> PathBuf {
+    let directory = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .join("cli")
+        .join(test);
+    fs::create_dir_all(&directory).unwrap();
+    let path = directory.join(name);
+    fs::write(&path, contents).unwrap();
+    path
+}
+
+fn rust_chumsky(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rust"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+const HELLO: &str = "    mov r3, 0xffff0000
+    mov r2, 104
+    store8 r2, [r3]
+    mov r2, 105
+    store8 r2, [r3]
+    mov r1, 42
+    halt
+";
+
+#[test]
+fn run() {
+    let path = scratch_file("run", "hello.s", HELLO);
+    let output = rust_chumsky(&["run", path.to_str().unwrap()]);
+    assert_eq!(stdout(&output), "hi");
+    assert_eq!(output.status.code(), Some(42));
+}
+
+#[test]
+fn run_exit_register() {
+    let path = scratch_file("run_exit_register", "hello.s", HELLO);
+    let output = rust_chumsky(&["run", path.to_str().unwrap(), "--exit-register", "r2"]);
+    assert_eq!(output.status.code(), Some(105));
+}
+
+#[test]
+fn assemble() {
+    let source = scratch_file("assemble", "hello.s", HELLO);
+    let binary = source.with_extension("bin");
+    let output = rust_chumsky(&[
+        "assemble",
+        source.to_str().unwrap(),
+        "-o",
+        binary.to_str().unwrap(),
+    ]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+
+    let output = rust_chumsky(&["run", binary.to_str().unwrap()]);
+    assert_eq!(stdout(&output), "hi");
+    assert_eq!(output.status.code(), Some(42));
+}
+
+#[test]
+fn trace() {
+    let path = scratch_file(
+        "trace",
+        "add.s",
+        "    mov r1, 40\n    add r1, r1, 2\n    halt\n",
+    );
+    let output = rust_chumsky(&["trace", path.to_str().unwrap()]);
+    let trace = stderr(&output);
+    let lines = trace.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3, "{}", trace);
+    assert!(lines[0].starts_with("0x0000: mov r1, 40"), "{}", trace);
+    assert!(lines[0].ends_with("r1: 0x0 -> 0x28"), "{}", trace);
+    assert!(lines[1].ends_with("r1: 0x28 -> 0x2a"), "{}", trace);
+    assert!(lines[2].contains("halt"), "{}", trace);
+    assert_eq!(output.status.code(), Some(42));
+}
+
+#[test]
+fn trap_exit_code() {
+    let path = scratch_file(
+        "trap_exit_code",
+        "trap.s",
+        "    mov r1, 1\n    div r2, r1, 0\n    halt\n",
+    );
+    let output = rust_chumsky(&["run", path.to_str().unwrap()]);
+    assert!(
+        stderr(&output).contains("trapped at"),
+        "{}",
+        stderr(&output)
+    );
+    assert_eq!(output.status.code(), Some(125));
+}
+
+#[test]
+fn step_limit_exit_code() {
+    let path = scratch_file(
+        "step_limit_exit_code",
+        "loop.s",
+        "forever:\n    jmp forever\n",
+    );
+    let output = rust_chumsky(&["run", path.to_str().unwrap(), "--max-steps", "100"]);
+    assert!(
+        stderr(&output).contains("gave up after 100 steps"),
+        "{}",
+        stderr(&output)
+    );
+    assert_eq!(output.status.code(), Some(124));
+}
+
+#[test]
+fn exit_value_too_big() {
+    for value in ["124", "256"] {
+        let path = scratch_file(
+            "exit_value_too_big",
+            &format!("{}.s", value),
+            &format!("    mov r1, {}\n    halt\n", value),
+        );
+        let output = rust_chumsky(&["run", path.to_str().unwrap()]);
+        assert!(
+            stderr(&output).contains("largest exit value"),
+            "{}",
+            stderr(&output)
+        );
+        assert_eq!(output.status.code(), Some(126));
+    }
+}
+
+#[test]
+fn largest_exit_value() {
+    let path = scratch_file(
+        "largest_exit_value",
+        "exit.s",
+        "    mov r1, 123\n    halt\n",
+    );
+    let output = rust_chumsky(&["run", path.to_str().unwrap()]);
+    assert_eq!(output.status.code(), Some(123));
+}
+
+#[test]
+fn usage_errors() {
+    let path = scratch_file("usage_errors", "hello.s", HELLO);
+    for args in [
+        vec![],
+        vec!["frobnicate"],
+        vec!["run"],
+        vec!["run", path.to_str().unwrap(), "--max-steps"],
+        vec!["run", path.to_str().unwrap(), "--bogus"],
+        vec!["assemble", path.to_str().unwrap()],
+    ] {
+        let output = rust_chumsky(&args);
+        assert_eq!(output.status.code(), Some(2), "{:?}", args);
+        assert!(!stderr(&output).is_empty(), "{:?}", args);
+    }
+}
+
+#[test]
+fn assembly_errors() {
+    let path = scratch_file(
+        "assembly_errors",
+        "bad.s",
+        "    mov r1, 1\n    jmp nowhere\n",
+    );
+    let output = rust_chumsky(&["run", path.to_str().unwrap()]);
+    assert!(stderr(&output).contains("bad.s"), "{}", stderr(&output));
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn halt_on_the_last_step() {
+    let path = scratch_file(
+        "halt_on_the_last_step",
+        "add.s",
+        "    mov r1, 40\n    add r1, r1, 2\n    halt\n",
+    );
+    let output = rust_chumsky(&["run", path.to_str().unwrap(), "--max-steps", "3"]);
+    assert_eq!(output.status.code(), Some(42), "{}", stderr(&output));
+}
@@ -0,0 +1,316 @@
+//! Runs every `.s` file in `tests/programs`, checking it against the expectations written in its comments:
+//!
+//! - `; expect r1 = 42` or `; expect r12 = 0x100000000`: a register when the program halts
+//! - `; expect byte [0x80] = 0x2a`, and `half` and `word` likewise: RAM when the program halts
+//! - `; expect output = "hi\n"`: everything stored to the console
+//! - `; expect trap = DivideByZero`: the program traps rather than halting
+//! - `; expect error on line 3: duplicate label`: the program doesn't parse, with an error on that line whose message
+//!   contains the rest
+//!
+//! Numbers are decimal or `0x` hex, and negative values are compared as two's complement.
+
+use std::{cell::RefCell, fs, io, path::Path, rc::Rc};
+
+use rust::{
+    assembler::assemble,
+    emulator::{Emulator, StepResult},
+    instruction_set::{MemoryWidth, Register32, Register64},
+    memory::{CONSOLE_OUTPUT_ADDRESS, ConsoleOutput, DEFAULT_MEMORY_SIZE, Memory},
+    parser,
+};
+
+/// Enough for any of the fixtures to halt, so that one that loops forever fails rather than hanging.
+const MAX_STEPS: u64 = 1_000_000;
+
+enum Expectation {
+    Register32(Register32, u32),
+    Register64(Register64, u64),
+    Memory(MemoryWidth, u32, u64),
+    Output(String),
+    Trap(String),
+    Error(usize, String),
+}
+
+/// Console output the test can read after the device has been handed to the emulator.
+#[derive(Clone, Default)]
+struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl io::Write for SharedOutput {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(bytes)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn parse_number(text: &str) -> Result<u64, String> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => digits.parse(),
+    }
+    .map_err(|e| format!("invalid number {text}: {e}"))?;
+    Ok(if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    })
+}
+
+fn parse_expectation(text: &str) -> Result<Expectation, String> {
+    if let Some(rest) = text.strip_prefix("error on line ") {
+        let (line, message) = rest.split_once(':').ok_or(format!(
+            "expected `error on line <n>: <message>`, found {text}"
+        ))?;
+        let line = line
+            .parse()
+            .map_err(|e| format!("invalid line {line}: {e}"))?;
+        return Ok(Expectation::Error(line, message.trim().to_string()));
+    }
+
+    let (what, value) = text
+        .split_once('=')
+        .ok_or(format!("expected `<what> = <value>`, found {text}"))?;
+    let (what, value) = (what.trim(), value.trim());
+    match what.split_once(' ') {
+        _ if what == "output" => {
+            let output = value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .ok_or(format!("expected a quoted string, found {value}"))?;
+            Ok(Expectation::Output(
+                output.replace("\\n", "\n").replace("\\t", "\t"),
+            ))
+        }
+        _ if what == "trap" => Ok(Expectation::Trap(value.to_string())),
+        Some((width, address)) => {
+            let width = match width {
+                "byte" => MemoryWidth::Byte,
+                "half" => MemoryWidth::Half,
+                "word" => MemoryWidth::Word,
+                _ => return Err(format!("invalid width {width}")),
+            };
+            let address = address
+                .strip_prefix('[')
+                .and_then(|address| address.strip_suffix(']'))
+                .ok_or(format!("expected an address in brackets, found {address}"))?;
+            Ok(Expectation::Memory(
+                width,
+                parse_number(address)? as u32,
+                parse_number(value)?,
+            ))
+        }
+        None => {
+            let value = parse_number(value)?;
+            match (what.parse::<Register32>(), what.parse::<Register64>()) {
+                (Ok(register), _) => Ok(Expectation::Register32(register, value as u32)),
+                (_, Ok(register)) => Ok(Expectation::Register64(register, value)),
+                _ => Err(format!("{what} isn't a register")),
+            }
+        }
+    }
+}
+
+fn expectations(source: &str) -> Result<Vec<Expectation>, String> {
+    source
+        .lines()
+        .filter_map(|line| line.split_once("; expect ").map(|(_, text)| text.trim()))
+        .map(parse_expectation)
+        .collect()
+}
+
+fn line_of(source: &str, offset: usize) -> usize {
+    source[..offset.min(source.len())].matches('\n').count() + 1
+}
+
+fn register_32(emulator: &Emulator, register: Register32) -> u32 {
+    Register32::ALL
+        .iter()
+        .zip(emulator.registers())
+        .find(|(other, _)| **other == register)
+        .map_or(0, |(_, value)| value)
+}
+
+fn run(source: &str) -> Vec<String> {
+    let expectations = match expectations(source) {
+        Ok(expectations) if expectations.is_empty() => {
+            return vec!["no expectations".to_string()];
+        }
+        Ok(expectations) => expectations,
+        Err(e) => return vec![e],
+    };
+
+    let program = match parser::parse(source) {
+        Ok(program) => program,
+        Err(errors) => {
+            let errors = errors
+                .iter()
+                .map(|error| (line_of(source, error.span().start), error.to_string()))
+                .collect::<Vec<_>>();
+            let mut failures = Vec::new();
+            let mut expected_errors = 0;
+            for expectation in &expectations {
+                if let Expectation::Error(line, message) = expectation {
+                    expected_errors += 1;
+                    if !errors
+                        .iter()
+                        .any(|(other, error)| other == line && error.contains(message.as_str()))
+                    {
+                        failures.push(format!(
+                            "expected an error on line {line} containing {message:?}, found {errors:?}"
+                        ));
+                    }
+                }
+            }
+            if expected_errors == 0 {
+                failures.push(format!("failed to parse: {errors:?}"));
+            } else if expected_errors != errors.len() {
+                failures.push(format!(
+                    "expected {expected_errors} errors, found {errors:?}"
+                ));
+            }
+            return failures;
+        }
+    };
+    if expectations
+        .iter()
+        .any(|expectation| matches!(expectation, Expectation::Error(..)))
+    {
+        return vec!["expected the source to be rejected".to_string()];
+    }
+    let image = match assemble(&program) {
+        Ok(image) => image,
+        Err(e) => return vec![format!("failed to assemble: {e}")],
+    };
+
+    let output = SharedOutput::default();
+    let mut memory = Memory::new(DEFAULT_MEMORY_SIZE);
+    memory
+        .map(
+            CONSOLE_OUTPUT_ADDRESS,
+            1,
+            Box::new(ConsoleOutput::new(output.clone())),
+        )
+        .unwrap();
+    let mut emulator = match Emulator::new(&image.bytes, memory) {
+        Ok(emulator) => emulator,
+        Err(e) => return vec![e],
+    };
+    let mut trap = None;
+    let mut halted = false;
+    for _ in 0..MAX_STEPS {
+        match emulator.step() {
+            StepResult::NotHalted => {}
+            StepResult::Halted => {
+                halted = true;
+                break;
+            }
+            StepResult::Trapped(t) => {
+                trap = Some(format!("{t:?}"));
+                break;
+            }
+        }
+    }
+
+    let mut failures = Vec::new();
+    let mut compare = |what: String, actual: String, expected: String| {
+        if actual != expected {
+            failures.push(format!("{what}: expected {expected}, found {actual}"));
+        }
+    };
+    let expected_trap = expectations
+        .iter()
+        .find_map(|expectation| match expectation {
+            Expectation::Trap(trap) => Some(trap.clone()),
+            _ => None,
+        });
+    compare(
+        "trap".to_string(),
+        format!("{trap:?}"),
+        format!("{expected_trap:?}"),
+    );
+    if trap.is_none() && !halted {
+        compare(
+            "halted".to_string(),
+            format!("still running after {MAX_STEPS} steps"),
+            "halted".to_string(),
+        );
+    }
+    let ram = emulator.memory().ram();
+    for expectation in &expectations {
+        match expectation {
+            Expectation::Register32(register, value) => compare(
+                register.to_string(),
+                format!("{:#x}", register_32(&emulator, *register)),
+                format!("{value:#x}"),
+            ),
+            Expectation::Register64(register, value) => {
+                let actual = (register_32(&emulator, register.high()) as u64) << 32
+                    | register_32(&emulator, register.low()) as u64;
+                compare(
+                    register.to_string(),
+                    format!("{actual:#x}"),
+                    format!("{value:#x}"),
+                )
+            }
+            Expectation::Memory(width, address, value) => {
+                let start = *address as usize;
+                let actual = ram
+                    .get(start..start + width.bytes() as usize)
+                    .map(|bytes| {
+                        bytes
+                            .iter()
+                            .rev()
+                            .fold(0u64, |value, byte| value << 8 | *byte as u64)
+                    })
+                    .map_or("out of bounds".to_string(), |value| format!("{value:#x}"));
+                let mask = u64::MAX >> (64 - 8 * width.bytes());
+                compare(
+                    format!("{width:?} at {address:#x}"),
+                    actual,
+                    format!("{:#x}", value & mask),
+                )
+            }
+            Expectation::Output(expected) => compare(
+                "output".to_string(),
+                format!("{:?}", String::from_utf8_lossy(&output.0.borrow())),
+                format!("{expected:?}"),
+            ),
+            Expectation::Trap(_) | Expectation::Error(..) => {}
+        }
+    }
+    failures
+}
+
+#[test]
+fn programs() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let mut paths = fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "s"))
+        .collect::<Vec<_>>();
+    paths.sort();
+    assert!(!paths.is_empty(), "no programs in {directory:?}");
+
+    let mut failures = Vec::new();
+    for path in &paths {
+        let file = path.file_name().unwrap().to_string_lossy().to_string();
+        let source = fs::read_to_string(path).unwrap();
+        for failure in run(&source) {
+            failures.push(format!("{file}: {failure}"));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} failures in {} programs:\n{}",
+        failures.len(),
+        paths.len(),
+        failures.join("\n")
+    );
+}
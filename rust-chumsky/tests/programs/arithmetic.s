@@ -0,0 +1,12 @@
+; 32-bit arithmetic wraps unless asked to trap
+    mov r1, 40
+    add r1, r1, 2           ; expect r1 = 42
+    sub r2, r1, 50          ; expect r2 = -8
+    mul r3, r2, -3          ; expect r3 = 24
+    div r4, r2, 3           ; expect r4 = -2
+    divu r5, r2, 0x10       ; expect r5 = 0x0fffffff
+    rem r6, r2, 3           ; expect r6 = -2
+    mov r7, 0xffffffff
+    add r7, r7, 1           ; expect r7 = 0
+    sar r8, r2, 1           ; expect r8 = -4
+    halt
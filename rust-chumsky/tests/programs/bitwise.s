@@ -0,0 +1,9 @@
+    mov r1, 0b1100
+    and r2, r1, 0b1010      ; expect r2 = 8
+    or r3, r1, 0b1010       ; expect r3 = 14
+    xor r4, r1, 0b1010      ; expect r4 = 6
+    not r5, r1              ; expect r5 = 0xfffffff3
+    shl r6, r1, 28          ; expect r6 = 0xc0000000
+    shr r7, r6, 30          ; expect r7 = 3
+    cmp r8, r1, 13          ; expect r8 = -1
+    halt
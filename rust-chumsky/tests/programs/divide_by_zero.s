@@ -0,0 +1,5 @@
+    mov r1, 1
+    mov r2, 0
+    div r3, r1, r2          ; expect trap = DivideByZero
+    mov r4, 1               ; expect r4 = 0
+    halt
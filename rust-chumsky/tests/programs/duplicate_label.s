@@ -0,0 +1,4 @@
+start:
+    mov r1, 1
+start:                      ; expect error on line 3: duplicate label: start
+    jmp start
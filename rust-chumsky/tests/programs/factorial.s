@@ -0,0 +1,27 @@
+; 5! with a loop, then 4! in a recursive function
+main:
+    mov r1, 5
+    mov r2, 1
+loop:
+    mul r2, r2, r1
+    sub r1, r1, 1
+    cmp r3, r1, 0
+    jnz loop
+    mov r8, r2              ; expect r8 = 120
+
+    mov r1, 4
+    call factorial          ; expect r2 = 24
+    halt
+
+; r2 = r1!, using r1
+factorial:
+    cmp r3, r1, 1
+    jle base
+    sub r1, r1, 1
+    call factorial
+    add r1, r1, 1
+    mul r2, r2, r1
+    ret
+base:
+    mov r2, 1
+    ret
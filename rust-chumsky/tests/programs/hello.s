@@ -0,0 +1,16 @@
+; expect output = "hello, world\n"
+    la r1, message
+    mov r3, 0xffff0000
+print:
+    load8 r2, [r1]
+    cmp r4, r2, 0
+    jz done
+    store8 r2, [r3]
+    add r1, r1, 1
+    jmp print
+done:
+    halt
+
+message:
+    .ascii "hello, world\n"
+    .byte 0
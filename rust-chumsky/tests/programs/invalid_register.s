@@ -0,0 +1,4 @@
+    mov r1, 1
+    add r9, r1, 1           ; expect error on line 2: invalid arguments to add
+    mov r12, r3             ; expect error on line 3: invalid arguments to mov
+    halt
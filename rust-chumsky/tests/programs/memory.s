@@ -0,0 +1,18 @@
+    la r1, data
+    load8 r2, [r1]          ; expect r2 = 0xf0
+    load8s r3, [r1]         ; expect r3 = -16
+    load16s r4, [r1 + 1]    ; expect r4 = 0x1234
+    load32 r5, [r1 + 4]     ; expect r5 = 0xdeadbeef
+
+    mov r6, 0x800
+    store32 r5, [r6]        ; expect word [0x800] = 0xdeadbeef
+    store8 r2, [r6 + 4]     ; expect byte [0x804] = 0xf0
+    store16 r4, [r6 + 8]    ; expect half [0x808] = 0x1234
+    load64 r78, [r1 + 4]
+    store64 r78, [r6 + 16]  ; expect word [0x810] = 0xdeadbeef
+    halt
+
+data:
+    .byte 0xf0, 0x34, 0x12, 0
+    .word 0xdeadbeef
+    .word 0
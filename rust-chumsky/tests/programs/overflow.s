@@ -0,0 +1,4 @@
+    mov r1, 0x7fffffff
+    add r2, r1, 1           ; expect r2 = 0x80000000
+    adds r3, r1, 1          ; expect trap = Overflow
+    halt
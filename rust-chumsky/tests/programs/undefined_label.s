@@ -0,0 +1 @@
+    jmp nowhere             ; expect error on line 1: undefined label: nowhere
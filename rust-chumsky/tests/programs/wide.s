@@ -0,0 +1,7 @@
+; r12 is r1:r2, r34 is r3:r4 and so on, high half first
+    mov r12, 0xffffffff
+    add r12, r12, 1         ; expect r12 = 0x100000000
+    mul r34, r12, 3         ; expect r34 = 0x300000000
+    sub r56, r12, r34       ; expect r56 = -0x200000000
+    mov r78, -1i64          ; expect r7 = 0xffffffff
+    halt
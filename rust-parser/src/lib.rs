@@ -1,4 +1,6 @@
 pub mod matchers;
+pub mod parser;
+pub mod pratt;
 pub mod strings;
 #[cfg(test)]
 mod tests;
@@ -1,24 +1,183 @@
-use std::{fmt::Debug, ops::RangeBounds};
+use std::{
+    fmt::{self, Debug, Display, Formatter},
+    ops::RangeBounds,
+};
 
-use crate::strings::{BadPositionError, Match, PosStr};
+use crate::{
+    parser::{seq, Parser},
+    strings::{BadPositionError, Match, PosStr, Position},
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum MatchError {
+    /// `expected` has more than one entry when several alternatives failed at the same place.
     Expected {
-        expected: String,
+        pos: Position,
+        expected: Vec<String>,
         got: String,
     },
     Parse {
+        pos: Position,
         expected: String,
         got: String,
         error: String,
     },
     EndOfInput {
-        expected: String,
+        pos: Position,
+        expected: Vec<String>,
     },
     BadPositionError,
 }
 
+impl MatchError {
+    pub fn pos(&self) -> Option<Position> {
+        match self {
+            MatchError::Expected { pos, .. }
+            | MatchError::Parse { pos, .. }
+            | MatchError::EndOfInput { pos, .. } => Some(*pos),
+            MatchError::BadPositionError => None,
+        }
+    }
+
+    /// Combines the errors from two alternatives tried at the same place into the one most worth reporting.
+    ///
+    /// Whichever got further into the input wins, since that's most likely what was meant. If they failed at the same
+    /// place, expectations are combined, so the error lists everything that would have worked there.
+    pub fn merge(self, other: MatchError) -> MatchError {
+        match (self.pos(), other.pos()) {
+            (Some(a), Some(b)) if a > b => return self,
+            (Some(a), Some(b)) if a < b => return other,
+            (None, Some(_)) => return other,
+            _ => {}
+        }
+        match (self, other) {
+            (
+                MatchError::Expected {
+                    pos,
+                    mut expected,
+                    got,
+                },
+                MatchError::Expected {
+                    expected: other, ..
+                },
+            ) => {
+                add_expectations(&mut expected, other);
+                MatchError::Expected { pos, expected, got }
+            }
+            (
+                MatchError::EndOfInput { pos, mut expected },
+                MatchError::EndOfInput {
+                    expected: other, ..
+                },
+            ) => {
+                add_expectations(&mut expected, other);
+                MatchError::EndOfInput { pos, expected }
+            }
+            // anything else at the same place is more specific than a list of expectations, e.g. a number that was
+            // matched but didn't parse
+            (error @ MatchError::Parse { .. }, _) | (_, error @ MatchError::Parse { .. }) => error,
+            (error, _) => error,
+        }
+    }
+
+    /// Replaces what was expected with `label`, if the error is at `pos`. Errors further on are left alone, since
+    /// they're about something more specific that `label` already matched the start of.
+    pub fn labelled(self, pos: Position, label: &str) -> MatchError {
+        match self {
+            MatchError::Expected {
+                pos: error_pos,
+                got,
+                ..
+            } if error_pos == pos => MatchError::Expected {
+                pos,
+                expected: vec![label.to_owned()],
+                got,
+            },
+            MatchError::EndOfInput { pos: error_pos, .. } if error_pos == pos => {
+                MatchError::EndOfInput {
+                    pos,
+                    expected: vec![label.to_owned()],
+                }
+            }
+            error => error,
+        }
+    }
+
+    /// The error followed by the line of `source` it's on, with a caret under where it happened.
+    ///
+    /// ```text
+    /// expected one of '+', '-' at 1:3, found 'x'
+    ///   |
+    /// 1 | 1 x
+    ///   |   ^
+    /// ```
+    pub fn report(&self, source: &str) -> String {
+        let Some(pos) = self.pos() else {
+            return self.to_string();
+        };
+        let line = source.split('\n').nth(pos.line).unwrap_or("");
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        let number = (pos.line + 1).to_string();
+        let gutter = " ".repeat(number.len());
+        // line up the caret with the character above it, keeping tabs so it's the same width
+        let indent = line
+            .chars()
+            .take(pos.column)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        format!("{self}\n{gutter} |\n{number} | {line}\n{gutter} | {indent}^")
+    }
+}
+
+fn add_expectations(expected: &mut Vec<String>, other: Vec<String>) {
+    for e in other {
+        if !expected.contains(&e) {
+            expected.push(e);
+        }
+    }
+}
+
+fn format_expected(expected: &[String]) -> String {
+    match expected {
+        [single] => single.clone(),
+        _ => format!("one of {}", expected.join(", ")),
+    }
+}
+
+impl Display for MatchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        // positions count from 0, but people count lines and columns from 1
+        match self {
+            MatchError::Expected { pos, expected, got } => write!(
+                f,
+                "expected {} at {}:{}, found {got}",
+                format_expected(expected),
+                pos.line + 1,
+                pos.column + 1
+            ),
+            MatchError::Parse {
+                pos,
+                expected,
+                got,
+                error,
+            } => write!(
+                f,
+                "invalid {expected} {got} at {}:{}: {error}",
+                pos.line + 1,
+                pos.column + 1
+            ),
+            MatchError::EndOfInput { pos, expected } => write!(
+                f,
+                "expected {} at {}:{}, found the end of the input",
+                format_expected(expected),
+                pos.line + 1,
+                pos.column + 1
+            ),
+            MatchError::BadPositionError => write!(f, "bad position"),
+        }
+    }
+}
+
 impl From<BadPositionError> for MatchError {
     fn from(_value: BadPositionError) -> Self {
         Self::BadPositionError
@@ -29,7 +188,8 @@ pub fn any_char(input: PosStr) -> Result<Match<char>, MatchError> {
     match input.take_single_char() {
         Some(result) => Ok(result),
         None => Err(MatchError::EndOfInput {
-            expected: "any character".to_owned(),
+            pos: input.pos,
+            expected: vec!["any character".to_owned()],
         }),
     }
 }
@@ -38,11 +198,13 @@ pub fn specific_char(input: PosStr, c: char) -> Result<Match<char>, MatchError>
     match input.take_single_char() {
         Some(result) if result.value == c => Ok(result),
         Some(result) => Err(MatchError::Expected {
-            expected: format!("{c}"),
-            got: format!("{}", result.value),
+            pos: input.pos,
+            expected: vec![format!("{c:?}")],
+            got: format!("{:?}", result.value),
         }),
         None => Err(MatchError::EndOfInput {
-            expected: format!("{c}"),
+            pos: input.pos,
+            expected: vec![format!("{c:?}")],
         }),
     }
 }
@@ -54,28 +216,27 @@ where
     match input.take_single_char() {
         Some(result) if r.contains(&result.value) => Ok(result),
         Some(result) => Err(MatchError::Expected {
-            expected: format!("{r:?}"),
-            got: format!("{}", result.value),
+            pos: input.pos,
+            expected: vec![format!("{r:?}")],
+            got: format!("{:?}", result.value),
         }),
         None => Err(MatchError::EndOfInput {
-            expected: format!("{r:?}"),
+            pos: input.pos,
+            expected: vec![format!("{r:?}")],
         }),
     }
 }
 
+#[deprecated(note = "use `parser::seq` with a tuple")]
 pub fn seq2<T1, M1, T2, M2>(input: PosStr, m1: M1, m2: M2) -> Result<Match<(T1, T2)>, MatchError>
 where
     M1: Fn(PosStr) -> Result<Match<T1>, MatchError>,
     M2: Fn(PosStr) -> Result<Match<T2>, MatchError>,
 {
-    let r1 = m1(input)?;
-    let r2 = m2(r1.remainder)?;
-    Ok(Match {
-        value: (r1.value, r2.value),
-        remainder: r2.remainder,
-    })
+    seq((m1, m2)).parse(input)
 }
 
+#[deprecated(note = "use `parser::seq` with a tuple")]
 pub fn seq3<T1, M1, T2, M2, T3, M3>(
     input: PosStr,
     m1: M1,
@@ -87,15 +248,10 @@ where
     M2: Fn(PosStr) -> Result<Match<T2>, MatchError>,
     M3: Fn(PosStr) -> Result<Match<T3>, MatchError>,
 {
-    let r1 = m1(input)?;
-    let r2 = m2(r1.remainder)?;
-    let r3 = m3(r2.remainder)?;
-    Ok(Match {
-        value: (r1.value, r2.value, r3.value),
-        remainder: r3.remainder,
-    })
+    seq((m1, m2, m3)).parse(input)
 }
 
+#[deprecated(note = "use `parser::choice` with a tuple, which merges the errors")]
 pub fn any2<T, M1, M2>(input: PosStr, m1: M1, m2: M2) -> Result<Match<T>, (MatchError, MatchError)>
 where
     M1: Fn(PosStr) -> Result<Match<T>, MatchError>,
@@ -109,6 +265,7 @@ where
     }
 }
 
+#[deprecated(note = "use `parser::choice` with a tuple, which merges the errors")]
 pub fn any3<T, M1, M2, M3>(
     input: PosStr,
     m1: M1,
@@ -206,7 +363,8 @@ where
         })
     } else {
         Err(MatchError::Expected {
-            expected: format!("{r:?} results"),
+            pos: input.pos,
+            expected: vec![format!("{r:?} results")],
             got: format!("{current_count}"),
         })
     }
@@ -214,5 +372,128 @@ where
 
 #[cfg(test)]
 mod tests {
-    // TODO tests
+    use crate::{
+        matchers::{any2, any3, seq2, seq3, specific_char, MatchError},
+        strings::Position,
+    };
+
+    fn expected(column: usize, expected: &[&str]) -> MatchError {
+        MatchError::Expected {
+            pos: Position { line: 0, column },
+            expected: expected.iter().map(|e| e.to_string()).collect(),
+            got: "'x'".to_owned(),
+        }
+    }
+
+    #[test]
+    fn errors_have_positions() {
+        let input = specific_char("ab".into(), 'a').unwrap().remainder;
+        assert_eq!(
+            specific_char(input, 'c'),
+            Err(MatchError::Expected {
+                pos: Position { line: 0, column: 1 },
+                expected: vec!["'c'".to_owned()],
+                got: "'b'".to_owned(),
+            })
+        );
+        assert_eq!(
+            specific_char(specific_char("a".into(), 'a').unwrap().remainder, 'b'),
+            Err(MatchError::EndOfInput {
+                pos: Position { line: 0, column: 1 },
+                expected: vec!["'b'".to_owned()],
+            })
+        );
+    }
+
+    #[test]
+    fn merge_keeps_the_furthest_failure() {
+        assert_eq!(
+            expected(1, &["'a'"]).merge(expected(3, &["'b'"])),
+            expected(3, &["'b'"])
+        );
+        assert_eq!(
+            expected(3, &["'a'"]).merge(expected(1, &["'b'"])),
+            expected(3, &["'a'"])
+        );
+    }
+
+    #[test]
+    fn merge_combines_expectations_at_the_same_place() {
+        let error = expected(2, &["'a'", "'b'"]).merge(expected(2, &["'b'", "'c'"]));
+        assert_eq!(error, expected(2, &["'a'", "'b'", "'c'"]));
+        assert_eq!(
+            error.to_string(),
+            "expected one of 'a', 'b', 'c' at 1:3, found 'x'"
+        );
+    }
+
+    #[test]
+    fn labelled_only_replaces_errors_at_the_start() {
+        let start = Position { line: 0, column: 2 };
+        assert_eq!(
+            expected(2, &["'a'", "'b'"]).labelled(start, "letter"),
+            expected(2, &["letter"])
+        );
+        assert_eq!(
+            expected(4, &["'a'"]).labelled(start, "letter"),
+            expected(4, &["'a'"])
+        );
+    }
+
+    #[test]
+    fn report() {
+        let error = MatchError::Expected {
+            pos: Position { line: 1, column: 2 },
+            expected: vec!["';'".to_owned()],
+            got: "'}'".to_owned(),
+        };
+        assert_eq!(
+            error.report("first\n\tb}\nthird"),
+            "expected ';' at 2:3, found '}'\n  |\n2 | \tb}\n  | \t ^"
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn fixed_size_sequences_and_alternatives() {
+        let result = seq2(
+            "ab".into(),
+            |input| specific_char(input, 'a'),
+            |input| specific_char(input, 'b'),
+        );
+        assert_eq!(result.map(|m| m.value), Ok(('a', 'b')));
+        let result = seq3(
+            "abc".into(),
+            |input| specific_char(input, 'a'),
+            |input| specific_char(input, 'b'),
+            |input| specific_char(input, 'c'),
+        );
+        assert_eq!(result.map(|m| m.value), Ok(('a', 'b', 'c')));
+        assert!(seq2(
+            "ax".into(),
+            |input| specific_char(input, 'a'),
+            |input| specific_char(input, 'b')
+        )
+        .is_err());
+
+        let result = any2(
+            "b".into(),
+            |input| specific_char(input, 'a'),
+            |input| specific_char(input, 'b'),
+        );
+        assert_eq!(result.map(|m| m.value), Ok('b'));
+        let result = any3(
+            "c".into(),
+            |input| specific_char(input, 'a'),
+            |input| specific_char(input, 'b'),
+            |input| specific_char(input, 'c'),
+        );
+        assert_eq!(result.map(|m| m.value), Ok('c'));
+        let result = any2(
+            "x".into(),
+            |input| specific_char(input, 'a'),
+            |input| specific_char(input, 'b'),
+        );
+        assert!(matches!(result, Err((e1, e2)) if e1 != e2));
+    }
 }
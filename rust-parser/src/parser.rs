@@ -0,0 +1,459 @@
+use std::{fmt::Debug, ops::RangeBounds};
+
+use crate::{
+    matchers::{char_range, specific_char, MatchError},
+    strings::{Match, PosStr},
+};
+
+/// Anything that can match the start of a `PosStr`. Every matcher in `matchers` with the signature
+/// `fn(PosStr) -> Result<Match<T>, MatchError>` is one, as is any closure with that signature, so grammars can mix
+/// hand-written matchers with the combinators here.
+pub trait Parser<T> {
+    fn parse<'a>(&self, input: PosStr<'a>) -> Result<Match<'a, T>, MatchError>;
+
+    fn map<U, F>(self, f: F) -> impl Parser<U>
+    where
+        Self: Sized,
+        F: Fn(T) -> U,
+    {
+        parser(move |input| Ok(self.parse(input)?.map(&f)))
+    }
+
+    /// Matches `self` and then `next`, keeping both values.
+    fn then<U, P>(self, next: P) -> impl Parser<(T, U)>
+    where
+        Self: Sized,
+        P: Parser<U>,
+    {
+        parser(move |input| {
+            let first = self.parse(input)?;
+            let second = next.parse(first.remainder)?;
+            Ok(second.map(|value| (first.value, value)))
+        })
+    }
+
+    /// Matches `self` and then `next`, keeping only the value of `next`.
+    fn ignore_then<U, P>(self, next: P) -> impl Parser<U>
+    where
+        Self: Sized,
+        P: Parser<U>,
+    {
+        parser(move |input| next.parse(self.parse(input)?.remainder))
+    }
+
+    /// Matches `self` and then `next`, keeping only the value of `self`.
+    fn then_ignore<U, P>(self, next: P) -> impl Parser<T>
+    where
+        Self: Sized,
+        P: Parser<U>,
+    {
+        parser(move |input| {
+            let first = self.parse(input)?;
+            let second = next.parse(first.remainder)?;
+            Ok(Match {
+                value: first.value,
+                remainder: second.remainder,
+            })
+        })
+    }
+
+    /// Matches `self`, or `other` from the same place if `self` fails. Fails with the errors merged, see
+    /// `MatchError::merge`, if both do.
+    fn or<P>(self, other: P) -> impl Parser<T>
+    where
+        Self: Sized,
+        P: Parser<T>,
+    {
+        parser(move |input| {
+            self.parse(input)
+                .or_else(|error| other.parse(input).map_err(|other| error.merge(other)))
+        })
+    }
+
+    /// Reports a failure to match at all as expecting `label`, e.g. "expected expression" rather than a list of every
+    /// character an expression could start with.
+    fn labelled(self, label: &str) -> impl Parser<T>
+    where
+        Self: Sized,
+    {
+        let label = label.to_owned();
+        parser(move |input| {
+            self.parse(input)
+                .map_err(|error| error.labelled(input.pos, &label))
+        })
+    }
+
+    /// Matches `self` as many times as possible, including none. Stops at the first failure, or at the first match that
+    /// doesn't consume anything, which would otherwise match forever.
+    fn many(self) -> impl Parser<Vec<T>>
+    where
+        Self: Sized,
+    {
+        parser(move |input| {
+            let mut values = Vec::new();
+            let mut remainder = input;
+            while let Ok(result) = self.parse(remainder) {
+                if result.remainder.pos == remainder.pos {
+                    break;
+                }
+                values.push(result.value);
+                remainder = result.remainder;
+            }
+            Ok(Match {
+                value: values,
+                remainder,
+            })
+        })
+    }
+
+    /// Like `many`, but fails with the first error if `self` doesn't match at least once.
+    fn many1(self) -> impl Parser<Vec<T>>
+    where
+        Self: Sized,
+    {
+        parser(move |input| {
+            let first = self.parse(input)?;
+            let mut values = vec![first.value];
+            let mut remainder = first.remainder;
+            while let Ok(result) = self.parse(remainder) {
+                if result.remainder.pos == remainder.pos {
+                    break;
+                }
+                values.push(result.value);
+                remainder = result.remainder;
+            }
+            Ok(Match {
+                value: values,
+                remainder,
+            })
+        })
+    }
+
+    /// Never fails. Gives `None`, consuming nothing, if `self` doesn't match.
+    fn optional(self) -> impl Parser<Option<T>>
+    where
+        Self: Sized,
+    {
+        parser(move |input| match self.parse(input) {
+            Ok(result) => Ok(result.map(Some)),
+            Err(_) => Ok(Match {
+                value: None,
+                remainder: input,
+            }),
+        })
+    }
+
+    /// Matches zero or more of `self` with `separator` between each pair, keeping only the values of `self`.
+    ///
+    /// Fails if a separator isn't followed by another `self`, the same as `binary_list`.
+    fn separated_by<U, P>(self, separator: P) -> impl Parser<Vec<T>>
+    where
+        Self: Sized,
+        P: Parser<U>,
+    {
+        parser(move |input| {
+            let Ok(first) = self.parse(input) else {
+                return Ok(Match {
+                    value: Vec::new(),
+                    remainder: input,
+                });
+            };
+            let mut values = vec![first.value];
+            let mut remainder = first.remainder;
+            while let Ok(separator) = separator.parse(remainder) {
+                let result = self.parse(separator.remainder)?;
+                values.push(result.value);
+                remainder = result.remainder;
+            }
+            Ok(Match {
+                value: values,
+                remainder,
+            })
+        })
+    }
+
+    /// Matches `open`, then `self`, then `close`, keeping only the value of `self`.
+    fn delimited_by<U, V, P1, P2>(self, open: P1, close: P2) -> impl Parser<T>
+    where
+        Self: Sized,
+        P1: Parser<U>,
+        P2: Parser<V>,
+    {
+        open.ignore_then(self).then_ignore(close)
+    }
+
+    /// Skips any whitespace before and after `self`.
+    fn padded(self) -> impl Parser<T>
+    where
+        Self: Sized,
+    {
+        parser(move |input| {
+            let result = self.parse(skip_whitespace(input))?;
+            Ok(Match {
+                value: result.value,
+                remainder: skip_whitespace(result.remainder),
+            })
+        })
+    }
+}
+
+impl<T, F> Parser<T> for F
+where
+    F: for<'a> Fn(PosStr<'a>) -> Result<Match<'a, T>, MatchError>,
+{
+    fn parse<'a>(&self, input: PosStr<'a>) -> Result<Match<'a, T>, MatchError> {
+        self(input)
+    }
+}
+
+/// Makes a parser out of a closure.
+///
+/// Passing the closure through here is what gives it a signature that works for any lifetime of input, which it
+/// wouldn't get from its own body, so a closure handed straight to something expecting a `Parser` won't compile.
+pub fn parser<T, F>(f: F) -> F
+where
+    F: for<'a> Fn(PosStr<'a>) -> Result<Match<'a, T>, MatchError>,
+{
+    f
+}
+
+/// Matches exactly `c`.
+pub fn just(c: char) -> impl Parser<char> {
+    parser(move |input| specific_char(input, c))
+}
+
+/// Matches any character in `r`.
+pub fn char_in<R>(r: R) -> impl Parser<char>
+where
+    R: RangeBounds<char> + Debug + Clone,
+{
+    parser(move |input| char_range(input, r.clone()))
+}
+
+fn skip_whitespace(input: PosStr) -> PosStr {
+    input.skip_while(|_, c| c.is_whitespace())
+}
+
+/// A tuple of parsers that can be matched one after another. See `seq`.
+pub trait Sequence<T> {
+    fn parse_sequence<'a>(&self, input: PosStr<'a>) -> Result<Match<'a, T>, MatchError>;
+}
+
+/// A tuple of parsers that can be tried in order. See `choice`.
+pub trait Choice<T> {
+    fn parse_choice<'a>(&self, input: PosStr<'a>) -> Result<Match<'a, T>, MatchError>;
+}
+
+macro_rules! impl_sequence_and_choice {
+    ($(($p:ident, $P:ident, $T:ident)),+) => {
+        impl<$($T, $P: Parser<$T>),+> Sequence<($($T,)+)> for ($($P,)+) {
+            fn parse_sequence<'a>(&self, input: PosStr<'a>) -> Result<Match<'a, ($($T,)+)>, MatchError> {
+                let ($($p,)+) = self;
+                let remainder = input;
+                $(let Match { value: $p, remainder } = $p.parse(remainder)?;)+
+                Ok(Match {
+                    value: ($($p,)+),
+                    remainder,
+                })
+            }
+        }
+
+        impl<T, $($P: Parser<T>),+> Choice<T> for ($($P,)+) {
+            fn parse_choice<'a>(&self, input: PosStr<'a>) -> Result<Match<'a, T>, MatchError> {
+                let ($($p,)+) = self;
+                let error: Option<MatchError> = None;
+                $(
+                    let error = match $p.parse(input) {
+                        Ok(result) => return Ok(result),
+                        Err(e) => Some(match error {
+                            Some(error) => error.merge(e),
+                            None => e,
+                        }),
+                    };
+                )+
+                Err(error.expect("a choice has at least two alternatives"))
+            }
+        }
+    };
+}
+
+impl_sequence_and_choice!((p1, P1, T1), (p2, P2, T2));
+impl_sequence_and_choice!((p1, P1, T1), (p2, P2, T2), (p3, P3, T3));
+impl_sequence_and_choice!((p1, P1, T1), (p2, P2, T2), (p3, P3, T3), (p4, P4, T4));
+impl_sequence_and_choice!(
+    (p1, P1, T1),
+    (p2, P2, T2),
+    (p3, P3, T3),
+    (p4, P4, T4),
+    (p5, P5, T5)
+);
+impl_sequence_and_choice!(
+    (p1, P1, T1),
+    (p2, P2, T2),
+    (p3, P3, T3),
+    (p4, P4, T4),
+    (p5, P5, T5),
+    (p6, P6, T6)
+);
+impl_sequence_and_choice!(
+    (p1, P1, T1),
+    (p2, P2, T2),
+    (p3, P3, T3),
+    (p4, P4, T4),
+    (p5, P5, T5),
+    (p6, P6, T6),
+    (p7, P7, T7)
+);
+impl_sequence_and_choice!(
+    (p1, P1, T1),
+    (p2, P2, T2),
+    (p3, P3, T3),
+    (p4, P4, T4),
+    (p5, P5, T5),
+    (p6, P6, T6),
+    (p7, P7, T7),
+    (p8, P8, T8)
+);
+
+/// Matches each parser in a tuple of up to 8 one after another, giving a tuple of their values.
+pub fn seq<T, S>(parsers: S) -> impl Parser<T>
+where
+    S: Sequence<T>,
+{
+    parser(move |input| parsers.parse_sequence(input))
+}
+
+/// Tries each parser in a tuple of up to 8 in order from the same place, giving the first match. Fails with all their
+/// errors merged, see `MatchError::merge`, if none of them match.
+pub fn choice<T, C>(parsers: C) -> impl Parser<T>
+where
+    C: Choice<T>,
+{
+    parser(move |input| parsers.parse_choice(input))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        matchers::{any_char, MatchError},
+        parser::{char_in, choice, just, seq, Parser},
+        strings::{Match, PosStr, Position},
+    };
+
+    fn remainder(column: usize, s: &str) -> PosStr<'_> {
+        PosStr {
+            pos: Position { line: 0, column },
+            s,
+        }
+    }
+
+    #[test]
+    fn then_and_map() {
+        let digit = char_in('0'..='9').map(|c| c.to_digit(10).unwrap());
+        assert_eq!(
+            digit.then(just('!')).parse("7!?".into()),
+            Ok(Match {
+                value: (7, '!'),
+                remainder: remainder(2, "?"),
+            })
+        );
+    }
+
+    #[test]
+    fn or_tries_the_second_from_the_same_place_and_merges_errors() {
+        let parser = just('a').then(just('b')).or(just('a').then(just('c')));
+        assert_eq!(
+            parser.parse("ac".into()),
+            Ok(Match {
+                value: ('a', 'c'),
+                remainder: remainder(2, ""),
+            })
+        );
+        assert_eq!(
+            parser.parse("ad".into()),
+            Err(MatchError::Expected {
+                pos: Position { line: 0, column: 1 },
+                expected: vec!["'b'".to_owned(), "'c'".to_owned()],
+                got: "'d'".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn many_and_many1() {
+        let digits = char_in('0'..='9').many();
+        assert_eq!(
+            digits.parse("12a".into()),
+            Ok(Match {
+                value: vec!['1', '2'],
+                remainder: remainder(2, "a"),
+            })
+        );
+        assert_eq!(
+            digits.parse("a".into()),
+            Ok(Match {
+                value: vec![],
+                remainder: remainder(0, "a"),
+            })
+        );
+        assert!(char_in('0'..='9').many1().parse("a".into()).is_err());
+    }
+
+    #[test]
+    fn many_stops_when_nothing_is_consumed() {
+        assert_eq!(
+            just('a').optional().many().parse("b".into()),
+            Ok(Match {
+                value: vec![],
+                remainder: remainder(0, "b"),
+            })
+        );
+    }
+
+    #[test]
+    fn separated_by_and_delimited_by() {
+        let list = char_in('0'..='9')
+            .padded()
+            .separated_by(just(','))
+            .delimited_by(just('['), just(']'));
+        assert_eq!(
+            list.parse("[1, 2 ,3]x".into()),
+            Ok(Match {
+                value: vec!['1', '2', '3'],
+                remainder: remainder(9, "x"),
+            })
+        );
+        assert_eq!(
+            list.parse("[]".into()),
+            Ok(Match {
+                value: vec![],
+                remainder: remainder(2, ""),
+            })
+        );
+        assert!(list.parse("[1,]".into()).is_err());
+    }
+
+    #[test]
+    fn seq_and_choice() {
+        let parser = seq((
+            just('a'),
+            any_char,
+            choice((just('x'), just('y'), just('z'))),
+        ));
+        assert_eq!(
+            parser.parse("a-zz".into()),
+            Ok(Match {
+                value: ('a', '-', 'z'),
+                remainder: remainder(3, "z"),
+            })
+        );
+        assert_eq!(
+            parser.parse("a-w".into()),
+            Err(MatchError::Expected {
+                pos: Position { line: 0, column: 2 },
+                expected: vec!["'x'".to_owned(), "'y'".to_owned(), "'z'".to_owned()],
+                got: "'w'".to_owned(),
+            })
+        );
+    }
+}
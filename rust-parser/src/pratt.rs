@@ -0,0 +1,269 @@
+use crate::{
+    matchers::MatchError,
+    parser::Parser,
+    strings::{Match, PosStr},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Associativity {
+    /// `a - b - c` is `(a - b) - c`.
+    Left,
+    /// `a ^ b ^ c` is `a ^ (b ^ c)`.
+    Right,
+}
+
+struct Prefix<'p, T> {
+    operator: Box<dyn Parser<()> + 'p>,
+    right_power: u32,
+    fold: Box<dyn Fn(T) -> T + 'p>,
+}
+
+struct Postfix<'p, T> {
+    operator: Box<dyn Parser<()> + 'p>,
+    left_power: u32,
+    fold: Box<dyn Fn(T) -> T + 'p>,
+}
+
+struct Infix<'p, T> {
+    operator: Box<dyn Parser<()> + 'p>,
+    left_power: u32,
+    right_power: u32,
+    fold: Box<dyn Fn(T, T) -> T + 'p>,
+}
+
+/// Builds a parser for expressions made of `atom`s and operators, folding them into a tree as it goes, using
+/// precedence climbing (a Pratt parser).
+///
+/// Every operator has a precedence, and higher precedences bind tighter, whatever kind of operator they are. For
+/// example with `-` as a prefix operator at 3, `*` as an infix operator at 2 and `+` at 1, `-a * b + c` is
+/// `((-a) * b) + c`. Operators are tried in the order they were added, and the first that matches is used.
+///
+/// ```
+/// use rust_parser::{
+///     parser::{char_in, just, Parser},
+///     pratt::{Associativity, Pratt},
+/// };
+///
+/// let digit = char_in('0'..='9').map(|c| c.to_digit(10).unwrap() as i64);
+/// let expression = Pratt::new(digit)
+///     .prefix(just('-'), 3, |x| -x)
+///     .infix(just('*'), 2, Associativity::Left, |l, r| l * r)
+///     .infix(just('-'), 1, Associativity::Left, |l, r| l - r);
+/// assert_eq!(expression.parse("9-2*-3-1".into()).unwrap().value, 14);
+/// ```
+pub struct Pratt<'p, A, T> {
+    atom: A,
+    prefix: Vec<Prefix<'p, T>>,
+    postfix: Vec<Postfix<'p, T>>,
+    infix: Vec<Infix<'p, T>>,
+}
+
+impl<'p, A, T> Pratt<'p, A, T>
+where
+    A: Parser<T>,
+{
+    /// `atom` is what the operators apply to. It can use the expression parser itself, for parentheses and so on, by
+    /// calling a function that builds it.
+    pub fn new(atom: A) -> Self {
+        Self {
+            atom,
+            prefix: Vec::new(),
+            postfix: Vec::new(),
+            infix: Vec::new(),
+        }
+    }
+
+    pub fn prefix<O, P, F>(mut self, operator: P, precedence: u32, fold: F) -> Self
+    where
+        O: 'p,
+        P: Parser<O> + 'p,
+        F: Fn(T) -> T + 'p,
+    {
+        self.prefix.push(Prefix {
+            operator: Box::new(operator.map(|_| ())),
+            right_power: precedence * 2,
+            fold: Box::new(fold),
+        });
+        self
+    }
+
+    pub fn postfix<O, P, F>(mut self, operator: P, precedence: u32, fold: F) -> Self
+    where
+        O: 'p,
+        P: Parser<O> + 'p,
+        F: Fn(T) -> T + 'p,
+    {
+        self.postfix.push(Postfix {
+            operator: Box::new(operator.map(|_| ())),
+            left_power: precedence * 2,
+            fold: Box::new(fold),
+        });
+        self
+    }
+
+    pub fn infix<O, P, F>(
+        mut self,
+        operator: P,
+        precedence: u32,
+        associativity: Associativity,
+        fold: F,
+    ) -> Self
+    where
+        O: 'p,
+        P: Parser<O> + 'p,
+        F: Fn(T, T) -> T + 'p,
+    {
+        // the operand on the side that binds less tightly stops at another operator with the same precedence, which
+        // leaves that operator to the other side
+        let (left_power, right_power) = match associativity {
+            Associativity::Left => (precedence * 2, precedence * 2 + 1),
+            Associativity::Right => (precedence * 2 + 1, precedence * 2),
+        };
+        self.infix.push(Infix {
+            operator: Box::new(operator.map(|_| ())),
+            left_power,
+            right_power,
+            fold: Box::new(fold),
+        });
+        self
+    }
+
+    /// Matches an expression made of operators that bind at least as tightly as `min_power`.
+    fn parse_expression<'a>(
+        &self,
+        input: PosStr<'a>,
+        min_power: u32,
+    ) -> Result<Match<'a, T>, MatchError> {
+        let mut error: Option<MatchError> = None;
+        let mut left = None;
+        for prefix in self.prefix.iter() {
+            match prefix.operator.parse(input) {
+                Ok(operator) => {
+                    let operand = self.parse_expression(operator.remainder, prefix.right_power)?;
+                    left = Some(operand.map(&prefix.fold));
+                    break;
+                }
+                Err(e) => error = Some(merge(error, e)),
+            }
+        }
+        let Match {
+            value: mut left,
+            mut remainder,
+        } = match left {
+            Some(left) => left,
+            None => self.atom.parse(input).map_err(|e| merge(error, e))?,
+        };
+
+        'operators: loop {
+            for postfix in self.postfix.iter() {
+                if postfix.left_power < min_power {
+                    continue;
+                }
+                if let Ok(operator) = postfix.operator.parse(remainder) {
+                    left = (postfix.fold)(left);
+                    remainder = operator.remainder;
+                    continue 'operators;
+                }
+            }
+            for infix in self.infix.iter() {
+                if infix.left_power < min_power {
+                    continue;
+                }
+                if let Ok(operator) = infix.operator.parse(remainder) {
+                    let right = self.parse_expression(operator.remainder, infix.right_power)?;
+                    left = (infix.fold)(left, right.value);
+                    remainder = right.remainder;
+                    continue 'operators;
+                }
+            }
+            break;
+        }
+
+        Ok(Match {
+            value: left,
+            remainder,
+        })
+    }
+}
+
+fn merge(error: Option<MatchError>, e: MatchError) -> MatchError {
+    match error {
+        Some(error) => error.merge(e),
+        None => e,
+    }
+}
+
+impl<A, T> Parser<T> for Pratt<'_, A, T>
+where
+    A: Parser<T>,
+{
+    fn parse<'a>(&self, input: PosStr<'a>) -> Result<Match<'a, T>, MatchError> {
+        self.parse_expression(input, 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        parser::{char_in, just, Parser},
+        pratt::{Associativity, Pratt},
+    };
+
+    /// Parses single letters and operators into a fully parenthesised string, so the tree is easy to check.
+    fn parse(source: &str) -> String {
+        Pratt::new(char_in('a'..='z').map(|c| c.to_string()))
+            .prefix(just('-'), 4, |x| format!("(-{x})"))
+            .postfix(just('!'), 5, |x| format!("({x}!)"))
+            .infix(just('^'), 3, Associativity::Right, |l, r| {
+                format!("({l}^{r})")
+            })
+            .infix(just('*'), 2, Associativity::Left, |l, r| {
+                format!("({l}*{r})")
+            })
+            .infix(just('+'), 1, Associativity::Left, |l, r| {
+                format!("({l}+{r})")
+            })
+            .parse(source.into())
+            .map(|result| result.value)
+            .unwrap_or_else(|e| e.to_string())
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(parse("a+b*c"), "(a+(b*c))");
+        assert_eq!(parse("a*b+c"), "((a*b)+c)");
+        assert_eq!(parse("a^b*c"), "((a^b)*c)");
+    }
+
+    #[test]
+    fn associativity() {
+        assert_eq!(parse("a+b+c"), "((a+b)+c)");
+        assert_eq!(parse("a^b^c"), "(a^(b^c))");
+    }
+
+    #[test]
+    fn prefix_and_postfix() {
+        assert_eq!(parse("-a*b"), "((-a)*b)");
+        assert_eq!(parse("--a"), "(-(-a))");
+        assert_eq!(parse("-a!"), "(-(a!))");
+        assert_eq!(parse("a!!+b"), "(((a!)!)+b)");
+        assert_eq!(parse("a^-b"), "(a^(-b))");
+    }
+
+    #[test]
+    fn stops_before_what_isnt_an_operator() {
+        let result = Pratt::new(char_in('a'..='z'))
+            .infix(just('+'), 1, Associativity::Left, |l, _| l)
+            .parse("a+b c".into())
+            .unwrap();
+        assert_eq!(result.remainder.s, " c");
+    }
+
+    #[test]
+    fn missing_operand() {
+        assert_eq!(
+            parse("a+*"),
+            "expected one of '-', 'a'..='z' at 1:3, found '*'"
+        );
+    }
+}
@@ -1,7 +1,9 @@
 use std::fmt::Debug;
 
 use crate::{
-    matchers::{any2, any3, binary_list, char_range, specific_char, MatchError},
+    matchers::{char_range, specific_char, MatchError},
+    parser::{choice, just, Parser},
+    pratt::{Associativity, Pratt},
     strings::{Match, PosStr},
 };
 
@@ -92,6 +94,7 @@ fn parse_number(input: PosStr) -> Result<Match<ASTNode>, MatchError> {
     match full_match.value.s.parse() {
         Ok(value) => Ok(full_match.map(|_| ASTNode::Number(value))),
         Err(e) => Err(MatchError::Parse {
+            pos: input.pos,
             expected: "number".to_owned(),
             got: full_match.value.s.to_owned(),
             error: format!("{e:?}"),
@@ -99,98 +102,33 @@ fn parse_number(input: PosStr) -> Result<Match<ASTNode>, MatchError> {
     }
 }
 
-fn parse_negated_number(input: PosStr) -> Result<Match<ASTNode>, MatchError> {
-    let remainder = specific_char(skip_whitespace(input), '-')?.remainder;
-    Ok(parse_number(remainder)?.map(|x| ASTNode::Negate(Box::new(x))))
-}
-
 fn parse_parenthesis(input: PosStr) -> Result<Match<ASTNode>, MatchError> {
-    let remainder = specific_char(skip_whitespace(input), '(')?.remainder;
-    let result = parse_expression(remainder)?;
-    let remainder = specific_char(skip_whitespace(result.remainder), ')')?.remainder;
-    Ok(Match {
-        value: result.value,
-        remainder: remainder,
-    })
+    parse_expression
+        .delimited_by(just('(').padded(), just(')').padded())
+        .parse(input)
 }
 
-fn parse_term(input: PosStr) -> Result<Match<ASTNode>, MatchError> {
-    any3(input, parse_number, parse_negated_number, parse_parenthesis).map_err(|e| {
-        MatchError::Expected {
-            expected: "expression".to_owned(),
-            got: format!("{e:?}"),
-        }
-    })
-}
-
-fn parse_mulops(input: PosStr) -> Result<Match<ASTNode>, MatchError> {
-    enum Op {
-        Multiply,
-        Divide,
-    }
-    Ok(binary_list(
-        input,
-        parse_term,
-        |input| {
-            any2(
-                skip_whitespace(input),
-                |input| specific_char(input, '*').map(|x| x.map(|_| Op::Multiply)),
-                |input| specific_char(input, '/').map(|x| x.map(|_| Op::Divide)),
-            )
-            .map_err(|e| MatchError::Expected {
-                expected: "* or /".to_owned(),
-                got: format!("{e:?}"),
-            })
-        },
-        1..,
-    )?
-    .map(|results| {
-        // unwrap is safe because range constraint
-        let (first, remainder) = results.unwrap();
-        remainder
-            .into_iter()
-            .fold(first, |left, (op, right)| match op {
-                Op::Multiply => ASTNode::Multiply(Box::new(left), Box::new(right)),
-                Op::Divide => ASTNode::Divide(Box::new(left), Box::new(right)),
-            })
-    }))
-}
-
-fn parse_addops(input: PosStr) -> Result<Match<ASTNode>, MatchError> {
-    enum Op {
-        Add,
-        Subtract,
-    }
-    Ok(binary_list(
-        input,
-        parse_mulops,
-        |input| {
-            any2(
-                skip_whitespace(input),
-                |input| specific_char(input, '+').map(|x| x.map(|_| Op::Add)),
-                |input| specific_char(input, '-').map(|x| x.map(|_| Op::Subtract)),
-            )
-            .map_err(|e| MatchError::Expected {
-                expected: "+ or -".to_owned(),
-                got: format!("{e:?}"),
-            })
-        },
-        1..,
-    )?
-    .map(|results| {
-        // unwrap is safe because range constraint
-        let (first, remainder) = results.unwrap();
-        remainder
-            .into_iter()
-            .fold(first, |left, (op, right)| match op {
-                Op::Add => ASTNode::Add(Box::new(left), Box::new(right)),
-                Op::Subtract => ASTNode::Subtract(Box::new(left), Box::new(right)),
-            })
-    }))
+fn parse_atom(input: PosStr) -> Result<Match<ASTNode>, MatchError> {
+    // skipped here so that a missing atom is reported where it should start, not before the whitespace
+    choice((parse_number.labelled("number"), parse_parenthesis)).parse(skip_whitespace(input))
 }
 
 fn parse_expression(input: PosStr) -> Result<Match<ASTNode>, MatchError> {
-    parse_addops(input)
+    Pratt::new(parse_atom)
+        .prefix(just('-').padded(), 3, |x| ASTNode::Negate(Box::new(x)))
+        .infix(just('*').padded(), 2, Associativity::Left, |l, r| {
+            ASTNode::Multiply(Box::new(l), Box::new(r))
+        })
+        .infix(just('/').padded(), 2, Associativity::Left, |l, r| {
+            ASTNode::Divide(Box::new(l), Box::new(r))
+        })
+        .infix(just('+').padded(), 1, Associativity::Left, |l, r| {
+            ASTNode::Add(Box::new(l), Box::new(r))
+        })
+        .infix(just('-').padded(), 1, Associativity::Left, |l, r| {
+            ASTNode::Subtract(Box::new(l), Box::new(r))
+        })
+        .parse(input)
 }
 
 #[cfg(test)]
@@ -388,6 +326,27 @@ mod tests {
         assert_eq!(result.unwrap().value.eval(), 15f64);
     }
 
+    #[test]
+    fn negated_parenthesis() {
+        let result = parse_expression("-(1 + 2) * 3".into()).unwrap();
+        assert_eq!(result.value.eval(), -9f64);
+        assert!(result.remainder.is_empty());
+    }
+
+    #[test]
+    fn missing_operand() {
+        let source = "1 +\n  2 * x";
+        let error = parse_expression(source.into()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "expected one of '-', number, '(' at 2:7, found 'x'"
+        );
+        assert_eq!(
+            error.report(source),
+            "expected one of '-', number, '(' at 2:7, found 'x'\n  |\n2 |   2 * x\n  |       ^"
+        );
+    }
+
     #[test]
     fn many_multiplications() {
         let result = parse_expression("1*2*3*4*5".into());